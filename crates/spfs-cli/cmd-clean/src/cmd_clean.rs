@@ -89,6 +89,47 @@ pub struct CmdClean {
     #[clap(long = "keep-proxies-with-no-links", group = "repo_data")]
     keep_proxies_with_no_links: bool,
 
+    /// Clean the repository in this many passes, each of which only
+    /// removes the objects and payloads in one part of the repository.
+    ///
+    /// Each pass only reads and remembers the payloads in its own part
+    /// of the repository, at the cost of reading all of the tags and
+    /// manifests once per pass.
+    #[clap(long, default_value = "1", conflicts_with = "remove_durable")]
+    shards: NonZero<usize>,
+
+    /// Save progress to this directory so that an interrupted clean
+    /// can be resumed by running it again with the same directory
+    /// and number of shards.
+    #[clap(long, value_name = "DIR", conflicts_with = "remove_durable")]
+    checkpoint: Option<std::path::PathBuf>,
+
+    /// Move removed objects and payloads into the repository's
+    /// quarantine instead of deleting them, along with any pruned
+    /// tags, so that they can be brought back with --restore until
+    /// they are expired.
+    ///
+    /// Only supported for filesystem repositories.
+    #[clap(long, conflicts_with = "remove_durable")]
+    quarantine: bool,
+
+    /// Restore data from the quarantine and exit without cleaning.
+    ///
+    /// Given a digest, that object and its payload are restored. Given
+    /// an age (eg: 1y, 8w, 10d, 3h, 4m, 8s), everything quarantined
+    /// more recently than that is restored, including pruned tags.
+    #[clap(
+        long,
+        value_name = "DIGEST|AGE",
+        conflicts_with_all = ["remove_durable", "expire_quarantine"]
+    )]
+    restore: Option<String>,
+
+    /// Permanently remove data that was quarantined before the given
+    /// age (eg: 1y, 8w, 10d, 3h, 4m, 8s) and exit without cleaning.
+    #[clap(long, value_name = "AGE", value_parser = age_to_date, conflicts_with = "remove_durable")]
+    expire_quarantine: Option<DateTime<Utc>>,
+
     // The number of concurrent tag stream scanning operations
     // that are buffered and allowed to run concurrently
     #[clap(
@@ -160,6 +201,10 @@ impl CmdClean {
             return Ok(0);
         }
 
+        if self.restore.is_some() || self.expire_quarantine.is_some() {
+            return self.manage_quarantine(&repo).await;
+        }
+
         let prune_repeated_tags = if self.prune_repeated {
             NonZero::new(1)
         } else {
@@ -177,9 +222,15 @@ impl CmdClean {
             .with_prune_tags_if_version_more_than(self.prune_if_more_than)
             .with_keep_tags_if_version_less_than(self.keep_if_less_than)
             .with_remove_proxies_with_no_links(!self.keep_proxies_with_no_links)
+            .with_quarantine(self.quarantine)
             .with_removal_concurrency(self.max_removal_concurrency)
             .with_discover_concurrency(self.max_discover_concurrency)
-            .with_tag_stream_concurrency(self.max_tag_stream_concurrency);
+            .with_tag_stream_concurrency(self.max_tag_stream_concurrency)
+            .with_shard_count(self.shards);
+        let cleaner = match &self.checkpoint {
+            Some(path) => cleaner.with_checkpoint(path),
+            None => cleaner,
+        };
 
         println!("{}", cleaner.format_plan());
         if !self.dry_run && !self.yes {
@@ -253,6 +304,40 @@ impl CmdClean {
 
         Ok(0)
     }
+
+    async fn manage_quarantine(&self, repo: &spfs::storage::RepositoryHandle) -> Result<i32> {
+        let spfs::storage::RepositoryHandle::FS(repo) = repo else {
+            miette::bail!("Quarantine is only supported for filesystem repositories");
+        };
+        let quarantine = spfs::storage::fs::Quarantine::new(repo.opened().await?);
+
+        if let Some(cutoff) = self.expire_quarantine {
+            let expired = quarantine.expire_older_than(cutoff).await?;
+            println!("{} {} quarantined batches", "Expired".bold(), expired.len());
+            return Ok(0);
+        }
+
+        let Some(target) = &self.restore else {
+            return Ok(0);
+        };
+        if let Ok(digest) = target.parse::<spfs::encoding::Digest>() {
+            if !quarantine.restore_digest(digest).await? {
+                tracing::error!(%digest, "Not found in quarantine");
+                return Ok(1);
+            }
+            println!("{} {digest}", "Restored".green());
+            return Ok(0);
+        }
+        let since = age_to_date(target)?;
+        let restored = quarantine.restore_since(since).await?;
+        println!(
+            "{} {} objects and payloads, and {} tags",
+            "Restored".green(),
+            restored.digests.len(),
+            restored.tags.len()
+        );
+        Ok(0)
+    }
 }
 
 fn age_to_date(age: &str) -> Result<DateTime<Utc>> {
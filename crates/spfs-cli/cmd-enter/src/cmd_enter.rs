@@ -21,6 +21,10 @@ use spfs_cli_common::CommandName;
 #[cfg(unix)]
 use tokio::io::AsyncWriteExt;
 
+#[cfg(all(test, unix))]
+#[path = "./cmd_enter_test.rs"]
+mod cmd_enter_test;
+
 // The runtime setup process manages the current namespace
 // which operates only on the current thread. For this reason
 // we must use a single threaded async runtime, if any.
@@ -116,6 +120,13 @@ pub struct EnterArgs {
     #[clap(long)]
     metrics_in_env: bool,
 
+    /// Set up the runtime in an unprivileged user namespace
+    ///
+    /// This happens automatically when spfs-enter is missing the
+    /// privileges that it needs to set up the runtime as root
+    #[clap(long)]
+    rootless: bool,
+
     /// The command to run after initialization
     ///
     /// If not given, run an interactive shell environment
@@ -137,6 +148,19 @@ impl CommandName for CmdEnter {
 
 impl CmdEnter {
     pub fn run(&mut self, config: &spfs::Config) -> Result<i32> {
+        // a user namespace can only be entered by a single-threaded
+        // process, so this must happen before any threads are started
+        #[cfg(unix)]
+        if self.make_durable.enabled || self.exit.enabled || self.remount.enabled {
+            // these operate on an existing runtime from inside of it, and
+            // need the privileges held in the user namespace of its mounts
+            // when it is a rootless runtime
+            spfs::env::enter_runtime_user_namespace()?;
+        } else if self.should_enter_rootless(config, spfs::env::have_required_privileges)? {
+            spfs::env::enter_user_namespace()?;
+            self.enter.rootless = true;
+        }
+
         // we need a single-threaded runtime in order to properly setup
         // and enter the namespace of the runtime
         let rt = tokio::runtime::Builder::new_current_thread()
@@ -166,6 +190,25 @@ impl CmdEnter {
         }
     }
 
+    #[cfg(unix)]
+    /// True if a new runtime should be set up in an unprivileged user namespace
+    fn should_enter_rootless(
+        &self,
+        config: &spfs::Config,
+        have_required_privileges: impl FnOnce() -> spfs::Result<bool>,
+    ) -> Result<bool> {
+        if self.enter.rootless {
+            return Ok(true);
+        }
+        if config.filesystem.disable_rootless || have_required_privileges()? {
+            return Ok(false);
+        }
+        tracing::debug!(
+            "missing privileges to set up the runtime as root, using a rootless runtime"
+        );
+        Ok(true)
+    }
+
     #[cfg(unix)]
     /// Setup the runtime.
     ///
@@ -204,7 +247,7 @@ impl CmdEnter {
             // Safety: the responsibility of the caller.
             let in_namespace =
                 unsafe { spfs::env::RuntimeConfigurator::default().current_runtime(&runtime)? };
-            let with_root = in_namespace.become_root()?;
+            let with_root = in_namespace.become_root_for_runtime(&runtime)?;
             const LAZY: bool = false;
             with_root.unmount_env(&runtime, LAZY).await?;
             with_root.unmount_runtime(&runtime.config)?;
@@ -221,6 +264,7 @@ impl CmdEnter {
             Ok(None)
         } else {
             let mut owned = spfs::runtime::OwnedRuntime::upgrade_as_owner(runtime).await?;
+            owned.config.rootless = self.enter.rootless;
 
             // Enter the mount namespace before spawning the monitor process
             // so that the monitor can properly view and manage that namespace.
@@ -366,6 +410,12 @@ impl CmdEnter {
             }
         };
 
+        #[cfg(unix)]
+        if rt.config.sandbox.pids {
+            return spfs::env::exec_in_pid_namespace(cmd)
+                .map(|_| 0)
+                .wrap_err("Failed to execute runtime command");
+        }
         cmd.exec()
             .map(|_| 0)
             .wrap_err("Failed to execute runtime command")
@@ -414,6 +464,7 @@ impl CmdEnter {
                         copy_link_limit_count = %render_summary.copy_link_limit_count.load(Ordering::Relaxed),
                         copy_wrong_mode_count = %render_summary.copy_wrong_mode_count.load(Ordering::Relaxed),
                         copy_wrong_owner_count = %render_summary.copy_wrong_owner_count.load(Ordering::Relaxed),
+                        copy_decompressed_count = %render_summary.copy_decompressed_count.load(Ordering::Relaxed),
                         link_count = %render_summary.link_count.load(Ordering::Relaxed),
                         symlink_count = %render_summary.symlink_count.load(Ordering::Relaxed),
                         total_bytes_rendered = %render_summary.total_bytes_rendered.load(Ordering::Relaxed),
@@ -422,6 +473,7 @@ impl CmdEnter {
                         total_bytes_copied_link_limit = %render_summary.total_bytes_copied_link_limit.load(Ordering::Relaxed),
                         total_bytes_copied_wrong_mode = %render_summary.total_bytes_copied_wrong_mode.load(Ordering::Relaxed),
                         total_bytes_copied_wrong_owner = %render_summary.total_bytes_copied_wrong_owner.load(Ordering::Relaxed),
+                        total_bytes_copied_decompressed = %render_summary.total_bytes_copied_decompressed.load(Ordering::Relaxed),
                         total_bytes_linked = %render_summary.total_bytes_linked.load(Ordering::Relaxed),
                         sync_time = %sync_time,
                         render_time = %render_time,
@@ -0,0 +1,35 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use clap::Parser;
+use rstest::rstest;
+
+use super::CmdEnter;
+
+#[rstest]
+#[case::privileged(&[], false, true, false)]
+#[case::missing_privileges(&[], false, false, true)]
+#[case::disabled(&[], true, false, false)]
+#[case::forced(&["--rootless"], false, true, true)]
+#[case::forced_when_disabled(&["--rootless"], true, false, true)]
+fn test_should_enter_rootless(
+    #[case] args: &[&str],
+    #[case] disable_rootless: bool,
+    #[case] have_required_privileges: bool,
+    #[case] expected: bool,
+) {
+    let cmd = CmdEnter::try_parse_from(
+        ["spfs-enter", "--runtime", "test"]
+            .iter()
+            .chain(args.iter()),
+    )
+    .expect("valid arguments");
+    let mut config = spfs::Config::default();
+    config.filesystem.disable_rootless = disable_rootless;
+
+    let rootless = cmd
+        .should_enter_rootless(&config, || Ok(have_required_privileges))
+        .expect("should not fail to check privileges");
+    assert_eq!(rootless, expected);
+}
@@ -101,6 +101,8 @@ pub struct CmdFuse {
     ///           as root/sudo.
     ///  remote - additional remote repository to read data from, can be given more
     ///           than once
+    ///  upper  - a directory where changes to the filesystem are stored, which
+    ///           is required when mounting with the rw option
     #[clap(long, short, value_delimiter = ',')]
     options: Vec<String>,
 
@@ -139,6 +141,7 @@ impl CmdFuse {
             uid: calling_uid,
             gid: calling_gid,
             remotes: Vec::new(),
+            upper_dir: None,
             mount_options: required_opts.into_iter().collect(),
         };
 
@@ -150,6 +153,9 @@ impl CmdFuse {
                         Some(("remote", name)) => {
                             opts.remotes.push(name.to_owned());
                         }
+                        Some(("upper", path)) => {
+                            opts.upper_dir = Some(path.into());
+                        }
                         Some(("uid", num)) if calling_uid.is_root() => {
                             opts.uid = num.parse::<u32>().map(nix::unistd::Uid::from_raw).map_err(
                                 |err| {
@@ -183,7 +189,13 @@ impl CmdFuse {
         tracing::debug!("FUSE Config: {opts:#?}");
 
         if opts.mount_options.contains(&MountOption::RW) {
-            bail!("rw mode is not supported, yet");
+            if opts.upper_dir.is_none() {
+                bail!("rw mode requires an upper directory to be given, eg: upper=/path");
+            }
+            opts.mount_options.remove(&MountOption::RO);
+            // the kernel must check permissions because the filesystem
+            // does not when files are being created and modified
+            opts.mount_options.insert(MountOption::DefaultPermissions);
         }
 
         let mountpoint = self
@@ -283,6 +295,7 @@ impl CmdFuse {
                 // Don't move the [only] sender or if heartbeats are not
                 // enabled it will be dropped and trigger the receiving end.
                 let heartbeat_send = heartbeat_send.clone();
+                let session = session.clone();
                 tokio::task::spawn(async move {
                     let mut interval = tokio::time::interval(std::time::Duration::from_secs(30.min((heartbeat_interval_seconds / 2).max(1))));
 
@@ -327,6 +340,10 @@ impl CmdFuse {
                 }
             }
 
+            // save the opened payloads before shutting down, rather than
+            // relying on the filesystem being destroyed cleanly
+            session.save_prefetch().await;
+
             // The filesystem task must be fully terminated in order for the subsequent unmount
             // process to function. Otherwise, the background task will keep this process alive
             // forever.
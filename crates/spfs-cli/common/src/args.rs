@@ -65,6 +65,20 @@ pub struct Sync {
     )]
     pub max_concurrent_payloads: usize,
 
+    /// Limit the rate at which payload data is transferred, in bytes per second
+    ///
+    /// When syncing with a named remote, this overrides any limit
+    /// configured for that remote.
+    #[clap(long, env = "SPFS_SYNC_MAX_BYTES_PER_SECOND")]
+    pub max_bytes_per_second: Option<std::num::NonZeroU64>,
+
+    /// Keep a journal of the completed work so that an interrupted
+    /// sync can be resumed by running the same command again
+    ///
+    /// The journal is removed once the sync completes successfully.
+    #[clap(long, env = "SPFS_SYNC_RESUMABLE")]
+    pub resumable: bool,
+
     /// Options for showing progress
     #[clap(long, value_enum)]
     pub progress: Option<Progress>,
@@ -82,7 +96,8 @@ impl Sync {
         let syncer = spfs::Syncer::new(src, dest)
             .with_policy(policy)
             .with_max_concurrent_manifests(self.max_concurrent_manifests)
-            .with_max_concurrent_payloads(self.max_concurrent_payloads);
+            .with_max_concurrent_payloads(self.max_concurrent_payloads)
+            .with_max_bytes_per_second(self.max_bytes_per_second);
 
         match self.progress.unwrap_or_default() {
             Progress::Bars => syncer.with_reporter(spfs::sync::reporter::SyncReporters::console()),
@@ -90,6 +105,31 @@ impl Sync {
         }
     }
 
+    /// Open the journal for syncing the given env, if requested.
+    ///
+    /// The journal should be given to the syncer for this env, and
+    /// finished once the sync has completed successfully.
+    #[allow(dead_code)] // not all commands use this function but some do
+    pub async fn open_journal(
+        &self,
+        src: &spfs::storage::RepositoryHandle,
+        dest: &spfs::storage::RepositoryHandle,
+        env: &spfs::tracking::EnvSpec,
+    ) -> spfs::Result<Option<std::sync::Arc<spfs::sync::journal::SyncJournal>>> {
+        if !self.resumable {
+            return Ok(None);
+        }
+        let journal = spfs::sync::journal::SyncJournal::open_for_env(src, dest, env).await?;
+        if journal.resumed_count() > 0 {
+            tracing::info!(
+                path = ?journal.path(),
+                "resuming from a previous sync of {} objects",
+                journal.resumed_count()
+            );
+        }
+        Ok(Some(std::sync::Arc::new(journal)))
+    }
+
     /// The selected sync policy for these options
     pub fn sync_policy(&self) -> spfs::sync::SyncPolicy {
         if self.resync {
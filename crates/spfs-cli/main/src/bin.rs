@@ -6,11 +6,15 @@ use clap::{Parser, Subcommand};
 use miette::Result;
 use spfs::{Error, OsErrorExt};
 
+mod cmd_apply_bundle;
 mod cmd_check;
 mod cmd_commit;
 mod cmd_config;
 mod cmd_diff;
+mod cmd_du;
 mod cmd_edit;
+mod cmd_export_oci;
+mod cmd_import_oci;
 mod cmd_info;
 mod cmd_init;
 mod cmd_layers;
@@ -18,6 +22,7 @@ mod cmd_log;
 mod cmd_ls;
 mod cmd_ls_tags;
 mod cmd_migrate;
+mod cmd_mirror;
 mod cmd_platforms;
 mod cmd_pull;
 mod cmd_push;
@@ -29,6 +34,11 @@ mod cmd_runtime_info;
 mod cmd_runtime_list;
 mod cmd_runtime_prune;
 mod cmd_runtime_remove;
+#[cfg(unix)]
+mod cmd_runtime_rollback;
+mod cmd_runtime_snapshot;
+#[cfg(unix)]
+mod cmd_runtime_update;
 mod cmd_search;
 #[cfg(feature = "server")]
 mod cmd_server;
@@ -87,9 +97,14 @@ pub enum Command {
     LsTags(cmd_ls_tags::CmdLsTags),
     Ls(cmd_ls::CmdLs),
     Migrate(cmd_migrate::CmdMigrate),
+    Mirror(cmd_mirror::CmdMirror),
     Check(cmd_check::CmdCheck),
     Read(cmd_read::CmdRead),
     Write(cmd_write::CmdWrite),
+    ExportOci(cmd_export_oci::CmdExportOci),
+    ImportOci(cmd_import_oci::CmdImportOci),
+    ApplyBundle(cmd_apply_bundle::CmdApplyBundle),
+    Du(cmd_du::CmdDu),
 
     #[cfg(feature = "server")]
     Server(cmd_server::CmdServer),
@@ -129,10 +144,15 @@ impl Opt {
             Command::Check(cmd) => cmd.run(config).await,
             Command::Read(cmd) => cmd.run(config).await,
             Command::Write(cmd) => cmd.run(config).await,
+            Command::ExportOci(cmd) => cmd.run(config).await,
+            Command::ImportOci(cmd) => cmd.run(config).await,
+            Command::ApplyBundle(cmd) => cmd.run(config).await,
+            Command::Du(cmd) => cmd.run(config).await,
             Command::Run(cmd) => cmd.run(config).await,
             Command::Shell(cmd) => cmd.run(config).await,
             Command::Pull(cmd) => cmd.run(config).await,
             Command::Push(cmd) => cmd.run(config).await,
+            Command::Mirror(cmd) => cmd.run(config).await,
             #[cfg(feature = "server")]
             Command::Server(cmd) => cmd.run(config).await,
             Command::External(args) => run_external_subcommand(args.clone()).await,
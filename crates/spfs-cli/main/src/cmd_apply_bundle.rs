@@ -0,0 +1,39 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::path::PathBuf;
+
+use clap::Args;
+use miette::Result;
+
+/// Import a bundle created by `spfs diff --bundle`
+///
+/// The repository must already contain the data that the
+/// bundle was made against.
+#[derive(Debug, Args)]
+pub struct CmdApplyBundle {
+    /// Apply the bundle to a remote repository instead of the local one
+    #[clap(long, short)]
+    remote: Option<String>,
+
+    /// The bundle file to apply
+    #[clap(value_name = "BUNDLE")]
+    bundle: PathBuf,
+}
+
+impl CmdApplyBundle {
+    pub async fn run(&mut self, config: &spfs::Config) -> Result<i32> {
+        let repo = spfs::config::open_repository_from_string(config, self.remote.as_ref()).await?;
+        let bundle = spfs::storage::tar::TarRepository::open(&self.bundle)
+            .await
+            .map_err(|source| spfs::Error::FailedToOpenRepository {
+                repository: self.bundle.display().to_string(),
+                source,
+            })?;
+        let bundle = spfs::storage::RepositoryHandle::from(bundle);
+        spfs::apply_bundle(&bundle, &repo).await?;
+        tracing::info!(bundle = %self.bundle.display(), "applied");
+        Ok(0)
+    }
+}
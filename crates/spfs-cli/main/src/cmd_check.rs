@@ -27,6 +27,11 @@ pub struct CmdCheck {
     #[clap(long)]
     pull: Option<Option<String>>,
 
+    /// Attempt to fix problems by restoring data from the repository's
+    /// quarantine, before pulling from any other repository.
+    #[clap(long)]
+    repair_from_quarantine: bool,
+
     /// Objects to recursively check, defaults to everything
     #[clap(name = "REF")]
     reference: Vec<String>,
@@ -59,8 +64,9 @@ impl CmdCheck {
             None => None,
         };
 
-        let mut checker =
-            spfs::Checker::new(&repo).with_reporter(spfs::check::ConsoleCheckReporter::default());
+        let mut checker = spfs::Checker::new(&repo)
+            .with_reporter(spfs::check::ConsoleCheckReporter::default())
+            .with_repair_from_quarantine(self.repair_from_quarantine);
         if let Some(pull_from) = &pull_from {
             checker = checker.with_repair_source(pull_from);
         }
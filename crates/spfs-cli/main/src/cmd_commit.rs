@@ -91,6 +91,7 @@ impl CmdCommit {
                 .with_reporter(spfs::commit::ConsoleCommitReporter::default())
                 .with_max_concurrent_branches(self.max_concurrent_branches)
                 .with_max_concurrent_blobs(self.max_concurrent_blobs)
+                .with_capture_xattrs(config.filesystem.capture_xattrs)
                 .with_allow_empty(self.allow_empty);
             if self.hash_while_committing {
                 let committer = committer
@@ -2,12 +2,25 @@
 // SPDX-License-Identifier: Apache-2.0
 // https://github.com/spkenv/spk
 
+use std::path::PathBuf;
+
 use clap::Args;
 use miette::Result;
 
 /// Compare two spfs file system states
 #[derive(Debug, Args)]
 pub struct CmdDiff {
+    /// Write the data that is in TO but not in FROM to a tar repository
+    ///
+    /// The resulting bundle can be given to `spfs apply-bundle` in
+    /// any repository that already has FROM.
+    #[clap(long, requires_all = ["base", "top", "output"])]
+    bundle: bool,
+
+    /// The file to write the bundle into
+    #[clap(long, short, requires = "bundle")]
+    output: Option<PathBuf>,
+
     /// The tag or id to use as the base of the computed diff, defaults to the current runtime
     #[clap(value_name = "FROM")]
     base: Option<String>,
@@ -18,7 +31,10 @@ pub struct CmdDiff {
 }
 
 impl CmdDiff {
-    pub async fn run(&mut self, _config: &spfs::Config) -> Result<i32> {
+    pub async fn run(&mut self, config: &spfs::Config) -> Result<i32> {
+        if self.bundle {
+            return self.write_bundle(config).await;
+        }
         let diffs = spfs::diff(self.base.as_ref(), self.top.as_ref()).await?;
         let out = spfs::io::format_changes(diffs.iter());
         if out.trim().is_empty() {
@@ -28,4 +44,19 @@ impl CmdDiff {
         }
         Ok(0)
     }
+
+    async fn write_bundle(&self, config: &spfs::Config) -> Result<i32> {
+        let (Some(base), Some(top), Some(output)) = (&self.base, &self.top, &self.output) else {
+            miette::bail!("FROM, TO and --output are all required to write a bundle");
+        };
+        let repo = config.get_local_repository_handle().await?;
+        let base = spfs::tracking::EnvSpec::parse(base)?;
+        let top = spfs::tracking::EnvSpec::parse(top)?;
+        let summary = spfs::create_bundle(&repo, &base, top, output)
+            .await?
+            .summary();
+        tracing::info!("{}", spfs::io::format_sync_summary(&summary));
+        tracing::info!(path = %output.display(), "bundle written");
+        Ok(0)
+    }
 }
@@ -0,0 +1,81 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use clap::Args;
+use miette::{Context, IntoDiagnostic, Result};
+use spfs::io::format_size;
+use spfs::stats::UsageSummary;
+
+/// Show how the storage in a repository is shared between tags
+///
+/// Payloads that are used by only one tag, tag stream or namespace
+/// are exclusive to it, and would be freed if it was removed.
+#[derive(Debug, Args)]
+#[clap(visible_aliases = &["repo-stats"])]
+pub struct CmdDu {
+    /// Inspect a remote repository instead of the local one
+    #[clap(long, short)]
+    remote: Option<String>,
+
+    /// Visit the tags in all tag namespaces, not just the configured one
+    #[clap(long)]
+    all_tag_namespaces: bool,
+
+    /// Print the full report as json
+    #[clap(long)]
+    json: bool,
+
+    /// The number of tag streams to show, largest exclusive size first
+    #[clap(long, default_value_t = 20)]
+    top: usize,
+}
+
+impl CmdDu {
+    pub async fn run(&mut self, config: &spfs::Config) -> Result<i32> {
+        let repo = spfs::config::open_repository_from_string(config, self.remote.as_ref()).await?;
+        let report = spfs::UsageAnalyzer::new(&repo)
+            .with_all_tag_namespaces(self.all_tag_namespaces)
+            .analyze()
+            .await?;
+
+        if self.json {
+            serde_json::to_writer_pretty(std::io::stdout(), &report)
+                .into_diagnostic()
+                .wrap_err("Failed to generate json output")?;
+            println!();
+            return Ok(0);
+        }
+
+        println!(
+            "{} in {} payloads",
+            format_size(report.total_bytes),
+            report.payloads
+        );
+        println!("\n{:>10} {:>10}  NAMESPACE", "EXCLUSIVE", "TOTAL");
+        for summary in report.namespaces.iter() {
+            print_summary(summary, summary.namespace.as_deref().unwrap_or("<none>"));
+        }
+
+        let mut streams: Vec<_> = report.tag_streams.iter().collect();
+        streams.sort_by(|a, b| b.exclusive_bytes.cmp(&a.exclusive_bytes));
+        println!("\n{:>10} {:>10}  TAG STREAM", "EXCLUSIVE", "TOTAL");
+        for summary in streams.into_iter().take(self.top) {
+            let name = match (&summary.namespace, &summary.tag) {
+                (Some(ns), Some(tag)) => format!("{ns}:{tag}"),
+                (None, Some(tag)) => tag.clone(),
+                (_, None) => continue,
+            };
+            print_summary(summary, &name);
+        }
+        Ok(0)
+    }
+}
+
+fn print_summary(summary: &UsageSummary, name: &str) {
+    println!(
+        "{:>10} {:>10}  {name}",
+        format_size(summary.exclusive_bytes),
+        format_size(summary.total_bytes)
+    );
+}
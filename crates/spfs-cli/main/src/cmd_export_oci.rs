@@ -0,0 +1,52 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::path::PathBuf;
+
+use clap::Args;
+use miette::Result;
+
+/// Export layers or platforms as an image in an OCI layout directory
+///
+/// Each layer becomes one tar layer in the image, with its contents
+/// under the /spfs directory. Any annotations become image labels.
+#[derive(Debug, Args)]
+pub struct CmdExportOci {
+    /// Export from a remote repository instead of the local one
+    #[clap(long, short)]
+    remote: Option<String>,
+
+    /// The name to give the image in the layout's index
+    ///
+    /// An existing image with the same name is replaced.
+    #[clap(long, short)]
+    name: Option<String>,
+
+    /// The tag or digest of what to export, use a '+' to join multiple layers
+    #[clap(value_name = "REF")]
+    reference: spfs::tracking::EnvSpec,
+
+    /// The OCI layout directory to write into, created if needed
+    #[clap(value_name = "LAYOUT")]
+    layout: PathBuf,
+}
+
+impl CmdExportOci {
+    pub async fn run(&mut self, config: &spfs::Config) -> Result<i32> {
+        let repo = spfs::config::open_repository_from_string(config, self.remote.as_ref()).await?;
+
+        let mut stack = spfs::graph::Stack::default();
+        for item in self.reference.iter() {
+            stack.push(item.resolve_digest(&repo).await?);
+        }
+
+        let mut exporter = spfs::oci::OciExporter::new(&repo);
+        if let Some(name) = &self.name {
+            exporter = exporter.with_reference(name);
+        }
+        let digest = exporter.export(&stack, &self.layout).await?;
+        tracing::info!(%digest, layout = %self.layout.display(), "exported image");
+        Ok(0)
+    }
+}
@@ -0,0 +1,65 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::path::PathBuf;
+
+use clap::Args;
+use miette::Result;
+use spfs::prelude::*;
+
+/// Import an image from an OCI layout directory as a platform
+///
+/// Each tar layer of the image becomes one layer of the platform,
+/// and any image labels are stored as annotations.
+#[derive(Debug, Args)]
+pub struct CmdImportOci {
+    /// Import into a remote repository instead of the local one
+    #[clap(long, short)]
+    remote: Option<String>,
+
+    /// The name of the image to import from the layout's index
+    ///
+    /// Required when the layout contains more than one image.
+    #[clap(long, short)]
+    name: Option<String>,
+
+    /// A human-readable tag for the imported platform
+    ///
+    /// Can be provided more than once.
+    #[clap(long = "tag", short)]
+    tags: Vec<String>,
+
+    /// The OCI layout directory to read from
+    #[clap(value_name = "LAYOUT")]
+    layout: PathBuf,
+}
+
+impl CmdImportOci {
+    pub async fn run(&mut self, config: &spfs::Config) -> Result<i32> {
+        let repo = spfs::config::open_repository_from_string(config, self.remote.as_ref()).await?;
+
+        let mut importer = spfs::oci::OciImporter::new(&repo)
+            .with_annotation_size_limit(config.filesystem.annotation_size_limit);
+        if let Some(name) = &self.name {
+            importer = importer.with_reference(name);
+        }
+        let platform = importer.import(&self.layout).await?;
+        let digest = platform.digest()?;
+        tracing::info!(%digest, "created");
+
+        for tag in self.tags.iter() {
+            let tag_spec = match spfs::tracking::TagSpec::parse(tag) {
+                Ok(tag_spec) => tag_spec,
+                Err(err) => {
+                    tracing::warn!("cannot set invalid tag '{tag}': {err:?}");
+                    continue;
+                }
+            };
+            repo.push_tag(&tag_spec, &digest).await?;
+            tracing::info!(?tag, "created");
+        }
+
+        Ok(0)
+    }
+}
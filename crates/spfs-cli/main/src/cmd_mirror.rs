@@ -0,0 +1,180 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::Args;
+use miette::{IntoDiagnostic, Result, WrapErr};
+use spfs::storage::TagNamespaceBuf;
+use spfs::sync::mirror::{Mirror, MirrorPollResult};
+use spfs_cli_common as cli;
+
+/// Continuously replicate tags from one repository into another
+///
+/// The source repository is checked periodically for new tag
+/// versions, and the latest version of each selected tag is synced
+/// into the destination along with all of its data.
+#[derive(Debug, Args)]
+pub struct CmdMirror {
+    #[clap(flatten)]
+    sync: cli::Sync,
+
+    #[clap(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// The name or address of the repository to mirror from
+    #[clap(long, default_value = "origin")]
+    from: String,
+
+    /// The name or address of the repository to mirror into
+    ///
+    /// Defaults to the local repository
+    #[clap(long)]
+    to: Option<String>,
+
+    /// Only mirror tags at or under this path (eg: spk/pkg), can be given many times
+    #[clap(long = "prefix", value_name = "PATH")]
+    prefixes: Vec<String>,
+
+    /// Mirror tags from this tag namespace into the same namespace
+    /// of the destination, can be given many times
+    ///
+    /// Defaults to the tag namespace configured for each repository
+    #[clap(long = "namespace", value_name = "NAMESPACE", value_parser = parse_namespace)]
+    namespaces: Vec<TagNamespaceBuf>,
+
+    /// The number of seconds to wait between checks of the source repository
+    #[clap(long, default_value_t = 60)]
+    interval: u64,
+
+    /// The file used to remember mirrored tags across restarts
+    ///
+    /// Defaults to a file in the user's cache directory
+    /// that is specific to the source and destination
+    #[clap(long)]
+    state_file: Option<PathBuf>,
+
+    /// Write progress and lag metrics as json to this file after each check
+    #[clap(long)]
+    metrics_file: Option<PathBuf>,
+
+    /// Check the source repository only once and then exit
+    #[clap(long)]
+    once: bool,
+}
+
+impl CmdMirror {
+    pub async fn run(&mut self, config: &spfs::Config) -> Result<i32> {
+        let (src, dest) = tokio::try_join!(
+            spfs::config::open_repository_from_string(config, Some(&self.from)),
+            spfs::config::open_repository_from_string(config, self.to.as_ref()),
+        )?;
+        if self.sync.max_bytes_per_second.is_none() {
+            self.sync.max_bytes_per_second = config.get_remote_max_bytes_per_second(&self.from);
+        }
+
+        let state_file = match &self.state_file {
+            Some(path) => path.clone(),
+            None => Mirror::default_state_path(&src, &dest)?,
+        };
+        let mut mirror = Mirror::new(self.sync.get_syncer(&src, &dest))
+            .with_prefixes(self.prefixes.iter().cloned())
+            .with_state_file(&state_file)
+            .await?;
+        if !self.namespaces.is_empty() {
+            mirror = mirror.with_namespaces(self.namespaces.iter().cloned());
+        }
+        tracing::info!(
+            state = ?state_file,
+            "mirroring from {} ({} tags mirrored previously)",
+            self.from,
+            mirror.mirrored_tag_count()
+        );
+
+        let mut failed = false;
+        let mut consecutive_errors = 0;
+        loop {
+            match mirror.poll().await {
+                Ok(result) => {
+                    self.report(&result)?;
+                    failed = !result.failed_tags.is_empty();
+                    consecutive_errors = 0;
+                }
+                Err(err) if self.once || is_unrecoverable(&err) => return Err(err.into()),
+                Err(err) => {
+                    failed = true;
+                    consecutive_errors += 1;
+                    tracing::error!(
+                        consecutive_errors,
+                        "failed to check {} for new tags: {err}",
+                        self.from
+                    );
+                }
+            }
+            if self.once {
+                break;
+            }
+            // back off while the source repository is failing, so that
+            // an outage is not made worse by checking it constantly
+            let delay = Duration::from_secs(self.interval)
+                .saturating_mul(1 << consecutive_errors.min(MAX_BACKOFF_DOUBLINGS));
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                res = tokio::signal::ctrl_c() => {
+                    res.into_diagnostic().wrap_err("Failed to wait for interrupt")?;
+                    tracing::info!("stopping mirror...");
+                    break;
+                }
+            }
+        }
+        Ok(if failed { 1 } else { 0 })
+    }
+
+    fn report(&self, result: &MirrorPollResult) -> Result<()> {
+        let lag_seconds = result.max_lag.map(|lag| lag.num_seconds());
+        if result.mirrored_tags > 0 || !result.failed_tags.is_empty() {
+            tracing::info!(
+                checked = result.checked_tags,
+                mirrored = result.mirrored_tags,
+                failed = result.failed_tags.len(),
+                lag_seconds,
+                "{}",
+                spfs::io::format_sync_summary(&result.summary)
+            );
+        } else {
+            tracing::debug!(checked = result.checked_tags, "all tags up to date");
+        }
+        let Some(path) = &self.metrics_file else {
+            return Ok(());
+        };
+        let metrics = serde_json::json!({
+            "started": result.started,
+            "finished": result.finished,
+            "checked_tags": result.checked_tags,
+            "mirrored_tags": result.mirrored_tags,
+            "failed_tags": result.failed_tags.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            "max_lag_seconds": lag_seconds,
+            "synced_objects": result.summary.synced_objects,
+            "synced_payloads": result.summary.synced_payloads,
+            "synced_payload_bytes": result.summary.synced_payload_bytes,
+        });
+        std::fs::write(path, metrics.to_string())
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to write metrics to {}", path.display()))
+    }
+}
+
+/// The most times that the interval is doubled after consecutive failed checks
+const MAX_BACKOFF_DOUBLINGS: u32 = 5;
+
+/// Errors that will not go away by checking the source repository again
+fn is_unrecoverable(err: &spfs::Error) -> bool {
+    // the mirror state cannot be saved, so any progress would be lost
+    matches!(err, spfs::Error::StorageWriteError(..))
+}
+
+fn parse_namespace(value: &str) -> spfs::Result<TagNamespaceBuf> {
+    TagNamespaceBuf::new(relative_path::RelativePath::new(value))
+}
@@ -2,6 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 // https://github.com/spkenv/spk
 
+use std::sync::Arc;
+
 use clap::Args;
 use miette::Result;
 use spfs_cli_common as cli;
@@ -36,13 +38,20 @@ impl CmdPull {
             spfs::config::open_repository_from_string(config, self.remote.as_ref())
         )?;
 
-        let env_spec = self.refs.iter().cloned().collect();
-        let summary = self
-            .sync
-            .get_syncer(&remote, &repo)
-            .sync_env(env_spec)
-            .await?
-            .summary();
+        if self.sync.max_bytes_per_second.is_none() {
+            let remote_name = self.remote.as_deref().unwrap_or("origin");
+            self.sync.max_bytes_per_second = config.get_remote_max_bytes_per_second(remote_name);
+        }
+        let env_spec: spfs::tracking::EnvSpec = self.refs.iter().cloned().collect();
+        let journal = self.sync.open_journal(&remote, &repo, &env_spec).await?;
+        let mut syncer = self.sync.get_syncer(&remote, &repo);
+        if let Some(journal) = &journal {
+            syncer = syncer.with_journal(Arc::clone(journal));
+        }
+        let summary = syncer.sync_env(env_spec).await?.summary();
+        if let Some(journal) = journal {
+            journal.finish().await?;
+        }
 
         tracing::info!("{}", spfs::io::format_sync_summary(&summary));
 
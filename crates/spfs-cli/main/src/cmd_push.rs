@@ -2,6 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 // https://github.com/spkenv/spk
 
+use std::sync::Arc;
+
 use clap::Args;
 use miette::Result;
 use spfs_cli_common as cli;
@@ -34,15 +36,21 @@ impl CmdPush {
             spfs::config::open_repository_from_string(config, Some(&self.remote)),
         )?;
 
-        let env_spec = self.refs.iter().cloned().collect();
+        let env_spec: spfs::tracking::EnvSpec = self.refs.iter().cloned().collect();
         // the latest tag is always synced when pushing
         self.sync.sync = true;
-        let summary = self
-            .sync
-            .get_syncer(&repo, &remote)
-            .sync_env(env_spec)
-            .await?
-            .summary();
+        if self.sync.max_bytes_per_second.is_none() {
+            self.sync.max_bytes_per_second = config.get_remote_max_bytes_per_second(&self.remote);
+        }
+        let journal = self.sync.open_journal(&repo, &remote, &env_spec).await?;
+        let mut syncer = self.sync.get_syncer(&repo, &remote);
+        if let Some(journal) = &journal {
+            syncer = syncer.with_journal(Arc::clone(journal));
+        }
+        let summary = syncer.sync_env(env_spec).await?.summary();
+        if let Some(journal) = journal {
+            journal.finish().await?;
+        }
         tracing::info!("{}", spfs::io::format_sync_summary(&summary));
 
         Ok(0)
@@ -106,6 +106,60 @@ impl Annotation {
     }
 }
 
+#[derive(Args, Clone, Debug)]
+pub struct Sandbox {
+    /// Run in a new network namespace, where only the loopback
+    /// interface is available
+    #[clap(long)]
+    pub isolate_network: bool,
+
+    /// Run in a new pid namespace, where processes outside of the
+    /// runtime cannot be signaled (they are still listed in /proc)
+    #[clap(long)]
+    pub isolate_pids: bool,
+
+    /// Run in a new ipc namespace, so that System V IPC objects and
+    /// POSIX message queues are not shared with the host
+    #[clap(long)]
+    pub isolate_ipc: bool,
+
+    /// Restrict access to host paths outside of the allowed paths
+    /// (see --allow-host-path)
+    ///
+    /// Host paths can be made 'read-only', or 'hidden' by replacing
+    /// them with empty directories. The allowed paths always include
+    /// /spfs, the runtime's own storage and the paths listed in the
+    /// 'filesystem.sandbox_allowed_paths' config value.
+    #[clap(long, value_name = "MODE")]
+    pub host_paths: Option<spfs::runtime::HostPaths>,
+
+    /// Additional host paths to leave visible and writable when
+    /// --host-paths is given
+    #[clap(long, value_name = "PATH", requires = "host_paths")]
+    pub allow_host_path: Vec<std::path::PathBuf>,
+}
+
+impl Sandbox {
+    /// Add the isolation requested on the command line
+    /// to the given runtime configuration.
+    pub fn apply(&self, runtime_config: &mut spfs::runtime::Config, config: &spfs::Config) {
+        let sandbox = &mut runtime_config.sandbox;
+        sandbox.network |= self.isolate_network;
+        sandbox.pids |= self.isolate_pids;
+        sandbox.ipc |= self.isolate_ipc;
+        if let Some(host_paths) = self.host_paths {
+            sandbox.host_paths = host_paths;
+            sandbox.allowed_host_paths = config
+                .filesystem
+                .sandbox_allowed_paths
+                .iter()
+                .chain(self.allow_host_path.iter())
+                .cloned()
+                .collect();
+        }
+    }
+}
+
 /// Run a program in a configured spfs environment
 #[derive(Debug, Args)]
 #[clap(group(
@@ -149,6 +203,9 @@ pub struct CmdRun {
     #[clap(flatten)]
     pub annotation: Annotation,
 
+    #[clap(flatten)]
+    pub sandbox: Sandbox,
+
     /// The tag or id of the desired runtime
     ///
     /// Use '-' to or an empty string to request an empty environment
@@ -208,6 +265,8 @@ impl CmdRun {
             }
             tracing::debug!("synced and about to launch process with durable runtime");
 
+            self.sandbox.apply(&mut runtime.config, config);
+
             self.exec_runtime_command(&mut runtime, &start_time).await
         } else if let Some(reference) = &self.reference {
             let live_layers = reference.load_live_layers();
@@ -312,6 +371,8 @@ impl CmdRun {
             }
             tracing::debug!("synced all the referenced objects locally");
 
+            self.sandbox.apply(&mut runtime.config, config);
+
             self.exec_runtime_command(&mut runtime, &start_time).await
         } else {
             // Guaranteed by Clap config.
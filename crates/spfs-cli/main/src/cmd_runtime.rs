@@ -25,6 +25,11 @@ pub enum Command {
     List(super::cmd_runtime_list::CmdRuntimeList),
     Prune(super::cmd_runtime_prune::CmdRuntimePrune),
     Remove(super::cmd_runtime_remove::CmdRuntimeRemove),
+    #[cfg(unix)]
+    Rollback(super::cmd_runtime_rollback::CmdRuntimeRollback),
+    Snapshot(super::cmd_runtime_snapshot::CmdRuntimeSnapshot),
+    #[cfg(unix)]
+    Update(super::cmd_runtime_update::CmdRuntimeUpdate),
 }
 
 impl Command {
@@ -34,6 +39,11 @@ impl Command {
             Self::List(cmd) => cmd.run(config).await,
             Self::Prune(cmd) => cmd.run(config).await,
             Self::Remove(cmd) => cmd.run(config).await,
+            #[cfg(unix)]
+            Self::Rollback(cmd) => cmd.run(config).await,
+            Self::Snapshot(cmd) => cmd.run(config).await,
+            #[cfg(unix)]
+            Self::Update(cmd) => cmd.run(config).await,
         }
     }
 }
@@ -0,0 +1,42 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use clap::Args;
+use miette::{Result, bail};
+
+/// Restore the working changes of a runtime from a snapshot
+///
+/// All current changes in the runtime are replaced with those in the
+/// snapshot, and so may need to be saved in a snapshot of their own
+/// first, see `spfs runtime snapshot`. This must be run from within
+/// the runtime.
+#[derive(Debug, Args)]
+pub struct CmdRuntimeRollback {
+    /// The name/id of the runtime to roll back
+    name: String,
+
+    /// The name of the snapshot to restore
+    snapshot: String,
+}
+
+impl CmdRuntimeRollback {
+    pub async fn run(&mut self, config: &spfs::Config) -> Result<i32> {
+        if std::env::var("SPFS_RUNTIME").ok().as_ref() != Some(&self.name) {
+            bail!(
+                "Cannot roll back '{}' from outside of the runtime, use 'spfs join' first",
+                self.name
+            );
+        }
+
+        let runtime_storage = config.get_runtime_storage().await?;
+        let mut runtime = runtime_storage.read_runtime(&self.name).await?;
+        let snapshot = runtime.rollback_to_snapshot(&self.snapshot).await?;
+        tracing::info!(
+            manifest = %snapshot.manifest,
+            "rolled back to snapshot {}",
+            snapshot.name
+        );
+        Ok(0)
+    }
+}
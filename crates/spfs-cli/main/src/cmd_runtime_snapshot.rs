@@ -0,0 +1,67 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use chrono::Local;
+use clap::Args;
+use colored::*;
+use miette::{Result, bail};
+
+/// Save the working changes of a runtime so that they can be restored later
+///
+/// Snapshots are stored in the local repository without creating
+/// a layer, see `spfs runtime rollback` to restore one.
+#[derive(Debug, Args)]
+pub struct CmdRuntimeSnapshot {
+    /// The name to give the snapshot, defaults to the current time
+    ///
+    /// An existing snapshot with the same name is replaced
+    #[clap(long, short)]
+    snapshot: Option<String>,
+
+    /// List the existing snapshots of the runtime instead
+    #[clap(long, short, conflicts_with = "snapshot")]
+    list: bool,
+
+    /// The name/id of the runtime to snapshot
+    #[clap(env = "SPFS_RUNTIME")]
+    name: String,
+}
+
+impl CmdRuntimeSnapshot {
+    pub async fn run(&mut self, config: &spfs::Config) -> Result<i32> {
+        let runtime_storage = config.get_runtime_storage().await?;
+
+        if self.list {
+            for snapshot in runtime_storage.list_snapshots(&self.name).await? {
+                println!(
+                    "{} {} {} {}",
+                    snapshot.manifest.to_string()[..10].yellow(),
+                    snapshot.name.bold(),
+                    snapshot.user.bright_blue(),
+                    snapshot.time.with_timezone(&Local).to_string().green(),
+                );
+            }
+            return Ok(0);
+        }
+
+        let runtime = runtime_storage.read_runtime(&self.name).await?;
+        let is_active = std::env::var("SPFS_RUNTIME").ok().as_ref() == Some(&self.name);
+        if !is_active && !runtime.is_durable() {
+            // the edits of other runtimes are only visible from within them
+            bail!(
+                "Cannot snapshot '{}' from outside of the runtime, unless it is durable",
+                self.name
+            );
+        }
+
+        let name = match &self.snapshot {
+            Some(name) => name.clone(),
+            None => Local::now().format("%Y%m%dT%H%M%S").to_string(),
+        };
+        let snapshot = runtime.snapshot(&name).await?;
+        tracing::info!(manifest = %snapshot.manifest, "created snapshot");
+        println!("{}", snapshot.name);
+        Ok(0)
+    }
+}
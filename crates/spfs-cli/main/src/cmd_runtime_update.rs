@@ -0,0 +1,152 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::ffi::OsString;
+
+use clap::Args;
+use miette::{Result, bail};
+use spfs::tracking::EnvSpec;
+use spfs_cli_common as cli;
+
+/// Add or remove layers in a running runtime, without restarting it
+///
+/// The runtime's filesystem is remounted with the updated stack of
+/// layers, which is seen by every process in the runtime. Processes
+/// with files or directories already open in /spfs will continue to
+/// see the previous layers for those files until they are reopened.
+/// The updated filesystem replaces the previous one in a single step,
+/// so /spfs is never empty while the runtime is being updated.
+#[derive(Debug, Args)]
+pub struct CmdRuntimeUpdate {
+    #[clap(flatten)]
+    sync: cli::Sync,
+
+    /// A tag or id to add to the top of the runtime's stack
+    #[clap(long, short, value_name = "REF")]
+    add: Vec<EnvSpec>,
+
+    /// A tag or id to remove from the runtime's stack
+    #[clap(long, short, value_name = "REF")]
+    remove: Vec<EnvSpec>,
+
+    /// Update the runtime even if some of its edits would hide
+    /// the changes made by the new layers
+    #[clap(long)]
+    force: bool,
+
+    /// The name/id of the runtime to update
+    #[clap(env = "SPFS_RUNTIME")]
+    name: String,
+}
+
+impl CmdRuntimeUpdate {
+    pub async fn run(&mut self, config: &spfs::Config) -> Result<i32> {
+        if self.add.is_empty() && self.remove.is_empty() {
+            bail!("Nothing to update, at least one --add or --remove is required");
+        }
+
+        let runtime_storage = config.get_runtime_storage().await?;
+        let mut runtime = runtime_storage.read_runtime(&self.name).await?;
+        let repo = config.get_local_repository_handle().await?;
+
+        let mut add = Vec::with_capacity(self.add.len());
+        for mut env_spec in self.add.iter().cloned() {
+            if let Some(origin) = config.try_get_remote("origin").await? {
+                env_spec = self
+                    .sync
+                    .get_syncer(&origin, &repo)
+                    .sync_env(env_spec)
+                    .await?
+                    .env;
+            }
+            for item in env_spec.iter() {
+                add.push(item.resolve_digest(&repo).await?);
+            }
+        }
+        let mut remove = Vec::with_capacity(self.remove.len());
+        for env_spec in self.remove.iter() {
+            for item in env_spec.iter() {
+                remove.push(item.resolve_digest(&repo).await?);
+            }
+        }
+
+        if std::env::var("SPFS_RUNTIME").ok().as_ref() != Some(&self.name) {
+            // the runtime can only be remounted from within its
+            // mount namespace, so the update is repeated from there
+            // with the references already resolved
+            if !runtime.status.running {
+                bail!("Runtime '{}' is not running", self.name);
+            }
+            return self.exec_in_runtime(&add, &remove);
+        }
+
+        let mut stack = runtime.status.stack.clone();
+        for digest in remove.iter() {
+            if !stack.remove(digest) {
+                bail!("{digest} is not in the stack of runtime '{}'", self.name);
+            }
+        }
+        for digest in add {
+            stack.push(digest);
+        }
+        if stack == runtime.status.stack {
+            tracing::info!("runtime stack is unchanged, nothing to update");
+            return Ok(0);
+        }
+
+        let conflicts = spfs::compute_stack_update_conflicts(&runtime, &stack).await?;
+        if !conflicts.is_empty() {
+            for conflict in conflicts.iter() {
+                tracing::warn!("edited in runtime: {conflict}");
+            }
+            if !self.force {
+                bail!(
+                    "{} edited path(s) in the runtime would hide changes from this update, use --force to update anyway",
+                    conflicts.len()
+                );
+            }
+        }
+
+        runtime.status.stack = stack;
+        runtime.save_state_to_storage().await?;
+        spfs::remount_runtime(&runtime).await?;
+        tracing::info!("runtime updated");
+        Ok(0)
+    }
+
+    /// Replace the current process with one that runs this
+    /// update from within the runtime, see `spfs join`
+    fn exec_in_runtime(&self, add: &[spfs::Digest], remove: &[spfs::Digest]) -> Result<i32> {
+        let Some(join) = spfs::which_spfs("join") else {
+            return Err(spfs::Error::MissingBinary("spfs-join").into());
+        };
+        let spfs = std::env::current_exe()
+            .map_err(|err| spfs::Error::process_spawn_error("current_exe()", err, None))?;
+
+        let mut args: Vec<OsString> = vec![
+            self.name.clone().into(),
+            "--".into(),
+            spfs.into(),
+            "runtime".into(),
+            "update".into(),
+        ];
+        for digest in add {
+            args.extend(["--add".into(), digest.to_string().into()]);
+        }
+        for digest in remove {
+            args.extend(["--remove".into(), digest.to_string().into()]);
+        }
+        if self.force {
+            args.push("--force".into());
+        }
+        args.push(self.name.clone().into());
+
+        let cmd = spfs::bootstrap::Command {
+            executable: join.into(),
+            args,
+            vars: Vec::new(),
+        };
+        match cmd.exec()? {}
+    }
+}
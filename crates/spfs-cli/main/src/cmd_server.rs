@@ -2,9 +2,12 @@
 // SPDX-License-Identifier: Apache-2.0
 // https://github.com/spkenv/spk
 
+use std::path::PathBuf;
+
 use clap::Args;
 use miette::{IntoDiagnostic, Result};
 use spfs_cli_common as cli;
+use tonic::service::interceptor::InterceptedService;
 
 /// Start an spfs server
 ///
@@ -33,20 +36,90 @@ pub struct CmdServer {
     /// The address to listen on for http requests
     #[clap(default_value = "0.0.0.0:7787")]
     http_address: std::net::SocketAddr,
+
+    /// Require clients to present one of the bearer tokens listed in this file
+    ///
+    /// The file is yaml, with a 'tokens' list where each entry has a
+    /// 'name', the 'sha256' of the token, an 'access' of either
+    /// 'read-only' or 'push', and optional 'tag_namespaces' that
+    /// restrict where the token can write tags. By default, the
+    /// server does not require any authentication.
+    #[clap(long, env = "SPFS_SERVER_TOKEN_FILE")]
+    token_file: Option<PathBuf>,
+
+    /// Serve grpc and http requests over tls using this PEM certificate chain
+    ///
+    /// The payloads root should also use the https scheme
+    /// so that clients know to download payloads over tls.
+    #[clap(long, env = "SPFS_SERVER_TLS_CERT", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// The PEM private key for the tls certificate
+    #[clap(long, env = "SPFS_SERVER_TLS_KEY", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Require clients to present a certificate signed by one of
+    /// the certificate authorities in this PEM file (mutual tls)
+    #[clap(long, env = "SPFS_SERVER_TLS_CLIENT_CA", requires = "tls_cert")]
+    tls_client_ca: Option<PathBuf>,
 }
 
 impl CmdServer {
     pub async fn run(&mut self, config: &spfs::Config) -> Result<i32> {
         let repo = spfs::config::open_repository_from_string(config, self.remote.as_ref()).await?;
         let repo = std::sync::Arc::new(repo);
+        let auth = match &self.token_file {
+            Some(path) => spfs::server::Authenticator::load(path)?,
+            None => {
+                tracing::warn!("no token file given, all clients will have full access");
+                spfs::server::Authenticator::default()
+            }
+        };
+
+        let tls = match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => {
+                let mut tls = spfs::server::TlsConfig::new(cert, key);
+                if let Some(ca) = &self.tls_client_ca {
+                    tls = tls.with_client_ca_file(ca);
+                }
+                if self.payloads_root.scheme() != "https" {
+                    tracing::warn!(
+                        "serving over tls, but the payloads root does not use https: {}",
+                        self.payloads_root
+                    );
+                }
+                Some(tls)
+            }
+            _ => None,
+        };
+        let http_acceptor = tls.as_ref().map(|tls| tls.http_acceptor()).transpose()?;
+        let mut grpc_server = tonic::transport::Server::builder();
+        if let Some(tls) = &tls {
+            grpc_server = grpc_server
+                .tls_config(tls.grpc_config()?)
+                .into_diagnostic()?;
+        }
 
         let payload_service =
-            spfs::server::PayloadService::new(repo.clone(), self.payloads_root.clone());
-        let grpc_future = tonic::transport::Server::builder()
-            .add_service(spfs::server::Repository::new_srv())
-            .add_service(spfs::server::TagService::new_srv(repo.clone()))
-            .add_service(spfs::server::DatabaseService::new_srv(repo))
-            .add_service(payload_service.clone().into_srv())
+            spfs::server::PayloadService::new(repo.clone(), self.payloads_root.clone())
+                .with_authenticator(auth.clone());
+        let grpc_future = grpc_server
+            .add_service(InterceptedService::new(
+                spfs::server::Repository::new_srv(),
+                auth.clone(),
+            ))
+            .add_service(InterceptedService::new(
+                spfs::server::TagService::new_srv(repo.clone()),
+                auth.clone(),
+            ))
+            .add_service(InterceptedService::new(
+                spfs::server::DatabaseService::new_srv(repo),
+                auth.clone(),
+            ))
+            .add_service(InterceptedService::new(
+                payload_service.clone().into_srv(),
+                auth,
+            ))
             .serve_with_shutdown(self.grpc_address, async {
                 if let Err(err) = tokio::signal::ctrl_c().await {
                     tracing::error!(?err, "Failed to setup graceful shutdown handler");
@@ -83,20 +156,39 @@ impl CmdServer {
                         continue;
                     }
                 };
-                let io = hyper_util::rt::TokioIo::new(stream);
                 let service = payload_service.clone();
+                let acceptor = http_acceptor.clone();
                 tokio::task::spawn(async move {
-                    if let Err(err) = hyper::server::conn::http1::Builder::new()
-                        .serve_connection(io, service)
-                        .await
-                    {
+                    let builder = hyper::server::conn::http1::Builder::new();
+                    let result = match acceptor {
+                        None => {
+                            let io = hyper_util::rt::TokioIo::new(stream);
+                            builder.serve_connection(io, service).await
+                        }
+                        Some(acceptor) => match acceptor.accept(stream).await {
+                            Ok(stream) => {
+                                let io = hyper_util::rt::TokioIo::new(stream);
+                                builder.serve_connection(io, service).await
+                            }
+                            Err(err) => {
+                                tracing::debug!("Failed to accept tls connection: {:?}", err);
+                                return;
+                            }
+                        },
+                    };
+                    if let Err(err) = result {
                         tracing::error!("Error serving connection: {:?}", err);
                     }
                 });
             }
             Result::<(), miette::Report>::Ok(())
         };
-        tracing::info!("listening on: {}, {}", self.grpc_address, self.http_address);
+        tracing::info!(
+            tls = tls.is_some(),
+            "listening on: {}, {}",
+            self.grpc_address,
+            self.http_address
+        );
 
         // TODO: stop the other server when one fails so that
         // the process can exit
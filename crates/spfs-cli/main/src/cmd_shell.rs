@@ -7,7 +7,7 @@ use miette::Result;
 use spfs_cli_common as cli;
 
 use super::cmd_run;
-use super::cmd_run::Annotation;
+use super::cmd_run::{Annotation, Sandbox};
 
 /// Enter a subshell in a configured spfs environment
 #[derive(Debug, Args)]
@@ -52,6 +52,9 @@ pub struct CmdShell {
     #[clap(flatten)]
     pub annotation: Annotation,
 
+    #[clap(flatten)]
+    pub sandbox: Sandbox,
+
     /// The tag or id of the desired runtime
     ///
     /// Use '-' or an empty string to request an empty environment
@@ -72,6 +75,7 @@ impl CmdShell {
             reference: self.reference.clone(),
             keep_runtime: self.keep_runtime,
             annotation: self.annotation.clone(),
+            sandbox: self.sandbox.clone(),
             command: Default::default(),
         };
         run_cmd.run(config).await
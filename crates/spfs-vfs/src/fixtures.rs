@@ -0,0 +1,13 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use rstest::fixture;
+
+#[fixture]
+pub fn tmpdir() -> tempfile::TempDir {
+    tempfile::Builder::new()
+        .prefix("spfs-vfs-test-")
+        .tempdir()
+        .expect("failed to create dir for test")
+}
@@ -2,13 +2,14 @@
 // SPDX-License-Identifier: Apache-2.0
 // https://github.com/spkenv/spk
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::ffi::{OsStr, OsString};
 use std::io::{Seek, SeekFrom};
 use std::mem::ManuallyDrop;
 use std::os::fd::{AsRawFd, FromRawFd};
 use std::os::unix::ffi::OsStrExt;
 use std::os::unix::prelude::FileExt;
+use std::path::PathBuf;
 #[cfg(feature = "fuse-backend-abi-7-31")]
 use std::pin::Pin;
 use std::sync::Arc;
@@ -26,6 +27,7 @@ use fuser::{
     ReplyDirectoryPlus,
     ReplyEntry,
     ReplyOpen,
+    ReplyXattr,
     Request,
 };
 use spfs::OsError;
@@ -36,7 +38,12 @@ use spfs::tracking::BlobRead;
 use spfs::tracking::{Entry, EntryKind, EnvSpec, Manifest};
 use tokio::io::AsyncReadExt;
 
+use self::upper::UpperDir;
 use crate::Error;
+use crate::payload_cache::PayloadCache;
+use crate::prefetch::PrefetchRecorder;
+
+mod upper;
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -57,6 +64,12 @@ pub struct Config {
     /// These are in addition to the local repository and
     /// are searched in order to find data.
     pub remotes: Vec<String>,
+    /// A directory where changes to the filesystem are stored.
+    ///
+    /// When given, the filesystem can be written to and all changes
+    /// are stored in this directory in the same format as an overlayfs
+    /// upper directory, so that they can be committed as a new layer.
+    pub upper_dir: Option<std::path::PathBuf>,
 }
 
 /// Handles the allocation of inodes, and async responses to all FUSE requests
@@ -68,8 +81,17 @@ struct Filesystem {
     next_inode: AtomicU64,
     next_handle: AtomicU64,
     inodes: DashMap<u64, Arc<Entry<u64>>>,
+    /// The link count of each file that was hard linked in the manifest
+    nlinks: HashMap<u64, u32>,
     handles: DashMap<u64, Handle>,
     fs_creation_time: SystemTime,
+    /// Holds payloads read from remote repositories or
+    /// decompressed from local ones, if enabled
+    payload_cache: Option<PayloadCache>,
+    /// Records opened payloads for future runtimes, if enabled
+    prefetch: Option<Arc<PrefetchRecorder>>,
+    /// Stores changes to the filesystem, if it is writable
+    upper: Option<UpperDir>,
 }
 
 impl Filesystem {
@@ -83,20 +105,27 @@ impl Filesystem {
         repos: Vec<Arc<spfs::storage::RepositoryHandle>>,
         manifest: Manifest,
         opts: Config,
+        payload_cache: Option<PayloadCache>,
+        prefetch: Option<Arc<PrefetchRecorder>>,
     ) -> Self {
-        let fs = Self {
+        let mut fs = Self {
             repos,
             opts,
+            payload_cache,
+            prefetch,
             ttl: Duration::from_secs(u64::MAX),
             // the root inode must be 1, which we are about to allocate
             next_inode: AtomicU64::new(1),
             // we do not allocate handle 0, so skip it for now
             next_handle: AtomicU64::new(1),
             inodes: Default::default(),
+            nlinks: Default::default(),
             handles: Default::default(),
             fs_creation_time: SystemTime::now(),
+            upper: None,
         };
         // pre-allocate inodes for all entries in the manifest
+        let mut links = LinkInodes::new(&manifest, fs.opts.upper_dir.is_none());
         let mut root = manifest.take_root();
         // often manifests do not have appropriate mode bits set
         // at the root because they are not captured from the
@@ -104,7 +133,14 @@ impl Filesystem {
         // report this mode as a directory, the kernel will
         // not like our FUSE filesystem.
         root.mode = fs.opts.root_mode | libc::S_IFDIR;
-        fs.allocate_inodes(root);
+        let root = fs.allocate_inodes(root, "", &mut links);
+        fs.nlinks = links.nlinks;
+        if let Some(upper_dir) = fs.opts.upper_dir.clone() {
+            fs.upper = Some(UpperDir::new(upper_dir, &root));
+            // the kernel should not hold onto entries for long
+            // when they can be changed at any time
+            fs.ttl = Duration::from_secs(1);
+        }
         fs
     }
 
@@ -116,7 +152,7 @@ impl Filesystem {
         self.next_inode.fetch_add(1, Ordering::Relaxed)
     }
 
-    fn allocate_inodes(&self, entry: Entry) -> Arc<Entry<u64>> {
+    fn allocate_inodes(&self, entry: Entry, path: &str, links: &mut LinkInodes) -> Arc<Entry<u64>> {
         let Entry {
             kind,
             object,
@@ -124,12 +160,24 @@ impl Filesystem {
             entries,
             user_data: _,
             legacy_size,
+            xattrs,
+            hardlink,
         } = entry;
 
-        let inode = self.allocate_inode();
+        let (inode, is_new) = links.inode_for(path, || self.allocate_inode());
         let entries = entries
             .into_iter()
-            .map(|(n, e)| (n, self.allocate_inodes(e).as_ref().clone()))
+            .map(|(n, e)| {
+                let child_path = if path.is_empty() {
+                    n.clone()
+                } else {
+                    format!("{path}/{n}")
+                };
+                (
+                    n,
+                    self.allocate_inodes(e, &child_path, links).as_ref().clone(),
+                )
+            })
             .collect();
         let entry = Arc::new(Entry {
             kind,
@@ -138,8 +186,12 @@ impl Filesystem {
             entries,
             user_data: inode,
             legacy_size,
+            xattrs,
+            hardlink,
         });
-        self.inodes.insert(inode, Arc::clone(&entry));
+        if is_new {
+            self.inodes.insert(inode, Arc::clone(&entry));
+        }
         entry
     }
 
@@ -181,8 +233,9 @@ impl Filesystem {
             // subdirectory. Symlinks do not count.
             (entry.entries.iter().filter(|(_n, e)| e.is_dir()).count() + 2) as u32
         } else {
-            // Everything else just has itself
-            1
+            // Everything else just has itself, unless
+            // it was hard linked to other files
+            self.nlinks.get(&entry.user_data).copied().unwrap_or(1)
         };
 
         Ok(FileAttr {
@@ -207,6 +260,64 @@ impl Filesystem {
     }
 }
 
+/// Allocates a single inode to each group of hard linked
+/// files in a manifest while its inodes are being allocated
+struct LinkInodes {
+    /// the path of the first file in the group of each linked file
+    groups: HashMap<String, String>,
+    /// the number of files in each group, by the path of its first file
+    group_sizes: HashMap<String, u32>,
+    /// the inode of each group, by the path of its first file
+    group_inodes: HashMap<String, u64>,
+    /// files in a group only share an inode when this is set, otherwise
+    /// each is allocated its own and only the link count is reported
+    share: bool,
+    nlinks: HashMap<u64, u32>,
+}
+
+impl LinkInodes {
+    fn new(manifest: &Manifest, share: bool) -> Self {
+        let mut groups = HashMap::new();
+        let mut group_sizes = HashMap::new();
+        for (primary, members) in manifest.hardlink_groups() {
+            let primary = primary.to_string();
+            group_sizes.insert(primary.clone(), members.len() as u32 + 1);
+            for member in members {
+                groups.insert(member.to_string(), primary.clone());
+            }
+            groups.insert(primary.clone(), primary);
+        }
+        Self {
+            groups,
+            group_sizes,
+            group_inodes: Default::default(),
+            share,
+            nlinks: Default::default(),
+        }
+    }
+
+    /// Get the inode for a path in the manifest, and whether it was
+    /// newly allocated rather than being shared with another file
+    fn inode_for(&mut self, path: &str, allocate: impl FnOnce() -> u64) -> (u64, bool) {
+        let Some(primary) = self.groups.get(path) else {
+            return (allocate(), true);
+        };
+        let count = self.group_sizes.get(primary).copied().unwrap_or(1);
+        if !self.share {
+            let inode = allocate();
+            self.nlinks.insert(inode, count);
+            return (inode, true);
+        }
+        if let Some(inode) = self.group_inodes.get(primary) {
+            return (*inode, false);
+        }
+        let inode = allocate();
+        self.group_inodes.insert(primary.clone(), inode);
+        self.nlinks.insert(inode, count);
+        (inode, true)
+    }
+}
+
 /// Extract the ok value from a result, or reply with an error in FUSE
 macro_rules! unwrap {
     ($reply:ident, $op:expr) => {{
@@ -256,6 +367,14 @@ impl Filesystem {
     }
 
     async fn lookup(&self, parent: u64, name: OsString, reply: ReplyEntry) {
+        if self.upper.is_some() {
+            match self.lookup_upper(parent, &name).await {
+                Ok(attr) => reply.entry(&self.ttl, &attr, 0),
+                Err(errno) => reply.error(errno),
+            }
+            return;
+        }
+
         let Some(name) = name.to_str() else {
             reply.error(libc::EINVAL);
             return;
@@ -299,7 +418,15 @@ impl Filesystem {
         // from the underlying manifest at startup
     }
 
-    async fn getattr(&self, ino: u64, reply: fuser::ReplyAttr) {
+    async fn getattr(&self, ino: u64, fh: Option<u64>, reply: fuser::ReplyAttr) {
+        if self.upper.is_some() {
+            match self.getattr_upper(ino, fh).await {
+                Ok(attr) => reply.attr(&self.ttl, &attr),
+                Err(errno) => reply.error(errno),
+            }
+            return;
+        }
+
         let Some(inode) = self.inodes.get(&ino) else {
             reply.error(libc::ENOENT);
             return;
@@ -312,7 +439,56 @@ impl Filesystem {
         reply.attr(&self.ttl, &attr);
     }
 
+    async fn xattrs(&self, ino: u64) -> std::result::Result<spfs::tracking::Xattrs, libc::c_int> {
+        if self.upper.is_some() {
+            return self.xattrs_upper(ino).await;
+        }
+        match self.inodes.get(&ino) {
+            Some(inode) => Ok(inode.xattrs.clone()),
+            None => Err(libc::ENOENT),
+        }
+    }
+
+    async fn getxattr(&self, ino: u64, name: OsString, size: u32, reply: ReplyXattr) {
+        let xattrs = match self.xattrs(ino).await {
+            Ok(xattrs) => xattrs,
+            Err(errno) => {
+                reply.error(errno);
+                return;
+            }
+        };
+        match name.to_str().and_then(|name| xattrs.get(name)) {
+            Some(value) => reply_xattr(reply, size, value),
+            None => reply.error(libc::ENODATA),
+        }
+    }
+
+    async fn listxattr(&self, ino: u64, size: u32, reply: ReplyXattr) {
+        match self.xattrs(ino).await {
+            Ok(xattrs) => {
+                let names = spfs::xattr::encode_names(xattrs.keys().map(OsStr::new));
+                reply_xattr(reply, size, &names);
+            }
+            Err(errno) => reply.error(errno),
+        }
+    }
+
     async fn readlink(&self, ino: u64, reply: ReplyData) {
+        if self.upper.is_some() {
+            match self.readlink_upper(ino).await {
+                Ok(Some(target)) => {
+                    reply.data(&target);
+                    return;
+                }
+                // unmodified links are read from the lower manifest below
+                Ok(None) => {}
+                Err(errno) => {
+                    reply.error(errno);
+                    return;
+                }
+            }
+        }
+
         let Some(entry) = self.inodes.get(&ino).map(|kv| Arc::clone(kv.value())) else {
             reply.error(libc::ENOENT);
             return;
@@ -345,6 +521,23 @@ impl Filesystem {
     }
 
     async fn open(&self, ino: u64, flags: i32, reply: ReplyOpen) {
+        if self.upper.is_some() {
+            match self.open_upper(ino, flags).await {
+                Ok(Some(handle)) => {
+                    let fh = self.allocate_handle(handle);
+                    tracing::trace!("open {ino} = {fh} [UPPER]");
+                    reply.opened(fh, 0);
+                    return;
+                }
+                // unmodified files are read from the lower manifest below
+                Ok(None) => {}
+                Err(errno) => {
+                    reply.error(errno);
+                    return;
+                }
+            }
+        }
+
         let Some(entry) = self.inodes.get(&ino).map(|kv| Arc::clone(kv.value())) else {
             tracing::debug!("open {ino} = ENOENT");
             reply.error(libc::ENOENT);
@@ -352,7 +545,6 @@ impl Filesystem {
         };
 
         if flags & (libc::O_WRONLY | libc::O_RDWR) != 0 {
-            // TODO: support creating files?
             tracing::debug!("open {flags} = EROFS");
             reply.error(libc::EROFS);
             return;
@@ -376,47 +568,75 @@ impl Filesystem {
         #[allow(unused_mut)]
         let mut flags = FOPEN_KEEP_CACHE;
         for repo in self.repos.iter() {
-            match &**repo {
-                spfs::storage::RepositoryHandle::FS(fs_repo) => {
-                    let Ok(fs_repo) = fs_repo.opened().await else {
-                        reply.error(libc::ENOENT);
-                        return;
+            if let spfs::storage::RepositoryHandle::FS(fs_repo) = &**repo {
+                let Ok(fs_repo) = fs_repo.opened().await else {
+                    reply.error(libc::ENOENT);
+                    return;
+                };
+                let payload_path = fs_repo.payloads().build_digest_path(digest);
+                match std::fs::OpenOptions::new().read(true).open(payload_path) {
+                    Ok(file) => {
+                        handle = Some(Handle::BlobFile { entry, file });
+                        break;
+                    }
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(err) => err!(reply, err),
+                }
+                // the payload may still be stored compressed, which
+                // is read and decompressed through the repository
+                // the same way as any remote payload
+                let compressed = fs_repo
+                    .compressed_payloads()
+                    .map(|store| store.build_digest_path(digest));
+                if !compressed.is_some_and(|path| path.exists()) {
+                    continue;
+                }
+            }
+
+            if let Some(cache) = &self.payload_cache {
+                if let Some(file) = cache.get(digest) {
+                    handle = Some(Handle::BlobFile { entry, file });
+                    break;
+                }
+                if cache.can_hold(entry.size()) {
+                    let result = match repo.open_payload(*digest).await {
+                        Ok((stream, _)) => cache.fill(*digest, stream).await,
+                        Err(spfs::Error::UnknownObject(_)) => continue,
+                        Err(err) => err!(reply, err),
                     };
-                    let payload_path = fs_repo.payloads().build_digest_path(digest);
-                    match std::fs::OpenOptions::new().read(true).open(payload_path) {
+                    match result {
                         Ok(file) => {
                             handle = Some(Handle::BlobFile { entry, file });
                             break;
                         }
-                        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
-                            continue;
+                        Err(err) => {
+                            tracing::warn!("failed to cache payload {digest}: {err}")
                         }
-                        Err(err) => err!(reply, err),
                     }
                 }
-                #[cfg(feature = "fuse-backend-abi-7-31")]
-                repo => match repo.open_payload(*digest).await {
-                    Ok((stream, _)) => {
-                        // TODO: try to leverage the returned file path?
-                        handle = Some(Handle::BlobStream {
-                            entry,
-                            stream: tokio::sync::Mutex::new(stream),
-                        });
-                        flags |= FOPEN_NONSEEKABLE | FOPEN_STREAM;
-                        break;
-                    }
-                    Err(spfs::Error::UnknownObject(_)) => continue,
-                    Err(err) => err!(reply, err),
-                },
-                #[cfg(not(feature = "fuse-backend-abi-7-31"))]
-                repo => {
-                    tracing::error!(
-                        "Attempting to use unsupported repo type with fuse: {}",
-                        repo.address(),
-                    );
-                    reply.error(libc::ECONNREFUSED);
-                    return;
+            }
+            #[cfg(feature = "fuse-backend-abi-7-31")]
+            match repo.open_payload(*digest).await {
+                Ok((stream, _)) => {
+                    // TODO: try to leverage the returned file path?
+                    handle = Some(Handle::BlobStream {
+                        entry,
+                        stream: tokio::sync::Mutex::new(stream),
+                    });
+                    flags |= FOPEN_NONSEEKABLE | FOPEN_STREAM;
+                    break;
                 }
+                Err(spfs::Error::UnknownObject(_)) => continue,
+                Err(err) => err!(reply, err),
+            }
+            #[cfg(not(feature = "fuse-backend-abi-7-31"))]
+            {
+                tracing::error!(
+                    "Remote and compressed payloads can only be read through the payload cache with this fuse version: {}",
+                    repo.address(),
+                );
+                reply.error(libc::ECONNREFUSED);
+                return;
             }
         }
 
@@ -426,6 +646,9 @@ impl Filesystem {
             return;
         };
 
+        if let (Some(prefetch), Some(entry)) = (&self.prefetch, handle.entry_owned()) {
+            prefetch.record(entry.object);
+        }
         let fh = self.allocate_handle(handle);
 
         tracing::trace!("open {ino} = {fh}");
@@ -449,11 +672,11 @@ impl Filesystem {
         };
 
         match handle.value() {
-            Handle::Tree { .. } => {
+            Handle::Tree { .. } | Handle::UpperTree { .. } => {
                 tracing::debug!("read {fh} = EISDIR");
                 reply.error(libc::EISDIR);
             }
-            Handle::BlobFile { entry: _, file } => {
+            Handle::BlobFile { entry: _, file } | Handle::UpperFile { file } => {
                 // Safety: the fd must be valid and open, which we know. We also
                 // know that the file will live for the livetime of this function
                 // and so can create a copy of it safely for use before that rather
@@ -504,7 +727,7 @@ impl Filesystem {
         fh: u64,
         _flags: i32,
         _lock_owner: Option<u64>,
-        // ignore flush because we don't support write operations
+        // ignore flush because writes are never buffered
         _flush: bool,
         reply: fuser::ReplyEmpty,
     ) {
@@ -517,6 +740,18 @@ impl Filesystem {
     }
 
     async fn opendir(&self, ino: u64, _flags: i32, reply: ReplyOpen) {
+        if self.upper.is_some() {
+            match self.opendir_upper(ino).await {
+                Ok(handle) => {
+                    let fh = self.allocate_handle(handle);
+                    tracing::trace!("opendir {ino} = {fh} [UPPER]");
+                    reply.opened(fh, 0);
+                }
+                Err(errno) => reply.error(errno),
+            }
+            return;
+        }
+
         let Some(entry) = self.inodes.get(&ino).map(|e| Arc::clone(e.value())) else {
             reply.error(libc::ENOENT);
             return;
@@ -547,26 +782,30 @@ impl Filesystem {
 
     async fn readdir(&self, _ino: u64, fh: u64, offset: i64, mut reply: ReplyDirectory) {
         tracing::trace!("readdir try_get_handle {fh} [{_ino}]");
-        let Some(entry) = self.handles.get(&fh).map(|h| h.value().entry_owned()) else {
+        let Some(handle) = self.handles.get(&fh) else {
             reply.error(libc::EBADF);
             return;
         };
-
-        let mut remaining = entry.entries.iter();
-        if offset != 0 {
-            // inode numbers are used to set dir offsets, so if the kernel gave one
-            // we must take all entries up to and including that number
-            //
-            // we don't assume any specific sorting of the inodes or the directory
-            // entries, only that the entry.entries field will not be reordered
-            let mut next = remaining.next();
-            while let Some(n) = next.take() {
-                if n.1.user_data != offset as u64 {
-                    next = remaining.next();
+        if let Handle::UpperTree { entries } = handle.value() {
+            // offsets into merged directories are the index of the next entry
+            for (i, (name, attr)) in entries.iter().enumerate().skip(offset as usize) {
+                if reply.add(attr.ino, i as i64 + 1, attr.kind, name) {
+                    break;
                 }
             }
+            reply.ok();
+            return;
         }
-        for (name, entry) in remaining {
+        let Some(entry) = handle.value().entry_owned() else {
+            reply.error(libc::EBADF);
+            return;
+        };
+        drop(handle);
+
+        // offsets are the index of the next entry, rather than an inode number,
+        // because hard linked files in the same directory share their inode.
+        // This relies on the entry.entries field never being reordered
+        for (i, (name, entry)) in entry.entries.iter().enumerate().skip(offset as usize) {
             let kind = match entry.kind {
                 EntryKind::Blob(_) if entry.is_symlink() => FileType::Symlink,
                 EntryKind::Blob(_) => FileType::RegularFile,
@@ -574,7 +813,7 @@ impl Filesystem {
                 EntryKind::Mask => continue,
             };
             let ino = entry.user_data;
-            let next_offset = ino as i64;
+            let next_offset = i as i64 + 1;
             let buffer_full = reply.add(ino, next_offset, kind, name);
             if buffer_full {
                 break;
@@ -585,28 +824,30 @@ impl Filesystem {
 
     async fn readdirplus(&self, _ino: u64, fh: u64, offset: i64, mut reply: ReplyDirectoryPlus) {
         tracing::trace!("readdirplus try_get_handle {fh} @{offset}");
-        let Some(entry) = self.handles.get(&fh).map(|h| h.value().entry_owned()) else {
+        let Some(handle) = self.handles.get(&fh) else {
             reply.error(libc::EBADF);
             return;
         };
-
-        let mut remaining = entry.entries.iter();
-        if offset != 0 {
-            // inode numbers are used to set dir offsets, so if the kernel gave one
-            // we must take all entries up to and including that number
-            //
-            // we don't assume any specific sorting of the inodes or the directory
-            // entries, only that the entry.entries field will not be reordered
-            let mut next = remaining.next();
-            while let Some(n) = next.take() {
-                if n.1.user_data != offset as u64 {
-                    next = remaining.next();
+        if let Handle::UpperTree { entries } = handle.value() {
+            // offsets into merged directories are the index of the next entry
+            for (i, (name, attr)) in entries.iter().enumerate().skip(offset as usize) {
+                if reply.add(attr.ino, i as i64 + 1, name, &self.ttl, attr, 0) {
+                    break;
                 }
             }
+            reply.ok();
+            return;
         }
-        for (name, entry) in remaining {
+        let Some(entry) = handle.value().entry_owned() else {
+            reply.error(libc::EBADF);
+            return;
+        };
+        drop(handle);
+
+        // offsets are the index of the next entry, as in readdir
+        for (i, (name, entry)) in entry.entries.iter().enumerate().skip(offset as usize) {
             let ino = entry.user_data;
-            let next_offset = ino as i64;
+            let next_offset = i as i64 + 1;
             let Ok(attr) = self.attr_from_entry(entry) else {
                 continue;
             };
@@ -635,12 +876,12 @@ impl Filesystem {
         };
 
         let file = match handle.value() {
-            Handle::Tree { .. } => {
+            Handle::Tree { .. } | Handle::UpperTree { .. } => {
                 tracing::debug!("lseek {fh} = EISDIR");
                 reply.error(libc::EISDIR);
                 return;
             }
-            Handle::BlobFile { entry: _, file } => file,
+            Handle::BlobFile { entry: _, file } | Handle::UpperFile { file } => file,
             #[cfg(feature = "fuse-backend-abi-7-31")]
             Handle::BlobStream { .. } => {
                 tracing::warn!("FUSE should not allow seek calls on streams");
@@ -681,6 +922,108 @@ impl Filesystem {
         let new_offset = unwrap!(reply, f.seek(pos));
         reply.offset(new_offset as i64);
     }
+
+    async fn create(
+        &self,
+        parent: u64,
+        name: OsString,
+        mode: u32,
+        umask: u32,
+        flags: i32,
+        reply: fuser::ReplyCreate,
+    ) {
+        match self.create_upper(parent, &name, mode, umask, flags).await {
+            Ok((attr, handle)) => {
+                let fh = self.allocate_handle(handle);
+                tracing::trace!("create {name:?} in {parent} = {fh}");
+                reply.created(&self.ttl, &attr, 0, fh, 0);
+            }
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    async fn write(
+        &self,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        data: Vec<u8>,
+        reply: fuser::ReplyWrite,
+    ) {
+        match self.write_upper(fh, offset, &data).await {
+            Ok(count) => reply.written(count),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    async fn fsync(&self, _ino: u64, fh: u64, datasync: bool, reply: fuser::ReplyEmpty) {
+        match self.fsync_upper(fh, datasync).await {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    async fn setattr(
+        &self,
+        ino: u64,
+        fh: Option<u64>,
+        mode: Option<u32>,
+        size: Option<u64>,
+        atime: Option<fuser::TimeOrNow>,
+        mtime: Option<fuser::TimeOrNow>,
+        reply: fuser::ReplyAttr,
+    ) {
+        match self.setattr_upper(ino, fh, mode, size, atime, mtime).await {
+            Ok(attr) => reply.attr(&self.ttl, &attr),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    async fn mkdir(&self, parent: u64, name: OsString, mode: u32, umask: u32, reply: ReplyEntry) {
+        match self.mkdir_upper(parent, &name, mode, umask).await {
+            Ok(attr) => reply.entry(&self.ttl, &attr, 0),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    async fn symlink(&self, parent: u64, name: OsString, target: PathBuf, reply: ReplyEntry) {
+        match self.symlink_upper(parent, &name, &target).await {
+            Ok(attr) => reply.entry(&self.ttl, &attr, 0),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    async fn unlink(&self, parent: u64, name: OsString, reply: fuser::ReplyEmpty) {
+        match self.unlink_upper(parent, &name).await {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    async fn rmdir(&self, parent: u64, name: OsString, reply: fuser::ReplyEmpty) {
+        match self.rmdir_upper(parent, &name).await {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    async fn rename(
+        &self,
+        parent: u64,
+        name: OsString,
+        new_parent: u64,
+        new_name: OsString,
+        flags: u32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        match self
+            .rename_upper(parent, &name, new_parent, &new_name, flags)
+            .await
+        {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
 }
 
 /// Represents a connected FUSE session.
@@ -710,6 +1053,19 @@ impl Session {
         }
     }
 
+    /// Save the payloads that were opened in this session, if they are
+    /// being recorded, so that they can be prefetched by future runtimes.
+    ///
+    /// This should be called once the session has ended.
+    pub async fn save_prefetch(&self) {
+        let Some(prefetch) = self.inner.fs.get().and_then(|fs| fs.prefetch.as_ref()) else {
+            return;
+        };
+        if let Err(err) = prefetch.save().await {
+            tracing::warn!("Failed to save the list of opened payloads: {err}");
+        }
+    }
+
     /// Return the number of seconds since the last heartbeat was received
     pub fn seconds_since_last_heartbeat(&self) -> u64 {
         self.inner.session_start.elapsed().as_secs()
@@ -753,16 +1109,77 @@ impl SessionInner {
                     unreachable!();
                 };
 
-                let repos = repo.into_stack().into_iter().map(Arc::new).collect();
+                let payload_cache = match config.fuse.payload_cache_path() {
+                    Some(root) => {
+                        let max_size = config.fuse.payload_cache_size_mb * 1024 * 1024;
+                        tracing::debug!("Opening payload cache in {}...", root.display());
+                        Some(PayloadCache::open(root, max_size)?)
+                    }
+                    None => None,
+                };
+
+                let stack = match config.fuse.enable_prefetch {
+                    true => self.resolve_stack(&repo).await?,
+                    false => None,
+                };
+                let repos: Vec<_> = repo.into_stack().into_iter().map(Arc::new).collect();
+                let prefetch = match stack {
+                    Some(stack) => Some(self.start_prefetch(&config, stack, &repos).await?),
+                    None => None,
+                };
                 Ok(Arc::new(Filesystem::new(
                     repos,
                     manifest,
                     self.opts.clone(),
+                    payload_cache,
+                    prefetch,
                 )))
             })
             .await
             .cloned()
     }
+
+    /// The stack of layers being served, or None if it includes live
+    /// layers, which cannot be identified by a digest
+    async fn resolve_stack(
+        &self,
+        repo: &spfs::storage::ProxyRepository,
+    ) -> spfs::Result<Option<spfs::graph::Stack>> {
+        let mut stack = spfs::graph::Stack::default();
+        for item in self.reference.iter() {
+            if let spfs::tracking::EnvSpecItem::SpecFile(_) = item {
+                return Ok(None);
+            }
+            stack.push(item.resolve_digest(repo).await?);
+        }
+        Ok(Some(stack))
+    }
+
+    /// Start prefetching the payloads recorded for this stack of layers,
+    /// returning the recorder for the payloads opened this time, which
+    /// is saved on the heartbeat interval
+    async fn start_prefetch(
+        &self,
+        config: &spfs::Config,
+        stack: spfs::graph::Stack,
+        repos: &[Arc<spfs::storage::RepositoryHandle>],
+    ) -> spfs::Result<Arc<PrefetchRecorder>> {
+        // the proxy is opened from the storage root, but the local
+        // repository is opened separately rather than assuming
+        // that it is always the first one in the proxy
+        let local = Arc::new(config.get_local_repository_handle().await?);
+        let recorder = Arc::new(PrefetchRecorder::new(stack, Arc::clone(&local))?);
+        let remotes = repos
+            .iter()
+            .filter(|repo| repo.address() != local.address())
+            .cloned()
+            .collect();
+        recorder.spawn_prefetch(remotes, config.fuse.prefetch_concurrency.get());
+        recorder.spawn_periodic_save(Duration::from_secs(
+            config.fuse.heartbeat_interval_seconds.get(),
+        ));
+        Ok(recorder)
+    }
 }
 
 impl fuser::Filesystem for Session {
@@ -843,11 +1260,35 @@ impl fuser::Filesystem for Session {
         });
     }
 
-    fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: fuser::ReplyAttr) {
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, fh: Option<u64>, reply: fuser::ReplyAttr) {
         let session = Arc::clone(&self.inner);
         tokio::task::spawn(async move {
             let fs = unwrap!(reply, session.get_fs().await);
-            fs.getattr(ino, reply).await
+            fs.getattr(ino, fh, reply).await
+        });
+    }
+
+    fn getxattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        size: u32,
+        reply: ReplyXattr,
+    ) {
+        let name = name.to_owned();
+        let session = Arc::clone(&self.inner);
+        tokio::task::spawn(async move {
+            let fs = unwrap!(reply, session.get_fs().await);
+            fs.getxattr(ino, name, size, reply).await
+        });
+    }
+
+    fn listxattr(&mut self, _req: &Request<'_>, ino: u64, size: u32, reply: ReplyXattr) {
+        let session = Arc::clone(&self.inner);
+        tokio::task::spawn(async move {
+            let fs = unwrap!(reply, session.get_fs().await);
+            fs.listxattr(ino, size, reply).await
         });
     }
 
@@ -971,6 +1412,168 @@ impl fuser::Filesystem for Session {
             fs.lseek(ino, fh, offset, whence, reply).await
         });
     }
+
+    fn create(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        flags: i32,
+        reply: fuser::ReplyCreate,
+    ) {
+        let name = name.to_owned();
+        let session = Arc::clone(&self.inner);
+        tokio::task::spawn(async move {
+            let fs = unwrap!(reply, session.get_fs().await);
+            fs.create(parent, name, mode, umask, flags, reply).await
+        });
+    }
+
+    fn write(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: fuser::ReplyWrite,
+    ) {
+        let data = data.to_vec();
+        let session = Arc::clone(&self.inner);
+        tokio::task::spawn(async move {
+            let fs = unwrap!(reply, session.get_fs().await);
+            fs.write(ino, fh, offset, data, reply).await
+        });
+    }
+
+    fn fsync(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        datasync: bool,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let session = Arc::clone(&self.inner);
+        tokio::task::spawn(async move {
+            let fs = unwrap!(reply, session.get_fs().await);
+            fs.fsync(ino, fh, datasync, reply).await
+        });
+    }
+
+    fn setattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        mode: Option<u32>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+        size: Option<u64>,
+        atime: Option<fuser::TimeOrNow>,
+        mtime: Option<fuser::TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: fuser::ReplyAttr,
+    ) {
+        let session = Arc::clone(&self.inner);
+        tokio::task::spawn(async move {
+            let fs = unwrap!(reply, session.get_fs().await);
+            fs.setattr(ino, fh, mode, size, atime, mtime, reply).await
+        });
+    }
+
+    fn mkdir(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        reply: ReplyEntry,
+    ) {
+        let name = name.to_owned();
+        let session = Arc::clone(&self.inner);
+        tokio::task::spawn(async move {
+            let fs = unwrap!(reply, session.get_fs().await);
+            fs.mkdir(parent, name, mode, umask, reply).await
+        });
+    }
+
+    fn symlink(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        link_name: &OsStr,
+        target: &std::path::Path,
+        reply: ReplyEntry,
+    ) {
+        let name = link_name.to_owned();
+        let target = target.to_owned();
+        let session = Arc::clone(&self.inner);
+        tokio::task::spawn(async move {
+            let fs = unwrap!(reply, session.get_fs().await);
+            fs.symlink(parent, name, target, reply).await
+        });
+    }
+
+    fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
+        let name = name.to_owned();
+        let session = Arc::clone(&self.inner);
+        tokio::task::spawn(async move {
+            let fs = unwrap!(reply, session.get_fs().await);
+            fs.unlink(parent, name, reply).await
+        });
+    }
+
+    fn rmdir(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
+        let name = name.to_owned();
+        let session = Arc::clone(&self.inner);
+        tokio::task::spawn(async move {
+            let fs = unwrap!(reply, session.get_fs().await);
+            fs.rmdir(parent, name, reply).await
+        });
+    }
+
+    fn rename(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        flags: u32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let name = name.to_owned();
+        let newname = newname.to_owned();
+        let session = Arc::clone(&self.inner);
+        tokio::task::spawn(async move {
+            let fs = unwrap!(reply, session.get_fs().await);
+            fs.rename(parent, name, newparent, newname, flags, reply)
+                .await
+        });
+    }
+}
+
+/// Reply to an xattr request with the given data, following the
+/// convention that a zero size is asking for the required size.
+fn reply_xattr(reply: ReplyXattr, size: u32, data: &[u8]) {
+    if size == 0 {
+        reply.size(data.len() as u32);
+    } else if data.len() > size as usize {
+        reply.error(libc::ERANGE);
+    } else {
+        reply.data(data);
+    }
 }
 
 enum Handle {
@@ -991,15 +1594,25 @@ enum Handle {
     Tree {
         entry: Arc<Entry<u64>>,
     },
+    /// A handle to a file in the upper directory, which can be written
+    UpperFile {
+        file: std::fs::File,
+    },
+    /// A snapshot of the merged contents of a directory
+    /// when the filesystem is writable
+    UpperTree {
+        entries: Arc<Vec<(OsString, FileAttr)>>,
+    },
 }
 
 impl Handle {
-    fn entry_owned(&self) -> Arc<Entry<u64>> {
+    fn entry_owned(&self) -> Option<Arc<Entry<u64>>> {
         match self {
-            Self::BlobFile { entry, .. } => Arc::clone(entry),
+            Self::BlobFile { entry, .. } => Some(Arc::clone(entry)),
             #[cfg(feature = "fuse-backend-abi-7-31")]
-            Self::BlobStream { entry, .. } => Arc::clone(entry),
-            Self::Tree { entry } => Arc::clone(entry),
+            Self::BlobStream { entry, .. } => Some(Arc::clone(entry)),
+            Self::Tree { entry } => Some(Arc::clone(entry)),
+            Self::UpperFile { .. } | Self::UpperTree { .. } => None,
         }
     }
 }
@@ -0,0 +1,926 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+//! Support for writing changes to a fuse filesystem.
+//!
+//! Changes are stored in an upper directory that uses the same layout
+//! as an overlayfs upper directory: new and modified files are written
+//! in full, and files that are removed from the lower manifest are
+//! replaced by whiteout files (character devices with the 0/0 device
+//! number). This allows the changes to be committed using the same
+//! logic as any other spfs runtime.
+//!
+//! Whiteout files are created with mknod, which requires Linux 5.8 or
+//! newer when the filesystem is not run with the CAP_MKNOD capability.
+//! Older kernels reject the removal of any file from the lower manifest.
+//!
+//! Files and directories in the upper directory keep the mode that they
+//! have in the runtime, so that they are committed as they appear. The
+//! filesystem runs as the runtime user, so it briefly grants itself
+//! access to read-only ones when they need to be modified, after the
+//! kernel has already checked the permissions of the caller.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use dashmap::DashMap;
+use fuser::{FileAttr, FileType, TimeOrNow};
+use spfs::OsError;
+use spfs::prelude::*;
+use spfs::tracking::{BlobRead, Entry};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::{Filesystem, Handle};
+
+#[cfg(test)]
+#[path = "./upper_test.rs"]
+mod upper_test;
+
+/// The result of an operation on the upper directory, where
+/// errors are the errno that should be reported to the kernel
+pub(super) type OpResult<T> = std::result::Result<T, libc::c_int>;
+
+fn errno(err: std::io::Error) -> libc::c_int {
+    err.raw_os_error().unwrap_or(libc::EIO)
+}
+
+/// Add the given permission bits for the owner of a path, returning
+/// its original mode if it had to be changed
+fn grant_access(path: &Path, bits: u32) -> std::io::Result<Option<u32>> {
+    let mode = std::fs::metadata(path)?.mode() & 0o7777;
+    if mode & bits == bits {
+        return Ok(None);
+    }
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode | bits))?;
+    Ok(Some(mode))
+}
+
+/// Remove a directory from the upper directory, including
+/// any read-only directories inside of it
+fn remove_upper_dir_all(path: &Path) -> std::io::Result<()> {
+    make_dirs_writable(path)?;
+    std::fs::remove_dir_all(path)
+}
+
+fn make_dirs_writable(path: &Path) -> std::io::Result<()> {
+    grant_access(path, 0o700)?;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            make_dirs_writable(&entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Restore the extended attributes of a lower entry onto its
+/// copy in the upper directory so that they are kept when committed.
+///
+/// Like a render, attributes that cannot be set are skipped.
+fn copy_up_xattrs(path: &Path, xattrs: &spfs::tracking::Xattrs) {
+    for (name, value) in xattrs {
+        if let Err(err) = spfs::xattr::set(path, OsStr::new(name), value) {
+            tracing::debug!("unable to copy up extended attribute {name}: {err}");
+        }
+    }
+}
+
+/// The state of a single path in the upper directory
+enum UpperState {
+    /// The path has not been modified
+    Missing,
+    /// The path was removed from the lower manifest
+    Removed,
+    /// The path was created or modified
+    Present(std::fs::Metadata),
+}
+
+/// The merged view of a single path in a writable filesystem
+pub(super) enum Node {
+    /// A file or directory that exists in the upper directory
+    Upper(std::fs::Metadata),
+    /// An entry from the lower manifest that has not been modified
+    Lower(Arc<Entry<u64>>),
+}
+
+impl Node {
+    fn is_dir(&self) -> bool {
+        match self {
+            Self::Upper(meta) => meta.is_dir(),
+            Self::Lower(entry) => entry.kind.is_tree(),
+        }
+    }
+}
+
+/// Tracks the upper directory of a writable filesystem,
+/// and the path of each inode that has been presented
+pub(super) struct UpperDir {
+    root: PathBuf,
+    /// the inode of each path in the lower manifest, which never changes
+    lower: HashMap<PathBuf, u64>,
+    /// the current path of each inode, which changes as files are renamed
+    inode_paths: DashMap<u64, PathBuf>,
+    path_inodes: DashMap<PathBuf, u64>,
+    /// held while copying data up from the lower manifest, so that
+    /// concurrent writers do not try to copy the same file at once
+    copy_up_lock: tokio::sync::Mutex<()>,
+    /// held while the mode of a path is changed, so that access which is
+    /// granted temporarily is not observed or restored by another operation
+    mode_lock: std::sync::Mutex<()>,
+}
+
+impl UpperDir {
+    /// Track changes in the given directory over top of the provided
+    /// root entry, which must already have its inodes allocated
+    pub fn new(root: PathBuf, lower_root: &Entry<u64>) -> Self {
+        let mut lower = HashMap::new();
+        let mut pending = vec![(PathBuf::new(), lower_root)];
+        while let Some((path, entry)) = pending.pop() {
+            for (name, child) in entry.entries.iter() {
+                if child.kind.is_mask() {
+                    continue;
+                }
+                pending.push((path.join(name), child));
+            }
+            lower.insert(path, entry.user_data);
+        }
+        let inode_paths = lower.iter().map(|(p, i)| (*i, p.clone())).collect();
+        let path_inodes = lower.iter().map(|(p, i)| (p.clone(), *i)).collect();
+        Self {
+            root,
+            lower,
+            inode_paths,
+            path_inodes,
+            copy_up_lock: Default::default(),
+            mode_lock: Default::default(),
+        }
+    }
+
+    fn full_path(&self, rel: &Path) -> PathBuf {
+        self.root.join(rel)
+    }
+
+    fn path_of(&self, ino: u64) -> OpResult<PathBuf> {
+        self.inode_paths
+            .get(&ino)
+            .map(|p| p.value().clone())
+            .ok_or(libc::ENOENT)
+    }
+
+    fn inode_for(&self, rel: &Path, allocate: impl FnOnce() -> u64) -> u64 {
+        if let Some(ino) = self.path_inodes.get(rel) {
+            return *ino;
+        }
+        match self.path_inodes.entry(rel.to_owned()) {
+            dashmap::mapref::entry::Entry::Occupied(e) => *e.get(),
+            dashmap::mapref::entry::Entry::Vacant(v) => {
+                let ino = allocate();
+                self.inode_paths.insert(ino, rel.to_owned());
+                v.insert(ino);
+                ino
+            }
+        }
+    }
+
+    /// Change the mode of the given path in the upper directory
+    fn set_mode(&self, rel: &Path, mode: u32) -> OpResult<()> {
+        let _guard = self.mode_lock.lock().expect("mode lock poisoned");
+        std::fs::set_permissions(self.full_path(rel), std::fs::Permissions::from_mode(mode))
+            .map_err(errno)
+    }
+
+    /// Run the given operation with the owner of the given path
+    /// granted the provided permission bits, restoring its mode after.
+    ///
+    /// The kernel checks the permissions of the caller against the
+    /// mode of each path, which may be read-only for the runtime user
+    /// that this filesystem is running as, even when the caller is root.
+    fn with_access<T>(
+        &self,
+        rel: &Path,
+        bits: u32,
+        op: impl FnOnce() -> std::io::Result<T>,
+    ) -> OpResult<T> {
+        let _guard = self.mode_lock.lock().expect("mode lock poisoned");
+        let path = self.full_path(rel);
+        let Some(mode) = grant_access(&path, bits).map_err(errno)? else {
+            return op().map_err(errno);
+        };
+        let result = op();
+        let restored = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode));
+        let value = result.map_err(errno)?;
+        restored.map_err(errno)?;
+        Ok(value)
+    }
+
+    /// Run the given operation with full access to the
+    /// parent directory of the given path
+    fn in_parent<T>(&self, rel: &Path, op: impl FnOnce() -> std::io::Result<T>) -> OpResult<T> {
+        let parent = rel.parent().unwrap_or(Path::new(""));
+        self.with_access(parent, 0o700, op)
+    }
+
+    /// Rename a path in the upper directory, with access to both
+    /// parent directories and to a directory that moves between them
+    fn rename(&self, from: &Path, to: &Path, is_dir: bool) -> OpResult<()> {
+        let _guard = self.mode_lock.lock().expect("mode lock poisoned");
+        let root = Path::new("");
+        let from_parent = from.parent().unwrap_or(root);
+        let to_parent = to.parent().unwrap_or(root);
+        // each path that needs access, and where it will be after the rename
+        let mut needed = vec![(from_parent, from_parent)];
+        if to_parent != from_parent {
+            needed.push((to_parent, to_parent));
+            if is_dir {
+                // the '..' entry of the moved directory is also updated
+                needed.push((from, to));
+            }
+        }
+        let mut granted = Vec::new();
+        let mut result = Ok(());
+        for (before, after) in needed {
+            match grant_access(&self.full_path(before), 0o700) {
+                Ok(Some(mode)) => granted.push((before, after, mode)),
+                Ok(None) => {}
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        if result.is_ok() {
+            result = std::fs::rename(self.full_path(from), self.full_path(to));
+        }
+        let renamed = result.is_ok();
+        for (before, after, mode) in granted {
+            let path = self.full_path(if renamed { after } else { before });
+            let restored = std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode));
+            if result.is_ok() {
+                result = restored;
+            }
+        }
+        result.map_err(errno)
+    }
+
+    fn stat(&self, rel: &Path) -> OpResult<UpperState> {
+        match std::fs::symlink_metadata(self.full_path(rel)) {
+            Ok(meta) if spfs::runtime::is_removed_entry(&meta) => Ok(UpperState::Removed),
+            Ok(meta) => Ok(UpperState::Present(meta)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(UpperState::Missing),
+            // one of the parents has been replaced with a file
+            Err(err) if err.raw_os_error() == Some(libc::ENOTDIR) => Ok(UpperState::Removed),
+            Err(err) => Err(errno(err)),
+        }
+    }
+
+    /// Replace the given path with a whiteout file.
+    ///
+    /// Unprivileged processes may only create the 0/0 device
+    /// on Linux 5.8 or newer, otherwise this fails with EPERM.
+    fn whiteout(&self, rel: &Path) -> OpResult<()> {
+        let path = self.full_path(rel);
+        self.in_parent(rel, || {
+            match std::fs::symlink_metadata(&path) {
+                Ok(meta) if meta.is_dir() => remove_upper_dir_all(&path)?,
+                Ok(_) => std::fs::remove_file(&path)?,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+            nix::sys::stat::mknod(
+                &path,
+                nix::sys::stat::SFlag::S_IFCHR,
+                nix::sys::stat::Mode::empty(),
+                0,
+            )
+            .map_err(std::io::Error::from)
+        })
+    }
+
+    /// Remove the whiteout file at the given path, if any,
+    /// returning true if one existed
+    fn remove_whiteout(&self, rel: &Path) -> OpResult<bool> {
+        match self.stat(rel)? {
+            UpperState::Removed => {
+                self.in_parent(rel, || std::fs::remove_file(self.full_path(rel)))?;
+                Ok(true)
+            }
+            UpperState::Missing | UpperState::Present(_) => Ok(false),
+        }
+    }
+
+    /// Stop tracking the inode of the given path, and
+    /// optionally everything underneath it
+    fn forget_path(&self, rel: &Path, recursive: bool) {
+        let paths: Vec<_> = match recursive {
+            true => self
+                .path_inodes
+                .iter()
+                .filter(|e| e.key().starts_with(rel))
+                .map(|e| e.key().clone())
+                .collect(),
+            false => vec![rel.to_owned()],
+        };
+        for path in paths {
+            if let Some((_, ino)) = self.path_inodes.remove(&path) {
+                self.inode_paths.remove(&ino);
+            }
+        }
+    }
+
+    /// Update the tracked inodes after a path has been renamed
+    fn moved(&self, from: &Path, to: &Path, recursive: bool) {
+        self.forget_path(to, recursive);
+        let moved: Vec<_> = match recursive {
+            true => self
+                .path_inodes
+                .iter()
+                .filter(|e| e.key().starts_with(from))
+                .map(|e| (e.key().clone(), *e.value()))
+                .collect(),
+            false => self
+                .path_inodes
+                .get(from)
+                .map(|ino| vec![(from.to_owned(), *ino)])
+                .unwrap_or_default(),
+        };
+        for (path, ino) in moved {
+            self.path_inodes.remove(&path);
+            let rest = path.strip_prefix(from).unwrap_or(&path);
+            let new_path = match rest.as_os_str().is_empty() {
+                true => to.to_owned(),
+                false => to.join(rest),
+            };
+            self.inode_paths.insert(ino, new_path.clone());
+            self.path_inodes.insert(new_path, ino);
+        }
+    }
+}
+
+// these functions mirror the actual fuse ones and
+// so we don't have much control over the shape
+#[allow(clippy::too_many_arguments)]
+impl Filesystem {
+    fn upper(&self) -> OpResult<&UpperDir> {
+        self.upper.as_ref().ok_or(libc::EROFS)
+    }
+
+    /// Find the current state of a path in the merged filesystem
+    fn resolve(&self, upper: &UpperDir, rel: &Path) -> OpResult<Option<Node>> {
+        match upper.stat(rel)? {
+            UpperState::Removed => Ok(None),
+            UpperState::Present(meta) => Ok(Some(Node::Upper(meta))),
+            UpperState::Missing => Ok(upper
+                .lower
+                .get(rel)
+                .and_then(|ino| self.inodes.get(ino))
+                .map(|e| Node::Lower(Arc::clone(e.value())))),
+        }
+    }
+
+    /// Find the current path and state of an inode in the merged filesystem
+    pub(super) fn upper_node(&self, upper: &UpperDir, ino: u64) -> OpResult<(PathBuf, Node)> {
+        let rel = upper.path_of(ino)?;
+        let node = self.resolve(upper, &rel)?.ok_or(libc::ENOENT)?;
+        Ok((rel, node))
+    }
+
+    fn child_path(&self, upper: &UpperDir, parent: u64, name: &OsStr) -> OpResult<PathBuf> {
+        Ok(upper.path_of(parent)?.join(name))
+    }
+
+    fn node_attr(&self, ino: u64, node: &Node) -> OpResult<FileAttr> {
+        match node {
+            Node::Upper(meta) => Ok(self.attr_from_metadata(ino, meta)),
+            Node::Lower(entry) => {
+                let mut attr = self.attr_from_entry(entry).map_err(|_| libc::ENOENT)?;
+                attr.ino = ino;
+                Ok(attr)
+            }
+        }
+    }
+
+    fn attr_from_metadata(&self, ino: u64, meta: &std::fs::Metadata) -> FileAttr {
+        let file_type = meta.file_type();
+        let kind = if file_type.is_dir() {
+            FileType::Directory
+        } else if file_type.is_symlink() {
+            FileType::Symlink
+        } else {
+            FileType::RegularFile
+        };
+        let ctime = SystemTime::UNIX_EPOCH
+            + Duration::new(meta.ctime().max(0) as u64, meta.ctime_nsec() as u32);
+        FileAttr {
+            ino,
+            size: meta.len(),
+            blocks: meta.blocks(),
+            atime: meta.accessed().unwrap_or(self.fs_creation_time),
+            mtime: meta.modified().unwrap_or(self.fs_creation_time),
+            ctime,
+            crtime: ctime,
+            kind,
+            perm: (meta.mode() & 0o7777) as u16,
+            nlink: meta.nlink() as u32,
+            uid: self.opts.uid.as_raw(),
+            gid: self.opts.gid.as_raw(),
+            rdev: 0,
+            blksize: Self::BLOCK_SIZE,
+            flags: 0,
+        }
+    }
+
+    fn upper_attr(&self, upper: &UpperDir, rel: &Path) -> OpResult<FileAttr> {
+        let meta = std::fs::symlink_metadata(upper.full_path(rel)).map_err(errno)?;
+        let ino = upper.inode_for(rel, || self.allocate_inode());
+        Ok(self.attr_from_metadata(ino, &meta))
+    }
+
+    /// Make sure that the given directory and all of its
+    /// parents exist in the upper directory
+    fn ensure_upper_dirs(&self, upper: &UpperDir, dir: &Path) -> OpResult<()> {
+        let mut current = PathBuf::new();
+        for name in dir.iter() {
+            current.push(name);
+            match upper.stat(&current)? {
+                UpperState::Present(meta) if meta.is_dir() => continue,
+                UpperState::Present(_) => return Err(libc::ENOTDIR),
+                UpperState::Removed => return Err(libc::ENOENT),
+                UpperState::Missing => {}
+            }
+            let lower = upper
+                .lower
+                .get(&current)
+                .and_then(|ino| self.inodes.get(ino));
+            let mode = lower.as_ref().map(|e| e.mode & 0o7777).unwrap_or(0o777);
+            let path = upper.full_path(&current);
+            upper.in_parent(&current, || std::fs::create_dir(&path))?;
+            if let Some(lower) = lower {
+                copy_up_xattrs(&path, &lower.xattrs);
+            }
+            upper.set_mode(&current, mode)?;
+        }
+        Ok(())
+    }
+
+    /// Make sure that all of the parent directories of the given
+    /// path exist in the upper directory
+    fn copy_up_parents(&self, upper: &UpperDir, rel: &Path) -> OpResult<()> {
+        match rel.parent() {
+            Some(parent) => self.ensure_upper_dirs(upper, parent),
+            None => Ok(()),
+        }
+    }
+
+    async fn open_lower_payload(
+        &self,
+        digest: spfs::encoding::Digest,
+    ) -> OpResult<Pin<Box<dyn BlobRead>>> {
+        for repo in self.repos.iter() {
+            match repo.open_payload(digest).await {
+                Ok((reader, _)) => return Ok(reader),
+                Err(spfs::Error::UnknownObject(_)) => continue,
+                Err(err) => {
+                    tracing::error!("{err:?}");
+                    return Err(err.os_error().unwrap_or(libc::EIO));
+                }
+            }
+        }
+        Err(libc::ENOENT)
+    }
+
+    /// Copy a file or symlink from the lower manifest into the
+    /// upper directory so that it can be modified
+    async fn copy_up(&self, upper: &UpperDir, rel: &Path) -> OpResult<()> {
+        let _guard = upper.copy_up_lock.lock().await;
+        let entry = match self.resolve(upper, rel)?.ok_or(libc::ENOENT)? {
+            Node::Upper(_) => return Ok(()),
+            Node::Lower(entry) if entry.kind.is_tree() => return Err(libc::EISDIR),
+            Node::Lower(entry) => entry,
+        };
+        self.copy_up_parents(upper, rel)?;
+
+        let path = upper.full_path(rel);
+        let mut reader = self.open_lower_payload(entry.object).await?;
+        if entry.is_symlink() {
+            let mut target = Vec::new();
+            reader.read_to_end(&mut target).await.map_err(errno)?;
+            return upper.in_parent(rel, || {
+                std::os::unix::fs::symlink(OsStr::from_bytes(&target), &path)
+            });
+        }
+        let file = upper.in_parent(rel, || {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path)
+        })?;
+        let result = async {
+            let mut file = tokio::fs::File::from_std(file);
+            tokio::io::copy_buf(&mut reader, &mut file).await?;
+            file.flush().await?;
+            copy_up_xattrs(&path, &entry.xattrs);
+            Ok::<_, std::io::Error>(())
+        }
+        .await
+        .map_err(errno)
+        .and_then(|_| upper.set_mode(rel, entry.mode & 0o7777));
+        if let Err(err) = result {
+            let _ = upper.in_parent(rel, || std::fs::remove_file(&path));
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// List the merged contents of a directory
+    fn merged_entries(&self, upper: &UpperDir, rel: &Path) -> OpResult<Vec<(OsString, Node)>> {
+        let mut entries = BTreeMap::new();
+        let mut removed = HashSet::new();
+        if let UpperState::Present(meta) = upper.stat(rel)? {
+            if !meta.is_dir() {
+                return Err(libc::ENOTDIR);
+            }
+            let dir_entries = upper.with_access(rel, 0o500, || {
+                std::fs::read_dir(upper.full_path(rel))?
+                    .map(|dir_entry| {
+                        let dir_entry = dir_entry?;
+                        Ok((dir_entry.file_name(), dir_entry.metadata()?))
+                    })
+                    .collect::<std::io::Result<Vec<_>>>()
+            })?;
+            for (name, meta) in dir_entries {
+                if spfs::runtime::is_removed_entry(&meta) {
+                    removed.insert(name);
+                } else {
+                    entries.insert(name, Node::Upper(meta));
+                }
+            }
+        }
+        let lower = upper.lower.get(rel).and_then(|ino| self.inodes.get(ino));
+        if let Some(lower) = lower {
+            for (name, child) in lower.entries.iter() {
+                let name = OsString::from(name);
+                if child.kind.is_mask() || removed.contains(&name) || entries.contains_key(&name) {
+                    continue;
+                }
+                if let Some(child) = self.inodes.get(&child.user_data) {
+                    entries.insert(name, Node::Lower(Arc::clone(child.value())));
+                }
+            }
+        }
+        Ok(entries.into_iter().collect())
+    }
+
+    pub(super) async fn lookup_upper(&self, parent: u64, name: &OsStr) -> OpResult<FileAttr> {
+        let upper = self.upper()?;
+        let rel = self.child_path(upper, parent, name)?;
+        let node = self.resolve(upper, &rel)?.ok_or(libc::ENOENT)?;
+        let ino = upper.inode_for(&rel, || self.allocate_inode());
+        self.node_attr(ino, &node)
+    }
+
+    pub(super) async fn getattr_upper(&self, ino: u64, fh: Option<u64>) -> OpResult<FileAttr> {
+        let upper = self.upper()?;
+        if let Some(handle) = fh.and_then(|fh| self.handles.get(&fh)) {
+            if let Handle::UpperFile { file } = handle.value() {
+                // the file may have been removed while still open
+                let meta = file.metadata().map_err(errno)?;
+                return Ok(self.attr_from_metadata(ino, &meta));
+            }
+        }
+        let (_, node) = self.upper_node(upper, ino)?;
+        self.node_attr(ino, &node)
+    }
+
+    /// Open a file from the upper directory, copying it up from the
+    /// lower manifest if it will be modified.
+    ///
+    /// Returns `None` for unmodified files that are being opened
+    /// read-only, which should be served from the lower manifest.
+    pub(super) async fn open_upper(&self, ino: u64, flags: i32) -> OpResult<Option<Handle>> {
+        let upper = self.upper()?;
+        let (rel, node) = self.upper_node(upper, ino)?;
+        let access = flags & libc::O_ACCMODE;
+        match node {
+            _ if node.is_dir() => return Err(libc::EISDIR),
+            Node::Lower(_) if access == libc::O_RDONLY => return Ok(None),
+            Node::Lower(_) => self.copy_up(upper, &rel).await?,
+            Node::Upper(_) => {}
+        }
+        let bits = match access {
+            libc::O_RDONLY => 0o400,
+            libc::O_WRONLY => 0o200,
+            _ => 0o600,
+        };
+        let file = upper.with_access(&rel, bits, || {
+            std::fs::OpenOptions::new()
+                .read(access != libc::O_WRONLY)
+                .write(access != libc::O_RDONLY)
+                .truncate(access != libc::O_RDONLY && flags & libc::O_TRUNC != 0)
+                .open(upper.full_path(&rel))
+        })?;
+        Ok(Some(Handle::UpperFile { file }))
+    }
+
+    pub(super) async fn create_upper(
+        &self,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        flags: i32,
+    ) -> OpResult<(FileAttr, Handle)> {
+        let upper = self.upper()?;
+        let rel = self.child_path(upper, parent, name)?;
+        if let Some(node) = self.resolve(upper, &rel)? {
+            if flags & libc::O_EXCL != 0 {
+                return Err(libc::EEXIST);
+            }
+            if let Node::Lower(_) = node {
+                self.copy_up(upper, &rel).await?;
+            }
+            let ino = upper.inode_for(&rel, || self.allocate_inode());
+            let handle = self.open_upper(ino, flags).await?.ok_or(libc::EIO)?;
+            return Ok((self.upper_attr(upper, &rel)?, handle));
+        }
+        self.copy_up_parents(upper, &rel)?;
+        upper.remove_whiteout(&rel)?;
+        let path = upper.full_path(&rel);
+        let file = upper.in_parent(&rel, || {
+            std::fs::OpenOptions::new()
+                .read(flags & libc::O_ACCMODE != libc::O_WRONLY)
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path)
+        })?;
+        upper.set_mode(&rel, mode & !umask & 0o7777)?;
+        let attr = self.upper_attr(upper, &rel)?;
+        Ok((attr, Handle::UpperFile { file }))
+    }
+
+    pub(super) async fn write_upper(&self, fh: u64, offset: i64, data: &[u8]) -> OpResult<u32> {
+        use std::os::unix::fs::FileExt;
+
+        let handle = self.handles.get(&fh).ok_or(libc::EBADF)?;
+        let Handle::UpperFile { file } = handle.value() else {
+            return Err(libc::EBADF);
+        };
+        file.write_all_at(data, offset as u64).map_err(errno)?;
+        Ok(data.len() as u32)
+    }
+
+    pub(super) async fn fsync_upper(&self, fh: u64, datasync: bool) -> OpResult<()> {
+        let handle = self.handles.get(&fh).ok_or(libc::EBADF)?;
+        match handle.value() {
+            Handle::UpperFile { file } if datasync => file.sync_data().map_err(errno),
+            Handle::UpperFile { file } => file.sync_all().map_err(errno),
+            _ => Ok(()),
+        }
+    }
+
+    pub(super) async fn setattr_upper(
+        &self,
+        ino: u64,
+        fh: Option<u64>,
+        mode: Option<u32>,
+        size: Option<u64>,
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
+    ) -> OpResult<FileAttr> {
+        let upper = self.upper()?;
+        let (rel, node) = self.upper_node(upper, ino)?;
+        let changed = mode.is_some() || size.is_some() || atime.is_some() || mtime.is_some();
+        if let Node::Lower(entry) = &node {
+            if !changed {
+                return self.node_attr(ino, &node);
+            }
+            if entry.kind.is_tree() {
+                self.ensure_upper_dirs(upper, &rel)?;
+            } else {
+                self.copy_up(upper, &rel).await?;
+            }
+        }
+        let path = upper.full_path(&rel);
+
+        if let Some(size) = size {
+            let handle = fh.and_then(|fh| self.handles.get(&fh));
+            match handle.as_ref().map(|h| h.value()) {
+                Some(Handle::UpperFile { file }) => file.set_len(size).map_err(errno)?,
+                _ => upper.with_access(&rel, 0o200, || {
+                    std::fs::OpenOptions::new()
+                        .write(true)
+                        .open(&path)
+                        .and_then(|file| file.set_len(size))
+                })?,
+            }
+        }
+        if let Some(mode) = mode {
+            upper.set_mode(&rel, mode & 0o7777)?;
+        }
+        if atime.is_some() || mtime.is_some() {
+            use nix::sys::time::TimeSpec;
+
+            let to_spec = |time: Option<TimeOrNow>| match time {
+                None => TimeSpec::UTIME_OMIT,
+                Some(TimeOrNow::Now) => TimeSpec::UTIME_NOW,
+                Some(TimeOrNow::SpecificTime(time)) => TimeSpec::from_duration(
+                    time.duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap_or_default(),
+                ),
+            };
+            nix::sys::stat::utimensat(
+                None,
+                &path,
+                &to_spec(atime),
+                &to_spec(mtime),
+                nix::sys::stat::UtimensatFlags::NoFollowSymlink,
+            )
+            .map_err(|err| err as libc::c_int)?;
+        }
+        let meta = std::fs::symlink_metadata(&path).map_err(errno)?;
+        Ok(self.attr_from_metadata(ino, &meta))
+    }
+
+    pub(super) async fn mkdir_upper(
+        &self,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+    ) -> OpResult<FileAttr> {
+        let upper = self.upper()?;
+        let rel = self.child_path(upper, parent, name)?;
+        if self.resolve(upper, &rel)?.is_some() {
+            return Err(libc::EEXIST);
+        }
+        self.copy_up_parents(upper, &rel)?;
+        let replaced = upper.remove_whiteout(&rel)?;
+        let path = upper.full_path(&rel);
+        upper.in_parent(&rel, || std::fs::create_dir(&path))?;
+        if replaced {
+            // a directory that replaces one from the lower manifest must
+            // not show the old contents, which are each masked explicitly
+            // because committed changes do not support opaque directories
+            let lower = upper.lower.get(&rel).and_then(|ino| self.inodes.get(ino));
+            if let Some(lower) = lower {
+                for (child, _) in lower.entries.iter().filter(|(_, e)| !e.kind.is_mask()) {
+                    upper.whiteout(&rel.join(child))?;
+                }
+            }
+        }
+        upper.set_mode(&rel, mode & !umask & 0o7777)?;
+        self.upper_attr(upper, &rel)
+    }
+
+    pub(super) async fn symlink_upper(
+        &self,
+        parent: u64,
+        name: &OsStr,
+        target: &Path,
+    ) -> OpResult<FileAttr> {
+        let upper = self.upper()?;
+        let rel = self.child_path(upper, parent, name)?;
+        if self.resolve(upper, &rel)?.is_some() {
+            return Err(libc::EEXIST);
+        }
+        self.copy_up_parents(upper, &rel)?;
+        upper.remove_whiteout(&rel)?;
+        upper.in_parent(&rel, || {
+            std::os::unix::fs::symlink(target, upper.full_path(&rel))
+        })?;
+        self.upper_attr(upper, &rel)
+    }
+
+    pub(super) async fn unlink_upper(&self, parent: u64, name: &OsStr) -> OpResult<()> {
+        let upper = self.upper()?;
+        let rel = self.child_path(upper, parent, name)?;
+        let node = self.resolve(upper, &rel)?.ok_or(libc::ENOENT)?;
+        if node.is_dir() {
+            return Err(libc::EISDIR);
+        }
+        if upper.lower.contains_key(&rel) {
+            self.copy_up_parents(upper, &rel)?;
+            upper.whiteout(&rel)?;
+        } else {
+            upper.in_parent(&rel, || std::fs::remove_file(upper.full_path(&rel)))?;
+        }
+        upper.forget_path(&rel, false);
+        Ok(())
+    }
+
+    pub(super) async fn rmdir_upper(&self, parent: u64, name: &OsStr) -> OpResult<()> {
+        let upper = self.upper()?;
+        let rel = self.child_path(upper, parent, name)?;
+        let node = self.resolve(upper, &rel)?.ok_or(libc::ENOENT)?;
+        if !node.is_dir() {
+            return Err(libc::ENOTDIR);
+        }
+        if !self.merged_entries(upper, &rel)?.is_empty() {
+            return Err(libc::ENOTEMPTY);
+        }
+        if upper.lower.contains_key(&rel) {
+            self.copy_up_parents(upper, &rel)?;
+            // also removes the upper directory, which can
+            // only contain whiteout files at this point
+            upper.whiteout(&rel)?;
+        } else {
+            upper.in_parent(&rel, || remove_upper_dir_all(&upper.full_path(&rel)))?;
+        }
+        upper.forget_path(&rel, true);
+        Ok(())
+    }
+
+    pub(super) async fn rename_upper(
+        &self,
+        parent: u64,
+        name: &OsStr,
+        new_parent: u64,
+        new_name: &OsStr,
+        flags: u32,
+    ) -> OpResult<()> {
+        let upper = self.upper()?;
+        if flags & libc::RENAME_EXCHANGE != 0 {
+            return Err(libc::EINVAL);
+        }
+        let from = self.child_path(upper, parent, name)?;
+        let to = self.child_path(upper, new_parent, new_name)?;
+        let node = self.resolve(upper, &from)?.ok_or(libc::ENOENT)?;
+        let is_dir = node.is_dir();
+        if is_dir && upper.lower.contains_key(&from) {
+            // like overlayfs without redirects, directories from the
+            // lower manifest cannot be moved, and callers are expected
+            // to fall back to copying them instead
+            return Err(libc::EXDEV);
+        }
+        if let Some(existing) = self.resolve(upper, &to)? {
+            if flags & libc::RENAME_NOREPLACE != 0 {
+                return Err(libc::EEXIST);
+            }
+            match (is_dir, existing.is_dir()) {
+                (false, true) => return Err(libc::EISDIR),
+                (true, false) => return Err(libc::ENOTDIR),
+                (true, true) if upper.lower.contains_key(&to) => return Err(libc::EXDEV),
+                (true, true) if !self.merged_entries(upper, &to)?.is_empty() => {
+                    return Err(libc::ENOTEMPTY);
+                }
+                _ => {}
+            }
+        }
+        if let Node::Lower(_) = node {
+            self.copy_up(upper, &from).await?;
+        }
+        self.copy_up_parents(upper, &to)?;
+        upper.remove_whiteout(&to)?;
+        upper.rename(&from, &to, is_dir)?;
+        if upper.lower.contains_key(&from) {
+            upper.whiteout(&from)?;
+        }
+        upper.moved(&from, &to, is_dir);
+        Ok(())
+    }
+
+    pub(super) async fn xattrs_upper(&self, ino: u64) -> OpResult<spfs::tracking::Xattrs> {
+        let upper = self.upper()?;
+        match self.upper_node(upper, ino)? {
+            (rel, Node::Upper(_)) => {
+                spfs::xattr::read_xattrs(&upper.full_path(&rel)).map_err(errno)
+            }
+            (_, Node::Lower(entry)) => Ok(entry.xattrs.clone()),
+        }
+    }
+
+    pub(super) async fn readlink_upper(&self, ino: u64) -> OpResult<Option<Vec<u8>>> {
+        let upper = self.upper()?;
+        match self.upper_node(upper, ino)? {
+            (_, Node::Lower(_)) => Ok(None),
+            (_, Node::Upper(meta)) if !meta.is_symlink() => Err(libc::EINVAL),
+            (rel, Node::Upper(_)) => std::fs::read_link(upper.full_path(&rel))
+                .map(|target| Some(target.into_os_string().into_vec()))
+                .map_err(errno),
+        }
+    }
+
+    pub(super) async fn opendir_upper(&self, ino: u64) -> OpResult<Handle> {
+        let upper = self.upper()?;
+        let (rel, node) = self.upper_node(upper, ino)?;
+        if !node.is_dir() {
+            return Err(libc::ENOTDIR);
+        }
+        let mut entries = Vec::new();
+        for (name, node) in self.merged_entries(upper, &rel)? {
+            let ino = upper.inode_for(&rel.join(&name), || self.allocate_inode());
+            entries.push((name, self.node_attr(ino, &node)?));
+        }
+        Ok(Handle::UpperTree {
+            entries: Arc::new(entries),
+        })
+    }
+}
@@ -0,0 +1,273 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::ffi::OsStr;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
+use std::sync::Arc;
+
+use rstest::rstest;
+
+use super::super::{Config, Filesystem};
+use crate::fixtures::*;
+
+/// The inode of the filesystem root
+const ROOT: u64 = 1;
+
+/// Create a writable filesystem over a lower manifest with the
+/// files `top.txt`, `dir/file.txt` and `dir/other.txt`
+async fn writable_fs(tmpdir: &tempfile::TempDir) -> Filesystem {
+    writable_fs_with_modes(tmpdir, &[]).await
+}
+
+/// Create a writable filesystem like [`writable_fs`], with
+/// the given modes set on the lower files and directories
+async fn writable_fs_with_modes(tmpdir: &tempfile::TempDir, modes: &[(&str, u32)]) -> Filesystem {
+    let source = tmpdir.path().join("source");
+    for (path, data) in [
+        ("top.txt", "top"),
+        ("dir/file.txt", "lower"),
+        ("dir/other.txt", "other"),
+    ] {
+        let path = source.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, data).unwrap();
+    }
+    for (path, mode) in modes {
+        std::fs::set_permissions(source.join(path), std::fs::Permissions::from_mode(*mode))
+            .unwrap();
+    }
+    let repo: spfs::storage::RepositoryHandle =
+        spfs::storage::fs::MaybeOpenFsRepository::create(tmpdir.path().join("repo"))
+            .await
+            .unwrap()
+            .into();
+    let manifest = spfs::Committer::new(&repo)
+        .commit_dir(&source)
+        .await
+        .unwrap();
+    super::make_dirs_writable(&source).unwrap();
+    let upper_dir = tmpdir.path().join("upper");
+    std::fs::create_dir(&upper_dir).unwrap();
+    let opts = Config {
+        root_mode: 0o777,
+        uid: nix::unistd::getuid(),
+        gid: nix::unistd::getgid(),
+        mount_options: Default::default(),
+        remotes: Vec::new(),
+        upper_dir: Some(upper_dir),
+    };
+    Filesystem::new(vec![Arc::new(repo)], manifest, opts, None, None)
+}
+
+fn lower_inode(fs: &Filesystem, path: &str) -> u64 {
+    fs.upper.as_ref().unwrap().lower[Path::new(path)]
+}
+
+fn upper_path(fs: &Filesystem, path: &str) -> std::path::PathBuf {
+    fs.upper.as_ref().unwrap().full_path(Path::new(path))
+}
+
+fn is_whiteout(fs: &Filesystem, path: &str) -> bool {
+    std::fs::symlink_metadata(upper_path(fs, path))
+        .is_ok_and(|meta| spfs::runtime::is_removed_entry(&meta))
+}
+
+async fn merged_names(fs: &Filesystem, dir: u64) -> Vec<String> {
+    let super::Handle::UpperTree { entries } = fs.opendir_upper(dir).await.unwrap() else {
+        panic!("expected a merged directory listing");
+    };
+    entries
+        .iter()
+        .map(|(name, _)| name.to_string_lossy().into_owned())
+        .collect()
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_upper_create_and_write(tmpdir: tempfile::TempDir) {
+    let fs = writable_fs(&tmpdir).await;
+    let (_, handle) = fs
+        .create_upper(ROOT, OsStr::new("new.txt"), 0o644, 0o022, libc::O_RDWR)
+        .await
+        .expect("create should succeed");
+    let fh = fs.allocate_handle(handle);
+    let written = fs.write_upper(fh, 0, b"hello").await.unwrap();
+    assert_eq!(written, 5);
+
+    assert_eq!(
+        std::fs::read_to_string(upper_path(&fs, "new.txt")).unwrap(),
+        "hello"
+    );
+    let attr = fs.lookup_upper(ROOT, OsStr::new("new.txt")).await.unwrap();
+    assert_eq!(attr.size, 5);
+    assert_eq!(attr.perm, 0o644);
+    assert_eq!(
+        fs.create_upper(ROOT, OsStr::new("new.txt"), 0o644, 0o022, libc::O_EXCL)
+            .await
+            .err(),
+        Some(libc::EEXIST)
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_upper_write_copies_up_lower_file(tmpdir: tempfile::TempDir) {
+    let fs = writable_fs(&tmpdir).await;
+    let ino = lower_inode(&fs, "dir/file.txt");
+    assert!(
+        fs.open_upper(ino, libc::O_RDONLY).await.unwrap().is_none(),
+        "reading an unmodified file should be served from the lower manifest"
+    );
+
+    let handle = fs.open_upper(ino, libc::O_WRONLY).await.unwrap().unwrap();
+    let fh = fs.allocate_handle(handle);
+    fs.write_upper(fh, 0, b"upper").await.unwrap();
+    assert_eq!(
+        std::fs::read_to_string(upper_path(&fs, "dir/file.txt")).unwrap(),
+        "upper"
+    );
+    assert!(
+        !upper_path(&fs, "dir/other.txt").exists(),
+        "only the modified file should be copied up"
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_upper_unlink(tmpdir: tempfile::TempDir) {
+    let fs = writable_fs(&tmpdir).await;
+    let dir = lower_inode(&fs, "dir");
+    fs.unlink_upper(dir, OsStr::new("file.txt")).await.unwrap();
+    assert!(
+        is_whiteout(&fs, "dir/file.txt"),
+        "removing a lower file should leave a whiteout"
+    );
+    assert_eq!(
+        fs.lookup_upper(dir, OsStr::new("file.txt")).await.err(),
+        Some(libc::ENOENT)
+    );
+    assert_eq!(merged_names(&fs, dir).await, vec!["other.txt"]);
+
+    let (_, handle) = fs
+        .create_upper(ROOT, OsStr::new("new.txt"), 0o644, 0, libc::O_RDWR)
+        .await
+        .unwrap();
+    drop(handle);
+    fs.unlink_upper(ROOT, OsStr::new("new.txt")).await.unwrap();
+    assert!(
+        std::fs::symlink_metadata(upper_path(&fs, "new.txt")).is_err(),
+        "removing a new file should not leave a whiteout"
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_upper_rename(tmpdir: tempfile::TempDir) {
+    let fs = writable_fs(&tmpdir).await;
+    let dir = lower_inode(&fs, "dir");
+    fs.rename_upper(
+        dir,
+        OsStr::new("file.txt"),
+        ROOT,
+        OsStr::new("moved.txt"),
+        0,
+    )
+    .await
+    .expect("rename should succeed");
+    assert_eq!(
+        std::fs::read_to_string(upper_path(&fs, "moved.txt")).unwrap(),
+        "lower"
+    );
+    assert!(is_whiteout(&fs, "dir/file.txt"));
+    assert_eq!(
+        fs.rename_upper(ROOT, OsStr::new("dir"), ROOT, OsStr::new("renamed"), 0)
+            .await
+            .err(),
+        Some(libc::EXDEV),
+        "lower directories cannot be renamed"
+    );
+    assert_eq!(
+        fs.rename_upper(
+            ROOT,
+            OsStr::new("moved.txt"),
+            ROOT,
+            OsStr::new("top.txt"),
+            libc::RENAME_NOREPLACE
+        )
+        .await
+        .err(),
+        Some(libc::EEXIST)
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_upper_mkdir_replaces_lower_dir(tmpdir: tempfile::TempDir) {
+    let fs = writable_fs(&tmpdir).await;
+    let dir = lower_inode(&fs, "dir");
+    assert_eq!(
+        fs.rmdir_upper(ROOT, OsStr::new("dir")).await.err(),
+        Some(libc::ENOTEMPTY)
+    );
+    fs.unlink_upper(dir, OsStr::new("file.txt")).await.unwrap();
+    fs.unlink_upper(dir, OsStr::new("other.txt")).await.unwrap();
+    fs.rmdir_upper(ROOT, OsStr::new("dir")).await.unwrap();
+    assert!(is_whiteout(&fs, "dir"));
+
+    fs.mkdir_upper(ROOT, OsStr::new("dir"), 0o755, 0)
+        .await
+        .expect("mkdir should replace the removed directory");
+    assert!(upper_path(&fs, "dir").is_dir());
+    assert!(
+        is_whiteout(&fs, "dir/file.txt") && is_whiteout(&fs, "dir/other.txt"),
+        "the lower contents should each be masked in the new directory"
+    );
+    let attr = fs.lookup_upper(ROOT, OsStr::new("dir")).await.unwrap();
+    assert!(merged_names(&fs, attr.ino).await.is_empty());
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_upper_read_only_lower_entries_keep_their_mode(tmpdir: tempfile::TempDir) {
+    let fs = writable_fs_with_modes(&tmpdir, &[("dir/file.txt", 0o444), ("dir", 0o555)]).await;
+    let dir = lower_inode(&fs, "dir");
+    let ino = lower_inode(&fs, "dir/file.txt");
+
+    // the kernel has already checked the permissions of the caller,
+    // which may be root even though the files are not writable
+    let handle = fs
+        .open_upper(ino, libc::O_WRONLY)
+        .await
+        .expect("a read-only lower file should be copied up and opened")
+        .unwrap();
+    let fh = fs.allocate_handle(handle);
+    fs.write_upper(fh, 0, b"upper").await.unwrap();
+    fs.create_upper(dir, OsStr::new("new.txt"), 0o644, 0, libc::O_RDWR)
+        .await
+        .expect("a file should be created in a read-only lower directory");
+    fs.unlink_upper(dir, OsStr::new("other.txt"))
+        .await
+        .expect("a file should be removed from a read-only lower directory");
+
+    let mode = |path| {
+        std::fs::symlink_metadata(upper_path(&fs, path))
+            .unwrap()
+            .mode()
+            & 0o7777
+    };
+    assert_eq!(
+        mode("dir"),
+        0o555,
+        "copied up directories should keep their mode"
+    );
+    assert_eq!(
+        mode("dir/file.txt"),
+        0o444,
+        "copied up files should keep their mode"
+    );
+    assert_eq!(fs.getattr_upper(ino, None).await.unwrap().perm, 0o444);
+
+    super::make_dirs_writable(&upper_path(&fs, "dir")).unwrap();
+}
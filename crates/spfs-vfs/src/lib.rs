@@ -12,8 +12,15 @@
 mod error;
 pub use error::Error;
 
+#[cfg(all(test, unix, feature = "fuse-backend"))]
+mod fixtures;
+
 #[cfg(all(unix, feature = "fuse-backend"))]
 mod fuse;
+#[cfg(all(unix, feature = "fuse-backend"))]
+mod payload_cache;
+#[cfg(all(unix, feature = "fuse-backend"))]
+mod prefetch;
 #[cfg(all(windows, feature = "winfsp-backend"))]
 pub mod proto;
 #[cfg(all(windows, feature = "winfsp-backend"))]
@@ -0,0 +1,266 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use dashmap::DashMap;
+use spfs::encoding::{Digest, Hasher};
+use spfs::tracking::BlobRead;
+use tokio::io::AsyncWriteExt;
+
+/// Files in the cache directory with this prefix are
+/// incomplete downloads and can be removed at any time
+const TMP_PREFIX: &str = ".tmp-";
+
+#[cfg(test)]
+#[path = "./payload_cache_test.rs"]
+mod payload_cache_test;
+
+/// A bounded, least-recently-used cache of payloads on local disk.
+///
+/// Payloads read from remote repositories are stored here so
+/// that they can be reopened and seeked without going back
+/// over the network. Compressed local payloads are decompressed
+/// into the cache for the same reason.
+///
+/// The state lock is only held to update the bookkeeping of the
+/// cache, and never across file operations or downloads.
+pub(crate) struct PayloadCache {
+    root: PathBuf,
+    max_size: u64,
+    next_tmp: AtomicU64,
+    state: Mutex<CacheState>,
+    /// payloads that are being downloaded, so that opening the same
+    /// payload again waits for that download rather than repeating it
+    filling: DashMap<Digest, Arc<tokio::sync::Mutex<()>>>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    /// the size and last use of each cached payload
+    entries: HashMap<Digest, (u64, u64)>,
+    /// cached payloads ordered from least to most recently used
+    by_use: BTreeMap<u64, Digest>,
+    total_size: u64,
+    clock: u64,
+}
+
+impl CacheState {
+    fn touch(&mut self, digest: Digest) {
+        self.clock += 1;
+        let clock = self.clock;
+        if let Some((_, last_use)) = self.entries.get_mut(&digest) {
+            self.by_use.remove(last_use);
+            *last_use = clock;
+            self.by_use.insert(clock, digest);
+        }
+    }
+
+    fn insert(&mut self, digest: Digest, size: u64) {
+        if self.entries.contains_key(&digest) {
+            self.touch(digest);
+            return;
+        }
+        self.clock += 1;
+        self.entries.insert(digest, (size, self.clock));
+        self.by_use.insert(self.clock, digest);
+        self.total_size += size;
+    }
+
+    fn remove(&mut self, digest: &Digest) {
+        if let Some((size, last_use)) = self.entries.remove(digest) {
+            self.by_use.remove(&last_use);
+            self.total_size -= size;
+        }
+    }
+
+    /// Forget the least recently used payloads until the total size
+    /// is within the given limit, returning the ones to be removed
+    fn evict(&mut self, max_size: u64) -> Vec<Digest> {
+        let mut evicted = Vec::new();
+        while self.total_size > max_size {
+            let Some((_, digest)) = self.by_use.pop_first() else {
+                break;
+            };
+            if let Some((size, _)) = self.entries.remove(&digest) {
+                self.total_size -= size;
+            }
+            evicted.push(digest);
+        }
+        evicted
+    }
+}
+
+impl PayloadCache {
+    /// Open the cache in the given directory, keeping no
+    /// more than `max_size` bytes of payload data.
+    ///
+    /// Payloads left over from previous sessions are kept
+    /// and count towards the size limit.
+    pub fn open(root: impl Into<PathBuf>, max_size: u64) -> spfs::Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(&root).map_err(|err| {
+            spfs::Error::StorageWriteError("create payload cache directory", root.clone(), err)
+        })?;
+        let read_dir = std::fs::read_dir(&root).map_err(|err| {
+            spfs::Error::StorageReadError("read payload cache directory", root.clone(), err)
+        })?;
+
+        let mut existing = Vec::new();
+        for entry in read_dir.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with(TMP_PREFIX) {
+                let _ = std::fs::remove_file(entry.path());
+                continue;
+            }
+            let (Ok(digest), Ok(meta)) = (Digest::parse(&name), entry.metadata()) else {
+                continue;
+            };
+            let modified = meta.modified().unwrap_or(std::time::UNIX_EPOCH);
+            existing.push((modified, digest, meta.len()));
+        }
+        existing.sort();
+
+        let mut state = CacheState::default();
+        for (_, digest, size) in existing {
+            state.insert(digest, size);
+        }
+        let evicted = state.evict(max_size);
+        let cache = Self {
+            root,
+            max_size,
+            next_tmp: AtomicU64::new(0),
+            state: Mutex::new(state),
+            filling: Default::default(),
+        };
+        cache.remove_files(evicted);
+        Ok(cache)
+    }
+
+    fn path(&self, digest: &Digest) -> PathBuf {
+        self.root.join(digest.to_string())
+    }
+
+    /// Open a cached payload, if it exists
+    pub fn get(&self, digest: &Digest) -> Option<std::fs::File> {
+        if !self.lock_state().entries.contains_key(digest) {
+            return None;
+        }
+        match std::fs::File::open(self.path(digest)) {
+            Ok(file) => {
+                self.lock_state().touch(*digest);
+                Some(file)
+            }
+            Err(err) => {
+                tracing::debug!(?err, "cached payload {digest} could not be opened");
+                self.lock_state().remove(digest);
+                None
+            }
+        }
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        self.state.lock().expect("cache lock poisoned")
+    }
+
+    /// True if a payload of the given size can be stored in this cache
+    pub fn can_hold(&self, size: u64) -> bool {
+        size <= self.max_size
+    }
+
+    /// Download a payload into the cache and open it.
+    ///
+    /// If the same payload is already being downloaded, this waits
+    /// for that download to finish and the reader is not used.
+    pub async fn fill(
+        &self,
+        digest: Digest,
+        mut reader: Pin<Box<dyn BlobRead>>,
+    ) -> spfs::Result<std::fs::File> {
+        let filling = Arc::clone(self.filling.entry(digest).or_default().value());
+        let _guard = filling.lock().await;
+        let result = match self.get(&digest) {
+            Some(file) => Ok(file),
+            None => self.download_and_open(digest, &mut reader).await,
+        };
+        self.filling
+            .remove_if(&digest, |_, filling| Arc::strong_count(filling) <= 2);
+        result
+    }
+
+    async fn download_and_open(
+        &self,
+        digest: Digest,
+        reader: &mut Pin<Box<dyn BlobRead>>,
+    ) -> spfs::Result<std::fs::File> {
+        let tmp = self.root.join(format!(
+            "{TMP_PREFIX}{}-{}",
+            std::process::id(),
+            self.next_tmp.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = self.download(&tmp, digest, reader).await;
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&tmp).await;
+        }
+        let size = result?;
+
+        let path = self.path(&digest);
+        tokio::fs::rename(&tmp, &path).await.map_err(|err| {
+            let _ = std::fs::remove_file(&tmp);
+            spfs::Error::StorageWriteError("rename of cached payload", path.clone(), err)
+        })?;
+        let file = std::fs::File::open(&path)
+            .map_err(|err| spfs::Error::StorageReadError("open of cached payload", path, err))?;
+        let evicted = {
+            let mut state = self.lock_state();
+            state.insert(digest, size);
+            state.evict(self.max_size)
+        };
+        self.remove_files(evicted);
+        Ok(file)
+    }
+
+    /// Copy a payload into the given file, returning its size
+    /// once it has been validated against the expected digest
+    async fn download(
+        &self,
+        path: &Path,
+        digest: Digest,
+        reader: &mut Pin<Box<dyn BlobRead>>,
+    ) -> spfs::Result<u64> {
+        let file = tokio::fs::File::create(path).await.map_err(|err| {
+            spfs::Error::StorageWriteError("create of cached payload", path.into(), err)
+        })?;
+        let mut hasher = Hasher::with_target(file);
+        let write_err =
+            |err| spfs::Error::StorageWriteError("write of cached payload", path.into(), err);
+        let size = tokio::io::copy_buf(reader, &mut hasher)
+            .await
+            .map_err(write_err)?;
+        hasher.flush().await.map_err(write_err)?;
+        let actual = hasher.digest();
+        if actual != digest {
+            return Err(spfs::Error::String(format!(
+                "Payload read from remote did not match expected digest: {actual} != {digest}"
+            )));
+        }
+        Ok(size)
+    }
+
+    /// Remove the files of evicted payloads, which are no longer in the
+    /// cache state, but stay readable through any handles still open
+    fn remove_files(&self, evicted: Vec<Digest>) {
+        for digest in evicted {
+            let path = self.path(&digest);
+            if let Err(err) = std::fs::remove_file(&path) {
+                tracing::debug!(?err, "failed to evict cached payload {}", path.display());
+            }
+        }
+    }
+}
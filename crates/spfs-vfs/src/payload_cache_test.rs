@@ -0,0 +1,108 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::io::Read;
+
+use rstest::rstest;
+use spfs::encoding::{Digest, Hasher};
+
+use super::{PayloadCache, TMP_PREFIX};
+use crate::fixtures::*;
+
+fn digest_of(data: &str) -> Digest {
+    Hasher::hash_reader(data.as_bytes()).unwrap()
+}
+
+async fn fill(cache: &PayloadCache, data: &str) -> Digest {
+    let digest = digest_of(data);
+    let reader = Box::pin(std::io::Cursor::new(data.to_owned().into_bytes()));
+    cache
+        .fill(digest, reader)
+        .await
+        .expect("fill should succeed");
+    digest
+}
+
+fn read(mut file: std::fs::File) -> String {
+    let mut data = String::new();
+    file.read_to_string(&mut data).unwrap();
+    data
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_payload_cache_hit(tmpdir: tempfile::TempDir) {
+    let cache = PayloadCache::open(tmpdir.path(), 1024).unwrap();
+    let digest = digest_of("hello");
+    assert!(cache.get(&digest).is_none(), "should miss before fill");
+
+    fill(&cache, "hello").await;
+    let file = cache.get(&digest).expect("should hit after fill");
+    assert_eq!(read(file), "hello");
+
+    let reopened = PayloadCache::open(tmpdir.path(), 1024).unwrap();
+    let file = reopened
+        .get(&digest)
+        .expect("payloads should be kept across sessions");
+    assert_eq!(read(file), "hello");
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_payload_cache_evicts_least_recently_used(tmpdir: tempfile::TempDir) {
+    // room for two of the payloads, but not three
+    let cache = PayloadCache::open(tmpdir.path(), 10).unwrap();
+    let first = fill(&cache, "first").await;
+    let other = fill(&cache, "other").await;
+    cache.get(&first).expect("first should still be cached");
+
+    let third = fill(&cache, "third").await;
+    assert!(cache.get(&first).is_some(), "recently used should be kept");
+    assert!(cache.get(&third).is_some(), "newest should be kept");
+    assert!(
+        cache.get(&other).is_none(),
+        "least recently used should be evicted"
+    );
+    assert!(
+        !tmpdir.path().join(other.to_string()).exists(),
+        "evicted payloads should be removed from disk"
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_payload_cache_size_limit(tmpdir: tempfile::TempDir) {
+    let cache = PayloadCache::open(tmpdir.path(), 10).unwrap();
+    assert!(cache.can_hold(10));
+    assert!(!cache.can_hold(11));
+
+    for data in ["one", "two", "three", "four", "five"] {
+        fill(&cache, data).await;
+        assert!(cache.lock_state().total_size <= 10);
+    }
+
+    let reopened = PayloadCache::open(tmpdir.path(), 4).unwrap();
+    assert!(
+        reopened.lock_state().total_size <= 4,
+        "a smaller limit should be applied to existing payloads"
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_payload_cache_rejects_invalid_payload(tmpdir: tempfile::TempDir) {
+    let cache = PayloadCache::open(tmpdir.path(), 1024).unwrap();
+    let digest = digest_of("expected");
+    let reader = Box::pin(std::io::Cursor::new(b"actual".to_vec()));
+    cache
+        .fill(digest, reader)
+        .await
+        .expect_err("a payload that does not match its digest should fail");
+    assert!(cache.get(&digest).is_none());
+    let leftover = std::fs::read_dir(tmpdir.path())
+        .unwrap()
+        .flatten()
+        .any(|entry| entry.file_name().to_string_lossy().starts_with(TMP_PREFIX));
+    assert!(!leftover, "failed downloads should be cleaned up");
+}
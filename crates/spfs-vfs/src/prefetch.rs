@@ -0,0 +1,224 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use spfs::encoding::Digest;
+use spfs::prelude::*;
+use spfs::storage::RepositoryHandle;
+use spfs::tracking::TagSpec;
+use tokio::io::AsyncReadExt;
+
+#[cfg(test)]
+#[path = "./prefetch_test.rs"]
+mod prefetch_test;
+
+/// The tag folder that holds the payloads opened by previous
+/// runtimes, with one tag for each platform
+const PREFETCH_TAG_DIR: &str = "spfs/prefetch";
+
+/// Records the payloads that are opened in a runtime so that
+/// they can be fetched ahead of time when the same stack of
+/// layers is mounted again.
+///
+/// The opened payloads are stored as a blob, one digest per line in
+/// the order that they were first opened, which is tagged alongside
+/// the platform of the stack as `spfs/prefetch/<platform digest>`.
+pub(crate) struct PrefetchRecorder {
+    /// the local repository, where the record is saved
+    local: Arc<RepositoryHandle>,
+    tag: TagSpec,
+    opened: DashMap<Digest, u64>,
+    next: AtomicU64,
+    /// the number of payloads that had been recorded at the last save
+    saved: AtomicU64,
+}
+
+impl PrefetchRecorder {
+    /// Create a recorder for the platform of the given stack of layers
+    pub fn new(stack: spfs::graph::Stack, local: Arc<RepositoryHandle>) -> spfs::Result<Self> {
+        let platform = spfs::graph::Platform::from(stack);
+        Ok(Self {
+            local,
+            tag: TagSpec::parse(format!("{PREFETCH_TAG_DIR}/{}", platform.digest()?))?,
+            opened: Default::default(),
+            next: AtomicU64::new(0),
+            saved: AtomicU64::new(0),
+        })
+    }
+
+    /// Note that the given payload was opened in this runtime
+    pub fn record(&self, digest: Digest) {
+        self.opened
+            .entry(digest)
+            .or_insert_with(|| self.next.fetch_add(1, Ordering::Relaxed));
+    }
+
+    /// The payloads recorded in this runtime, in the order that they were first opened
+    fn recorded(&self) -> Vec<Digest> {
+        let mut opened: Vec<_> = self
+            .opened
+            .iter()
+            .map(|entry| (*entry.value(), *entry.key()))
+            .collect();
+        opened.sort();
+        opened.into_iter().map(|(_, digest)| digest).collect()
+    }
+
+    /// Replace the stored list with the payloads recorded in this runtime.
+    ///
+    /// Nothing is written if no payloads were opened, so that short lived
+    /// runtimes do not discard a list from a more representative one,
+    /// or if none have been opened since the last save.
+    pub async fn save(&self) -> spfs::Result<()> {
+        let count = self.next.load(Ordering::Relaxed);
+        if count == self.saved.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let recorded = self.recorded();
+        let mut data = String::new();
+        for digest in recorded {
+            data.push_str(&digest.to_string());
+            data.push('\n');
+        }
+        let digest = self
+            .local
+            .commit_blob(Box::pin(std::io::Cursor::new(data.into_bytes())))
+            .await?;
+        self.local.push_tag(&self.tag, &digest).await?;
+        self.saved.fetch_max(count, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Save the recorded payloads every period in the background,
+    /// until this recorder is dropped.
+    ///
+    /// The filesystem is not guaranteed to be shut down cleanly,
+    /// so this keeps the stored list close to what was opened
+    /// even when the final save never happens.
+    pub fn spawn_periodic_save(self: &Arc<Self>, period: Duration) {
+        let recorder: Weak<Self> = Arc::downgrade(self);
+        tokio::task::spawn(async move {
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                let Some(recorder) = recorder.upgrade() else {
+                    break;
+                };
+                if let Err(err) = recorder.save().await {
+                    tracing::warn!("Failed to save the list of opened payloads: {err}");
+                }
+            }
+        });
+    }
+
+    /// Pull the payloads recorded by a previous runtime into the
+    /// local repository from the given remotes, in the background
+    pub fn spawn_prefetch(&self, remotes: Vec<Arc<RepositoryHandle>>, concurrency: usize) {
+        let tag = self.tag.clone();
+        let local = Arc::clone(&self.local);
+        tokio::task::spawn(async move {
+            let digests = load(&tag, &local, &remotes).await;
+            prefetch(digests, local, remotes, concurrency).await;
+        });
+    }
+}
+
+/// Load the payloads that were recorded by a previous runtime, from
+/// the local repository or else the first remote that has a record
+async fn load(
+    tag: &TagSpec,
+    local: &Arc<RepositoryHandle>,
+    remotes: &[Arc<RepositoryHandle>],
+) -> Vec<Digest> {
+    for repo in std::iter::once(local).chain(remotes) {
+        match load_from(tag, repo).await {
+            Ok(Some(digests)) => return digests,
+            Ok(None) => continue,
+            Err(err) => {
+                tracing::warn!(
+                    "failed to read prefetch list {tag} from {}: {err}",
+                    repo.address()
+                );
+            }
+        }
+    }
+    Vec::new()
+}
+
+async fn load_from(tag: &TagSpec, repo: &RepositoryHandle) -> spfs::Result<Option<Vec<Digest>>> {
+    let tag = match repo.resolve_tag(tag).await {
+        Ok(tag) => tag,
+        Err(spfs::Error::UnknownReference(_)) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let (mut reader, filename) = repo.open_payload(tag.target).await?;
+    let mut data = String::new();
+    reader
+        .read_to_string(&mut data)
+        .await
+        .map_err(|err| spfs::Error::StorageReadError("read of prefetch list", filename, err))?;
+    Ok(Some(
+        data.lines()
+            .filter_map(|line| Digest::parse(line.trim()).ok())
+            .collect(),
+    ))
+}
+
+/// Pull the given payloads into the local repository from the remotes,
+/// skipping any that are already available locally.
+async fn prefetch(
+    digests: Vec<Digest>,
+    local: Arc<RepositoryHandle>,
+    remotes: Vec<Arc<RepositoryHandle>>,
+    concurrency: usize,
+) {
+    if digests.is_empty() || remotes.is_empty() {
+        return;
+    }
+    tracing::debug!("Prefetching {} payloads...", digests.len());
+    let queue = Arc::new(Mutex::new(VecDeque::from(digests)));
+    let mut workers = tokio::task::JoinSet::new();
+    for _ in 0..concurrency {
+        let queue = Arc::clone(&queue);
+        let local = Arc::clone(&local);
+        let remotes = remotes.clone();
+        workers.spawn(async move {
+            loop {
+                let Some(digest) = queue.lock().expect("prefetch lock poisoned").pop_front() else {
+                    break;
+                };
+                prefetch_one(digest, &local, &remotes).await;
+            }
+        });
+    }
+    workers.join_all().await;
+}
+
+async fn prefetch_one(digest: Digest, local: &RepositoryHandle, remotes: &[Arc<RepositoryHandle>]) {
+    if local.has_payload(digest).await {
+        return;
+    }
+    for remote in remotes {
+        match spfs::Syncer::new(remote, local).sync_digest(digest).await {
+            Ok(_) => {
+                tracing::trace!("prefetched {digest}");
+                return;
+            }
+            Err(spfs::Error::UnknownObject(_)) => continue,
+            Err(err) => {
+                tracing::debug!(
+                    "failed to prefetch {digest} from {}: {err}",
+                    remote.address()
+                );
+                continue;
+            }
+        }
+    }
+}
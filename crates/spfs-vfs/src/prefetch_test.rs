@@ -0,0 +1,127 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::sync::Arc;
+
+use rstest::rstest;
+use spfs::encoding::Digest;
+use spfs::prelude::*;
+use spfs::storage::RepositoryHandle;
+
+use super::{PrefetchRecorder, load, prefetch};
+use crate::fixtures::*;
+
+async fn open_repo(root: &std::path::Path) -> Arc<RepositoryHandle> {
+    let repo = spfs::storage::fs::MaybeOpenFsRepository::create(root)
+        .await
+        .expect("failed to create a repository for test");
+    Arc::new(repo.into())
+}
+
+async fn commit(repo: &RepositoryHandle, data: &str) -> Digest {
+    repo.commit_blob(Box::pin(std::io::Cursor::new(data.to_owned().into_bytes())))
+        .await
+        .unwrap()
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_prefetch_record_and_replay(tmpdir: tempfile::TempDir) {
+    let local = open_repo(&tmpdir.path().join("local")).await;
+    let remote = open_repo(&tmpdir.path().join("remote")).await;
+    let first = commit(&remote, "first").await;
+    let second = commit(&remote, "second").await;
+    let stack = spfs::graph::Stack::from(commit(&remote, "layer").await);
+
+    let recorder = PrefetchRecorder::new(stack.clone(), Arc::clone(&local)).unwrap();
+    recorder.record(second);
+    recorder.record(first);
+    recorder.record(second);
+    recorder.save().await.unwrap();
+
+    let replay = PrefetchRecorder::new(stack, Arc::clone(&local)).unwrap();
+    let recorded = load(&replay.tag, &local, &[]).await;
+    assert_eq!(
+        recorded,
+        vec![second, first],
+        "payloads should be replayed in the order they were first opened"
+    );
+
+    prefetch(recorded, Arc::clone(&local), vec![remote], 2).await;
+    assert!(local.has_payload(first).await);
+    assert!(local.has_payload(second).await);
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_prefetch_records_are_per_stack(tmpdir: tempfile::TempDir) {
+    let local = open_repo(&tmpdir.path().join("local")).await;
+    let remote = open_repo(&tmpdir.path().join("remote")).await;
+    let payload = commit(&local, "payload").await;
+
+    // recorded by a runtime on another host that shares the remote
+    let recorder = PrefetchRecorder::new(
+        spfs::graph::Stack::from(commit(&local, "one").await),
+        Arc::clone(&remote),
+    )
+    .unwrap();
+    recorder.record(payload);
+    recorder.save().await.unwrap();
+
+    let other = PrefetchRecorder::new(
+        spfs::graph::Stack::from(commit(&local, "two").await),
+        Arc::clone(&local),
+    )
+    .unwrap();
+    assert!(
+        load(&other.tag, &local, std::slice::from_ref(&remote))
+            .await
+            .is_empty(),
+        "a different stack should have nothing recorded"
+    );
+    assert_eq!(
+        load(&recorder.tag, &local, std::slice::from_ref(&remote)).await,
+        vec![payload],
+        "a record should be loaded from a remote when not available locally"
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_prefetch_nothing_recorded_keeps_previous(tmpdir: tempfile::TempDir) {
+    let local = open_repo(&tmpdir.path().join("local")).await;
+    let payload = commit(&local, "payload").await;
+    let stack = spfs::graph::Stack::from(commit(&local, "layer").await);
+
+    let recorder = PrefetchRecorder::new(stack.clone(), Arc::clone(&local)).unwrap();
+    recorder.record(payload);
+    recorder.save().await.unwrap();
+
+    let empty = PrefetchRecorder::new(stack, Arc::clone(&local)).unwrap();
+    empty.save().await.unwrap();
+    assert_eq!(load(&empty.tag, &local, &[]).await, vec![payload]);
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_prefetch_saved_periodically(tmpdir: tempfile::TempDir) {
+    let local = open_repo(&tmpdir.path().join("local")).await;
+    let payload = commit(&local, "payload").await;
+    let stack = spfs::graph::Stack::from(commit(&local, "layer").await);
+
+    let recorder = Arc::new(PrefetchRecorder::new(stack, Arc::clone(&local)).unwrap());
+    recorder.spawn_periodic_save(std::time::Duration::from_millis(10));
+    recorder.record(payload);
+    for _ in 0..100 {
+        if !load(&recorder.tag, &local, &[]).await.is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(
+        load(&recorder.tag, &local, &[]).await,
+        vec![payload],
+        "the record should be saved without an explicit save"
+    );
+}
@@ -132,6 +132,8 @@ impl Mount {
             entries,
             user_data: _,
             legacy_size,
+            xattrs,
+            hardlink,
         } = entry;
 
         let inode = self.allocate_inode();
@@ -146,6 +148,8 @@ impl Mount {
             entries,
             user_data: inode,
             legacy_size,
+            xattrs,
+            hardlink,
         });
         self.inodes.insert(inode, Arc::clone(&entry));
         entry
@@ -281,39 +285,42 @@ impl winfsp::filesystem::FileSystemContext for Mount {
         let digest = entry.object;
         self.rt.spawn(async move {
             for repo in repos.into_iter() {
-                match &*repo {
-                    spfs::storage::RepositoryHandle::FS(fs_repo) => {
-                        let Ok(fs_repo) = fs_repo.opened().await else {
-                            let _ =
-                                send.send(Err(winfsp::FspError::IO(std::io::ErrorKind::NotFound)));
+                if let spfs::storage::RepositoryHandle::FS(fs_repo) = &*repo {
+                    let Ok(fs_repo) = fs_repo.opened().await else {
+                        let _ = send.send(Err(winfsp::FspError::IO(std::io::ErrorKind::NotFound)));
+                        return;
+                    };
+                    let payload_path = fs_repo.payloads().build_digest_path(&digest);
+                    match std::fs::OpenOptions::new().read(true).open(payload_path) {
+                        Ok(file) => {
+                            let _ = send.send(Ok(Some(Handle::BlobFile { entry, file })));
                             return;
-                        };
-                        let payload_path = fs_repo.payloads().build_digest_path(&digest);
-                        match std::fs::OpenOptions::new().read(true).open(payload_path) {
-                            Ok(file) => {
-                                let _ = send.send(Ok(Some(Handle::BlobFile { entry, file })));
-                                return;
-                            }
-                            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
-                                continue;
-                            }
-                            Err(err) => err!(send, err),
                         }
-                    }
-                    repo => match repo.open_payload(digest).await {
-                        Ok((stream, _)) => {
-                            // TODO: try to leverage the returned file path?
-                            let _ = send.send(Ok(Some(Handle::BlobStream {
-                                entry,
-                                offset: Arc::new(AtomicU64::new(0)),
-                                stream: Arc::new(tokio::sync::Mutex::new(stream)),
-                            })));
-                            // TODO: are there attribute flags to identify this as a non-seekable file?
-                            return;
-                        }
-                        Err(spfs::Error::UnknownObject(_)) => continue,
+                        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
                         Err(err) => err!(send, err),
-                    },
+                    }
+                    // the payload may still be stored compressed, which
+                    // is read and decompressed through the repository
+                    let compressed = fs_repo
+                        .compressed_payloads()
+                        .map(|store| store.build_digest_path(&digest));
+                    if !compressed.is_some_and(|path| path.exists()) {
+                        continue;
+                    }
+                }
+                match repo.open_payload(digest).await {
+                    Ok((stream, _)) => {
+                        // TODO: try to leverage the returned file path?
+                        let _ = send.send(Ok(Some(Handle::BlobStream {
+                            entry,
+                            offset: Arc::new(AtomicU64::new(0)),
+                            stream: Arc::new(tokio::sync::Mutex::new(stream)),
+                        })));
+                        // TODO: are there attribute flags to identify this as a non-seekable file?
+                        return;
+                    }
+                    Err(spfs::Error::UnknownObject(_)) => continue,
+                    Err(err) => err!(send, err),
                 }
             }
             let _ = send.send(Ok(None));
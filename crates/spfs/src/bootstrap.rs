@@ -63,10 +63,20 @@ impl Command {
     /// to that of this command, and caution should be taken.
     #[cfg(unix)]
     pub fn exec(self) -> Result<std::convert::Infallible> {
+        tracing::debug!("{self:#?}");
+        self.prepare()?.exec()
+    }
+
+    /// Convert this command into the arguments and environment
+    /// that are needed to execute it.
+    ///
+    /// The environment of the current process is captured, with
+    /// the variables of this command applied on top of it.
+    #[cfg(unix)]
+    pub fn prepare(self) -> Result<PreparedCommand> {
         use std::collections::HashMap;
         use std::os::unix::prelude::OsStringExt;
 
-        tracing::debug!("{self:#?}");
         // ensure that all components of this command are utilized
         let Self {
             executable,
@@ -96,7 +106,7 @@ impl Command {
             k.push(&v);
             env.push(CString::new(k.into_vec()).map_err(crate::Error::CommandHasNul)?);
         }
-        nix::unistd::execve(&argv[0], argv.as_slice(), env.as_slice()).map_err(crate::Error::from)
+        Ok(PreparedCommand { argv, env })
     }
 
     /// Execute this command, replacing the current program.
@@ -124,6 +134,39 @@ impl Command {
     }
 }
 
+/// A [`Command`] that is ready to be executed, see [`Command::prepare`]
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct PreparedCommand {
+    argv: Vec<CString>,
+    env: Vec<CString>,
+}
+
+#[cfg(unix)]
+impl PreparedCommand {
+    /// Execute this command, replacing the current program.
+    ///
+    /// Upon success, this function will never return.
+    pub fn exec(self) -> Result<std::convert::Infallible> {
+        nix::unistd::execve(&self.argv[0], self.argv.as_slice(), self.env.as_slice())
+            .map_err(crate::Error::from)
+    }
+
+    /// The null-terminated arrays of arguments and environment
+    /// variables that are passed to `execve`, which remain valid
+    /// for as long as this command does.
+    pub(crate) fn as_ptrs(&self) -> (Vec<*const libc::c_char>, Vec<*const libc::c_char>) {
+        let to_ptrs = |strings: &[CString]| -> Vec<*const libc::c_char> {
+            strings
+                .iter()
+                .map(|s| s.as_ptr())
+                .chain(std::iter::once(std::ptr::null()))
+                .collect()
+        };
+        (to_ptrs(&self.argv), to_ptrs(&self.env))
+    }
+}
+
 /// Construct a bootstrap command.
 ///
 /// The returned command properly calls through the relevant spfs
@@ -179,6 +222,15 @@ pub fn build_interactive_shell_command(
             ],
         }),
         #[cfg(unix)]
+        Shell::Fish(fish) => Ok(Command {
+            executable: fish.into(),
+            args: vec![
+                "--init-command".into(),
+                format!("source '{}'", rt.config.fish_startup_file.display()).into(),
+            ],
+            vars: vec![shell_message],
+        }),
+        #[cfg(unix)]
         Shell::Bash(bash) => Ok(Command {
             executable: bash.into(),
             args: vec![
@@ -222,6 +274,7 @@ where
     let startup_file = match shell.kind() {
         ShellKind::Bash => &runtime.config.sh_startup_file,
         ShellKind::Tcsh => &runtime.config.csh_startup_file,
+        ShellKind::Fish => &runtime.config.fish_startup_file,
         ShellKind::Powershell => {
             let mut cmd = command.into();
             for arg in args.into_iter().map(Into::into) {
@@ -394,6 +447,7 @@ where
 pub enum ShellKind {
     Bash,
     Tcsh,
+    Fish,
     Powershell,
 }
 
@@ -402,6 +456,7 @@ impl AsRef<str> for ShellKind {
         match self {
             Self::Bash => "bash",
             Self::Tcsh => "tcsh",
+            Self::Fish => "fish",
             Self::Powershell => "powershell.exe",
         }
     }
@@ -414,6 +469,8 @@ pub enum Shell {
     Bash(PathBuf),
     #[cfg(unix)]
     Tcsh(PathBuf),
+    #[cfg(unix)]
+    Fish(PathBuf),
     #[cfg(windows)]
     Powershell(PathBuf),
 }
@@ -425,6 +482,8 @@ impl Shell {
             Self::Bash(_) => ShellKind::Bash,
             #[cfg(unix)]
             Self::Tcsh(_) => ShellKind::Tcsh,
+            #[cfg(unix)]
+            Self::Fish(_) => ShellKind::Fish,
             #[cfg(windows)]
             Self::Powershell(_) => ShellKind::Powershell,
         }
@@ -437,6 +496,8 @@ impl Shell {
             Self::Bash(p) => p,
             #[cfg(unix)]
             Self::Tcsh(p) => p,
+            #[cfg(unix)]
+            Self::Fish(p) => p,
             #[cfg(windows)]
             Self::Powershell(p) => p,
         }
@@ -452,6 +513,8 @@ impl Shell {
             Some(n) if n == ShellKind::Bash.as_ref() => Ok(Self::Bash(path.to_owned())),
             #[cfg(unix)]
             Some(n) if n == ShellKind::Tcsh.as_ref() => Ok(Self::Tcsh(path.to_owned())),
+            #[cfg(unix)]
+            Some(n) if n == ShellKind::Fish.as_ref() => Ok(Self::Fish(path.to_owned())),
             #[cfg(windows)]
             Some(n) if n == ShellKind::Powershell.as_ref() => Ok(Self::Powershell(path.to_owned())),
             Some(_) => Err(Error::new(format!("Unsupported shell: {path:?}"))),
@@ -487,7 +550,12 @@ impl Shell {
             }
         }
 
-        for kind in &[ShellKind::Bash, ShellKind::Tcsh, ShellKind::Powershell] {
+        for kind in &[
+            ShellKind::Bash,
+            ShellKind::Tcsh,
+            ShellKind::Fish,
+            ShellKind::Powershell,
+        ] {
             if let Some(path) = which(kind) {
                 if let Ok(shell) = Shell::from_path(path) {
                     return Ok(shell);
@@ -15,6 +15,8 @@ use crate::runtime;
 #[rstest]
 #[case::bash("bash", "test.sh", "echo hi; export TEST_VALUE='spfs-test-value'")]
 #[case::tcsh("tcsh", "test.csh", "echo hi; setenv TEST_VALUE 'spfs-test-value'")]
+#[case::fish("fish", "test.fish", "echo hi; set -gx TEST_VALUE 'spfs-test-value'")]
+#[case::fish_from_sh("fish", "test.sh", "echo hi; export TEST_VALUE='spfs-test-value'")]
 #[tokio::test]
 #[serial_test::serial(env)] // env and config manipulation must be reliable
 async fn test_shell_initialization_startup_scripts(
@@ -54,7 +56,11 @@ async fn test_shell_initialization_startup_scripts(
     let tmp_startup_dir = tmpdir.path().join("startup.d");
     std::fs::create_dir(&tmp_startup_dir).unwrap();
     rt.ensure_startup_scripts(&[]).unwrap();
-    for startup_script in &[&rt.config.sh_startup_file, &rt.config.csh_startup_file] {
+    for startup_script in &[
+        &rt.config.sh_startup_file,
+        &rt.config.csh_startup_file,
+        &rt.config.fish_startup_file,
+    ] {
         let mut cmd = Command::new("sed");
         cmd.arg("-i");
         cmd.arg(format!(
@@ -74,20 +80,10 @@ async fn test_shell_initialization_startup_scripts(
         std::env::set_var("SHELL", &shell_path);
     }
 
-    match crate::Shell::find_best(None).unwrap() {
-        #[cfg(unix)]
-        crate::Shell::Bash(_) if shell == "tcsh" => {
-            // Test will fail because we weren't able to
-            // find the shell we are trying to test
-            return;
-        }
-        #[cfg(unix)]
-        crate::Shell::Tcsh(_) if shell == "bash" => {
-            // Test will fail because we weren't able to
-            // find the shell we are trying to test
-            return;
-        }
-        _ => {}
+    if crate::Shell::find_best(None).unwrap().kind().as_ref() != shell {
+        // Test will fail because we weren't able to
+        // find the shell we are trying to test
+        return;
     }
 
     let cmd = build_shell_initialized_command(&rt, None, "printenv", vec!["TEST_VALUE"]).unwrap();
@@ -102,6 +98,7 @@ async fn test_shell_initialization_startup_scripts(
 #[rstest]
 #[case::bash("bash")]
 #[case::tcsh("tcsh")]
+#[case::fish("fish")]
 #[tokio::test]
 #[serial_test::serial(env)] // env and config manipulation must be reliable
 async fn test_shell_initialization_no_startup_scripts(
@@ -137,7 +134,11 @@ async fn test_shell_initialization_no_startup_scripts(
     let tmp_startup_dir = tmpdir.path().join("startup.d");
     std::fs::create_dir(&tmp_startup_dir).unwrap();
     rt.ensure_startup_scripts(&[]).unwrap();
-    for startup_script in &[&rt.config.sh_startup_file, &rt.config.csh_startup_file] {
+    for startup_script in &[
+        &rt.config.sh_startup_file,
+        &rt.config.csh_startup_file,
+        &rt.config.fish_startup_file,
+    ] {
         let mut cmd = Command::new("sed");
         cmd.arg("-i");
         cmd.arg(format!(
@@ -166,6 +167,7 @@ async fn test_shell_initialization_no_startup_scripts(
 #[rstest]
 #[case::bash("bash")]
 #[case::tcsh("tcsh")]
+#[case::fish("fish")]
 #[tokio::test]
 #[serial_test::serial(env)] // env manipulation must be reliable
 async fn test_find_alternate_bash(#[case] shell: &str, tmpdir: tempfile::TempDir) {
@@ -0,0 +1,114 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use futures::TryStreamExt;
+
+use crate::prelude::*;
+use crate::storage::RepositoryHandle;
+use crate::sync::reporter::SyncEnvResult;
+use crate::{Error, Result, Syncer, encoding, graph, storage, tracking};
+
+#[cfg(test)]
+#[path = "./bundle_test.rs"]
+mod bundle_test;
+
+/// Find every object and payload that is needed by the given items.
+///
+/// Blobs are not always stored as objects, so a digest that cannot be
+/// read as an object is assumed to identify a payload.
+async fn collect_reachable_digests(
+    repo: &RepositoryHandle,
+    env: &tracking::EnvSpec,
+) -> Result<HashSet<encoding::Digest>> {
+    let mut queue = Vec::with_capacity(env.len());
+    for item in env.iter() {
+        queue.push(item.resolve_digest(repo).await?);
+    }
+    let mut reachable = HashSet::new();
+    while let Some(digest) = queue.pop() {
+        if !reachable.insert(digest) {
+            continue;
+        }
+        match repo.read_object(digest).await {
+            Ok(obj) => queue.extend(obj.child_objects()),
+            Err(Error::UnknownObject(_)) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(reachable)
+}
+
+/// Write the data needed by `top` that is not already part of `base`
+/// into a new tar repository at the given path.
+///
+/// The resulting bundle can be applied to any repository that has
+/// the data for `base` to reproduce `top` there, see [`apply_bundle`].
+/// Any tags in `top` are included in the bundle.
+pub async fn create_bundle<P: AsRef<Path>>(
+    repo: &RepositoryHandle,
+    base: &tracking::EnvSpec,
+    top: tracking::EnvSpec,
+    path: P,
+) -> Result<SyncEnvResult> {
+    let path = path.as_ref();
+    let skipped = collect_reachable_digests(repo, base).await?;
+    tracing::debug!(count = skipped.len(), "found objects and payloads in base");
+
+    match tokio::fs::remove_file(path).await {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => {
+            return Err(Error::StorageWriteError(
+                "remove of existing bundle",
+                path.to_owned(),
+                err,
+            ));
+        }
+    }
+    let tar_repo = storage::tar::TarRepository::create(path)
+        .await
+        .map_err(|source| Error::FailedToOpenRepository {
+            repository: "<TAR Bundle>".into(),
+            source,
+        })?;
+    // a bundle only carries data, not runtime edits
+    tar_repo.remove_durable_dir().await?;
+    let bundle = RepositoryHandle::from(tar_repo);
+
+    let result = Syncer::new(repo, &bundle)
+        .with_skipped_digests(skipped)
+        .sync_env(top)
+        .await?;
+    if let RepositoryHandle::Tar(tar_repo) = &bundle {
+        tar_repo.flush()?;
+    }
+    Ok(result)
+}
+
+/// Copy everything in a bundle into the given repository.
+///
+/// The repository must already have all of the data that
+/// the bundle was made against, otherwise this will fail
+/// without updating any tags.
+pub async fn apply_bundle(bundle: &RepositoryHandle, repo: &RepositoryHandle) -> Result<()> {
+    let syncer = Syncer::new(bundle, repo);
+    // bundles can be made from digests without tags, so every object
+    // in the bundle is synced rather than starting from its tags
+    let digests: Vec<_> = bundle
+        .find_digests(graph::DigestSearchCriteria::All)
+        .try_collect()
+        .await?;
+    for digest in digests {
+        syncer.sync_digest(digest).await?;
+    }
+
+    let tags: Vec<_> = bundle.iter_tags().try_collect().await?;
+    for (_, tag) in tags {
+        repo.insert_tag(&tag).await?;
+    }
+    Ok(())
+}
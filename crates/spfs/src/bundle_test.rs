@@ -0,0 +1,115 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use rstest::rstest;
+
+use super::{apply_bundle, create_bundle};
+use crate::fixtures::*;
+use crate::prelude::*;
+use crate::storage::RepositoryHandle;
+use crate::{Syncer, tracking};
+
+#[rstest]
+#[case::fs(tmprepo("fs"), tmprepo("fs"))]
+#[tokio::test]
+// This test just needs the config to not change while it is running.
+#[serial_test::serial(config)]
+async fn test_bundle_round_trip(
+    #[case]
+    #[future]
+    repo_a: TempRepo,
+    #[case]
+    #[future]
+    repo_b: TempRepo,
+    tmpdir: tempfile::TempDir,
+) {
+    init_logging();
+    let repo_a = repo_a.await;
+    let repo_b = repo_b.await;
+
+    ensure(tmpdir.path().join("base/dir/file.txt"), "hello");
+    let base = crate::Committer::new(&repo_a)
+        .commit_dir(tmpdir.path().join("base").as_path())
+        .await
+        .unwrap();
+    let base_layer = repo_a.create_layer_from_manifest(&base).await.unwrap();
+    let base_platform = repo_a
+        .create_platform(base_layer.digest().unwrap().into())
+        .await
+        .unwrap();
+    repo_a
+        .push_tag(
+            &tracking::TagSpec::parse("base").unwrap(),
+            &base_platform.digest().unwrap(),
+        )
+        .await
+        .unwrap();
+
+    ensure(tmpdir.path().join("update/dir/new.txt"), "hello, update");
+    let update = crate::Committer::new(&repo_a)
+        .commit_dir(tmpdir.path().join("update").as_path())
+        .await
+        .unwrap();
+    let update_layer = repo_a.create_layer_from_manifest(&update).await.unwrap();
+    let top_platform = repo_a
+        .create_platform(
+            [base_layer.digest().unwrap(), update_layer.digest().unwrap()]
+                .into_iter()
+                .collect(),
+        )
+        .await
+        .unwrap();
+    repo_a
+        .push_tag(
+            &tracking::TagSpec::parse("top").unwrap(),
+            &top_platform.digest().unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let bundle_path = tmpdir.path().join("delta.tar");
+    create_bundle(
+        &repo_a,
+        &"base".parse().unwrap(),
+        "top".parse().unwrap(),
+        &bundle_path,
+    )
+    .await
+    .expect("bundle should be created");
+
+    let bundle = RepositoryHandle::from(
+        crate::storage::tar::TarRepository::open(&bundle_path)
+            .await
+            .unwrap(),
+    );
+    assert!(bundle.has_object(top_platform.digest().unwrap()).await);
+    assert!(bundle.has_object(update_layer.digest().unwrap()).await);
+    assert!(
+        !bundle.has_object(base_layer.digest().unwrap()).await,
+        "data in the base should not be bundled"
+    );
+    let base_file = base.get_path("dir/file.txt").unwrap();
+    assert!(!bundle.has_payload(base_file.object).await);
+
+    apply_bundle(&bundle, &repo_b)
+        .await
+        .expect_err("should fail to apply without the base data");
+
+    Syncer::new(&repo_a, &repo_b)
+        .sync_ref("base")
+        .await
+        .unwrap();
+    apply_bundle(&bundle, &repo_b)
+        .await
+        .expect("should apply once the base data is available");
+
+    let digest = repo_b.resolve_ref("top").await.unwrap();
+    assert_eq!(digest, top_platform.digest().unwrap());
+    let manifest =
+        crate::compute_object_manifest(repo_b.read_object(digest).await.unwrap(), &repo_b)
+            .await
+            .unwrap();
+    let new_file = manifest.get_path("dir/new.txt").unwrap();
+    assert!(repo_b.has_payload(new_file.object).await);
+}
@@ -28,8 +28,12 @@ mod check_test;
 pub struct Checker<'repo, 'sync, Reporter: CheckReporter = SilentCheckReporter> {
     repo: &'repo storage::RepositoryHandle,
     repair_with: Option<super::Syncer<'sync, 'repo>>,
+    repair_from_quarantine: bool,
     reporter: Arc<Reporter>,
     processed_digests: Arc<dashmap::DashMap<encoding::Digest, CheckProgress>>,
+    /// the known existence of payloads, found by walking the graph
+    /// on a remote server and consumed by the first check that uses it
+    payload_hints: Arc<dashmap::DashMap<encoding::Digest, bool>>,
     tag_stream_semaphore: Semaphore,
     object_semaphore: Semaphore,
 }
@@ -45,7 +49,9 @@ impl<'repo> Checker<'repo, 'static> {
             repo,
             reporter: Arc::new(SilentCheckReporter::default()),
             repair_with: None,
+            repair_from_quarantine: false,
             processed_digests: Arc::new(Default::default()),
+            payload_hints: Arc::new(Default::default()),
             tag_stream_semaphore: Semaphore::new(Self::DEFAULT_MAX_TAG_STREAM_CONCURRENCY),
             object_semaphore: Semaphore::new(Self::DEFAULT_MAX_OBJECT_CONCURRENCY),
         }
@@ -66,7 +72,9 @@ where
             repo: self.repo,
             reporter: reporter.into(),
             repair_with: self.repair_with,
+            repair_from_quarantine: self.repair_from_quarantine,
             processed_digests: self.processed_digests,
+            payload_hints: self.payload_hints,
             tag_stream_semaphore: self.tag_stream_semaphore,
             object_semaphore: self.object_semaphore,
         }
@@ -88,12 +96,23 @@ where
                 crate::Syncer::new(source, self.repo)
                     .with_policy(SyncPolicy::LatestTagsAndResyncObjects),
             ),
+            repair_from_quarantine: self.repair_from_quarantine,
             processed_digests: self.processed_digests,
+            payload_hints: self.payload_hints,
             tag_stream_semaphore: self.tag_stream_semaphore,
             object_semaphore: self.object_semaphore,
         }
     }
 
+    /// Restore any missing data from the repository's quarantine.
+    ///
+    /// This is tried before any repair source, and only applies to
+    /// filesystem repositories. See [`storage::fs::Quarantine`].
+    pub fn with_repair_from_quarantine(mut self, repair_from_quarantine: bool) -> Self {
+        self.repair_from_quarantine = repair_from_quarantine;
+        self
+    }
+
     /// The maximum number of tag streams that can be read and processed at once
     pub fn with_max_tag_stream_concurrency(mut self, max_tag_stream_concurrency: usize) -> Self {
         self.tag_stream_semaphore = Semaphore::new(max_tag_stream_concurrency);
@@ -312,6 +331,9 @@ where
                 self.must_check_blob_with_perms_opt(&obj, perms).await?
             }),
             Enum::Manifest(obj) => CheckObjectResult::Manifest(self.check_manifest(obj).await?),
+            Enum::ChunkedBlob(obj) => {
+                CheckObjectResult::ChunkedBlob(self.check_chunked_blob(obj).await?)
+            }
         };
         self.reporter.checked_object(&res);
         Ok(res)
@@ -374,6 +396,7 @@ where
     ///
     /// To also check if the manifest object exists, use [`Self::check_digest`]
     pub async fn check_manifest(&self, manifest: graph::Manifest) -> Result<CheckManifestResult> {
+        self.prefetch_manifest_payloads(&manifest).await;
         let futures: FuturesUnordered<_> = manifest
             .iter_entries()
             .filter(|e| e.kind().is_blob())
@@ -390,6 +413,61 @@ where
         Ok(res)
     }
 
+    /// Find the missing payloads of a manifest with a single walk
+    /// on the server when checking a remote repository, rather
+    /// than asking about each payload separately.
+    async fn prefetch_manifest_payloads(&self, manifest: &graph::Manifest) {
+        let storage::RepositoryHandle::Rpc(repo) = self.repo else {
+            return;
+        };
+        let digest = match manifest.digest() {
+            Ok(digest) => digest,
+            Err(_) => return,
+        };
+        let missing = match repo.find_missing_data(digest).await {
+            Ok(missing) => missing,
+            Err(err) => {
+                // older servers do not support this request
+                tracing::debug!(%digest, "failed to find missing data on server: {err}");
+                return;
+            }
+        };
+        for entry in manifest.iter_entries() {
+            let digest = *entry.object();
+            // large entries may be stored as chunked blobs,
+            // which do not have a payload with the same digest
+            if !entry.kind().is_blob()
+                || entry.size() >= crate::chunking::MIN_CHUNKED_PAYLOAD_SIZE
+                || missing.objects.contains(&digest)
+            {
+                continue;
+            }
+            self.payload_hints
+                .insert(digest, !missing.payloads.contains(&digest));
+        }
+    }
+
+    /// Validate that the identified chunked blob's chunks all exist.
+    ///
+    /// To also check if the chunked blob object exists, use [`Self::check_digest`]
+    pub async fn check_chunked_blob(
+        &self,
+        blob: graph::ChunkedBlob,
+    ) -> Result<CheckChunkedBlobResult> {
+        let futures: FuturesUnordered<_> = blob
+            .child_objects()
+            .into_iter()
+            .map(|d| self.check_digest(d))
+            .collect();
+        let results = futures.try_collect().await?;
+        let res = CheckChunkedBlobResult {
+            blob,
+            results,
+            repaired: false,
+        };
+        Ok(res)
+    }
+
     /// Validate that the identified annotation layer's value exists.
     pub async fn check_annotation(
         &self,
@@ -398,11 +476,12 @@ where
         let res = match annotation.value() {
             AnnotationValue::String(_) => CheckAnnotationResult::InternalValue,
             AnnotationValue::Blob(d) => {
-                let blob = self.repo.read_blob(*d).await?;
-                let result = unsafe { self.check_blob(&blob).await? };
+                // checked as any object, since the value may be stored
+                // as a chunked blob rather than a plain one
+                let result = self.check_digest(*d).await?;
                 CheckAnnotationResult::Checked {
                     digest: *d,
-                    result,
+                    result: Box::new(result),
                     repaired: false,
                 }
             }
@@ -495,8 +574,14 @@ where
     ) -> Result<CheckPayloadResult> {
         self.reporter.visit_payload(digest);
         let mut result = CheckPayloadResult::Missing(digest);
-        if self.repo.has_payload(digest).await {
+        let exists = match self.payload_hints.remove(&digest) {
+            Some((_, exists)) => exists,
+            None => self.repo.has_payload(digest).await,
+        };
+        if exists {
             result = CheckPayloadResult::Ok;
+        } else if self.restore_from_quarantine(digest).await? {
+            result = CheckPayloadResult::Repaired;
         } else if let Some(syncer) = &self.repair_with {
             // Safety: this sync is unsafe unless the blob is also created
             // or exists. We pass this rule up to the caller.
@@ -520,6 +605,13 @@ where
                 let Error::UnknownObject(digest) = err else {
                     return Err(err);
                 };
+                if self.restore_from_quarantine(digest).await? {
+                    return self
+                        .repo
+                        .read_object(digest)
+                        .await
+                        .map(|o| (o, Fallback::Repaired));
+                }
                 let Some(syncer) = &self.repair_with else {
                     return Err(err);
                 };
@@ -536,6 +628,20 @@ where
             res => res.map(|o| (o, Fallback::None)),
         }
     }
+
+    /// Move the identified object or payload out of quarantine,
+    /// if enabled, returning true if it was found there
+    async fn restore_from_quarantine(&self, digest: encoding::Digest) -> Result<bool> {
+        if !self.repair_from_quarantine {
+            return Ok(false);
+        }
+        let storage::RepositoryHandle::FS(repo) = self.repo else {
+            return Ok(false);
+        };
+        storage::fs::Quarantine::new(repo.opened().await?)
+            .restore_digest(digest)
+            .await
+    }
 }
 
 enum Fallback {
@@ -850,6 +956,7 @@ pub enum CheckObjectResult {
     Blob(CheckBlobResult),
     Manifest(CheckManifestResult),
     Annotation(CheckAnnotationResult),
+    ChunkedBlob(CheckChunkedBlobResult),
 }
 
 impl CheckObjectResult {
@@ -865,6 +972,7 @@ impl CheckObjectResult {
             CheckObjectResult::Blob(r) => r.set_repaired(),
             CheckObjectResult::Manifest(r) => r.set_repaired(),
             CheckObjectResult::Annotation(r) => r.set_repaired(),
+            CheckObjectResult::ChunkedBlob(r) => r.set_repaired(),
         }
     }
 
@@ -882,6 +990,7 @@ impl CheckObjectResult {
             Blob(res) => res.summary(),
             Manifest(res) => res.summary(),
             Annotation(res) => res.summary(),
+            ChunkedBlob(res) => res.summary(),
         }
     }
 }
@@ -909,6 +1018,29 @@ impl CheckPlatformResult {
     }
 }
 
+#[derive(Debug)]
+pub struct CheckChunkedBlobResult {
+    pub repaired: bool,
+    pub blob: graph::ChunkedBlob,
+    pub results: Vec<CheckObjectResult>,
+}
+
+impl CheckChunkedBlobResult {
+    /// Marks this result as being repaired.
+    fn set_repaired(&mut self) {
+        self.repaired = true;
+    }
+
+    pub fn summary(&self) -> CheckSummary {
+        let mut summary: CheckSummary = self.results.iter().map(|r| r.summary()).sum();
+        summary += CheckSummary::checked_one_object();
+        if self.repaired {
+            summary.repaired_objects += 1;
+        }
+        summary
+    }
+}
+
 #[derive(Debug)]
 pub struct CheckLayerResult {
     pub repaired: bool,
@@ -963,7 +1095,7 @@ pub enum CheckAnnotationResult {
     /// The annotation was stored in a blob and was checked
     Checked {
         digest: encoding::Digest,
-        result: CheckBlobResult,
+        result: Box<CheckObjectResult>,
         repaired: bool,
     },
 }
@@ -0,0 +1,310 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+//! Content-defined chunking of large payloads.
+//!
+//! Large payloads can be split into a sequence of smaller chunks
+//! whose boundaries are determined by the content itself, rather
+//! than by fixed offsets. This means that a small change to a large
+//! file only affects the chunks around the change, and all other
+//! chunks can be shared with previous versions of the same file.
+//! Chunked payloads are represented in the object graph by a
+//! [`graph::ChunkedBlob`].
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::Poll;
+
+use bytes::{Bytes, BytesMut};
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncReadExt};
+
+use crate::tracking::BlobRead;
+use crate::tracking::blob_reader::SyncReader;
+use crate::{Error, Result, encoding, graph};
+
+#[cfg(test)]
+#[path = "./chunking_test.rs"]
+mod chunking_test;
+
+/// The smallest payload that will ever be split into chunks,
+/// regardless of the configured threshold.
+///
+/// This allows readers of a repository to avoid looking for
+/// chunked blobs when dealing with any smaller payload.
+pub const MIN_CHUNKED_PAYLOAD_SIZE: u64 = 1024 * 1024;
+
+/// Configures how large payloads are split into chunks
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct ChunkingConfig {
+    /// Whether payloads should be chunked when syncing
+    /// them into another repository.
+    pub enabled: bool,
+    /// Payloads smaller than this size are never chunked
+    ///
+    /// This value is never allowed to be less than [`MIN_CHUNKED_PAYLOAD_SIZE`].
+    pub threshold: u64,
+    /// The smallest size of any chunk, except the last one
+    pub min_size: u32,
+    /// The target average chunk size, which must be a power of two
+    pub avg_size: u32,
+    /// The largest size of any chunk
+    pub max_size: u32,
+}
+
+impl Default for ChunkingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 8 * 1024 * 1024,
+            min_size: 256 * 1024,
+            avg_size: 1024 * 1024,
+            max_size: 4 * 1024 * 1024,
+        }
+    }
+}
+
+impl ChunkingConfig {
+    /// Check that these settings are usable for chunking
+    pub fn validate(&self) -> Result<()> {
+        if !self.avg_size.is_power_of_two() {
+            return Err(Error::String(format!(
+                "Chunking average size must be a power of two, got {}",
+                self.avg_size
+            )));
+        }
+        if self.min_size == 0 || self.min_size >= self.avg_size || self.avg_size >= self.max_size {
+            return Err(Error::String(format!(
+                "Chunking sizes must satisfy 0 < min < avg < max, got {} / {} / {}",
+                self.min_size, self.avg_size, self.max_size
+            )));
+        }
+        Ok(())
+    }
+
+    /// True if a payload of the given size should be split into chunks
+    pub fn should_chunk(&self, size: u64) -> bool {
+        size >= self.threshold.max(MIN_CHUNKED_PAYLOAD_SIZE)
+    }
+
+    /// Find the length of the first chunk in `data`.
+    ///
+    /// When `data` holds less than [`Self::max_size`] bytes, it
+    /// is assumed to be the end of the payload, and so the entire
+    /// remaining data may be returned as the final chunk.
+    pub fn cut_point(&self, data: &[u8]) -> usize {
+        let min_size = self.min_size as usize;
+        if data.len() <= min_size {
+            return data.len();
+        }
+        let end = data.len().min(self.max_size as usize);
+        let normal = end.min(self.avg_size as usize);
+        // normalized chunking: boundaries are harder to find before
+        // the average size and easier after it, which narrows the
+        // distribution of chunk sizes around the average
+        let bits = self.avg_size.trailing_zeros();
+        let mask_hard = high_bits_mask(bits + 1);
+        let mask_easy = high_bits_mask(bits.saturating_sub(1));
+
+        let mut hash = 0_u64;
+        for (i, byte) in data.iter().enumerate().take(normal).skip(min_size) {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+            if hash & mask_hard == 0 {
+                return i + 1;
+            }
+        }
+        for (i, byte) in data.iter().enumerate().take(end).skip(normal) {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+            if hash & mask_easy == 0 {
+                return i + 1;
+            }
+        }
+        end
+    }
+
+    /// Split all of the data from the given reader into chunks.
+    pub fn chunk_reader<R>(&self, mut reader: R) -> impl Stream<Item = Result<Bytes>> + Send
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let config = self.clone();
+        async_stream::try_stream! {
+            let max_size = config.max_size as usize;
+            let mut buffer = BytesMut::with_capacity(max_size * 2);
+            let mut eof = false;
+            loop {
+                while !eof && buffer.len() < max_size {
+                    let count = reader.read_buf(&mut buffer).await.map_err(|err| {
+                        Error::StorageReadError("read_buf on chunked payload", "".into(), err)
+                    })?;
+                    eof = count == 0;
+                }
+                if buffer.is_empty() {
+                    break;
+                }
+                let cut = config.cut_point(&buffer);
+                yield buffer.split_to(cut).freeze();
+            }
+        }
+    }
+}
+
+/// A mask selecting the given number of most significant bits
+const fn high_bits_mask(bits: u32) -> u64 {
+    if bits == 0 {
+        0
+    } else {
+        u64::MAX << (64 - bits)
+    }
+}
+
+/// Random values used to roll the gear hash over payload data.
+///
+/// These values determine where chunk boundaries are found, and so
+/// must never change, or previously chunked data will not be shared
+/// with newly chunked data.
+static GEAR: [u64; 256] = {
+    let mut table = [0_u64; 256];
+    // splitmix64, from a fixed seed
+    let mut state = 0x5350_4653_4348_554E_u64;
+    let mut i = 0;
+    while i < table.len() {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+type ChunkFuture = Pin<Box<dyn Future<Output = Result<Pin<Box<dyn BlobRead>>>> + Send>>;
+
+/// Presents the chunks of a [`graph::ChunkedBlob`] as one contiguous payload.
+///
+/// Each chunk is opened only once the previous one has been read
+/// to completion.
+pub struct ChunkedBlobReader {
+    state: SyncReader<ReaderState>,
+}
+
+struct ReaderState {
+    open_chunk: Box<dyn FnMut(encoding::Digest) -> ChunkFuture + Send>,
+    remaining: std::vec::IntoIter<encoding::Digest>,
+    current: Current,
+}
+
+enum Current {
+    Idle,
+    Opening(ChunkFuture),
+    Reading(Pin<Box<dyn BlobRead>>),
+}
+
+impl ChunkedBlobReader {
+    /// Read the chunks of `blob`, using `open_chunk` to
+    /// open the payload of each chunk as it's needed.
+    pub fn new<F, Fut>(blob: &graph::ChunkedBlob, mut open_chunk: F) -> Self
+    where
+        F: FnMut(encoding::Digest) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<Pin<Box<dyn BlobRead>>>> + Send + 'static,
+    {
+        Self {
+            state: SyncReader::new(ReaderState {
+                open_chunk: Box::new(move |digest| Box::pin(open_chunk(digest))),
+                remaining: blob.child_objects().into_iter(),
+                current: Current::Idle,
+            }),
+        }
+    }
+
+    /// Read the chunks of `blob` from the payloads of `repo`
+    pub fn from_repo<R>(blob: &graph::ChunkedBlob, repo: R) -> Self
+    where
+        R: crate::storage::PayloadStorage + Clone + 'static,
+    {
+        Self::new(blob, move |digest| {
+            let repo = repo.clone();
+            async move { repo.open_payload(digest).await.map(|(reader, _)| reader) }
+        })
+    }
+
+    fn state(self: Pin<&mut Self>) -> &mut ReaderState {
+        self.get_mut().state.get_mut()
+    }
+}
+
+impl AsyncRead for ChunkedBlobReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let available = match self.as_mut().poll_fill_buf(cx) {
+            Poll::Ready(Ok(available)) => available,
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        };
+        let count = available.len().min(buf.remaining());
+        buf.put_slice(&available[..count]);
+        self.consume(count);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncBufRead for ChunkedBlobReader {
+    fn poll_fill_buf(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<&[u8]>> {
+        let state = self.state();
+        loop {
+            match &mut state.current {
+                Current::Idle => match state.remaining.next() {
+                    Some(digest) => {
+                        state.current = Current::Opening((state.open_chunk)(digest));
+                    }
+                    None => return Poll::Ready(Ok(&[])),
+                },
+                Current::Opening(future) => match future.as_mut().poll(cx) {
+                    Poll::Ready(Ok(reader)) => state.current = Current::Reading(reader),
+                    Poll::Ready(Err(err)) => {
+                        state.current = Current::Idle;
+                        return Poll::Ready(Err(std::io::Error::other(err)));
+                    }
+                    Poll::Pending => return Poll::Pending,
+                },
+                Current::Reading(reader) => match reader.as_mut().poll_fill_buf(cx) {
+                    Poll::Ready(Ok([])) => state.current = Current::Idle,
+                    Poll::Ready(Ok(_)) => break,
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                    Poll::Pending => return Poll::Pending,
+                },
+            }
+        }
+        // the borrow checker cannot see that the buffer filled above could
+        // be returned from within the loop, so it is requested again. Any
+        // reader with data already buffered returns it without more io
+        match &mut state.current {
+            Current::Reading(reader) => reader.as_mut().poll_fill_buf(cx),
+            _ => unreachable!("only exits the loop while reading"),
+        }
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        if let Current::Reading(reader) = &mut self.state().current {
+            reader.as_mut().consume(amt);
+        }
+    }
+}
+
+impl BlobRead for ChunkedBlobReader {}
+
+impl std::fmt::Debug for ChunkedBlobReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChunkedBlobReader").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use futures::TryStreamExt;
+use rstest::rstest;
+use tokio::io::AsyncReadExt;
+
+use super::{ChunkedBlobReader, ChunkingConfig};
+use crate::fixtures::*;
+use crate::prelude::*;
+use crate::{encoding, graph};
+
+/// Small chunk sizes so that tests can work with small payloads
+fn small_chunking() -> ChunkingConfig {
+    ChunkingConfig {
+        enabled: true,
+        threshold: 0,
+        min_size: 256,
+        avg_size: 1024,
+        max_size: 4096,
+    }
+}
+
+/// Generate repeatable, pseudo-random data of the given length
+fn random_data(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 56) as u8
+        })
+        .collect()
+}
+
+async fn chunk_all(config: &ChunkingConfig, data: &[u8]) -> Vec<bytes::Bytes> {
+    config
+        .chunk_reader(data)
+        .try_collect()
+        .await
+        .expect("chunking in-memory data should not fail")
+}
+
+#[rstest]
+fn test_chunking_config_validate() {
+    ChunkingConfig::default()
+        .validate()
+        .expect("default config should be valid");
+    small_chunking()
+        .validate()
+        .expect("test config should be valid");
+
+    let mut config = small_chunking();
+    config.avg_size = 1000;
+    assert!(config.validate().is_err(), "avg must be a power of two");
+
+    let mut config = small_chunking();
+    config.min_size = config.avg_size;
+    assert!(config.validate().is_err(), "min must be less than avg");
+}
+
+#[rstest]
+fn test_should_chunk_never_below_minimum() {
+    let config = small_chunking();
+    assert!(!config.should_chunk(super::MIN_CHUNKED_PAYLOAD_SIZE - 1));
+    assert!(config.should_chunk(super::MIN_CHUNKED_PAYLOAD_SIZE));
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_chunk_reader_sizes() {
+    let config = small_chunking();
+    let data = random_data(64 * 1024, 1);
+    let chunks = chunk_all(&config, &data).await;
+
+    assert_eq!(chunks.concat(), data, "chunks should reassemble the data");
+    let (last, rest) = chunks.split_last().unwrap();
+    for chunk in rest {
+        assert!(chunk.len() >= config.min_size as usize);
+        assert!(chunk.len() <= config.max_size as usize);
+    }
+    assert!(!last.is_empty());
+    assert!(last.len() <= config.max_size as usize);
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_chunk_reader_is_content_defined() {
+    let config = small_chunking();
+    let original = random_data(64 * 1024, 2);
+    let mut edited = original.clone();
+    // a small insertion near the start shifts all later
+    // data, but should only change the chunks around it
+    edited.splice(1000..1000, b"some inserted data".iter().copied());
+
+    let original = chunk_all(&config, &original).await;
+    let edited = chunk_all(&config, &edited).await;
+    let shared = edited.iter().filter(|c| original.contains(c)).count();
+    assert!(
+        shared >= original.len() - 3,
+        "expected most chunks to be shared, got {shared} of {}",
+        original.len()
+    );
+}
+
+#[rstest]
+#[case::fs(tmprepo("fs"))]
+#[case::tar(tmprepo("tar"))]
+#[tokio::test]
+async fn test_chunked_blob_reader(
+    #[case]
+    #[future]
+    tmprepo: TempRepo,
+) {
+    let tmprepo = tmprepo.await;
+    let config = small_chunking();
+    let data = random_data(16 * 1024, 3);
+    let payload = encoding::Hasher::hash_reader(data.as_slice()).unwrap();
+
+    let mut builder = graph::ChunkedBlob::builder().with_payload(payload);
+    for chunk in chunk_all(&config, &data).await {
+        let digest = tmprepo
+            .commit_blob(Box::pin(std::io::Cursor::new(chunk.clone())))
+            .await
+            .unwrap();
+        builder = builder.with_chunk(digest, chunk.len() as u64);
+    }
+    let blob = builder.build();
+    assert_eq!(blob.size(), data.len() as u64);
+
+    let mut actual = Vec::new();
+    ChunkedBlobReader::from_repo(&blob, tmprepo.repo())
+        .read_to_end(&mut actual)
+        .await
+        .unwrap();
+    assert_eq!(actual, data, "reader should present chunks as one payload");
+}
@@ -8,10 +8,13 @@ use std::future::ready;
 use std::num::NonZero;
 #[cfg(unix)]
 use std::os::linux::fs::MetadataExt;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
 
 use chrono::{DateTime, Duration, Local, Utc};
 use colored::Colorize;
-use dashmap::DashSet;
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
 use futures::{FutureExt, StreamExt, TryFutureExt, TryStreamExt};
 use once_cell::sync::OnceCell;
 use progress_bar_derive_macro::ProgressBar;
@@ -20,10 +23,14 @@ use super::prune::PruneParameters;
 use crate::io::Pluralize;
 use crate::prelude::*;
 use crate::runtime::makedirs_with_perms;
-use crate::storage::fs::FsRepositoryOps;
+use crate::storage::fs::{FsRepositoryOps, Quarantine, QuarantineBatch};
 use crate::storage::{TagNamespace, TagNamespaceBuf};
 use crate::{Digest, Error, Result, encoding, graph, storage, tracking};
 
+pub mod checkpoint;
+
+use checkpoint::CleanCheckpoint;
+
 #[cfg(test)]
 #[path = "./clean_test.rs"]
 mod clean_test;
@@ -43,13 +50,20 @@ where
     tag_stream_concurrency: usize,
     removal_concurrency: usize,
     discover_concurrency: usize,
-    attached: DashSet<encoding::Digest>,
+    /// digests that were found to be attached, and whether they and
+    /// all of their children have been walked completely
+    attached: DashMap<encoding::Digest, bool>,
     dry_run: bool,
     must_be_older_than: DateTime<Utc>,
     prune_all_tag_namespaces: bool,
     prune_repeated_tags: Option<NonZero<u64>>,
     prune_params: PruneParameters,
     remove_proxies_with_no_links: bool,
+    shard_count: usize,
+    checkpoint: Option<PathBuf>,
+    quarantine: bool,
+    /// attached digests that are not yet saved to the checkpoint
+    unsaved: Mutex<Vec<encoding::Digest>>,
 }
 
 impl<'repo> Cleaner<'repo, SilentCleanReporter> {
@@ -74,6 +88,10 @@ impl<'repo> Cleaner<'repo, SilentCleanReporter> {
             prune_repeated_tags: None,
             prune_params: Default::default(),
             remove_proxies_with_no_links: true,
+            shard_count: 1,
+            checkpoint: None,
+            quarantine: false,
+            unsaved: Default::default(),
         }
     }
 }
@@ -98,6 +116,10 @@ where
             discover_concurrency: self.discover_concurrency,
             tag_stream_concurrency: self.tag_stream_concurrency,
             remove_proxies_with_no_links: self.remove_proxies_with_no_links,
+            shard_count: self.shard_count,
+            checkpoint: self.checkpoint,
+            quarantine: self.quarantine,
+            unsaved: self.unsaved,
         }
     }
 
@@ -222,6 +244,47 @@ where
         self
     }
 
+    /// Split the clean into this many passes, each of which only
+    /// removes the objects and payloads whose digests fall into
+    /// one shard.
+    ///
+    /// Every pass walks all of the tags in the repository, and still
+    /// remembers every platform, layer and manifest that it finds. The
+    /// blobs listed in a manifest are only read and remembered by the
+    /// pass for their own shard, and are skipped by all of the others.
+    pub fn with_shard_count(mut self, shard_count: NonZero<usize>) -> Self {
+        self.shard_count = shard_count.get();
+        self
+    }
+
+    /// Save the progress of the clean into the given directory,
+    /// so that it can be resumed if interrupted.
+    ///
+    /// Running the clean again with the same directory and shard
+    /// count skips any shards that were already cleaned, and any
+    /// objects that were already found to be attached. The tags
+    /// are still read again in full, so anything newly tagged
+    /// since the interruption is kept, and data that is newer
+    /// than the required age is never removed. The directory is
+    /// removed once the clean completes. Checkpoints are not
+    /// read or written for dry runs.
+    pub fn with_checkpoint<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.checkpoint = Some(path.into());
+        self
+    }
+
+    /// Move detached objects and payloads into the repository's
+    /// [`Quarantine`] instead of deleting them, and save any pruned
+    /// tags there too, so that they can be restored if the clean
+    /// removed more than was intended.
+    ///
+    /// Renders and proxies are still deleted, since they can always
+    /// be recreated. This is only supported for filesystem repositories.
+    pub fn with_quarantine(mut self, quarantine: bool) -> Self {
+        self.quarantine = quarantine;
+        self
+    }
+
     /// Provide a human-readable summary of the current
     /// configuration for this cleaner.
     ///
@@ -231,11 +294,29 @@ where
     pub fn format_plan(&self) -> String {
         let find = "FIND".cyan();
         let scan = "SCAN".cyan();
-        let remove = "REMOVE".red();
+        let remove = if self.quarantine {
+            "QUARANTINE".red()
+        } else {
+            "REMOVE".red()
+        };
         let prune = "PRUNE".yellow();
         let identify = "IDENTIFY".cyan();
 
         let mut out = format!("{}:\n", "Cleaning Plan".bold());
+        if self.shard_count > 1 {
+            let _ = writeln!(
+                &mut out,
+                "Repeat these steps for each of {} shards, only removing the objects and payloads in that shard.",
+                self.shard_count
+            );
+        }
+        if let Some(path) = self.checkpoint.as_ref().filter(|_| !self.dry_run) {
+            let _ = writeln!(
+                &mut out,
+                "Save progress to {}, resuming from any progress already there.",
+                path.display()
+            );
+        }
         let _ = writeln!(&mut out, "First, {scan} all of the tags in the repository.",);
         let _ = writeln!(
             &mut out,
@@ -317,7 +398,8 @@ where
         );
         let _ = writeln!(
             &mut out,
-            " - {remove} any render that is not connected to an object"
+            " - {} any render that is not connected to an object",
+            "REMOVE".red()
         );
         out
     }
@@ -328,6 +410,75 @@ where
     /// function returns as a success. In these cases, the clean should be considered
     /// partially complete depending on the nature of the errors.
     pub async fn prune_all_tags_and_clean(&self) -> Result<CleanResult> {
+        let checkpoint = match &self.checkpoint {
+            Some(path) if !self.dry_run => {
+                Some(CleanCheckpoint::open(path, self.shard_count).await?)
+            }
+            _ => None,
+        };
+        let quarantine = match self.repo {
+            _ if !self.quarantine || self.dry_run => None,
+            storage::RepositoryHandle::FS(repo) => {
+                Some(Quarantine::new(repo.opened().await?).start_batch())
+            }
+            _ => {
+                return Err(Error::String(format!(
+                    "Quarantine is only supported for filesystem repositories, not {}",
+                    self.repo.address()
+                )));
+            }
+        };
+        let mut result = CleanResult::default();
+        for index in 0..self.shard_count {
+            let shard = Shard {
+                index,
+                count: self.shard_count,
+            };
+            if let Some(checkpoint) = &checkpoint {
+                if checkpoint.is_shard_done(index).await? {
+                    tracing::info!(shard = index, "skipping shard that was already cleaned");
+                    continue;
+                }
+            }
+            let shard_result = self
+                .clean_shard(shard, checkpoint.as_ref(), quarantine.as_ref())
+                .await?;
+            let is_ok = shard_result.is_ok();
+            result += shard_result;
+            if !is_ok {
+                return Ok(result);
+            }
+            if let Some(checkpoint) = &checkpoint {
+                checkpoint.finish_shard(index).await?;
+            }
+        }
+        if let Some(checkpoint) = &checkpoint {
+            checkpoint.finish().await?;
+        }
+        Ok(result)
+    }
+
+    /// Visit all tags, pruning as configured and then cleaning the
+    /// detached objects and payloads in one shard of the repository
+    async fn clean_shard(
+        &self,
+        shard: Shard,
+        checkpoint: Option<&CleanCheckpoint>,
+        quarantine: Option<&QuarantineBatch>,
+    ) -> Result<CleanResult> {
+        self.attached.clear();
+        if let Some(checkpoint) = checkpoint {
+            let marks = checkpoint.read_marks(shard.index).await?;
+            tracing::debug!(
+                shard = shard.index,
+                count = marks.len(),
+                "resuming with attached digests from checkpoint"
+            );
+            for digest in marks {
+                self.attached.insert(digest, true);
+            }
+        }
+
         let mut result = CleanResult::default();
         let tag_namespace_to_prune = self.repo.get_tag_namespace();
         let namespaces = self.repo.ls_tag_namespaces();
@@ -352,21 +503,31 @@ where
                 // before adding additional futures. This is a crude way to
                 // try and maximize parallel processing while also not leaving
                 // completed futures for too long or needing to wait for the
-                // slowest ones too often
+                // slowest ones too often.
                 while futures.len() > self.tag_stream_concurrency / 2 {
-                    futures.try_next().await?;
+                    if let Some(r) = futures.try_next().await? {
+                        result += r;
+                    }
+                }
+                if let Some(checkpoint) = checkpoint {
+                    self.save_checkpoint(checkpoint, shard, &result).await?;
                 }
             }
             futures.push(self.prune_tag_stream_and_walk(
                 tag_namespace_to_prune.as_ref(),
                 tag_namespace,
                 tag_spec,
+                shard,
+                quarantine,
             ));
         }
         drop(stream);
         while let Some(r) = futures.try_next().await? {
             result += r;
         }
+        if let Some(checkpoint) = checkpoint {
+            self.save_checkpoint(checkpoint, shard, &result).await?;
+        }
 
         if !result.errors.is_empty() {
             // although we've already begun pruning some references,
@@ -384,52 +545,93 @@ where
         unsafe {
             // because we don't yet know if some detached objects will be
             // kept due to age, we cannot process these two steps in parallel
-            result += self.remove_unvisited_objects_and_payloads().await?;
-            result += self.remove_unvisited_renders_and_proxies().await?;
+            result += self
+                .remove_unvisited_objects_and_payloads(shard, quarantine)
+                .await?;
+            result += self.remove_unvisited_renders_and_proxies(shard).await?;
         }
         Ok(result)
     }
 
+    /// Save the attached digests found since the last save.
+    ///
+    /// Only digests whose children have all been found are waiting
+    /// to be saved, so this can be called while walks are in progress.
+    async fn save_checkpoint(
+        &self,
+        checkpoint: &CleanCheckpoint,
+        shard: Shard,
+        result: &CleanResult,
+    ) -> Result<()> {
+        if !result.errors.is_empty() {
+            // a failed walk may have left children undiscovered
+            return Ok(());
+        }
+        let unsaved = std::mem::take(&mut *self.lock_unsaved()?);
+        checkpoint.record_marks(shard.index, &unsaved).await
+    }
+
     async fn prune_tag_stream_and_walk<T>(
         &self,
         tag_namespace_to_prune: Option<T>,
         tag_namespace_to_visit: Option<TagNamespaceBuf>,
         tag_spec: tracking::TagSpec,
+        shard: Shard,
+        quarantine: Option<&QuarantineBatch>,
     ) -> Result<CleanResult>
     where
         T: AsRef<TagNamespace>,
     {
         let (mut result, to_keep) = self
-            .prune_tag_stream(tag_namespace_to_prune, tag_namespace_to_visit, tag_spec)
+            .prune_tag_stream(
+                tag_namespace_to_prune,
+                tag_namespace_to_visit,
+                tag_spec,
+                quarantine,
+            )
             .await?;
-        result += self.walk_attached_objects(&to_keep).await?;
+        let (walked, _) = self.walk_attached_objects(&to_keep, shard).await?;
+        result += walked;
 
         Ok(result)
     }
 
+    /// Visit each of the given digests, returning true if
+    /// they were all walked completely.
+    ///
+    /// See [`Self::visit_attached_objects`].
     #[async_recursion::async_recursion]
-    async fn walk_attached_objects(&self, digests: &[encoding::Digest]) -> Result<CleanResult> {
+    async fn walk_attached_objects(
+        &self,
+        digests: &[encoding::Digest],
+        shard: Shard,
+    ) -> Result<(CleanResult, bool)> {
         let mut result = CleanResult::default();
+        let mut complete = true;
 
         let mut walk_stream = futures::stream::iter(digests.iter())
-            .then(|digest| ready(self.visit_attached_objects(*digest).boxed()))
+            .then(|digest| ready(self.visit_attached_objects(*digest, shard).boxed()))
             .buffer_unordered(self.discover_concurrency)
             .boxed();
-        while let Some(res) = walk_stream.try_next().await? {
+        while let Some((res, res_complete)) = walk_stream.try_next().await? {
             result += res;
+            complete &= res_complete;
         }
 
-        Ok(result)
+        Ok((result, complete))
     }
 
     /// Visit the tag and its history, pruning as configured and
     /// returning a cleaning (pruning) result and list of tags that
     /// were kept.
+    ///
+    /// Pruned tags are saved into the quarantine batch, if one is given.
     pub async fn prune_tag_stream<T>(
         &self,
         tag_namespace_to_prune: Option<T>,
         tag_namespace_to_visit: Option<TagNamespaceBuf>,
         tag_spec: tracking::TagSpec,
+        quarantine: Option<&QuarantineBatch>,
     ) -> Result<(CleanResult, Vec<encoding::Digest>)>
     where
         T: AsRef<TagNamespace>,
@@ -488,6 +690,11 @@ where
 
         for tag in to_prune.iter() {
             if !self.dry_run {
+                if let Some(quarantine) = quarantine {
+                    quarantine
+                        .quarantine_tag(tag_namespace_to_visit.as_deref(), tag)
+                        .await?;
+                }
                 self.repo
                     .remove_tag_in_namespace(tag_namespace_to_visit.as_deref(), tag)
                     .await?;
@@ -504,51 +711,163 @@ where
         Ok((result, to_keep))
     }
 
+    /// Mark the digest and all of its children as attached.
+    ///
+    /// Returns false if the digest was not walked completely, because
+    /// some child could not be read or is still being walked by another
+    /// visit. Only completely walked digests are saved to the checkpoint,
+    /// so that none of their children are missed when a clean is resumed.
     #[async_recursion::async_recursion]
-    async fn visit_attached_objects(&self, digest: encoding::Digest) -> Result<CleanResult> {
+    async fn visit_attached_objects(
+        &self,
+        digest: encoding::Digest,
+        shard: Shard,
+    ) -> Result<(CleanResult, bool)> {
         let mut result = CleanResult::default();
-        if !self.attached.insert(digest) {
-            return Ok(result);
+        match self.attached.entry(digest) {
+            Entry::Occupied(entry) => return Ok((result, *entry.get())),
+            Entry::Vacant(entry) => {
+                entry.insert(false);
+            }
         }
 
         let obj = match self.repo.read_object(digest).await {
             Ok(obj) => obj,
             Err(Error::UnknownObject(_)) => {
+                // blobs are not always stored as objects, so this
+                // may be the digest of a payload.
                 // TODO: it would be nice to have an option to prune
                 // broken tags that cause this error
-                return Ok(result);
+                self.finish_attached(digest)?;
+                return Ok((result, true));
             }
             Err(err) => {
                 self.reporter.error_encountered(&err);
                 result.errors.push(err);
-                return Ok(result);
+                return Ok((result, false));
             }
         };
         self.reporter.visit_object(&obj);
         result.visited_objects += 1;
-        if let graph::object::Enum::Blob(b) = obj.to_enum() {
-            result.visited_payloads += 1;
-            self.reporter.visit_payload(&b);
-        }
+        let is_manifest = match obj.to_enum() {
+            graph::object::Enum::Blob(b) => {
+                result.visited_payloads += 1;
+                self.reporter.visit_payload(&b);
+                false
+            }
+            graph::object::Enum::Manifest(_) => true,
+            _ => false,
+        };
+
+        // the blobs of a manifest that belong to other shards are
+        // never removed by this pass, so they are neither read nor
+        // remembered, which keeps the attached set from growing with
+        // the total number of payloads in the repository
+        let (children, other_shards): (Vec<_>, Vec<_>) = obj
+            .child_objects()
+            .into_iter()
+            .partition(|child| !is_manifest || shard.contains(child));
 
         // This recursively calls visit_attached_objects() (this
         // method) on any child objects.
-        result += self.walk_attached_objects(&obj.child_objects()).await?;
+        let (walked, mut complete) = self.walk_attached_objects(&children, shard).await?;
+        result += walked;
+        let (walked, other_complete) = self.walk_other_shard_blobs(&other_shards, shard).await?;
+        result += walked;
+        complete &= other_complete;
+        if complete {
+            self.finish_attached(digest)?;
+        }
 
-        Ok(result)
+        Ok((result, complete))
+    }
+
+    /// Visit the blobs of a manifest that are not in the given shard.
+    ///
+    /// Most blobs are only stored as a payload, and can be skipped
+    /// without being read. Any that are also stored as an object are
+    /// read so that their children are still walked, since those
+    /// may belong to the current shard, but none of them are added
+    /// to the attached set.
+    #[async_recursion::async_recursion]
+    async fn walk_other_shard_blobs(
+        &self,
+        digests: &[encoding::Digest],
+        shard: Shard,
+    ) -> Result<(CleanResult, bool)> {
+        let mut result = CleanResult::default();
+        let mut complete = true;
+
+        let mut walk_stream = futures::stream::iter(digests.iter())
+            .then(|digest| ready(self.visit_other_shard_blob(*digest, shard).boxed()))
+            .buffer_unordered(self.discover_concurrency)
+            .boxed();
+        while let Some((res, res_complete)) = walk_stream.try_next().await? {
+            result += res;
+            complete &= res_complete;
+        }
+
+        Ok((result, complete))
+    }
+
+    async fn visit_other_shard_blob(
+        &self,
+        digest: encoding::Digest,
+        shard: Shard,
+    ) -> Result<(CleanResult, bool)> {
+        let mut result = CleanResult::default();
+        if !self.repo.has_object(digest).await {
+            return Ok((result, true));
+        }
+        let obj = match self.repo.read_object(digest).await {
+            Ok(obj) => obj,
+            Err(Error::UnknownObject(_)) => return Ok((result, true)),
+            Err(err) => {
+                self.reporter.error_encountered(&err);
+                result.errors.push(err);
+                return Ok((result, false));
+            }
+        };
+        let (walked, complete) = self
+            .walk_attached_objects(&obj.child_objects(), shard)
+            .await?;
+        result += walked;
+        Ok((result, complete))
+    }
+
+    /// Record that an attached digest has been walked completely.
+    fn finish_attached(&self, digest: encoding::Digest) -> Result<()> {
+        self.attached.insert(digest, true);
+        if self.checkpoint.is_some() && !self.dry_run {
+            self.lock_unsaved()?.push(digest);
+        }
+        Ok(())
+    }
+
+    fn lock_unsaved(&self) -> Result<MutexGuard<'_, Vec<encoding::Digest>>> {
+        self.unsaved.lock().map_err(|_| {
+            Error::String("Clean checkpoint state was poisoned by a previous panic".into())
+        })
     }
 
     /// # Safety
     /// This function should only be called once the discovery of all attached
     /// objects has completed successfully and with no errors. Otherwise, it may
     /// remove data that is still being used
-    async unsafe fn remove_unvisited_objects_and_payloads(&self) -> Result<CleanResult> {
+    async unsafe fn remove_unvisited_objects_and_payloads(
+        &self,
+        shard: Shard,
+        quarantine: Option<&QuarantineBatch>,
+    ) -> Result<CleanResult> {
         let mut result = CleanResult::default();
         let mut stream = self
             .repo
             .iter_objects()
             // we have no interest in removing attached items
-            .try_filter(|(digest, _object)| ready(!self.attached.contains(digest)))
+            // or anything that is handled by another shard
+            .try_filter(|(digest, _object)| {
+                ready(shard.contains(digest) && !self.attached.contains_key(digest))
+            })
             // we have already visited all attached objects
             // but also want to report these ones
             .and_then(|obj| {
@@ -561,8 +880,7 @@ where
                     return ready(Ok(ready(Ok((digest, object, true))).boxed()));
                 }
                 let future = self
-                    .repo
-                    .remove_object_if_older_than(self.must_be_older_than, digest)
+                    .remove_object_if_older_than(digest, quarantine)
                     .map(|res| {
                         if let Err(Error::UnknownObject(_)) = res {
                             return Ok(true);
@@ -577,7 +895,7 @@ where
                 if !removed {
                     // objects that are too new to be removed become
                     // implicitly attached
-                    self.attached.insert(digest);
+                    self.attached.insert(digest, true);
                 }
                 ready(Ok(removed.then_some(obj)))
             })
@@ -598,8 +916,7 @@ where
                 self.reporter.visit_payload(&blob);
                 result.visited_payloads += 1;
                 let future = self
-                    .repo
-                    .remove_payload(*blob.payload())
+                    .remove_payload(*blob.payload(), quarantine)
                     .map(|res| {
                         if let Err(Error::UnknownObject(_)) = res {
                             return Ok(());
@@ -622,7 +939,7 @@ where
             .repo
             .iter_payload_digests()
             .try_filter_map(|payload| {
-                if self.attached.contains(&payload) {
+                if !shard.contains(&payload) || self.attached.contains_key(&payload) {
                     return ready(Ok(None));
                 }
                 // TODO: this should be able to get the size of the payload, but
@@ -638,8 +955,7 @@ where
                     return ready(Ok(ready(Ok(blob)).boxed()));
                 }
                 let future = self
-                    .repo
-                    .remove_payload(*blob.payload())
+                    .remove_payload(*blob.payload(), quarantine)
                     .map(|res| {
                         if let Err(Error::UnknownObject(_)) = res {
                             return Ok(());
@@ -660,11 +976,47 @@ where
         Ok(result)
     }
 
+    /// Remove an object that is older than the required age,
+    /// or move it into the quarantine batch if one is given
+    async fn remove_object_if_older_than(
+        &self,
+        digest: encoding::Digest,
+        quarantine: Option<&QuarantineBatch>,
+    ) -> Result<bool> {
+        match quarantine {
+            Some(batch) => {
+                batch
+                    .quarantine_object_if_older_than(self.must_be_older_than, digest)
+                    .await
+            }
+            None => {
+                self.repo
+                    .remove_object_if_older_than(self.must_be_older_than, digest)
+                    .await
+            }
+        }
+    }
+
+    /// Remove a payload, or move it into the quarantine batch if one is given
+    async fn remove_payload(
+        &self,
+        digest: encoding::Digest,
+        quarantine: Option<&QuarantineBatch>,
+    ) -> Result<()> {
+        match quarantine {
+            Some(batch) => batch.quarantine_payload(digest).await,
+            None => self.repo.remove_payload(digest).await,
+        }
+    }
+
     /// # Safety
     /// This function should only be called once the discovery of all attached
     /// objects has completed successfully and with no errors. Otherwise, it may
     /// remove data that is still being used
-    async unsafe fn remove_unvisited_renders_and_proxies(&self) -> Result<CleanResult> {
+    async unsafe fn remove_unvisited_renders_and_proxies(
+        &self,
+        shard: Shard,
+    ) -> Result<CleanResult> {
         let mut result = CleanResult::default();
         let storage::RepositoryHandle::FS(repo) = self.repo else {
             return Ok(result);
@@ -672,7 +1024,7 @@ where
         let repo = repo.opened().await?;
 
         result += match self
-            .remove_unvisited_renders_and_proxies_for_storage(None, &repo)
+            .remove_unvisited_renders_and_proxies_for_storage(None, &repo, shard)
             .await
         {
             // If the repository is not setup for render storage, then we
@@ -696,7 +1048,11 @@ where
             }
 
             result += self
-                .remove_unvisited_renders_and_proxies_for_storage(Some(username.clone()), sub_repo)
+                .remove_unvisited_renders_and_proxies_for_storage(
+                    Some(username.clone()),
+                    sub_repo,
+                    shard,
+                )
                 .await?;
         }
         Ok(result)
@@ -706,14 +1062,16 @@ where
         &self,
         username: Option<String>,
         repo: impl FsRepositoryOps,
+        shard: Shard,
     ) -> Result<CleanResult> {
         let mut result = CleanResult::default();
         let mut stream = repo
             .iter_rendered_manifests()
+            .try_filter(|digest| ready(shard.contains(digest)))
             .try_filter_map(|digest| {
                 self.reporter.visit_render(&digest);
                 result.visited_renders += 1;
-                if self.attached.contains(&digest) {
+                if self.attached.contains_key(&digest) {
                     return ready(Ok(None));
                 }
                 ready(Ok(Some(digest)))
@@ -744,7 +1102,9 @@ where
         drop(stream);
 
         if let Some(proxy_path) = repo.proxy_path() {
-            result += self.clean_proxies(username, proxy_path.to_owned()).await?;
+            result += self
+                .clean_proxies(username, proxy_path.to_owned(), shard)
+                .await?;
         }
         Ok(result)
     }
@@ -755,6 +1115,7 @@ where
         &self,
         username: Option<String>,
         proxy_path: std::path::PathBuf,
+        shard: Shard,
     ) -> Result<CleanResult> {
         let mut result = CleanResult::default();
         let removed = result.removed_proxies.entry(username).or_default();
@@ -763,12 +1124,13 @@ where
         let proxy_storage = storage::fs::FsHashStore::open_unchecked(proxy_path);
         let mut stream = proxy_storage
             .iter()
+            .try_filter(|digest| ready(shard.contains(digest)))
             .try_filter_map(|digest| {
                 self.reporter.visit_proxy(&digest);
                 result.visited_proxies += 1;
                 let path = proxy_storage.build_digest_path(&digest);
                 async move {
-                    if !self.attached.contains(&digest) {
+                    if !self.attached.contains_key(&digest) {
                         // a detached object is always cleaned
                         return Ok(Some(digest));
                     }
@@ -840,6 +1202,30 @@ where
     }
 }
 
+/// The part of the repository being cleaned by one pass of a
+/// sharded clean, see [`Cleaner::with_shard_count`]
+#[derive(Debug, Clone, Copy)]
+struct Shard {
+    index: usize,
+    count: usize,
+}
+
+impl Shard {
+    /// The only shard of a clean that is not split up
+    #[cfg(test)]
+    const ALL: Self = Self { index: 0, count: 1 };
+
+    fn contains(&self, digest: &encoding::Digest) -> bool {
+        if self.count <= 1 {
+            return true;
+        }
+        let prefix = digest.as_bytes()[..4]
+            .iter()
+            .fold(0usize, |n, b| (n << 8) | *b as usize);
+        prefix % self.count == self.index
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct CleanResult {
     /// The number of tags visited when walking the database
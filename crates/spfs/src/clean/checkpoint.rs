@@ -0,0 +1,171 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::path::{Path, PathBuf};
+
+use tokio::io::AsyncWriteExt;
+
+use crate::{Error, Result, encoding};
+
+#[cfg(test)]
+#[path = "./checkpoint_test.rs"]
+mod checkpoint_test;
+
+const SHARDS_FILE: &str = "shards";
+
+/// Records the progress of a sharded clean, so that an
+/// interrupted clean can be resumed without walking all
+/// of the tags in the repository from scratch.
+///
+/// The checkpoint is a directory holding, for each shard, a file
+/// of attached digests, one per line, that is only ever appended
+/// to. Digests are only recorded once they and all of their
+/// children have been found, and a shard is marked as done once
+/// all of its detached data has been removed.
+#[derive(Debug)]
+pub struct CleanCheckpoint {
+    root: PathBuf,
+    shard_count: usize,
+}
+
+impl CleanCheckpoint {
+    /// Open the checkpoint in the given directory, loading any
+    /// progress recorded there by a previous clean.
+    ///
+    /// A checkpoint can only be resumed with the same number of
+    /// shards that it was created with.
+    pub async fn open<P: Into<PathBuf>>(root: P, shard_count: usize) -> Result<Self> {
+        let root = root.into();
+        tokio::fs::create_dir_all(&root).await.map_err(|err| {
+            Error::StorageWriteError("create_dir_all on clean checkpoint", root.clone(), err)
+        })?;
+        let shards_file = root.join(SHARDS_FILE);
+        match tokio::fs::read_to_string(&shards_file).await {
+            Ok(existing) => {
+                if existing.trim().parse::<usize>().ok() != Some(shard_count) {
+                    return Err(Error::String(format!(
+                        "Clean checkpoint in {} was created with {} shards, cannot resume with {shard_count}",
+                        root.display(),
+                        existing.trim(),
+                    )));
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                tokio::fs::write(&shards_file, shard_count.to_string())
+                    .await
+                    .map_err(|err| {
+                        Error::StorageWriteError("write on clean checkpoint", shards_file, err)
+                    })?;
+            }
+            Err(err) => {
+                return Err(Error::StorageReadError(
+                    "read_to_string on clean checkpoint",
+                    shards_file,
+                    err,
+                ));
+            }
+        }
+        Ok(Self { root, shard_count })
+    }
+
+    /// The location of this checkpoint on disk
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    /// The number of shards that the clean is split into
+    pub fn shard_count(&self) -> usize {
+        self.shard_count
+    }
+
+    fn marks_path(&self, shard: usize) -> PathBuf {
+        self.root.join(format!("shard-{shard}.marks"))
+    }
+
+    fn done_path(&self, shard: usize) -> PathBuf {
+        self.root.join(format!("shard-{shard}.done"))
+    }
+
+    /// True if a previous clean has completely finished the given shard
+    pub async fn is_shard_done(&self, shard: usize) -> Result<bool> {
+        let path = self.done_path(shard);
+        tokio::fs::try_exists(&path)
+            .await
+            .map_err(|err| Error::StorageReadError("try_exists on clean checkpoint", path, err))
+    }
+
+    /// Load the attached digests recorded for the given shard
+    pub async fn read_marks(&self, shard: usize) -> Result<Vec<encoding::Digest>> {
+        let path = self.marks_path(shard);
+        match tokio::fs::read_to_string(&path).await {
+            // the last line may be incomplete if the previous
+            // clean was interrupted while it was being written
+            Ok(existing) => Ok(existing.lines().filter_map(|l| l.parse().ok()).collect()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(Error::StorageReadError(
+                "read_to_string on clean checkpoint",
+                path,
+                err,
+            )),
+        }
+    }
+
+    /// Record that the given digests are attached, for the given shard
+    pub async fn record_marks(&self, shard: usize, digests: &[encoding::Digest]) -> Result<()> {
+        if digests.is_empty() {
+            return Ok(());
+        }
+        let path = self.marks_path(shard);
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(|err| {
+                Error::StorageWriteError("open on clean checkpoint", path.clone(), err)
+            })?;
+        // start on a fresh line, in case the last one was incomplete
+        let mut lines = String::from("\n");
+        for digest in digests {
+            lines.push_str(&digest.to_string());
+            lines.push('\n');
+        }
+        file.write_all(lines.as_bytes())
+            .await
+            .and(file.flush().await)
+            .map_err(|err| Error::StorageWriteError("write on clean checkpoint", path, err))
+    }
+
+    /// Record that the given shard has been completely cleaned
+    pub async fn finish_shard(&self, shard: usize) -> Result<()> {
+        let path = self.done_path(shard);
+        tokio::fs::write(&path, b"")
+            .await
+            .map_err(|err| Error::StorageWriteError("write on clean checkpoint", path, err))?;
+        // the marks are no longer needed once the shard is done
+        let path = self.marks_path(shard);
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(Error::StorageWriteError(
+                "remove_file on clean checkpoint",
+                path,
+                err,
+            )),
+        }
+    }
+
+    /// Remove this checkpoint, once the clean that it tracks has succeeded
+    pub async fn finish(&self) -> Result<()> {
+        match tokio::fs::remove_dir_all(&self.root).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(Error::StorageWriteError(
+                "remove_dir_all on clean checkpoint",
+                self.root.clone(),
+                err,
+            )),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use rstest::rstest;
+
+use super::CleanCheckpoint;
+use crate::fixtures::*;
+
+#[rstest]
+#[tokio::test]
+async fn test_checkpoint_resume(tmpdir: tempfile::TempDir) {
+    let path = tmpdir.path().join("nested/checkpoint");
+    let first = random_digest();
+    let second = random_digest();
+
+    let checkpoint = CleanCheckpoint::open(&path, 2).await.unwrap();
+    assert!(checkpoint.read_marks(0).await.unwrap().is_empty());
+    checkpoint.record_marks(0, &[first]).await.unwrap();
+    checkpoint.record_marks(1, &[second]).await.unwrap();
+    checkpoint.finish_shard(1).await.unwrap();
+    drop(checkpoint);
+
+    let checkpoint = CleanCheckpoint::open(&path, 2).await.unwrap();
+    assert_eq!(checkpoint.read_marks(0).await.unwrap(), vec![first]);
+    assert!(!checkpoint.is_shard_done(0).await.unwrap());
+    assert!(checkpoint.is_shard_done(1).await.unwrap());
+    assert!(
+        checkpoint.read_marks(1).await.unwrap().is_empty(),
+        "marks should be discarded once a shard is done"
+    );
+
+    checkpoint.finish().await.unwrap();
+    assert!(!path.exists());
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_checkpoint_shard_count_mismatch(tmpdir: tempfile::TempDir) {
+    let path = tmpdir.path().join("checkpoint");
+    CleanCheckpoint::open(&path, 4).await.unwrap();
+    CleanCheckpoint::open(&path, 2)
+        .await
+        .expect_err("should not resume with a different number of shards");
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_checkpoint_ignores_incomplete_lines(tmpdir: tempfile::TempDir) {
+    let path = tmpdir.path().join("checkpoint");
+    let digest = random_digest();
+    let checkpoint = CleanCheckpoint::open(&path, 1).await.unwrap();
+    let partial = &digest.to_string()[..10];
+    std::fs::write(path.join("shard-0.marks"), format!("{digest}\n{partial}")).unwrap();
+
+    let other = random_digest();
+    checkpoint.record_marks(0, &[other]).await.unwrap();
+    assert_eq!(checkpoint.read_marks(0).await.unwrap(), vec![digest, other]);
+}
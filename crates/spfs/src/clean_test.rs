@@ -10,7 +10,7 @@ use rstest::rstest;
 use storage::prelude::*;
 use tokio::time::sleep;
 
-use super::{Cleaner, TracingCleanReporter};
+use super::{Cleaner, Shard, TracingCleanReporter};
 use crate::encoding::prelude::*;
 use crate::fixtures::*;
 use crate::{Error, storage, tracking};
@@ -24,7 +24,7 @@ async fn test_attached_objects(#[future] tmprepo: TempRepo) {
 
     let cleaner = Cleaner::new(&tmprepo).with_reporter(TracingCleanReporter);
     cleaner
-        .visit_attached_objects(manifest.digest().unwrap())
+        .visit_attached_objects(manifest.digest().unwrap(), Shard::ALL)
         .await
         .unwrap();
 
@@ -76,7 +76,7 @@ async fn test_get_attached_unattached_objects_blob(
     println!("{result:#?}");
 
     assert!(
-        cleaner.attached.contains(&blob_digest),
+        cleaner.attached.contains_key(&blob_digest),
         "blob in manifest in tag should be attached"
     );
 }
@@ -505,6 +505,243 @@ async fn test_clean_manifest_renders(tmpdir: tempfile::TempDir) {
     );
 }
 
+#[rstest]
+#[tokio::test]
+async fn test_clean_in_shards_with_checkpoint(
+    #[future] tmprepo: TempRepo,
+    tmpdir: tempfile::TempDir,
+) {
+    init_logging();
+    let tmprepo = tmprepo.await;
+
+    let untagged_dir = tmpdir.path().join("untagged");
+    for i in 0..8 {
+        ensure(
+            untagged_dir.join(format!("file{i}")),
+            &format!("untagged {i}"),
+        );
+    }
+    let untagged = crate::Committer::new(&tmprepo)
+        .commit_dir(untagged_dir.as_path())
+        .await
+        .unwrap();
+
+    let tagged_dir = tmpdir.path().join("tagged");
+    for i in 0..8 {
+        ensure(tagged_dir.join(format!("file{i}")), &format!("tagged {i}"));
+    }
+    let tagged = crate::Committer::new(&tmprepo)
+        .commit_dir(tagged_dir.as_path())
+        .await
+        .unwrap();
+    let layer = tmprepo.create_layer_from_manifest(&tagged).await.unwrap();
+    tmprepo
+        .push_tag(
+            &tracking::TagSpec::parse("tagged").unwrap(),
+            &layer.digest().unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let checkpoint = tmpdir.path().join("checkpoint");
+    let cleaner = Cleaner::new(&tmprepo)
+        .with_reporter(TracingCleanReporter)
+        .with_shard_count(std::num::NonZero::new(3).unwrap())
+        .with_checkpoint(&checkpoint);
+    let result = cleaner
+        .prune_all_tags_and_clean()
+        .await
+        .expect("failed to clean objects");
+    println!("{result:#?}");
+    assert!(result.is_ok());
+
+    for node in untagged.walk() {
+        if !node.entry.kind.is_blob() {
+            continue;
+        }
+        assert!(
+            !tmprepo.has_payload(node.entry.object).await,
+            "untagged payload should be removed by its shard"
+        );
+    }
+    for node in tagged.walk() {
+        if !node.entry.kind.is_blob() {
+            continue;
+        }
+        assert!(
+            tmprepo.has_payload(node.entry.object).await,
+            "tagged payload should be kept by every shard"
+        );
+    }
+    assert!(
+        !checkpoint.exists(),
+        "checkpoint should be removed after a successful clean"
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_visit_shard_skips_other_payloads(
+    #[future] tmprepo: TempRepo,
+    tmpdir: tempfile::TempDir,
+) {
+    init_logging();
+    let tmprepo = tmprepo.await;
+
+    let manifest = generate_tree(&tmprepo).await.to_graph_manifest();
+    let manifest_digest = manifest.digest().unwrap();
+    let shard = Shard { index: 0, count: 2 };
+
+    let cleaner = Cleaner::new(&tmprepo)
+        .with_reporter(TracingCleanReporter)
+        .with_checkpoint(tmpdir.path().join("checkpoint"));
+    let (_, complete) = cleaner
+        .visit_attached_objects(manifest_digest, shard)
+        .await
+        .unwrap();
+    assert!(complete, "manifest should be walked completely");
+
+    let unsaved = cleaner.unsaved.lock().unwrap().clone();
+    assert_eq!(
+        unsaved.last(),
+        Some(&manifest_digest),
+        "manifest should be saved after all of its children"
+    );
+    for digest in manifest.child_objects() {
+        if shard.contains(&digest) {
+            assert_eq!(cleaner.attached.get(&digest).map(|e| *e), Some(true));
+            assert!(unsaved.contains(&digest));
+        } else {
+            assert!(
+                !cleaner.attached.contains_key(&digest),
+                "payloads from other shards should not be remembered"
+            );
+            assert!(!unsaved.contains(&digest));
+        }
+    }
+
+    cleaner.attached.remove(&manifest_digest);
+    let (result, _) = cleaner
+        .visit_attached_objects(manifest_digest, shard)
+        .await
+        .unwrap();
+    assert_eq!(
+        result.visited_objects, 1,
+        "only the manifest should be read again, not any of its payloads"
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_clean_into_quarantine(#[future] tmprepo: TempRepo, tmpdir: tempfile::TempDir) {
+    init_logging();
+    let tmprepo = tmprepo.await;
+
+    ensure(tmpdir.path().join("dir/file.txt"), "quarantined");
+    let manifest = crate::Committer::new(&tmprepo)
+        .commit_dir(tmpdir.path())
+        .await
+        .unwrap();
+    let manifest_digest = manifest.to_graph_manifest().digest().unwrap();
+    let payload = manifest.get_path("dir/file.txt").unwrap().object;
+
+    let cleaner = Cleaner::new(&tmprepo)
+        .with_reporter(TracingCleanReporter)
+        .with_quarantine(true);
+    let result = cleaner
+        .prune_all_tags_and_clean()
+        .await
+        .expect("failed to clean objects");
+    println!("{result:#?}");
+    assert!(result.removed_payloads.contains(&payload));
+    assert!(!tmprepo.has_object(manifest_digest).await);
+    assert!(!tmprepo.has_payload(payload).await);
+
+    let checker = crate::Checker::new(&tmprepo);
+    let res = checker.check_digest(manifest_digest).await.unwrap();
+    assert!(
+        !res.summary().missing_objects.is_empty(),
+        "should not repair from quarantine unless enabled"
+    );
+
+    let checker = crate::Checker::new(&tmprepo).with_repair_from_quarantine(true);
+    let res = checker.check_digest(manifest_digest).await.unwrap();
+    let summary = res.summary();
+    assert!(summary.missing_objects.is_empty());
+    assert!(summary.missing_payloads.is_empty());
+    assert!(tmprepo.has_object(manifest_digest).await);
+    assert!(tmprepo.has_payload(payload).await);
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_clean_restore_and_clean_again(
+    #[future] tmprepo: TempRepo,
+    tmpdir: tempfile::TempDir,
+) {
+    init_logging();
+    let tmprepo = tmprepo.await;
+
+    ensure(tmpdir.path().join("file.txt"), "restored");
+    let manifest = crate::Committer::new(&tmprepo)
+        .commit_dir(tmpdir.path())
+        .await
+        .unwrap();
+    let layer = tmprepo
+        .create_layer(&manifest.to_graph_manifest())
+        .await
+        .unwrap();
+    let layer_digest = layer.digest().unwrap();
+    let payload = manifest.get_path("file.txt").unwrap().object;
+    let tag = tracking::TagSpec::parse("quarantined").unwrap();
+    tmprepo.push_tag(&tag, &layer_digest).await.unwrap();
+
+    let cleaner = Cleaner::new(&tmprepo)
+        .with_reporter(TracingCleanReporter)
+        .with_prune_tags_older_than(Some(Utc::now()))
+        .with_quarantine(true);
+    let result = cleaner.prune_all_tags_and_clean().await.unwrap();
+    println!("{result:#?}");
+    assert!(tmprepo.resolve_tag(&tag).await.is_err());
+    assert!(!tmprepo.has_object(layer_digest).await);
+    assert!(!tmprepo.has_payload(payload).await);
+
+    // anything older than this would be removed by the next clean
+    let cutoff = Utc::now();
+    sleep(Duration::from_millis(10)).await;
+
+    let storage::RepositoryHandle::FS(fs_repo) = &*tmprepo else {
+        panic!("Unexpected tmprepo type!");
+    };
+    let quarantine = storage::fs::Quarantine::new(fs_repo.opened().await.unwrap());
+    let restored = quarantine
+        .restore_since(chrono::DateTime::UNIX_EPOCH)
+        .await
+        .unwrap();
+    assert_eq!(restored.tags.len(), 1, "pruned tag should be restored");
+    let restored_tag = tmprepo.resolve_tag(&tag).await.unwrap();
+    assert_eq!(restored_tag.target, layer_digest);
+    assert!(tmprepo.has_object(layer_digest).await);
+    assert!(tmprepo.has_payload(payload).await);
+
+    // detach the restored data again, which should still be kept
+    // because it was restored after the cutoff
+    tmprepo.remove_tag_stream(&tag).await.unwrap();
+    let cleaner = Cleaner::new(&tmprepo)
+        .with_reporter(TracingCleanReporter)
+        .with_required_age_cutoff(cutoff);
+    let result = cleaner.prune_all_tags_and_clean().await.unwrap();
+    println!("{result:#?}");
+    assert!(
+        tmprepo.has_object(layer_digest).await,
+        "restored objects should not be older than the cutoff"
+    );
+    assert!(
+        tmprepo.has_payload(payload).await,
+        "restored payloads should not be older than the cutoff"
+    );
+}
+
 fn list_files<P: AsRef<std::path::Path>>(dirname: P) -> Vec<String> {
     let mut all_files = Vec::new();
 
@@ -131,6 +131,15 @@ where
         self
     }
 
+    /// Set whether extended attributes should be captured
+    /// along with each file and directory.
+    ///
+    /// Defaults to false.
+    pub fn with_capture_xattrs(mut self, capture_xattrs: bool) -> Self {
+        self.builder = self.builder.with_capture_xattrs(capture_xattrs);
+        self
+    }
+
     /// Use the given [`BlobHasher`] when building the manifest.
     ///
     /// See [`InMemoryBlobHasher`] and [`WriteToRepositoryBlobHasher`] for
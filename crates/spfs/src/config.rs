@@ -67,6 +67,11 @@ const fn default_fuse_heartbeat_grace_period_seconds() -> NonZeroU64 {
     unsafe { NonZeroU64::new_unchecked(300) }
 }
 
+const fn default_fuse_prefetch_concurrency() -> NonZeroUsize {
+    // Safety: this is a hard-coded non-zero value
+    unsafe { NonZeroUsize::new_unchecked(8) }
+}
+
 static CONFIG: OnceCell<RwLock<Arc<Config>>> = OnceCell::new();
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -74,6 +79,21 @@ static CONFIG: OnceCell<RwLock<Arc<Config>>> = OnceCell::new();
 pub struct User {
     pub name: String,
     pub domain: Option<String>,
+    /// A PKCS#8 ed25519 private key used to sign new tags
+    pub signing_key: Option<PathBuf>,
+}
+
+impl User {
+    /// Load the configured tag signing key, if any
+    ///
+    /// This is loaded once when opening the local repository,
+    /// which then signs every tag pushed to it.
+    pub fn load_signing_key(&self) -> Result<Option<tracking::TagSigningKey>> {
+        self.signing_key
+            .as_ref()
+            .map(tracking::TagSigningKey::load)
+            .transpose()
+    }
 }
 
 impl Default for User {
@@ -81,6 +101,7 @@ impl Default for User {
         Self {
             name: whoami::username(),
             domain: whoami::fallible::hostname().ok(),
+            signing_key: None,
         }
     }
 }
@@ -131,9 +152,23 @@ pub struct Storage {
     /// All available formats are still supported for reading.
     #[serde(default)]
     pub encoding_format: graph::object::EncodingFormat,
+    /// Settings for splitting large payloads into
+    /// content-defined chunks when syncing them.
+    pub chunking: crate::chunking::ChunkingConfig,
+    /// If true, tags that are not signed by one of the
+    /// trusted keys cannot be resolved from the local repository.
+    pub require_signed_tags: bool,
+    /// The base64 encoded ed25519 public keys that are
+    /// trusted to sign tags in the local repository.
+    pub trusted_tag_keys: Vec<String>,
 }
 
 impl Storage {
+    /// The signature policy for tags in the local repository
+    pub fn tag_signature_policy(&self) -> Result<tracking::TagSignaturePolicy> {
+        tracking::TagSignaturePolicy::new(self.require_signed_tags, &self.trusted_tag_keys)
+    }
+
     /// Get the repository address for the root storage
     pub fn address(&self) -> url::Url {
         url::Url::from_directory_path(&self.root)
@@ -153,12 +188,16 @@ impl Default for Storage {
             tag_namespace: None,
             digest_strategy: graph::object::DigestStrategy::default(),
             encoding_format: graph::object::EncodingFormat::default(),
+            chunking: Default::default(),
+            require_signed_tags: false,
+            trusted_tag_keys: Vec::new(),
         }
     }
 }
 
 #[derive(Clone, Debug, Serialize)]
 #[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
 pub enum Remote {
     Address(RemoteAddress),
     Config(RemoteConfig),
@@ -206,6 +245,11 @@ pub struct RemoteConfig {
     #[builder(setter(strip_option), default)]
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub tag_namespace: Option<TagNamespaceBuf>,
+    /// Limits the rate at which payload data is synced
+    /// to or from this remote
+    #[builder(setter(strip_option), default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes_per_second: Option<NonZeroU64>,
     #[serde(flatten)]
     pub inner: RepositoryConfig,
 }
@@ -215,6 +259,7 @@ impl ToAddress for RemoteConfig {
         let Self {
             when,
             tag_namespace,
+            max_bytes_per_second,
             inner,
         } = self;
         let mut inner = inner.to_address()?;
@@ -232,6 +277,13 @@ impl ToAddress for RemoteConfig {
                 Some(q) => inner.set_query(Some(&format!("{q}&{query}"))),
             }
         }
+        if let Some(max_bytes_per_second) = max_bytes_per_second {
+            let query = format!("max_bytes_per_second={max_bytes_per_second}");
+            match inner.query() {
+                None | Some("") => inner.set_query(Some(&query)),
+                Some(q) => inner.set_query(Some(&format!("{q}&{query}"))),
+            }
+        }
         Ok(inner)
     }
 }
@@ -241,6 +293,7 @@ impl ToAddress for RemoteConfig {
 pub enum RepositoryConfig {
     Fs(storage::fs::Config),
     Grpc(storage::rpc::Config),
+    S3(storage::s3::Config),
     Tar(storage::tar::Config),
     Proxy(storage::proxy::Config),
 }
@@ -250,6 +303,7 @@ impl ToAddress for RepositoryConfig {
         match self {
             Self::Fs(c) => c.to_address(),
             Self::Grpc(c) => c.to_address(),
+            Self::S3(c) => c.to_address(),
             Self::Tar(c) => c.to_address(),
             Self::Proxy(c) => c.to_address(),
         }
@@ -268,6 +322,11 @@ impl RemoteConfig {
                 "tag_namespace" => {
                     builder.tag_namespace(TagNamespaceBuf::new(RelativePath::new(&v))?);
                 }
+                "max_bytes_per_second" => {
+                    builder.max_bytes_per_second(v.parse().map_err(|err| {
+                        Error::String(format!("Invalid max_bytes_per_second '{v}': {err}"))
+                    })?);
+                }
                 _ => (),
             }
         }
@@ -278,9 +337,12 @@ impl RemoteConfig {
             "file" | "" => storage::fs::Config::from_url(&url)
                 .await
                 .map(RepositoryConfig::Fs),
-            "http2" | "grpc" => storage::rpc::Config::from_url(&url)
+            "http2" | "grpc" | storage::rpc::TLS_SCHEME => storage::rpc::Config::from_url(&url)
                 .await
                 .map(RepositoryConfig::Grpc),
+            "s3" => storage::s3::Config::from_url(&url)
+                .await
+                .map(RepositoryConfig::S3),
             "proxy" => storage::proxy::Config::from_url(&url)
                 .await
                 .map(RepositoryConfig::Proxy),
@@ -308,6 +370,7 @@ impl RemoteConfig {
         let Self {
             when,
             tag_namespace,
+            max_bytes_per_second: _,
             inner,
         } = self;
         let mut handle: storage::RepositoryHandle = match inner.clone() {
@@ -320,6 +383,9 @@ impl RemoteConfig {
             RepositoryConfig::Grpc(config) => storage::rpc::RpcRepository::from_config(config)
                 .await?
                 .into(),
+            RepositoryConfig::S3(config) => {
+                storage::s3::S3Repository::from_config(config).await?.into()
+            }
             RepositoryConfig::Proxy(config) => storage::proxy::ProxyRepository::from_config(config)
                 .await?
                 .into(),
@@ -372,6 +438,24 @@ pub struct Filesystem {
     /// Use the "mount" command when false. Defaults to false.
     #[serde(default)]
     pub use_mount_syscalls: bool,
+
+    /// Fail to set up runtimes when spfs-enter is missing the privileges
+    /// that it needs, rather than falling back to a rootless runtime in
+    /// an unprivileged user namespace. Defaults to false.
+    #[serde(default)]
+    pub disable_rootless: bool,
+
+    /// The host paths that remain available in runtimes that are
+    /// sandboxed to restrict host paths, in addition to those given
+    /// when the runtime is created.
+    #[serde(default = "Filesystem::default_sandbox_allowed_paths")]
+    pub sandbox_allowed_paths: Vec<PathBuf>,
+
+    /// Capture the extended attributes of files and directories when
+    /// committing, so that things like file capabilities and security
+    /// labels are restored when the layer is used. Defaults to false.
+    #[serde(default)]
+    pub capture_xattrs: bool,
 }
 
 impl Filesystem {
@@ -381,6 +465,14 @@ impl Filesystem {
         vec![String::from("origin")]
     }
 
+    /// The default host paths that remain available in a sandboxed
+    /// runtime, which are needed to run most programs
+    pub fn default_sandbox_allowed_paths() -> Vec<PathBuf> {
+        ["/bin", "/etc", "/lib", "/lib64", "/sbin", "/tmp", "/usr"]
+            .map(PathBuf::from)
+            .to_vec()
+    }
+
     /// The default size limit for a piece of annotation data before it
     /// is stored in a separate blob payload from the annotation
     /// layer that contains it
@@ -407,6 +499,21 @@ pub struct Fuse {
     /// seconds
     #[serde(default = "default_fuse_heartbeat_grace_period_seconds")]
     pub heartbeat_grace_period_seconds: NonZeroU64,
+    /// The maximum total size, in megabytes, of payloads from remote
+    /// repositories that are kept on disk after being read so that
+    /// they can be reopened and seeked locally. Compressed payloads from
+    /// local repositories are also decompressed into this cache. Zero
+    /// disables the cache.
+    pub payload_cache_size_mb: u64,
+    /// Where to cache payloads from remote repositories, defaults to a
+    /// folder in the user's cache directory
+    pub payload_cache_dir: Option<PathBuf>,
+    /// Record the payloads that are opened in each runtime and fetch
+    /// them in the background when the same stack is mounted again
+    pub enable_prefetch: bool,
+    /// The number of payloads to prefetch from remote repositories at once
+    #[serde(default = "default_fuse_prefetch_concurrency")]
+    pub prefetch_concurrency: NonZeroUsize,
 }
 
 impl Fuse {
@@ -416,6 +523,17 @@ impl Fuse {
     /// does not need to process the related file I/O normally.
     pub const HEARTBEAT_FILENAME_PREFIX: &'static str =
         ".spfs-heartbeat-436cd8d6-60d1-11ef-9c93-00155dab73c6-";
+
+    /// The directory used to cache remote payloads, or None
+    /// if the cache is disabled
+    pub fn payload_cache_path(&self) -> Option<PathBuf> {
+        if self.payload_cache_size_mb == 0 {
+            return None;
+        }
+        self.payload_cache_dir
+            .clone()
+            .or_else(|| dirs::cache_dir().map(|dir| dir.join("spfs").join("fuse-payloads")))
+    }
 }
 
 impl Default for Fuse {
@@ -426,6 +544,10 @@ impl Default for Fuse {
             enable_heartbeat: false,
             heartbeat_interval_seconds: default_fuse_heartbeat_interval_seconds(),
             heartbeat_grace_period_seconds: default_fuse_heartbeat_grace_period_seconds(),
+            payload_cache_size_mb: 0,
+            payload_cache_dir: None,
+            enable_prefetch: false,
+            prefetch_concurrency: default_fuse_prefetch_concurrency(),
         }
     }
 }
@@ -581,6 +703,10 @@ impl Config {
 
         Arc::make_mut(&mut local_repo.fs_impl)
             .set_tag_namespace(self.storage.tag_namespace.clone());
+        Arc::make_mut(&mut local_repo.fs_impl)
+            .set_tag_signature_policy(self.storage.tag_signature_policy()?);
+        Arc::make_mut(&mut local_repo.fs_impl)
+            .set_tag_signing_key(self.user.load_signing_key()?.map(Arc::new));
 
         Ok(local_repo)
     }
@@ -671,6 +797,23 @@ impl Config {
         })
     }
 
+    /// The limit on payload transfer rate for a remote repository by name.
+    ///
+    /// Returns `None` if there is no limit, or the remote is not configured.
+    pub fn get_remote_max_bytes_per_second<S: AsRef<str>>(
+        &self,
+        remote_name: S,
+    ) -> Option<NonZeroU64> {
+        match self.remote.get(remote_name.as_ref())? {
+            Remote::Address(remote) => remote
+                .address
+                .query_pairs()
+                .find(|(k, _)| k == "max_bytes_per_second")
+                .and_then(|(_, v)| v.parse().ok()),
+            Remote::Config(config) => config.max_bytes_per_second,
+        }
+    }
+
     pub fn get_secondary_runtime_repositories(&self) -> Vec<url::Url> {
         let mut addrs = Vec::new();
         for name in self.filesystem.secondary_repositories.iter() {
@@ -4,7 +4,7 @@
 
 use rstest::rstest;
 
-use super::{Config, Remote, RemoteConfig, RepositoryConfig};
+use super::{Config, Remote, RemoteConfig, RepositoryConfig, ToAddress};
 use crate::storage::RepositoryHandle;
 use crate::storage::prelude::*;
 use crate::{get_config, load_config};
@@ -170,6 +170,81 @@ async fn test_remote_config_with_tag_namespace_from_address() {
     )
 }
 
+#[rstest]
+#[tokio::test]
+async fn test_remote_config_max_bytes_per_second_from_address() {
+    let address = url::Url::parse("http2://test.local?lazy=true&max_bytes_per_second=1024")
+        .expect("a valid url");
+    let config = RemoteConfig::from_address(address)
+        .await
+        .expect("can parse address with 'max_bytes_per_second' query");
+    assert_eq!(config.max_bytes_per_second.map(|l| l.get()), Some(1024));
+    let address = config.to_address().expect("config should have an address");
+    assert!(
+        address.as_str().contains("max_bytes_per_second=1024"),
+        "the limit should be preserved in the address, got {address}"
+    );
+
+    let address = url::Url::parse("http2://test.local?lazy=true&max_bytes_per_second=0")
+        .expect("a valid url");
+    RemoteConfig::from_address(address)
+        .await
+        .expect_err("a limit of zero should be rejected");
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_remote_config_s3_from_address() {
+    let address = url::Url::parse(
+        "s3://bucket/some/prefix?endpoint=http://localhost:9000&access_key_id=id&secret_access_key=secret",
+    )
+    .expect("a valid url");
+    let config = RemoteConfig::from_address(address)
+        .await
+        .expect("can parse s3 address");
+    let RepositoryConfig::S3(s3) = &config.inner else {
+        panic!("expected an s3 config, got {:?}", config.inner);
+    };
+    assert_eq!(s3.address.as_str(), "s3://bucket/some/prefix");
+    assert_eq!(s3.params.secret_access_key.as_deref(), Some("secret"));
+    let repo = config.open().await.expect("should open s3 repo address");
+    assert!(
+        !repo.address().as_str().contains("secret"),
+        "the secret access key should not be included in the repo address"
+    );
+}
+
+#[rstest]
+#[case::http("http://localhost:9000", true)]
+#[case::https("https://s3.example.com", true)]
+#[case::ftp("ftp://localhost:9000", false)]
+#[tokio::test]
+async fn test_remote_config_s3_endpoint_scheme(#[case] endpoint: &str, #[case] valid: bool) {
+    let address = url::Url::parse(&format!("s3://bucket?endpoint={endpoint}&region=test"))
+        .expect("a valid url");
+    let config = RemoteConfig::from_address(address)
+        .await
+        .expect("can parse s3 address");
+    assert_eq!(config.open().await.is_ok(), valid);
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_remote_config_grpcs_requires_host() {
+    let address = url::Url::parse("grpcs:///?lazy=true").expect("a valid url");
+    let config = crate::storage::rpc::Config::from_url(&address)
+        .await
+        .expect("can parse grpcs address");
+    let result = crate::storage::rpc::RpcRepository::new(config).await;
+    assert!(
+        matches!(
+            result,
+            Err(crate::storage::OpenRepositoryError::MissingHost { .. })
+        ),
+        "a tls address without a host should be rejected"
+    );
+}
+
 #[rstest]
 #[case::single_underscores_still_works(&["SPFS_STORAGE_ROOT"], 0, &[], |config: &Config| config.storage.root.display().to_string())]
 #[case::single_underscores_has_precedence(&["SPFS_STORAGE_ROOT", "SPFS_STORAGE__ROOT"], 0, &[], |config: &Config| config.storage.root.display().to_string())]
@@ -22,9 +22,12 @@ use linux_syscall::{
 use super::runtime;
 use crate::{Error, Result, which};
 
+#[cfg(test)]
+#[path = "./env_test.rs"]
+mod env_test;
+
 pub const SPFS_DIR: &str = "/spfs";
 pub const SPFS_DIR_PREFIX: &str = "/spfs/";
-const SPFS_DIR_CSTR: &CStr = c"/spfs";
 
 const EMPTY_CSTR: &CStr = c"";
 const FSCONFIG_INDEX_CSTR: &CStr = c"index";
@@ -51,11 +54,13 @@ const FSMOUNT_CLOEXEC: u32 = 0x01;
 const MOUNT_ATTR_RDONLY: u32 = 0x01;
 const MOUNT_ATTR_SIZE_VER0: u32 = 32;
 const MOVE_MOUNT_F_EMPTY_PATH: u32 = 0x04;
+const MOVE_MOUNT_BENEATH: u32 = 0x200;
 const OPEN_TREE_CLOEXEC: u32 = 0x80000;
 const OPEN_TREE_CLONE: u32 = 0x01;
 
 // Linux fcntl constants from /usr/include/fcntl.h.
 const AT_EMPTY_PATH: u32 = 0x1000;
+const AT_RECURSIVE: u32 = 0x8000;
 const AT_FDCWD: i32 = -100;
 
 /// Manages the configuration of an spfs runtime environment.
@@ -80,6 +85,9 @@ pub struct NoMountNamespace;
 pub struct IsRootUser {
     pub original_uid: nix::unistd::Uid,
     pub original_euid: nix::unistd::Uid,
+    /// True when the process holds its privileges within a user
+    /// namespace for a rootless runtime, rather than as root on the host
+    pub user_namespace: bool,
 }
 
 /// Signifies that the process became root and has since dropped it
@@ -212,10 +220,52 @@ impl<MountNamespace> RuntimeConfigurator<NoUserChanges, MountNamespace> {
             IsRootUser {
                 original_euid,
                 original_uid,
+                user_namespace: false,
+            },
+            self.ns,
+        ))
+    }
+
+    /// Use the privileges held within the user namespace of a rootless
+    /// runtime, in place of becoming root.
+    ///
+    /// The process keeps its original user id, which is mapped to itself
+    /// in the user namespace. See [`enter_user_namespace`] and
+    /// [`enter_runtime_user_namespace`].
+    pub fn become_user_namespace_root(
+        self,
+    ) -> Result<RuntimeConfigurator<IsRootUser, MountNamespace>> {
+        tracing::debug!("using user namespace privileges...");
+        let effective = caps::read(None, caps::CapSet::Effective)?;
+        if !effective.contains(&caps::Capability::CAP_SYS_ADMIN) {
+            return Err(
+                "Missing required capabilities in the user namespace of a rootless runtime".into(),
+            );
+        }
+        raise_user_namespace_capabilities()?;
+        Ok(RuntimeConfigurator::new(
+            IsRootUser {
+                original_euid: nix::unistd::geteuid(),
+                original_uid: nix::unistd::getuid(),
+                user_namespace: true,
             },
             self.ns,
         ))
     }
+
+    /// Escalate the current process' privileges as needed to modify
+    /// the given runtime, see [`Self::become_root`] and
+    /// [`Self::become_user_namespace_root`].
+    pub fn become_root_for_runtime(
+        self,
+        rt: &runtime::Runtime,
+    ) -> Result<RuntimeConfigurator<IsRootUser, MountNamespace>> {
+        if rt.config.rootless {
+            self.become_user_namespace_root()
+        } else {
+            self.become_root()
+        }
+    }
 }
 
 impl<User> RuntimeConfigurator<User, NoMountNamespace> {
@@ -234,6 +284,21 @@ impl<User> RuntimeConfigurator<User, NoMountNamespace> {
         Ok(RuntimeConfigurator::new(self.user, ns))
     }
 
+    /// Use the mount namespace that was created along with the user
+    /// namespace of a rootless runtime, see [`enter_user_namespace`].
+    pub fn rootless_mount_namespace(
+        self,
+    ) -> Result<RuntimeConfigurator<User, ThreadIsInMountNamespace>> {
+        if nested_mount_namespace_owner()?.is_none() {
+            return Err("Mount namespace for a rootless runtime has not been created".into());
+        }
+
+        // Safety: the thread is in the mount namespace that
+        // was created by enter_user_namespace, as checked above
+        let ns = unsafe { ThreadIsInMountNamespace::existing() }?;
+        Ok(RuntimeConfigurator::new(self.user, ns))
+    }
+
     /// Make this configurator for an existing runtime.
     ///
     /// The calling thread must already be operating in the provided runtime.
@@ -279,31 +344,28 @@ impl<User> RuntimeConfigurator<User, NoMountNamespace> {
         self,
         rt: &runtime::Runtime,
     ) -> Result<RuntimeConfigurator<User, ThreadIsInMountNamespace>> {
-        check_can_join()?;
-
         let pid = match rt.status.owner {
             None => return Err(Error::RuntimeNotInitialized(rt.name().into())),
             Some(pid) => pid,
         };
 
-        let ns_path = std::path::Path::new("/proc")
-            .join(pid.to_string())
-            .join("ns/mnt");
-
-        tracing::debug!(?ns_path, "Getting process namespace");
-        let file = match std::fs::File::open(&ns_path) {
-            Ok(file) => file,
-            Err(err) => {
-                return match err.kind() {
-                    std::io::ErrorKind::NotFound => Err(Error::UnknownRuntime {
-                        runtime: rt.name().into(),
-                        source: Box::new(err),
-                    }),
-                    _ => Err(Error::RuntimeReadError(ns_path, err)),
-                };
+        if rt.config.rootless {
+            // the user that created a user namespace holds all
+            // capabilities within it, and so needs none on the host.
+            // These are lost again when the command is executed
+            check_single_threaded("join an existing runtime")?;
+            let file = open_runtime_namespace(rt, pid, "user")?;
+            if let Err(err) = nix::sched::setns(file, nix::sched::CloneFlags::CLONE_NEWUSER) {
+                return Err(Error::wrap_nix(
+                    err,
+                    "Failed to enter user namespace of rootless runtime",
+                ));
             }
-        };
+        } else {
+            check_can_join()?;
+        }
 
+        let file = open_runtime_namespace(rt, pid, "mnt")?;
         if let Err(err) = nix::sched::setns(file, nix::sched::CloneFlags::empty()) {
             return Err(match err {
                 nix::errno::Errno::EPERM => Error::new_errno(
@@ -314,6 +376,26 @@ impl<User> RuntimeConfigurator<User, NoMountNamespace> {
             });
         }
 
+        use nix::sched::CloneFlags;
+        let sandbox = &rt.config.sandbox;
+        for (enabled, ns, flag) in [
+            (sandbox.network, "net", CloneFlags::CLONE_NEWNET),
+            (sandbox.ipc, "ipc", CloneFlags::CLONE_NEWIPC),
+            // this only applies to processes started after joining
+            (sandbox.pids, "pid_for_children", CloneFlags::CLONE_NEWPID),
+        ] {
+            if !enabled {
+                continue;
+            }
+            let file = open_runtime_namespace(rt, pid, ns)?;
+            if let Err(err) = nix::sched::setns(file, flag) {
+                return Err(Error::wrap_nix(
+                    err,
+                    format!("Failed to enter {ns} namespace of sandboxed runtime"),
+                ));
+            }
+        }
+
         // Safety: the responsibility of the caller.
         unsafe {
             std::env::set_var("SPFS_RUNTIME", rt.name());
@@ -495,6 +577,72 @@ where
         Ok(())
     }
 
+    /// Isolate the current thread as configured by the sandbox options
+    /// of the runtime.
+    ///
+    /// This moves the thread into new network and IPC namespaces, and
+    /// restricts the host paths visible in the mount namespace. The PID
+    /// namespace can only be entered once the runtime's command is about
+    /// to be run, see [`exec_in_pid_namespace`].
+    pub fn apply_sandbox(&self, config: &runtime::Config) -> Result<()> {
+        use nix::sched::CloneFlags;
+
+        let sandbox = &config.sandbox;
+        if sandbox.network && self.user.user_namespace {
+            // the user namespace of a rootless runtime also owns
+            // the new network namespace, so it can be configured here
+            tracing::debug!("entering network namespace...");
+            if let Err(err) = nix::sched::unshare(CloneFlags::CLONE_NEWNET) {
+                return Err(Error::wrap_nix(err, "Failed to enter network namespace"));
+            }
+            bring_up_loopback()?;
+        } else if sandbox.network {
+            enter_user_owned_network_namespace(self.user.original_uid)?;
+        }
+        if sandbox.ipc {
+            tracing::debug!("entering ipc namespace...");
+            if let Err(err) = nix::sched::unshare(CloneFlags::CLONE_NEWIPC) {
+                return Err(Error::wrap_nix(err, "Failed to enter ipc namespace"));
+            }
+        }
+        if !sandbox.host_paths.is_visible() {
+            self.restrict_host_paths(config)?;
+        }
+        Ok(())
+    }
+
+    /// Make host paths outside of the sandbox's allowed paths
+    /// read-only or hidden, as configured.
+    fn restrict_host_paths(&self, config: &runtime::Config) -> Result<()> {
+        tracing::debug!(mode = %config.sandbox.host_paths, "restricting host paths...");
+        // spfs itself needs to keep working from inside the runtime,
+        // including the monitor that is started once this is done
+        let mut allowed = ["/dev", "/proc", "/sys", SPFS_DIR]
+            .map(PathBuf::from)
+            .to_vec();
+        allowed.extend(config.runtime_dir.iter().cloned());
+        allowed.push(crate::Config::current()?.storage.root.clone());
+        if let Some(bin_dir) = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_owned))
+        {
+            allowed.push(bin_dir);
+        }
+        allowed.extend(config.sandbox.allowed_host_paths.iter().cloned());
+
+        let root_entries = std::fs::read_dir("/")
+            .map_err(|err| Error::RuntimeReadError("/".into(), err))?
+            .filter_map(|entry| entry.ok())
+            // symlinks point to other entries in the root, and
+            // files cannot be restricted in the same way
+            .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+            .map(|entry| entry.path());
+        for restriction in runtime::Sandbox::host_path_restrictions(root_entries, &allowed) {
+            restrict_host_path(&restriction, config.sandbox.host_paths)?;
+        }
+        Ok(())
+    }
+
     pub async fn setup_runtime(&self, rt: &runtime::Runtime) -> Result<()> {
         tracing::debug!("setting up runtime...");
         rt.ensure_required_directories().await
@@ -515,28 +663,58 @@ where
         rt: &runtime::Runtime,
         layer_dirs: &[P],
     ) -> Result<()> {
-        let spfs_config = crate::Config::current()?;
-        if spfs_config.filesystem.use_mount_syscalls {
-            mount_overlayfs_syscalls(rt, layer_dirs)?;
+        self.mount_env_overlayfs_onto(rt, layer_dirs, Path::new(SPFS_DIR))
+            .await
+    }
+
+    /// Mount the overlayfs for the given runtime at the given path
+    /// instead of /spfs, see [`Self::mount_env_overlayfs`].
+    pub(crate) async fn mount_env_overlayfs_onto<P: AsRef<Path>>(
+        &self,
+        rt: &runtime::Runtime,
+        layer_dirs: &[P],
+        target: &Path,
+    ) -> Result<()> {
+        if use_mount_syscalls(rt)? {
+            mount_overlayfs_syscalls(rt, layer_dirs, target)?;
         } else {
-            mount_overlayfs_command(rt, layer_dirs).await?;
+            mount_overlayfs_command(rt, layer_dirs, target).await?;
         }
-        mount_live_layers(rt).await
+        mount_live_layers(rt, target).await
     }
 
     #[cfg(feature = "fuse-backend")]
     pub(crate) async fn mount_fuse_lower_dir(&self, rt: &runtime::Runtime) -> Result<()> {
-        self.mount_fuse_onto(rt, &rt.config.lower_dir).await
+        self.mount_fuse_onto(rt, &rt.config.lower_dir, true).await
     }
 
     #[cfg(feature = "fuse-backend")]
     pub(crate) async fn mount_env_fuse(&self, rt: &runtime::Runtime) -> Result<()> {
-        self.mount_fuse_onto(rt, SPFS_DIR).await?;
-        mount_live_layers(rt).await
+        self.mount_env_fuse_onto(rt, Path::new(SPFS_DIR)).await
+    }
+
+    /// Mount the fuse filesystem for the given runtime at the given
+    /// path instead of /spfs, see [`Self::mount_env_fuse`].
+    #[cfg(feature = "fuse-backend")]
+    pub(crate) async fn mount_env_fuse_onto(
+        &self,
+        rt: &runtime::Runtime,
+        target: &Path,
+    ) -> Result<()> {
+        // without overlayfs, changes are stored by the fuse
+        // filesystem itself in the runtime's upper directory
+        self.mount_fuse_onto(rt, target, !rt.status.editable)
+            .await?;
+        mount_live_layers(rt, target).await
     }
 
     #[cfg(feature = "fuse-backend")]
-    async fn mount_fuse_onto<P>(&self, rt: &runtime::Runtime, path: P) -> Result<()>
+    async fn mount_fuse_onto<P>(
+        &self,
+        rt: &runtime::Runtime,
+        path: P,
+        read_only: bool,
+    ) -> Result<()>
     where
         P: AsRef<std::ffi::OsStr>,
     {
@@ -544,7 +722,7 @@ where
 
         let path = path.as_ref().to_owned();
         let platform = rt.to_platform().digest()?.to_string();
-        let opts = get_fuse_args(&rt.config, &self.user, true);
+        let opts = get_fuse_args(&rt.config, &self.user, read_only);
 
         // A new thread created in mount namespace will be inside the same
         // mount namespace...
@@ -815,6 +993,36 @@ where
         Ok(())
     }
 
+    /// Replace the filesystem mounted at /spfs with the one that is
+    /// already mounted at the given staging path, which is left empty.
+    ///
+    /// The new filesystem is moved beneath the current one, which is
+    /// then lazily unmounted, so that processes in the runtime always
+    /// see either the previous or the new filesystem in /spfs. Kernels
+    /// older than 6.5 cannot mount beneath an existing mount, and the
+    /// new filesystem is instead mounted over top of the previous one,
+    /// which stays hidden underneath it until the runtime exits.
+    pub(crate) fn replace_env(&self, staging: &Path) -> Result<()> {
+        tracing::debug!(?staging, "replacing the existing env...");
+        let spfs = Path::new(SPFS_DIR);
+        let mount = clone_mount_tree(staging)?;
+        self.detach_mount(staging)?;
+        match move_mount_tree_with_flags(&mount, spfs, MOVE_MOUNT_BENEATH) {
+            Ok(()) => self.detach_mount(spfs),
+            Err(err) => {
+                tracing::debug!("unable to mount beneath {SPFS_DIR}, mounting over it: {err}");
+                move_mount_tree(&mount, spfs)
+            }
+        }
+    }
+
+    /// Lazily unmount the top-most mount at the given path, along
+    /// with everything that is mounted below it.
+    pub(crate) fn detach_mount(&self, path: &Path) -> Result<()> {
+        nix::mount::umount2(path, nix::mount::MntFlags::MNT_DETACH)
+            .map_err(|err| Error::wrap_nix(err, format!("Failed to unmount {path:?}")))
+    }
+
     /// Unmount the overlayfs portion of the provided runtime, if applicable
     pub async fn unmount_env_overlayfs(&self, rt: &runtime::Runtime, lazy: bool) -> Result<()> {
         match rt.config.mount_backend {
@@ -861,6 +1069,22 @@ where
         };
         tracing::debug!(%lazy, "unmounting existing fuse env @ {mount_path:?}...");
 
+        if rt.config.rootless {
+            // fusermount relies on being setuid root, which has no effect
+            // in a user namespace, but the mount can be removed directly
+            let mut flags = nix::mount::MntFlags::empty();
+            if lazy {
+                flags |= nix::mount::MntFlags::MNT_DETACH;
+            }
+            if let Err(err) = nix::mount::umount2(mount_path, flags) {
+                return Err(Error::wrap_nix(
+                    err,
+                    format!("Failed to unmount {mount_path:?}"),
+                ));
+            }
+            return Ok(());
+        }
+
         // The FUSE filesystem can take some time to start up, and
         // if the runtime tries to exit too quickly, the fusermount
         // command can return with errors because the filesystem has
@@ -919,8 +1143,36 @@ impl<MountNamespace> RuntimeConfigurator<IsRootUser, MountNamespace> {
     /// this thread was running as before becoming root
     pub fn become_original_user(
         self,
+    ) -> Result<RuntimeConfigurator<IsNonRootUser, MountNamespace>> {
+        self.become_original_user_retaining(&[])
+    }
+
+    /// Drop root like [`Self::become_original_user`], but keep the
+    /// capability needed to move the runtime's command into a new
+    /// PID namespace just before it is run, see [`exec_in_pid_namespace`].
+    pub fn become_original_user_for_pid_namespace(
+        self,
+    ) -> Result<RuntimeConfigurator<IsNonRootUser, MountNamespace>> {
+        self.become_original_user_retaining(&[caps::Capability::CAP_SYS_ADMIN])
+    }
+
+    fn become_original_user_retaining(
+        self,
+        retain: &[caps::Capability],
     ) -> Result<RuntimeConfigurator<IsNonRootUser, MountNamespace>> {
         tracing::debug!("dropping root...");
+        if !retain.is_empty() {
+            // otherwise, all capabilities are lost when changing
+            // from the root user back to the original user
+            // Safety: prctl has no memory safety requirements
+            let result = unsafe { libc::prctl(libc::PR_SET_KEEPCAPS, 1) };
+            if result != 0 {
+                return Err(Error::wrap_nix(
+                    nix::errno::Errno::last(),
+                    "Failed to keep capabilities",
+                ));
+            }
+        }
         let mut result = nix::unistd::setuid(self.user.original_uid);
         if let Err(err) = result {
             return Err(Error::wrap_nix(
@@ -935,16 +1187,27 @@ impl<MountNamespace> RuntimeConfigurator<IsRootUser, MountNamespace> {
                 "Failed to become regular user (effective)",
             ));
         }
-        self.drop_all_capabilities()?;
+        self.drop_all_capabilities(retain)?;
         Ok(RuntimeConfigurator::new(IsNonRootUser, self.ns))
     }
 
-    // Drop all of the capabilities held by the current thread
-    fn drop_all_capabilities(&self) -> Result<()> {
+    // Drop all of the capabilities held by the current thread,
+    // except for any that should be retained as permitted
+    fn drop_all_capabilities(&self, retain: &[caps::Capability]) -> Result<()> {
         tracing::debug!("drop all capabilities/privileges...");
         caps::clear(None, caps::CapSet::Effective)?;
-        caps::clear(None, caps::CapSet::Permitted)?;
+        caps::set(
+            None,
+            caps::CapSet::Permitted,
+            &retain.iter().copied().collect(),
+        )?;
         caps::clear(None, caps::CapSet::Inheritable)?;
+        if self.user.user_namespace {
+            // the capabilities raised to set up a rootless runtime must not
+            // be passed on to its command or monitor, which can re-enter the
+            // runtime's user namespace when they are needed instead
+            caps::clear(None, caps::CapSet::Ambient)?;
+        }
 
         // the dumpable attribute can become unset when changing pids or
         // calling a binary with capabilities (spfs). Resetting this to one
@@ -959,8 +1222,358 @@ impl<MountNamespace> RuntimeConfigurator<IsRootUser, MountNamespace> {
     }
 }
 
+/// Signals that are passed on to the runtime's command when it
+/// is running in a new PID namespace, see [`exec_in_pid_namespace`]
+const FORWARDED_SIGNALS: [libc::c_int; 4] =
+    [libc::SIGINT, libc::SIGTERM, libc::SIGQUIT, libc::SIGHUP];
+
+/// The init process of the PID namespace created by [`exec_in_pid_namespace`],
+/// which receives the signals that are forwarded from the calling process
+static PID_NAMESPACE_INIT: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(0);
+
+extern "C" fn forward_to_pid_namespace(
+    sig: libc::c_int,
+    info: *mut libc::siginfo_t,
+    _context: *mut libc::c_void,
+) {
+    // signals from the terminal are already sent to the
+    // command when it is in the same process group
+    // Safety: the kernel provides a valid siginfo to the handler
+    if unsafe { (*info).si_code } == libc::SI_KERNEL {
+        return;
+    }
+    let pid = PID_NAMESPACE_INIT.load(std::sync::atomic::Ordering::SeqCst);
+    if pid > 0 {
+        // Safety: kill is async-signal-safe
+        unsafe { libc::kill(pid, sig) };
+    }
+}
+
+/// Run the runtime's command in a new PID namespace.
+///
+/// The calling process cannot move into a new PID namespace itself, so
+/// a child process becomes the init process of the namespace and starts
+/// the command. The init process passes on any SIGINT, SIGTERM, SIGQUIT
+/// or SIGHUP to the command, which would otherwise ignore them for lack
+/// of a handler if it were the init process, and reaps any orphaned
+/// processes until the command exits. The calling process forwards
+/// the same signals to the namespace, waits, and then exits with the
+/// same status as the command, which means that this function only
+/// returns if the command could not be started. The calling process
+/// must have kept the capability to do this, see
+/// [`RuntimeConfigurator::become_original_user_for_pid_namespace`].
+///
+/// Processes outside of the namespace cannot be signaled by the command,
+/// but the proc filesystem is shared with the rest of the runtime, so that
+/// spfs-monitor can still track it, and so continues to list them.
+pub fn exec_in_pid_namespace(cmd: crate::bootstrap::Command) -> Result<std::convert::Infallible> {
+    use nix::sys::signal::{SaFlags, SigAction, SigHandler, SigSet, Signal, sigaction};
+    use nix::sys::wait::{WaitStatus, waitpid};
+    use nix::unistd::{ForkResult, fork};
+
+    tracing::debug!("{cmd:#?}");
+    // other threads may still be running, so the child process can only
+    // make async-signal-safe calls and everything it needs is prepared here
+    let cmd = cmd.prepare()?;
+    let (argv, envp) = cmd.as_ptrs();
+    // the write end is closed by a successful exec, otherwise
+    // the child reports why the command could not be started
+    let (error_read, error_write) = cloexec_pipe()?;
+
+    tracing::debug!("entering pid namespace...");
+    caps::raise(
+        None,
+        caps::CapSet::Effective,
+        caps::Capability::CAP_SYS_ADMIN,
+    )?;
+    let result = nix::sched::unshare(nix::sched::CloneFlags::CLONE_NEWPID);
+    caps::clear(None, caps::CapSet::Effective)?;
+    caps::clear(None, caps::CapSet::Permitted)?;
+    if let Err(err) = result {
+        return Err(Error::wrap_nix(err, "Failed to enter pid namespace"));
+    }
+
+    // Safety: the child process only makes raw system calls using
+    // the values prepared above, and exits without unwinding
+    match unsafe { fork() } {
+        Err(err) => Err(Error::wrap_nix(err, "Failed to fork into pid namespace")),
+        // Safety: only raw system calls are made in the child
+        Ok(ForkResult::Child) => unsafe { run_pid_namespace_init(&argv, &envp, &error_write) },
+        Ok(ForkResult::Parent { child }) => {
+            drop(error_write);
+            PID_NAMESPACE_INIT.store(child.as_raw(), std::sync::atomic::Ordering::SeqCst);
+            let forward = SigAction::new(
+                SigHandler::SigAction(forward_to_pid_namespace),
+                SaFlags::SA_RESTART | SaFlags::SA_SIGINFO,
+                SigSet::empty(),
+            );
+            for sig in FORWARDED_SIGNALS {
+                let sig =
+                    Signal::try_from(sig).map_err(|err| Error::wrap_nix(err, "Invalid signal"))?;
+                // Safety: the handler only makes async-signal-safe calls
+                unsafe { sigaction(sig, &forward) }
+                    .map_err(|err| Error::wrap_nix(err, "Failed to forward signal"))?;
+            }
+            if let Some(errno) = read_errno(&error_read) {
+                let _ = waitpid(child, None);
+                return Err(Error::wrap_nix(
+                    nix::errno::Errno::from_raw(errno),
+                    "Failed to execute command in pid namespace",
+                ));
+            }
+            let code = loop {
+                match waitpid(child, None) {
+                    Ok(WaitStatus::Exited(_, code)) => break code,
+                    Ok(WaitStatus::Signaled(_, sig, _)) => break 128 + sig as i32,
+                    Ok(_) | Err(nix::errno::Errno::EINTR) => continue,
+                    Err(err) => {
+                        return Err(Error::wrap_nix(
+                            err,
+                            "Failed to wait for process in pid namespace",
+                        ));
+                    }
+                }
+            };
+            std::process::exit(code)
+        }
+    }
+}
+
+/// Act as the init process of a new PID namespace, running the
+/// given command in a child process until it exits.
+///
+/// # Safety
+/// Only raw system calls are made, so this can be called in a child
+/// process that was forked from one with other threads.
+unsafe fn run_pid_namespace_init(
+    argv: &[*const libc::c_char],
+    envp: &[*const libc::c_char],
+    error_write: &CloseFd,
+) -> ! {
+    // Safety: the responsibility of the caller, all of these
+    // are raw system calls or only operate on local memory
+    unsafe {
+        // signals are blocked before the command is started so that
+        // none are missed, and then collected synchronously below
+        let mut signals: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, libc::SIGCHLD);
+        for sig in FORWARDED_SIGNALS {
+            libc::sigaddset(&mut signals, sig);
+        }
+        let mut previous: libc::sigset_t = std::mem::zeroed();
+        libc::sigprocmask(libc::SIG_BLOCK, &signals, &mut previous);
+
+        let command = libc::fork();
+        if command == 0 {
+            libc::sigprocmask(libc::SIG_SETMASK, &previous, std::ptr::null_mut());
+            libc::execve(argv[0], argv.as_ptr(), envp.as_ptr());
+            write_errno(error_write, nix::errno::Errno::last() as i32);
+            libc::_exit(127)
+        }
+        // the calling process waits for the command to be
+        // started, and only the command should hold this open
+        libc::close(error_write.fd);
+        if command < 0 {
+            libc::_exit(127)
+        }
+
+        let mut info: libc::siginfo_t = std::mem::zeroed();
+        loop {
+            let sig = libc::sigwaitinfo(&signals, &mut info);
+            if sig < 0 {
+                continue;
+            }
+            if sig != libc::SIGCHLD {
+                // signals from the terminal are already sent to
+                // the command when it is in the same process group
+                if info.si_code != libc::SI_KERNEL {
+                    libc::kill(command, sig);
+                }
+                continue;
+            }
+            // orphaned processes in the namespace are reparented to
+            // this one, and are reaped along with the command itself
+            loop {
+                let mut status = 0;
+                let pid = libc::waitpid(-1, &mut status, libc::WNOHANG);
+                if pid <= 0 {
+                    break;
+                }
+                if pid != command {
+                    continue;
+                }
+                // everything else in the namespace is killed
+                // by the kernel once this process exits
+                if libc::WIFEXITED(status) {
+                    libc::_exit(libc::WEXITSTATUS(status));
+                }
+                if libc::WIFSIGNALED(status) {
+                    libc::_exit(128 + libc::WTERMSIG(status));
+                }
+            }
+        }
+    }
+}
+
+/// Move the current thread into a new network namespace, where
+/// only the loopback interface is up.
+///
+/// Bringing up an interface needs `CAP_NET_ADMIN` over the user namespace
+/// that owns the network namespace. Rather than granting that to spfs-enter,
+/// a child process drops back to the original user and creates the network
+/// namespace inside of a new user namespace, which that user owns and so
+/// can configure. This thread then joins the network namespace, staying in
+/// its own user namespace. This fails if unprivileged user namespaces are
+/// disabled on the host.
+fn enter_user_owned_network_namespace(uid: nix::unistd::Uid) -> Result<()> {
+    use nix::unistd::{ForkResult, fork};
+
+    tracing::debug!("entering user-owned network namespace...");
+    // other threads may still be running, so the child process can only
+    // make async-signal-safe calls and everything it needs is prepared here
+    let mut request = loopback_request();
+    let (ready_read, ready_write) = cloexec_pipe()?;
+    let (done_read, done_write) = cloexec_pipe()?;
+
+    // Safety: the child process only makes raw system calls using
+    // the values prepared above, and exits without unwinding
+    match unsafe { fork() } {
+        Err(err) => Err(Error::wrap_nix(
+            err,
+            "Failed to fork to create network namespace",
+        )),
+        Ok(ForkResult::Child) => unsafe {
+            libc::close(done_write.fd);
+            let uid = uid.as_raw();
+            let errno = if libc::setresuid(uid, uid, uid) != 0
+                || libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) != 0
+            {
+                nix::errno::Errno::last() as i32
+            } else {
+                set_loopback_up(&mut request)
+            };
+            write_errno(&ready_write, errno);
+            // the namespace only exists while a process is in it,
+            // so wait until the parent has joined before exiting
+            wait_for_close(&done_read);
+            libc::_exit(0)
+        },
+        Ok(ForkResult::Parent { child }) => {
+            drop(ready_write);
+            let result = match read_errno(&ready_read) {
+                None => Err(Error::String(
+                    "Process creating the network namespace exited unexpectedly".into(),
+                )),
+                Some(0) => {
+                    let ns_path = format!("/proc/{child}/ns/net");
+                    std::fs::File::open(&ns_path)
+                        .map_err(|err| Error::RuntimeReadError(ns_path.into(), err))
+                        .and_then(|file| {
+                            nix::sched::setns(file, nix::sched::CloneFlags::CLONE_NEWNET).map_err(
+                                |err| Error::wrap_nix(err, "Failed to join network namespace"),
+                            )
+                        })
+                }
+                Some(errno) => Err(Error::wrap_nix(
+                    nix::errno::Errno::from_raw(errno),
+                    "Failed to create network namespace, unprivileged user namespaces may be disabled on this host",
+                )),
+            };
+            // closing the pipe lets the child exit
+            drop((done_read, done_write));
+            let _ = nix::sys::wait::waitpid(child, None);
+            result
+        }
+    }
+}
+
+/// Create a pipe whose ends are closed when a program is executed.
+fn cloexec_pipe() -> Result<(CloseFd, CloseFd)> {
+    let mut fds = [0; 2];
+    // Safety: fds has room for the two file descriptors
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(Error::wrap_nix(
+            nix::errno::Errno::last(),
+            "Failed to create pipe",
+        ));
+    }
+    Ok((CloseFd::new(fds[0]), CloseFd::new(fds[1])))
+}
+
+/// Write an errno to a pipe, to be read by [`read_errno`].
+///
+/// # Safety
+/// Only raw system calls are made, so this can be called in a child
+/// process that was forked from one with other threads.
+unsafe fn write_errno(pipe: &CloseFd, errno: i32) {
+    // Safety: the responsibility of the caller
+    unsafe {
+        libc::write(
+            pipe.fd,
+            std::ptr::addr_of!(errno).cast(),
+            std::mem::size_of::<i32>(),
+        );
+    }
+}
+
+/// Block until every write end of a pipe has been closed.
+///
+/// # Safety
+/// Only raw system calls are made, so this can be called in a child
+/// process that was forked from one with other threads.
+unsafe fn wait_for_close(pipe: &CloseFd) {
+    let mut byte = 0u8;
+    // Safety: the responsibility of the caller
+    while unsafe { libc::read(pipe.fd, std::ptr::addr_of_mut!(byte).cast(), 1) } != 0 {
+        if nix::errno::Errno::last() != nix::errno::Errno::EINTR {
+            break;
+        }
+    }
+}
+
+/// Read an errno that was written to a pipe by a child process,
+/// returning None if the pipe was closed without one.
+fn read_errno(pipe: &CloseFd) -> Option<i32> {
+    let mut errno = 0i32;
+    loop {
+        // Safety: errno has room for the number of bytes being read
+        let count = unsafe {
+            libc::read(
+                pipe.fd,
+                std::ptr::addr_of_mut!(errno).cast(),
+                std::mem::size_of::<i32>(),
+            )
+        };
+        match count {
+            n if n == std::mem::size_of::<i32>() as isize => return Some(errno),
+            n if n < 0 && nix::errno::Errno::last() == nix::errno::Errno::EINTR => continue,
+            _ => return None,
+        }
+    }
+}
+
 // Checks if the current process will be able to join an existing runtime
 fn check_can_join() -> Result<()> {
+    check_single_threaded("join an existing runtime")?;
+
+    if !have_required_join_capabilities()? {
+        return Err("Missing required capabilities to join an existing runtime".into());
+    }
+    Ok(())
+}
+
+// Checks if the current process has the capabilities required
+// to join an existing runtime
+fn have_required_join_capabilities() -> Result<bool> {
+    let effective = caps::read(None, caps::CapSet::Effective)?;
+    Ok(effective.contains(&caps::Capability::CAP_SYS_ADMIN)
+        && effective.contains(&caps::Capability::CAP_SYS_CHROOT))
+}
+
+// Checks that the current process has only one thread, which the
+// kernel requires before changing user or mount namespaces
+fn check_single_threaded(action: &str) -> Result<()> {
     match procfs::process::Process::myself()
         .map_err(|err| Error::String(err.to_string()))?
         .stat()
@@ -968,29 +1581,232 @@ fn check_can_join() -> Result<()> {
         .num_threads
     {
         count @ 2.. => {
-            return Err(format!(
-                "Program must be single-threaded to join an existing runtime (has {count} threads)"
-            )
-            .into());
-        }
-        1 => (),
-        i => {
-            return Err(format!("Unexpected negative thread count: {i}").into());
+            Err(format!("Program must be single-threaded to {action} (has {count} threads)").into())
         }
+        1 => Ok(()),
+        i => Err(format!("Unexpected negative thread count: {i}").into()),
     }
+}
 
-    if !have_required_join_capabilities()? {
-        return Err("Missing required capabilities to join an existing runtime".into());
+// Opens one of the namespaces of the process that owns a runtime
+fn open_runtime_namespace(rt: &runtime::Runtime, pid: u32, ns: &str) -> Result<std::fs::File> {
+    let ns_path = std::path::Path::new("/proc")
+        .join(pid.to_string())
+        .join("ns")
+        .join(ns);
+
+    tracing::debug!(?ns_path, "Getting process namespace");
+    std::fs::File::open(&ns_path).map_err(|err| match err.kind() {
+        std::io::ErrorKind::NotFound => Error::UnknownRuntime {
+            runtime: rt.name().into(),
+            source: Box::new(err),
+        },
+        _ => Error::RuntimeReadError(ns_path, err),
+    })
+}
+
+/// The capabilities passed on to the processes that set up a rootless
+/// runtime, such as spfs-fuse, within its user namespace. These match the
+/// capabilities that spfs-enter is normally installed with, except for
+/// setuid which is not needed when the user id does not change.
+const USER_NAMESPACE_CAPABILITIES: [caps::Capability; 5] = [
+    caps::Capability::CAP_CHOWN,
+    caps::Capability::CAP_DAC_OVERRIDE,
+    caps::Capability::CAP_FOWNER,
+    caps::Capability::CAP_MKNOD,
+    caps::Capability::CAP_SYS_ADMIN,
+];
+
+// Make the user namespace capabilities of the current thread ambient, so that
+// they are kept by any processes that it runs, such as spfs-fuse
+fn raise_user_namespace_capabilities() -> Result<()> {
+    for cap in USER_NAMESPACE_CAPABILITIES {
+        caps::raise(None, caps::CapSet::Inheritable, cap)?;
+        caps::raise(None, caps::CapSet::Ambient, cap)?;
     }
     Ok(())
 }
 
-// Checks if the current process has the capabilities required
-// to join an existing runtime
-fn have_required_join_capabilities() -> Result<bool> {
+/// Check if the current process has the privileges needed to set up
+/// a runtime as root, without entering a user namespace.
+pub fn have_required_privileges() -> Result<bool> {
     let effective = caps::read(None, caps::CapSet::Effective)?;
-    Ok(effective.contains(&caps::Capability::CAP_SYS_ADMIN)
-        && effective.contains(&caps::Capability::CAP_SYS_CHROOT))
+    Ok(privileges_are_sufficient(
+        nix::unistd::geteuid(),
+        &effective,
+    ))
+}
+
+// Checks if a process with the given effective user and
+// capabilities can set up a runtime as root
+fn privileges_are_sufficient(euid: nix::unistd::Uid, effective: &caps::CapsHashSet) -> bool {
+    euid.is_root()
+        || (effective.contains(&caps::Capability::CAP_SETUID)
+            && effective.contains(&caps::Capability::CAP_SYS_ADMIN))
+}
+
+/// Move the current process into a new user namespace, in which it
+/// can set up a rootless runtime without any privileges on the host.
+///
+/// The current user and group are mapped to themselves, so that any
+/// files created in the runtime remain owned by the calling user. This
+/// must be called before the process has started any other threads.
+///
+/// The process is also moved into the mount namespace for the runtime,
+/// which is owned by a second user namespace nested inside of the first.
+/// The runtime's command is run in the first user namespace, where it
+/// holds no capabilities, but any process of the same user can enter the
+/// nested one to remount or tear down the runtime, see
+/// [`enter_runtime_user_namespace`].
+pub fn enter_user_namespace() -> Result<()> {
+    tracing::debug!("entering user namespace...");
+    check_single_threaded("enter a user namespace")?;
+    let uid = nix::unistd::getuid();
+    let gid = nix::unistd::getgid();
+    if let Err(err) = nix::sched::unshare(nix::sched::CloneFlags::CLONE_NEWUSER) {
+        return Err(Error::wrap_nix(
+            err,
+            "Failed to enter user namespace, unprivileged user namespaces may be disabled on this host",
+        ));
+    }
+
+    write_id_mappings(Path::new("/proc/self"), uid, gid)?;
+    enter_nested_mount_namespace(uid, gid)
+}
+
+/// Move the current process into the user namespace that owns its
+/// mount namespace, if that is nested within its current one.
+///
+/// Processes in a rootless runtime hold no capabilities, but can use this
+/// to gain the ones needed to remount or tear down the runtime, see
+/// [`enter_user_namespace`]. This does nothing in other runtimes. It must
+/// be called before the process has started any other threads.
+pub fn enter_runtime_user_namespace() -> Result<()> {
+    let Some(owner) = nested_mount_namespace_owner()? else {
+        return Ok(());
+    };
+    tracing::debug!("entering user namespace of rootless runtime...");
+    check_single_threaded("enter the user namespace of a rootless runtime")?;
+    if let Err(err) = nix::sched::setns(owner, nix::sched::CloneFlags::CLONE_NEWUSER) {
+        return Err(Error::wrap_nix(
+            err,
+            "Failed to enter user namespace of rootless runtime",
+        ));
+    }
+    Ok(())
+}
+
+// Maps the given user and group to themselves in the user namespace of
+// the process with the given proc directory. An unprivileged process must
+// give up the ability to change its groups before it can write a group mapping
+fn write_id_mappings(proc_dir: &Path, uid: nix::unistd::Uid, gid: nix::unistd::Gid) -> Result<()> {
+    for (file, contents) in [
+        ("setgroups", "deny".to_string()),
+        ("uid_map", format!("{uid} {uid} 1")),
+        ("gid_map", format!("{gid} {gid} 1")),
+    ] {
+        let path = proc_dir.join(file);
+        std::fs::write(&path, contents).map_err(|err| Error::RuntimeWriteError(path, err))?;
+    }
+    Ok(())
+}
+
+/// Move the current process into a new mount namespace, which is owned
+/// by a new user namespace that is nested within its current one.
+///
+/// The current process stays in its own user namespace, but as the owner
+/// of the nested one it still holds all capabilities over the new mount
+/// namespace. Must be called from a single-threaded process.
+fn enter_nested_mount_namespace(uid: nix::unistd::Uid, gid: nix::unistd::Gid) -> Result<()> {
+    use nix::unistd::{ForkResult, fork};
+
+    tracing::debug!("entering nested mount namespace...");
+    let (ready_read, ready_write) = cloexec_pipe()?;
+    let (done_read, done_write) = cloexec_pipe()?;
+
+    // Safety: the process is single-threaded, and the
+    // child process exits without unwinding
+    match unsafe { fork() } {
+        Err(err) => Err(Error::wrap_nix(
+            err,
+            "Failed to fork to create mount namespace",
+        )),
+        Ok(ForkResult::Child) => unsafe {
+            libc::close(done_write.fd);
+            let errno = if libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNS) != 0 {
+                nix::errno::Errno::last() as i32
+            } else {
+                0
+            };
+            write_errno(&ready_write, errno);
+            // the namespaces only exist while a process is in them,
+            // so wait until the parent has joined before exiting
+            wait_for_close(&done_read);
+            libc::_exit(0)
+        },
+        Ok(ForkResult::Parent { child }) => {
+            drop(ready_write);
+            let proc_dir = Path::new("/proc").join(child.to_string());
+            let result = match read_errno(&ready_read) {
+                None => Err(Error::String(
+                    "Process creating the mount namespace exited unexpectedly".into(),
+                )),
+                Some(0) => write_id_mappings(&proc_dir, uid, gid).and_then(|_| {
+                    let ns_path = proc_dir.join("ns/mnt");
+                    let file = std::fs::File::open(&ns_path)
+                        .map_err(|err| Error::RuntimeReadError(ns_path, err))?;
+                    nix::sched::setns(file, nix::sched::CloneFlags::CLONE_NEWNS)
+                        .map_err(|err| Error::wrap_nix(err, "Failed to join mount namespace"))
+                }),
+                Some(errno) => Err(Error::wrap_nix(
+                    nix::errno::Errno::from_raw(errno),
+                    "Failed to create mount namespace for rootless runtime",
+                )),
+            };
+            // closing the pipe lets the child exit
+            drop((done_read, done_write));
+            let _ = nix::sys::wait::waitpid(child, None);
+            result
+        }
+    }
+}
+
+// Opens the user namespace that owns the mount namespace of the current
+// thread, returning None if that is the thread's own user namespace
+fn nested_mount_namespace_owner() -> Result<Option<std::fs::File>> {
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::os::unix::fs::MetadataExt;
+
+    let mount_ns_path = Path::new("/proc/thread-self/ns/mnt");
+    let mount_ns = std::fs::File::open(mount_ns_path)
+        .map_err(|err| Error::RuntimeReadError(mount_ns_path.into(), err))?;
+    // Safety: this only creates a new file descriptor, or fails
+    let fd = unsafe {
+        libc::ioctl(
+            mount_ns.as_raw_fd(),
+            linux_raw_sys::ioctl::NS_GET_USERNS as _,
+        )
+    };
+    if fd < 0 {
+        return Err(Error::wrap_nix(
+            nix::errno::Errno::last(),
+            "Failed to get the owner of the mount namespace",
+        ));
+    }
+    // Safety: the file descriptor was just created and is owned here
+    let owner = unsafe { std::fs::File::from_raw_fd(fd) };
+    let owner_meta = owner
+        .metadata()
+        .map_err(|err| Error::RuntimeReadError(mount_ns_path.into(), err))?;
+
+    let user_ns_path = Path::new("/proc/thread-self/ns/user");
+    let user_ns_meta = std::fs::metadata(user_ns_path)
+        .map_err(|err| Error::RuntimeReadError(user_ns_path.into(), err))?;
+    if (owner_meta.dev(), owner_meta.ino()) == (user_ns_meta.dev(), user_ns_meta.ino()) {
+        Ok(None)
+    } else {
+        Ok(Some(owner))
+    }
 }
 
 const OVERLAY_ARGS_RO_PREFIX: &str = "ro";
@@ -1173,7 +1989,12 @@ fn get_fuse_args(config: &runtime::Config, owner: &IsRootUser, read_only: bool)
         CUSTOM(format!("uid={}", owner.original_uid)),
         CUSTOM(format!("gid={}", nix::unistd::getgid())),
     ];
-    opts.push(if read_only { RO } else { RW });
+    if read_only {
+        opts.push(RO);
+    } else {
+        opts.push(RW);
+        opts.push(CUSTOM(format!("upper={}", config.upper_dir.display())));
+    }
     opts.extend(
         config
             .secondary_repositories
@@ -1214,15 +2035,23 @@ pub fn option_to_string(option: &fuser::MountOption) -> String {
     }
 }
 
+/// True if mounts should be made using syscalls rather than the mount
+/// command, which refuses to run for non-root users and so can never
+/// be used in rootless runtimes.
+fn use_mount_syscalls(rt: &runtime::Runtime) -> Result<bool> {
+    Ok(rt.config.rootless || crate::Config::current()?.filesystem.use_mount_syscalls)
+}
+
 /// Mount overlayfs layers using the mount command.
 async fn mount_overlayfs_command<P: AsRef<Path>>(
     rt: &runtime::Runtime,
     layer_dirs: &[P],
+    target: &Path,
 ) -> Result<()> {
     tracing::debug!("mounting the overlay filesystem using mount...");
     let overlay_args = get_overlay_args(rt, layer_dirs)?;
     let mount = super::resolve::which("mount").unwrap_or_else(|| "/usr/bin/mount".into());
-    tracing::debug!("{mount:?} -t overlay -o {overlay_args} none {SPFS_DIR}",);
+    tracing::debug!("{mount:?} -t overlay -o {overlay_args} none {target:?}",);
     // for some reason, the overlay mount process creates a bad filesystem if the
     // mount command is called directly from this process. It may be some default
     // option or minor detail in how the standard mount command works - possibly related
@@ -1232,7 +2061,7 @@ async fn mount_overlayfs_command<P: AsRef<Path>>(
     cmd.arg("-o");
     cmd.arg(overlay_args);
     cmd.arg("none");
-    cmd.arg(SPFS_DIR);
+    cmd.arg(target);
     match cmd.status().await {
         Err(err) => Err(Error::process_spawn_error("mount", err, None)),
         Ok(status) => match status.code() {
@@ -1243,8 +2072,14 @@ async fn mount_overlayfs_command<P: AsRef<Path>>(
 }
 
 /// Mount overlayfs layers using mount syscalls.
-fn mount_overlayfs_syscalls<P: AsRef<Path>>(rt: &runtime::Runtime, layer_dirs: &[P]) -> Result<()> {
+fn mount_overlayfs_syscalls<P: AsRef<Path>>(
+    rt: &runtime::Runtime,
+    layer_dirs: &[P],
+    target: &Path,
+) -> Result<()> {
     tracing::debug!("mounting the overlay filesystem using syscalls...");
+    let target = CString::new(target.as_os_str().as_encoded_bytes())
+        .map_err(|err| format!("unable to create CString from {target:?}: {err}"))?;
 
     let mount_options = OverlayMountOptions::new(rt);
     let params = runtime::overlayfs::overlayfs_available_options();
@@ -1550,14 +2385,14 @@ fn mount_overlayfs_syscalls<P: AsRef<Path>>(rt: &runtime::Runtime, layer_dirs: &
         .into());
     }
 
-    // Safety: mount_fd is valid and can be mounted at "/spfs".
+    // Safety: mount_fd is valid and can be mounted at the target.
     let rc = unsafe {
         syscall!(
             SYS_move_mount,
             mount_fd,
             EMPTY_CSTR.as_ptr(),
             AT_FDCWD,
-            SPFS_DIR_CSTR.as_ptr(),
+            target.as_ptr(),
             MOVE_MOUNT_F_EMPTY_PATH
         )
     }
@@ -1565,7 +2400,7 @@ fn mount_overlayfs_syscalls<P: AsRef<Path>>(rt: &runtime::Runtime, layer_dirs: &
     if rc != 0 {
         return Err(format!(
             "mount_overlayfs_syscalls::SYS_move_mount({}, \"\", AT_FDCWD, {:?}, MOVE_MOUNT_F_EMPTY_PATH) error: {}",
-            mount_fd, SPFS_DIR_CSTR, rc
+            mount_fd, target, rc
         )
         .into());
     }
@@ -1574,20 +2409,19 @@ fn mount_overlayfs_syscalls<P: AsRef<Path>>(rt: &runtime::Runtime, layer_dirs: &
 }
 
 /// Mount bind mounts from live layers in the runtime over the top of paths inside /spfs.
-async fn mount_live_layers(rt: &runtime::Runtime) -> Result<()> {
+async fn mount_live_layers(rt: &runtime::Runtime, root: &Path) -> Result<()> {
     // This requires the mount destinations to exist under
-    // /spfs/. If they do not, the mount commands will error. The
+    // the root. If they do not, the mount commands will error. The
     // mount destinations are either provided by one of the layers
     // in the runtime, or by an earlier call to
     // ensure_extra_bind_mount_locations_exist() made in
     // initialize_runtime()
     let live_layers = rt.live_layers();
     if !live_layers.is_empty() {
-        let spfs_config = crate::Config::current()?;
-        if spfs_config.filesystem.use_mount_syscalls {
-            mount_live_layers_syscalls(live_layers)?;
+        if use_mount_syscalls(rt)? {
+            mount_live_layers_syscalls(live_layers, root)?;
         } else {
-            mount_live_layers_command(live_layers).await?;
+            mount_live_layers_command(live_layers, root).await?;
         }
     }
 
@@ -1595,7 +2429,10 @@ async fn mount_live_layers(rt: &runtime::Runtime) -> Result<()> {
 }
 
 /// Bind-mount live layers using the "mount" command.
-async fn mount_live_layers_command(live_layers: &Vec<runtime::LiveLayer>) -> Result<()> {
+async fn mount_live_layers_command(
+    live_layers: &Vec<runtime::LiveLayer>,
+    root: &Path,
+) -> Result<()> {
     tracing::debug!(
         "mounting extra bind mounts over the {SPFS_DIR} filesystem using the mount command"
     );
@@ -1605,11 +2442,7 @@ async fn mount_live_layers_command(live_layers: &Vec<runtime::LiveLayer>) -> Res
         let injection_mounts = layer.bind_mounts();
 
         for extra_mount in injection_mounts {
-            let dest = if extra_mount.dest.starts_with(SPFS_DIR_PREFIX) {
-                PathBuf::from(extra_mount.dest.clone())
-            } else {
-                PathBuf::from(SPFS_DIR).join(extra_mount.dest.clone())
-            };
+            let dest = live_layer_mount_dest(&extra_mount.dest, root);
 
             let mut cmd = tokio::process::Command::new(mount.clone());
             cmd.arg("--bind");
@@ -1634,7 +2467,7 @@ async fn mount_live_layers_command(live_layers: &Vec<runtime::LiveLayer>) -> Res
 }
 
 /// Bind-mount live layers using mount syscalls.
-fn mount_live_layers_syscalls(live_layers: &Vec<runtime::LiveLayer>) -> Result<()> {
+fn mount_live_layers_syscalls(live_layers: &Vec<runtime::LiveLayer>, root: &Path) -> Result<()> {
     tracing::debug!(
         "mounting extra bind mounts over the {SPFS_DIR} filesystem using mount syscalls"
     );
@@ -1646,11 +2479,7 @@ fn mount_live_layers_syscalls(live_layers: &Vec<runtime::LiveLayer>) -> Result<(
             let Ok(src) = extra_mount.src.canonicalize() else {
                 return Err(format!("unable to canonicalize {:?}", extra_mount.src).into());
             };
-            let dest = if extra_mount.dest.starts_with(SPFS_DIR_PREFIX) {
-                PathBuf::from(extra_mount.dest.clone())
-            } else {
-                PathBuf::from(SPFS_DIR).join(extra_mount.dest.clone())
-            };
+            let dest = live_layer_mount_dest(&extra_mount.dest, root);
             let src_path = CString::new(src.to_string_lossy().as_ref()).map_err(|err| {
                 format!(
                     "unable to create CString from {}: {err}",
@@ -1705,12 +2534,17 @@ fn mount_live_layers_syscalls(live_layers: &Vec<runtime::LiveLayer>) -> Result<(
     Ok(())
 }
 
+/// The path where a live layer bind mount is placed, when
+/// the runtime's filesystem is mounted at the given root
+fn live_layer_mount_dest(dest: &str, root: &Path) -> PathBuf {
+    root.join(dest.strip_prefix(SPFS_DIR_PREFIX).unwrap_or(dest))
+}
+
 /// Unmount the bind mounted items from the live layers
 async fn unmount_live_layers(rt: &runtime::Runtime) -> Result<()> {
     let live_layers = rt.live_layers();
     if !live_layers.is_empty() {
-        let spfs_config = crate::Config::current()?;
-        if spfs_config.filesystem.use_mount_syscalls {
+        if use_mount_syscalls(rt)? {
             unmount_live_layers_syscalls(live_layers)?;
         } else {
             unmount_live_layers_command(live_layers).await?;
@@ -1771,6 +2605,233 @@ fn unmount_live_layers_syscalls(live_layers: &Vec<runtime::LiveLayer>) -> Result
     Ok(())
 }
 
+/// Bring up the loopback interface, which starts
+/// out down in a new network namespace.
+fn bring_up_loopback() -> Result<()> {
+    tracing::debug!("bringing up loopback interface...");
+    let mut request = loopback_request();
+    // Safety: request is a valid ifreq naming the loopback interface
+    match unsafe { set_loopback_up(&mut request) } {
+        0 => Ok(()),
+        errno => Err(Error::wrap_nix(
+            nix::errno::Errno::from_raw(errno),
+            "Failed to bring up loopback interface",
+        )),
+    }
+}
+
+/// A request that names the loopback interface, see [`set_loopback_up`]
+fn loopback_request() -> libc::ifreq {
+    // Safety: ifreq is a plain C struct that is valid when zeroed
+    let mut request: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dest, src) in request.ifr_name.iter_mut().zip(b"lo") {
+        *dest = *src as libc::c_char;
+    }
+    request
+}
+
+/// Bring up the loopback interface of the current network
+/// namespace, returning zero or the errno of the failed call.
+///
+/// # Safety
+/// The request must be from [`loopback_request`]. Only raw system
+/// calls are made, so this can be called in a child process that
+/// was forked from one with other threads.
+unsafe fn set_loopback_up(request: &mut libc::ifreq) -> i32 {
+    // Safety: the responsibility of the caller
+    unsafe {
+        let fd = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+        if fd < 0 {
+            return nix::errno::Errno::last() as i32;
+        }
+        let mut rc = libc::ioctl(fd, libc::SIOCGIFFLAGS, &mut *request);
+        if rc == 0 {
+            request.ifr_ifru.ifru_flags |= (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short;
+            rc = libc::ioctl(fd, libc::SIOCSIFFLAGS, &*request);
+        }
+        let errno = if rc < 0 {
+            nix::errno::Errno::last() as i32
+        } else {
+            0
+        };
+        libc::close(fd);
+        errno
+    }
+}
+
+/// Make a host directory read-only or hidden, leaving any
+/// allowed paths within it as they were.
+fn restrict_host_path(
+    restriction: &runtime::HostPathRestriction,
+    mode: runtime::HostPaths,
+) -> Result<()> {
+    use nix::mount::{MsFlags, mount};
+
+    if mode.is_visible() {
+        return Ok(());
+    }
+    let path = &restriction.path;
+    tracing::debug!(?path, allowed = ?restriction.allowed, "restricting host path...");
+
+    // allowed paths are copied before the directory is changed,
+    // and then put back over the top of it
+    let allowed = restriction
+        .allowed
+        .iter()
+        .filter_map(|allowed| {
+            let metadata = std::fs::metadata(allowed).ok()?;
+            Some(clone_mount_tree(allowed).map(|fd| (allowed, metadata.is_dir(), fd)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    match mode {
+        runtime::HostPaths::Visible => {}
+        runtime::HostPaths::ReadOnly => {
+            if let Err(err) = mount(
+                Some(path),
+                path,
+                NONE,
+                MsFlags::MS_BIND | MsFlags::MS_REC,
+                NONE,
+            ) {
+                return Err(Error::wrap_nix(
+                    err,
+                    format!("Failed to bind mount {path:?}"),
+                ));
+            }
+            set_mount_tree_read_only(path)?;
+        }
+        runtime::HostPaths::Hidden => {
+            if let Err(err) = mount(
+                NONE,
+                path,
+                Some("tmpfs"),
+                MsFlags::empty(),
+                Some("mode=755"),
+            ) {
+                return Err(Error::wrap_nix(err, format!("Failed to hide {path:?}")));
+            }
+            // mount points are needed for the allowed paths
+            for (allowed, is_dir, _) in allowed.iter() {
+                let target = if *is_dir {
+                    allowed.as_path()
+                } else {
+                    allowed.parent().unwrap_or(allowed)
+                };
+                std::fs::create_dir_all(target)
+                    .map_err(|err| Error::RuntimeWriteError(target.to_owned(), err))?;
+                if !*is_dir {
+                    std::fs::File::create(allowed)
+                        .map_err(|err| Error::RuntimeWriteError(allowed.to_path_buf(), err))?;
+                }
+            }
+        }
+    }
+
+    for (allowed, _, fd) in allowed.iter() {
+        move_mount_tree(fd, allowed)?;
+    }
+
+    if matches!(mode, runtime::HostPaths::Hidden) {
+        let flags = MsFlags::MS_REMOUNT | MsFlags::MS_BIND | MsFlags::MS_RDONLY;
+        if let Err(err) = mount(NONE, path, NONE, flags, NONE) {
+            return Err(Error::wrap_nix(
+                err,
+                format!("Failed to make {path:?} read-only"),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Make a detached copy of the mounts at and below the given path.
+fn clone_mount_tree(path: &Path) -> Result<CloseFd> {
+    let c_path = CString::new(path.as_os_str().as_encoded_bytes())
+        .map_err(|err| format!("unable to create CString from {path:?}: {err}"))?;
+    // Safety: the path is a valid, nul-terminated string.
+    let fd = unsafe {
+        syscall!(
+            SYS_open_tree,
+            AT_FDCWD,
+            c_path.as_ptr(),
+            OPEN_TREE_CLONE | OPEN_TREE_CLOEXEC | AT_RECURSIVE
+        )
+    }
+    .as_u64_unchecked() as i32;
+    if fd < 0 {
+        return Err(format!(
+            "clone_mount_tree::SYS_open_tree(AT_FDCWD, {c_path:?}, OPEN_TREE_CLONE | OPEN_TREE_CLOEXEC | AT_RECURSIVE) error: {fd}"
+        )
+        .into());
+    }
+    Ok(CloseFd::new(fd))
+}
+
+/// Attach a copy of a mount tree from [`clone_mount_tree`] at the given path.
+fn move_mount_tree(mount: &CloseFd, path: &Path) -> Result<()> {
+    move_mount_tree_with_flags(mount, path, 0)
+}
+
+/// Attach a copy of a mount tree from [`clone_mount_tree`] at the
+/// given path, with any additional `MOVE_MOUNT_*` flags.
+fn move_mount_tree_with_flags(mount: &CloseFd, path: &Path, flags: u32) -> Result<()> {
+    let c_path = CString::new(path.as_os_str().as_encoded_bytes())
+        .map_err(|err| format!("unable to create CString from {path:?}: {err}"))?;
+    // Safety: mount.fd is a valid detached mount and the path
+    // is a valid, nul-terminated string.
+    let rc = unsafe {
+        syscall!(
+            SYS_move_mount,
+            mount.fd,
+            EMPTY_CSTR.as_ptr(),
+            AT_FDCWD,
+            c_path.as_ptr(),
+            MOVE_MOUNT_F_EMPTY_PATH | flags
+        )
+    }
+    .as_u64_unchecked() as i32;
+    if rc != 0 {
+        return Err(format!(
+            "move_mount_tree::SYS_move_mount({}, \"\", AT_FDCWD, {c_path:?}, MOVE_MOUNT_F_EMPTY_PATH | {flags:#x}) error: {rc}",
+            mount.fd
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Make the mount at the given path, and every mount below it, read-only.
+fn set_mount_tree_read_only(path: &Path) -> Result<()> {
+    let c_path = CString::new(path.as_os_str().as_encoded_bytes())
+        .map_err(|err| format!("unable to create CString from {path:?}: {err}"))?;
+    let mount_attrs = mount_attr {
+        attr_set: MOUNT_ATTR_RDONLY as u64,
+        attr_clr: 0,
+        propagation: 0,
+        userns_fd: 0,
+    };
+    // Safety: the path is a valid, nul-terminated string and
+    // mount_attr matches the C struct from mount.h.
+    let rc = unsafe {
+        syscall!(
+            SYS_mount_setattr,
+            AT_FDCWD,
+            c_path.as_ptr(),
+            AT_RECURSIVE,
+            std::ptr::addr_of!(mount_attrs),
+            MOUNT_ATTR_SIZE_VER0
+        )
+    }
+    .as_u64_unchecked() as i32;
+    if rc != 0 {
+        return Err(format!(
+            "set_mount_tree_read_only::SYS_mount_setattr(AT_FDCWD, {c_path:?}, AT_RECURSIVE, &mount_attrs, {MOUNT_ATTR_SIZE_VER0}) error: {rc}"
+        )
+        .into());
+    }
+    Ok(())
+}
+
 /// Prevent a structure from being [`Send`].
 struct NotSendMarker(std::marker::PhantomData<*mut u8>);
 
@@ -0,0 +1,28 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use caps::Capability;
+use nix::unistd::Uid;
+use rstest::rstest;
+
+use super::privileges_are_sufficient;
+
+#[rstest]
+#[case::root(Uid::from_raw(0), &[], true)]
+#[case::installed_capabilities(
+    Uid::from_raw(1000),
+    &[Capability::CAP_SETUID, Capability::CAP_SYS_ADMIN],
+    true
+)]
+#[case::no_capabilities(Uid::from_raw(1000), &[], false)]
+#[case::missing_setuid(Uid::from_raw(1000), &[Capability::CAP_SYS_ADMIN], false)]
+#[case::missing_sys_admin(Uid::from_raw(1000), &[Capability::CAP_SETUID], false)]
+fn test_privileges_are_sufficient(
+    #[case] euid: Uid,
+    #[case] effective: &[Capability],
+    #[case] expected: bool,
+) {
+    let effective = effective.iter().copied().collect();
+    assert_eq!(privileges_are_sufficient(euid, &effective), expected);
+}
@@ -116,7 +116,7 @@ async fn find_path_in_spfs_item(
             }
         }
 
-        graph::object::Enum::Blob(_) => {
+        graph::object::Enum::Blob(_) | graph::object::Enum::ChunkedBlob(_) => {
             // Not examined here when searching for the filepath because
             // filepaths are only found by walking Manifest objects.
         }
@@ -22,6 +22,12 @@ pub enum TempRepo {
         http_shutdown: tokio::sync::oneshot::Sender<()>,
         tmpdir: TempDir,
     },
+    S3 {
+        repo: Arc<spfs::storage::RepositoryHandle>,
+        server_join_handle: Option<tokio::task::JoinHandle<()>>,
+        /// dropped along with the repo, which stops the server
+        server_shutdown: tokio::sync::oneshot::Sender<()>,
+    },
 }
 
 impl TempRepo {
@@ -30,6 +36,7 @@ impl TempRepo {
             Self::FS(r, _) => Arc::clone(r),
             Self::Tar(r, _) => Arc::clone(r),
             Self::Rpc { repo, .. } => Arc::clone(repo),
+            Self::S3 { repo, .. } => Arc::clone(repo),
         }
     }
 
@@ -67,6 +74,7 @@ impl std::ops::Deref for TempRepo {
             Self::FS(r, _) => r,
             Self::Tar(r, _) => r,
             Self::Rpc { repo, .. } => repo,
+            Self::S3 { repo, .. } => repo,
         }
     }
 }
@@ -235,10 +243,263 @@ pub async fn tmprepo(kind: &str) -> TempRepo {
                 tmpdir,
             }
         }
+        #[cfg(feature = "server")]
+        "s3" => {
+            use crate::storage::prelude::*;
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let endpoint: url::Url = format!("http://{}", listener.local_addr().unwrap())
+                .parse()
+                .unwrap();
+            let (server_shutdown, server_shutdown_recv) = tokio::sync::oneshot::channel::<()>();
+            let server_join_handle =
+                tokio::task::spawn(s3_standin::serve(listener, server_shutdown_recv));
+            let config = spfs::storage::s3::Config {
+                address: "s3://spfs-test/repo".parse().unwrap(),
+                params: spfs::storage::s3::Params {
+                    endpoint: Some(endpoint),
+                    access_key_id: Some("spfs-test".into()),
+                    secret_access_key: Some("spfs-test-secret".into()),
+                    ..Default::default()
+                },
+            };
+            let repo = spfs::storage::s3::S3Repository::from_config(config)
+                .await
+                .unwrap()
+                .into();
+            TempRepo::S3 {
+                repo: Arc::new(repo),
+                server_join_handle: Some(server_join_handle),
+                server_shutdown,
+            }
+        }
         _ => panic!("unknown repo kind '{kind}'"),
     }
 }
 
+/// A minimal, in-memory stand-in for an s3-compatible object store.
+///
+/// Supports only the subset of the api used by the s3 repository and
+/// does not validate request signatures.
+#[cfg(feature = "server")]
+mod s3_standin {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    use bytes::Bytes;
+    use chrono::{DateTime, Utc};
+    use http_body_util::{BodyExt, Full};
+    use hyper::http::{Method, StatusCode, header};
+
+    /// Deliberately small so that tests exercise pagination
+    const LIST_PAGE_SIZE: usize = 10;
+
+    struct StoredObject {
+        data: Bytes,
+        etag: String,
+        last_modified: DateTime<Utc>,
+    }
+
+    type Store = Arc<Mutex<BTreeMap<String, StoredObject>>>;
+    type Response = hyper::Response<Full<Bytes>>;
+
+    pub async fn serve(
+        listener: tokio::net::TcpListener,
+        mut shutdown: tokio::sync::oneshot::Receiver<()>,
+    ) {
+        let store = Store::default();
+        loop {
+            let stream = tokio::select! {
+                conn = listener.accept() => match conn {
+                    Ok((stream, _)) => stream,
+                    Err(err) => {
+                        tracing::error!("Error accepting connection: {err:?}");
+                        continue;
+                    }
+                },
+                _ = &mut shutdown => break,
+            };
+            let io = hyper_util::rt::TokioIo::new(stream);
+            let store = Arc::clone(&store);
+            tokio::task::spawn(async move {
+                let service = hyper::service::service_fn(move |req| {
+                    let store = Arc::clone(&store);
+                    async move { Ok::<_, std::convert::Infallible>(handle(store, req).await) }
+                });
+                if let Err(err) = hyper::server::conn::http1::Builder::new()
+                    .serve_connection(io, service)
+                    .await
+                {
+                    tracing::error!("Error serving connection: {err:?}");
+                }
+            });
+        }
+    }
+
+    async fn handle(store: Store, req: hyper::Request<hyper::body::Incoming>) -> Response {
+        if !req.headers().contains_key(header::AUTHORIZATION) {
+            return error(StatusCode::FORBIDDEN, "AccessDenied");
+        }
+        let path = percent_decode(req.uri().path().trim_start_matches('/'));
+        let query: BTreeMap<String, String> =
+            url::form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+                .into_owned()
+                .collect();
+        let (method, headers) = (req.method().clone(), req.headers().clone());
+        let data = req.into_body().collect().await.unwrap().to_bytes();
+        let mut store = store.lock().unwrap();
+        match (method.clone(), path.split_once('/')) {
+            (Method::GET, None) if query.get("list-type").map(String::as_str) == Some("2") => {
+                list(&store, &path, &query)
+            }
+            (Method::GET | Method::HEAD, Some(_)) => match store.get(&path) {
+                None => error(StatusCode::NOT_FOUND, "NoSuchKey"),
+                Some(object) => hyper::Response::builder()
+                    .header(header::ETAG, &object.etag)
+                    .header(
+                        header::LAST_MODIFIED,
+                        object
+                            .last_modified
+                            .format("%a, %d %b %Y %H:%M:%S GMT")
+                            .to_string(),
+                    )
+                    .header(header::CONTENT_LENGTH, object.data.len())
+                    .body(match method {
+                        Method::HEAD => Full::default(),
+                        _ => Full::new(object.data.clone()),
+                    })
+                    .unwrap(),
+            },
+            (Method::PUT, Some(_)) => {
+                let existing = store.get(&path).map(|o| o.etag.as_str());
+                let if_match = headers.get(header::IF_MATCH).and_then(|v| v.to_str().ok());
+                let if_none_match = headers.get(header::IF_NONE_MATCH).is_some();
+                if (if_none_match && existing.is_some())
+                    || if_match.is_some_and(|etag| Some(etag) != existing)
+                {
+                    return error(StatusCode::PRECONDITION_FAILED, "PreconditionFailed");
+                }
+                let etag = format!(
+                    "\"{}\"",
+                    data_encoding::HEXLOWER
+                        .encode(ring::digest::digest(&ring::digest::SHA256, &data).as_ref())
+                );
+                store.insert(
+                    path,
+                    StoredObject {
+                        data,
+                        etag: etag.clone(),
+                        last_modified: Utc::now(),
+                    },
+                );
+                hyper::Response::builder()
+                    .header(header::ETAG, etag)
+                    .body(Full::default())
+                    .unwrap()
+            }
+            (Method::DELETE, Some(_)) => {
+                store.remove(&path);
+                hyper::Response::builder()
+                    .status(StatusCode::NO_CONTENT)
+                    .body(Full::default())
+                    .unwrap()
+            }
+            _ => error(StatusCode::NOT_IMPLEMENTED, "NotImplemented"),
+        }
+    }
+
+    fn list(
+        store: &BTreeMap<String, StoredObject>,
+        bucket: &str,
+        query: &BTreeMap<String, String>,
+    ) -> Response {
+        let bucket_prefix = format!("{bucket}/");
+        let prefix = query.get("prefix").cloned().unwrap_or_default();
+        let delimiter = query.get("delimiter").filter(|d| !d.is_empty());
+        // the continuation token is simply the last entry of the previous page
+        let after = query.get("continuation-token");
+
+        // objects and common prefixes are paginated together, in order
+        let mut entries = std::collections::BTreeSet::new();
+        for key in store.keys() {
+            let Some(key) = key.strip_prefix(&bucket_prefix) else {
+                continue;
+            };
+            let Some(rest) = key.strip_prefix(&prefix) else {
+                continue;
+            };
+            let entry = match delimiter.and_then(|d| rest.find(d.as_str()).map(|i| (d, i))) {
+                Some((d, i)) => (format!("{prefix}{}", &rest[..i + d.len()]), true),
+                None => (key.to_string(), false),
+            };
+            if after.is_some_and(|after| &entry.0 <= after) {
+                continue;
+            }
+            entries.insert(entry);
+        }
+        let truncated = entries.len() > LIST_PAGE_SIZE;
+        let page = entries.into_iter().take(LIST_PAGE_SIZE).collect::<Vec<_>>();
+
+        let mut body = String::from("<ListBucketResult>");
+        body.push_str(&format!("<Prefix>{}</Prefix>", escape(&prefix)));
+        for (entry, is_prefix) in page.iter() {
+            match is_prefix {
+                true => body.push_str(&format!(
+                    "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+                    escape(entry)
+                )),
+                false => body.push_str(&format!(
+                    "<Contents><Key>{}</Key></Contents>",
+                    escape(entry)
+                )),
+            }
+        }
+        body.push_str(&format!("<IsTruncated>{truncated}</IsTruncated>"));
+        if let (true, Some((last, _))) = (truncated, page.last()) {
+            body.push_str(&format!(
+                "<NextContinuationToken>{}</NextContinuationToken>",
+                escape(last)
+            ));
+        }
+        body.push_str("</ListBucketResult>");
+        hyper::Response::new(Full::new(body.into()))
+    }
+
+    fn error(status: StatusCode, code: &str) -> Response {
+        hyper::Response::builder()
+            .status(status)
+            .body(Full::new(
+                format!("<Error><Code>{code}</Code><Message>{code}</Message></Error>").into(),
+            ))
+            .unwrap()
+    }
+
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    }
+
+    fn percent_decode(text: &str) -> String {
+        let bytes = text.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'%' if i + 3 <= bytes.len() => {
+                    let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap();
+                    decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                    i += 3;
+                }
+                byte => {
+                    decoded.push(byte);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8(decoded).unwrap()
+    }
+}
+
 pub fn ensure(path: std::path::PathBuf, data: &str) {
     std::fs::create_dir_all(path.parent().unwrap()).expect("failed to make dirs");
     let mut file = std::fs::OpenOptions::new()
@@ -0,0 +1,180 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use super::object::{EncodingFormat, HeaderBuilder};
+use super::{Blob, ObjectKind};
+use crate::encoding::Digest;
+
+#[cfg(test)]
+#[path = "./chunked_blob_test.rs"]
+mod chunked_blob_test;
+
+/// Chunked blobs represent a large payload that is stored as a
+/// sequence of smaller, content-defined chunks.
+///
+/// Like a [`Blob`], a chunked blob shares its digest with the
+/// complete payload that it represents, so that it can be used
+/// anywhere that a blob is expected. Each chunk is itself stored
+/// as a blob, and the complete payload is the concatenation of
+/// all chunks in order.
+pub type ChunkedBlob = super::FlatObject<spfs_proto::ChunkedBlob<'static>>;
+
+impl std::fmt::Debug for ChunkedBlob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChunkedBlob")
+            .field("payload", &self.payload().to_string())
+            .field("size", &self.size())
+            .field("chunks", &self.proto().chunks().len())
+            .finish()
+    }
+}
+
+impl ChunkedBlob {
+    #[inline]
+    pub fn builder() -> ChunkedBlobBuilder {
+        ChunkedBlobBuilder::default()
+    }
+
+    #[inline]
+    pub fn digest(&self) -> &Digest {
+        self.proto().payload()
+    }
+
+    /// The digest of the complete, reassembled payload
+    #[inline]
+    pub fn payload(&self) -> &Digest {
+        self.digest()
+    }
+
+    /// The size of the complete, reassembled payload
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.proto().size_()
+    }
+
+    /// Iterate the chunks of this blob in the order that
+    /// they appear in the complete payload
+    pub fn iter_chunks(&self) -> impl Iterator<Item = Blob> + '_ {
+        self.proto()
+            .chunks()
+            .iter()
+            .map(|chunk| Blob::new(*chunk.payload(), chunk.size_()))
+    }
+
+    /// Return the digests of objects that this blob refers to.
+    pub fn child_objects(&self) -> Vec<Digest> {
+        self.proto()
+            .chunks()
+            .iter()
+            .map(|chunk| *chunk.payload())
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct ChunkedBlobBuilder {
+    header: HeaderBuilder,
+    payload: Digest,
+    chunks: Vec<(Digest, u64)>,
+}
+
+impl Default for ChunkedBlobBuilder {
+    fn default() -> Self {
+        Self {
+            // chunked blobs were never supported by the legacy
+            // encoding format, and so always use flatbuffers
+            header: HeaderBuilder::new(ObjectKind::ChunkedBlob)
+                .with_encoding_format(EncodingFormat::FlatBuffers),
+            payload: Default::default(),
+            chunks: Default::default(),
+        }
+    }
+}
+
+impl ChunkedBlobBuilder {
+    pub fn with_header<F>(mut self, mut header: F) -> Self
+    where
+        F: FnMut(HeaderBuilder) -> HeaderBuilder,
+    {
+        self.header = header(self.header)
+            .with_object_kind(ObjectKind::ChunkedBlob)
+            .with_encoding_format(EncodingFormat::FlatBuffers);
+        self
+    }
+
+    /// The digest of the complete, reassembled payload
+    pub fn with_payload(mut self, payload: Digest) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Append a chunk to the end of this blob
+    pub fn with_chunk(mut self, payload: Digest, size: u64) -> Self {
+        self.chunks.push((payload, size));
+        self
+    }
+
+    /// Append a sequence of chunks to the end of this blob
+    pub fn with_chunks<I>(mut self, chunks: I) -> Self
+    where
+        I: IntoIterator<Item = (Digest, u64)>,
+    {
+        self.chunks.extend(chunks);
+        self
+    }
+
+    /// Build the chunked blob, whose size is
+    /// the total size of all of its chunks
+    pub fn build(&self) -> ChunkedBlob {
+        super::BUILDER.with_borrow_mut(|builder| {
+            let chunks = self
+                .chunks
+                .iter()
+                .map(|(payload, size)| {
+                    spfs_proto::BlobChunk::create(
+                        builder,
+                        &spfs_proto::BlobChunkArgs {
+                            size_: *size,
+                            payload: Some(payload),
+                        },
+                    )
+                })
+                .collect::<Vec<_>>();
+            let chunks = builder.create_vector(&chunks);
+            let blob = spfs_proto::ChunkedBlob::create(
+                builder,
+                &spfs_proto::ChunkedBlobArgs {
+                    size_: self.chunks.iter().map(|(_, size)| size).sum(),
+                    payload: Some(&self.payload),
+                    chunks: Some(chunks),
+                },
+            );
+            let any = spfs_proto::AnyObject::create(
+                builder,
+                &spfs_proto::AnyObjectArgs {
+                    object_type: spfs_proto::Object::ChunkedBlob,
+                    object: Some(blob.as_union_value()),
+                },
+            );
+            builder.finish_minimal(any);
+            let offset = unsafe {
+                // Safety: we have just created this buffer
+                // so already know the root type with certainty
+                flatbuffers::root_unchecked::<spfs_proto::AnyObject>(builder.finished_data())
+                    .object_as_chunked_blob()
+                    .unwrap()
+                    ._tab
+                    .loc()
+            };
+            let obj = unsafe {
+                // Safety: the provided buf and offset mut contain
+                // a valid object and point to the contained blob
+                // which is what we've done
+                ChunkedBlob::new_with_header(self.header.build(), builder.finished_data(), offset)
+            };
+            builder.reset(); // to be used again
+            obj
+        })
+    }
+}
@@ -0,0 +1,61 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use rstest::rstest;
+
+use super::ChunkedBlob;
+use crate::encoding;
+use crate::encoding::prelude::*;
+use crate::graph::object::{EncodingFormat, HeaderBuilder};
+
+#[rstest]
+#[serial_test::serial(config)]
+fn test_chunked_blob_encoding() {
+    let payload = encoding::Hasher::hash_reader(b"first chunksecond chunk".as_slice()).unwrap();
+    let first = encoding::Hasher::hash_reader(b"first chunk".as_slice()).unwrap();
+    let second = encoding::Hasher::hash_reader(b"second chunk".as_slice()).unwrap();
+    let expected = ChunkedBlob::builder()
+        .with_header(|h| h.with_encoding_format(EncodingFormat::Legacy))
+        .with_payload(payload)
+        .with_chunk(first, 11)
+        .with_chunk(second, 12)
+        .build();
+
+    let mut stream = Vec::new();
+    expected.encode(&mut stream).unwrap();
+    let actual = crate::graph::Object::decode(&mut stream.as_slice())
+        .unwrap()
+        .into_chunked_blob()
+        .unwrap();
+    assert_eq!(actual.inner_bytes(), expected.inner_bytes());
+    assert_eq!(actual.size(), 23, "size should be the total of all chunks");
+    assert_eq!(
+        actual.digest().unwrap(),
+        payload,
+        "chunked blobs should share a digest with their payload"
+    );
+    let chunks = actual
+        .iter_chunks()
+        .map(|c| (*c.payload(), c.size()))
+        .collect::<Vec<_>>();
+    assert_eq!(chunks, vec![(first, 11), (second, 12)]);
+    assert_eq!(actual.child_objects(), vec![first, second]);
+}
+
+#[rstest]
+#[serial_test::serial(config)]
+fn test_chunked_blob_always_flatbuffers() {
+    let blob = ChunkedBlob::builder()
+        .with_header(|_| HeaderBuilder::new(crate::graph::ObjectKind::Blob))
+        .build();
+    assert_eq!(
+        blob.header().encoding_format(),
+        Some(EncodingFormat::FlatBuffers),
+        "the legacy format has no representation for chunked blobs"
+    );
+    assert_eq!(
+        blob.header().object_kind(),
+        Some(crate::graph::ObjectKind::ChunkedBlob)
+    );
+}
@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 // https://github.com/spkenv/spk
 
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 use std::pin::Pin;
 use std::task::Poll;
 
@@ -155,6 +155,20 @@ pub trait DatabaseView: Sync + Send {
     /// Return true if this database contains the identified object
     async fn has_object(&self, digest: encoding::Digest) -> bool;
 
+    /// Return the subset of the identified objects that exist in this database.
+    ///
+    /// By default this checks each object individually.
+    /// Other implementations may provide better results.
+    async fn has_objects(&self, digests: &[encoding::Digest]) -> HashSet<encoding::Digest> {
+        let mut existing = HashSet::new();
+        for digest in digests {
+            if self.has_object(*digest).await {
+                existing.insert(*digest);
+            }
+        }
+        existing
+    }
+
     /// Iterate all the object in this database.
     fn iter_objects(&self) -> DatabaseIterator<'_>;
 
@@ -228,6 +242,10 @@ impl<T: DatabaseView> DatabaseView for &T {
         DatabaseView::has_object(&**self, digest).await
     }
 
+    async fn has_objects(&self, digests: &[encoding::Digest]) -> HashSet<encoding::Digest> {
+        DatabaseView::has_objects(&**self, digests).await
+    }
+
     async fn read_object(&self, digest: encoding::Digest) -> Result<Object> {
         DatabaseView::read_object(&**self, digest).await
     }
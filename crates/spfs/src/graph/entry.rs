@@ -7,7 +7,7 @@ use std::io::BufRead;
 use encoding::prelude::*;
 use spfs_proto::EntryArgs;
 
-use crate::{Result, encoding, tracking};
+use crate::{Error, Result, encoding, tracking};
 
 #[cfg(test)]
 #[path = "./entry_test.rs"]
@@ -26,6 +26,11 @@ impl std::fmt::Debug for Entry<'_> {
             .field("mode", &self.mode())
             .field("size", &self.size())
             .field("object", self.object())
+            .field(
+                "xattrs",
+                &self.xattrs().map(|(name, _)| name).collect::<Vec<_>>(),
+            )
+            .field("hardlink", &self.hardlink())
             .finish()
     }
 }
@@ -46,8 +51,30 @@ impl<'buf> Entry<'buf> {
         mode: u32,
         size: u64,
         object: &encoding::Digest,
+        xattrs: &tracking::Xattrs,
+        hardlink: Option<&str>,
     ) -> flatbuffers::WIPOffset<spfs_proto::Entry<'fbb>> {
         let name = builder.create_string(name);
+        let hardlink = hardlink.map(|path| builder.create_string(path));
+        // leave the field empty when possible, so that entries
+        // without any xattrs are identical to older ones
+        let xattrs = (!xattrs.is_empty()).then(|| {
+            let xattrs = xattrs
+                .iter()
+                .map(|(name, value)| {
+                    let name = builder.create_string(name);
+                    let value = builder.create_vector(value);
+                    spfs_proto::Xattr::create(
+                        builder,
+                        &spfs_proto::XattrArgs {
+                            name: Some(name),
+                            value: Some(value),
+                        },
+                    )
+                })
+                .collect::<Vec<_>>();
+            builder.create_vector(&xattrs)
+        });
         spfs_proto::Entry::create(
             builder,
             &EntryArgs {
@@ -56,6 +83,8 @@ impl<'buf> Entry<'buf> {
                 mode,
                 size_: size,
                 object: Some(object),
+                xattrs,
+                hardlink,
             },
         )
     }
@@ -72,6 +101,8 @@ impl<'buf> Entry<'buf> {
             entry.mode,
             entry.size_for_legacy_encode(),
             &entry.object,
+            &entry.xattrs,
+            entry.hardlink.as_deref(),
         )
     }
 
@@ -107,6 +138,35 @@ impl<'buf> Entry<'buf> {
         self.0.object()
     }
 
+    /// Iterate the extended attributes of this entry, sorted by name
+    pub fn xattrs(&self) -> impl Iterator<Item = (&'buf str, &'buf [u8])> {
+        self.0
+            .xattrs()
+            .into_iter()
+            .flatten()
+            .map(|xattr| (xattr.name(), xattr.value().bytes()))
+    }
+
+    /// True if this entry has any extended attributes
+    #[inline]
+    pub fn has_xattrs(&self) -> bool {
+        self.0.xattrs().is_some_and(|xattrs| !xattrs.is_empty())
+    }
+
+    /// Collect the extended attributes of this entry
+    pub fn to_xattrs(&self) -> tracking::Xattrs {
+        self.xattrs()
+            .map(|(name, value)| (name.to_owned(), value.to_owned()))
+            .collect()
+    }
+
+    /// The manifest path of the entry that this file
+    /// was hard linked to when it was captured, if any
+    #[inline]
+    pub fn hardlink(&self) -> Option<&'buf str> {
+        self.0.hardlink()
+    }
+
     #[inline]
     pub fn is_symlink(&self) -> bool {
         unix_mode::is_symlink(self.mode())
@@ -174,7 +234,7 @@ impl encoding::Digestible for Entry<'_> {
 
     fn digest(&self) -> std::result::Result<spfs_proto::Digest, Self::Error> {
         let mut hasher = encoding::Hasher::new_sync();
-        self.legacy_encode(&mut hasher)?;
+        self.digest_encode(&mut hasher)?;
         Ok(hasher.digest())
     }
 }
@@ -185,11 +245,39 @@ impl Entry<'_> {
         self.kind().encode(&mut *writer)?;
         encoding::write_uint64(&mut *writer, self.mode() as u64)?;
         encoding::write_uint64(&mut *writer, self.size_for_legacy_encode())?;
-        encoding::write_string(writer, self.name())?;
+        encoding::write_string(&mut *writer, self.name())?;
+        // xattrs and hard links are only included when present so
+        // that the digest of all existing entries remains unchanged
+        let hardlink = self.hardlink();
+        if self.has_xattrs() || hardlink.is_some() {
+            encoding::write_uint64(&mut *writer, self.xattrs().count() as u64)?;
+            for (name, value) in self.xattrs() {
+                encoding::write_string(&mut *writer, name)?;
+                encoding::write_uint64(&mut *writer, value.len() as u64)?;
+                writer
+                    .write_all(value)
+                    .map_err(encoding::Error::FailedWrite)?;
+            }
+        }
+        if let Some(hardlink) = hardlink {
+            encoding::write_string(&mut *writer, hardlink)?;
+        }
         Ok(())
     }
 
     pub(super) fn legacy_encode(&self, writer: &mut impl std::io::Write) -> Result<()> {
+        if self.has_xattrs() {
+            return Err(Error::String(format!(
+                "Entry '{}' has extended attributes, which cannot be stored in the legacy encoding format",
+                self.name()
+            )));
+        }
+        if self.hardlink().is_some() {
+            return Err(Error::String(format!(
+                "Entry '{}' is a hard link, which cannot be stored in the legacy encoding format",
+                self.name()
+            )));
+        }
         encoding::write_digest(&mut *writer, self.object())?;
         self.kind().encode(&mut *writer)?;
         encoding::write_uint64(&mut *writer, self.mode() as u64)?;
@@ -211,7 +299,16 @@ impl Entry<'_> {
         if kind.is_blob() {
             kind = tracking::EntryKind::Blob(size);
         }
-        Ok(Self::build(builder, &name, kind, mode, size, &object))
+        Ok(Self::build(
+            builder,
+            &name,
+            kind,
+            mode,
+            size,
+            &object,
+            &Default::default(),
+            None,
+        ))
     }
 }
 
@@ -246,6 +343,8 @@ impl EntryBuf {
                         }
                     },
                     name: Some(name),
+                    xattrs: None,
+                    hardlink: None,
                 },
             );
             builder.finish_minimal(e);
@@ -277,6 +376,8 @@ impl EntryBuf {
                         }
                     },
                     name: Some(name),
+                    xattrs: None,
+                    hardlink: None,
                 },
             );
             builder.finish_minimal(e);
@@ -13,6 +13,7 @@ pub enum ObjectKind {
     Platform = 3,
     Tree = 4,
     Mask = 5,
+    ChunkedBlob = 6,
 }
 
 impl ObjectKind {
@@ -27,6 +28,7 @@ impl ObjectKind {
             x if x == spfs_proto::Object::Manifest => Some(Self::Manifest),
             x if x == spfs_proto::Object::Layer => Some(Self::Layer),
             x if x == spfs_proto::Object::Platform => Some(Self::Platform),
+            x if x == spfs_proto::Object::ChunkedBlob => Some(Self::ChunkedBlob),
             _ => None,
         }
     }
@@ -99,3 +101,17 @@ impl HasKind for spfs_proto::Blob<'_> {
         <Self as Kind>::kind()
     }
 }
+
+impl Kind for spfs_proto::ChunkedBlob<'_> {
+    #[inline]
+    fn kind() -> ObjectKind {
+        ObjectKind::ChunkedBlob
+    }
+}
+
+impl HasKind for spfs_proto::ChunkedBlob<'_> {
+    #[inline]
+    fn kind(&self) -> ObjectKind {
+        <Self as Kind>::kind()
+    }
+}
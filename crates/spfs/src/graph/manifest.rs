@@ -128,6 +128,8 @@ impl Manifest {
                     object: *entry.object(),
                     user_data: (),
                     legacy_size: entry.size_for_legacy_encode(),
+                    xattrs: entry.to_xattrs(),
+                    hardlink: entry.hardlink().map(ToOwned::to_owned),
                 };
                 if entry.kind().is_tree() {
                     new_entry.object = encoding::NULL_DIGEST.into();
@@ -322,6 +324,8 @@ impl ManifestBuilder {
                         node.entry.mode,
                         node.entry.size_for_legacy_encode(),
                         &sub_root_digest,
+                        &node.entry.xattrs,
+                        node.entry.hardlink.as_deref(),
                     )
                 }
                 _ => Entry::from(builder, node.path.as_str(), node.entry),
@@ -5,6 +5,7 @@
 use rstest::rstest;
 
 use super::Manifest;
+use crate::encoding::Digestible;
 use crate::tracking::{self, EntryKind};
 
 #[rstest]
@@ -36,3 +37,36 @@ fn test_manifest_from_tracking_manifest() {
 
     assert!(tm == gm2tm);
 }
+
+#[rstest]
+fn test_manifest_xattrs_round_trip() {
+    let mut plain = tracking::Manifest::<()>::default();
+    plain.mkdir("bin").unwrap();
+    plain.mkfile("bin/ping").unwrap();
+    let plain = plain.to_graph_manifest();
+
+    let mut tm = tracking::Manifest::<()>::default();
+    let mut dir = tracking::Entry::empty_dir_with_open_perms();
+    dir.xattrs.insert(
+        "security.selinux".into(),
+        b"system_u:object_r:bin_t:s0".to_vec(),
+    );
+    tm.mknod("bin", dir).unwrap();
+    let mut file = tracking::Entry::empty_file_with_open_perms();
+    file.xattrs
+        .insert("security.capability".into(), vec![1, 0, 0, 2]);
+    tm.mknod("bin/ping", file).unwrap();
+
+    let gm = tm.to_graph_manifest();
+    let gm2tm = gm.to_tracking_manifest();
+    assert!(tm == gm2tm, "xattrs should survive conversion");
+    assert_eq!(
+        gm2tm.get_path("bin/ping").unwrap().xattrs,
+        tm.get_path("bin/ping").unwrap().xattrs
+    );
+    assert_ne!(
+        gm.digest().unwrap(),
+        plain.digest().unwrap(),
+        "xattrs should be included in the manifest digest"
+    );
+}
@@ -6,6 +6,7 @@
 
 mod annotation;
 mod blob;
+mod chunked_blob;
 mod database;
 mod entry;
 pub mod error;
@@ -25,6 +26,7 @@ pub use annotation::{
     DEFAULT_SPFS_ANNOTATION_LAYER_MAX_STRING_VALUE_SIZE,
 };
 pub use blob::Blob;
+pub use chunked_blob::ChunkedBlob;
 pub use database::{
     Database,
     DatabaseExt,
@@ -10,7 +10,18 @@ use encoding::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use super::error::{ObjectError, ObjectResult};
-use super::{Annotation, Blob, DatabaseView, HasKind, Kind, Layer, Manifest, ObjectKind, Platform};
+use super::{
+    Annotation,
+    Blob,
+    ChunkedBlob,
+    DatabaseView,
+    HasKind,
+    Kind,
+    Layer,
+    Manifest,
+    ObjectKind,
+    Platform,
+};
 use crate::encoding;
 use crate::storage::RepositoryHandle;
 
@@ -99,6 +110,11 @@ impl Object {
                         // separately into files and so should not appear in this context
                         return Err(ObjectError::UnexpectedKind(kind as u8).into());
                     }
+                    ObjectKind::ChunkedBlob => {
+                        // chunked blobs were added after the legacy format and
+                        // are always encoded as flatbuffers
+                        return Err(ObjectError::UnexpectedKind(kind as u8).into());
+                    }
                 };
                 Ok(object)
             }
@@ -165,6 +181,7 @@ impl Object {
             Enum::Layer(layer) => layer.child_objects(),
             Enum::Manifest(manifest) => manifest.child_objects(),
             Enum::Blob(_blob) => Vec::new(),
+            Enum::ChunkedBlob(blob) => blob.child_objects(),
         }
     }
 
@@ -205,6 +222,7 @@ impl Object {
                         }
                     }
                     Enum::Blob(object) => total_size += object.size(),
+                    Enum::ChunkedBlob(object) => total_size += object.size(),
                 }
             }
             items_to_process = std::mem::take(&mut next_iter_objects);
@@ -230,11 +248,13 @@ impl<T: ObjectProto> encoding::Digestible for FlatObject<T> {
             super::error::ObjectError::UnknownDigestStrategy(header.digest_strategy_number())
         })?;
         let variant = self.to_enum();
-        if let Enum::Blob(b) = variant {
+        match variant {
             // blobs share a digest with the payload that they represent.
             // Much of the codebase leverages this fact to skip additional
             // steps, just as we are doing here to avoid running the hasher
-            return Ok(*b.payload());
+            Enum::Blob(b) => return Ok(*b.payload()),
+            Enum::ChunkedBlob(b) => return Ok(*b.payload()),
+            _ => {}
         };
         let mut hasher = encoding::Hasher::new_sync();
         match strategy {
@@ -255,7 +275,7 @@ impl<T: ObjectProto> encoding::Digestible for FlatObject<T> {
             Enum::Platform(obj) => obj.digest_encode(&mut hasher)?,
             Enum::Layer(obj) => obj.digest_encode(&mut hasher)?,
             Enum::Manifest(obj) => obj.digest_encode(&mut hasher)?,
-            Enum::Blob(_obj) => unreachable!("handled above"),
+            Enum::Blob(_) | Enum::ChunkedBlob(_) => unreachable!("handled above"),
         }
         Ok(hasher.digest())
     }
@@ -279,6 +299,9 @@ impl<T: ObjectProto> encoding::Encodable for FlatObject<T> {
                     Enum::Manifest(obj) => obj.legacy_encode(&mut writer),
                     Enum::Layer(obj) => obj.legacy_encode(&mut writer),
                     Enum::Platform(obj) => obj.legacy_encode(&mut writer),
+                    Enum::ChunkedBlob(_) => {
+                        Err(ObjectError::UnexpectedKind(ObjectKind::ChunkedBlob as u8).into())
+                    }
                 }
             }
             EncodingFormat::FlatBuffers => {
@@ -309,6 +332,7 @@ pub enum Enum {
     Layer(super::Layer),
     Manifest(super::Manifest),
     Blob(super::Blob),
+    ChunkedBlob(super::ChunkedBlob),
 }
 
 impl HasKind for Enum {
@@ -318,6 +342,7 @@ impl HasKind for Enum {
             Enum::Layer(_) => super::ObjectKind::Layer,
             Enum::Manifest(_) => super::ObjectKind::Manifest,
             Enum::Blob(_) => super::ObjectKind::Blob,
+            Enum::ChunkedBlob(_) => super::ObjectKind::ChunkedBlob,
         }
     }
 }
@@ -434,6 +459,11 @@ impl<T: ObjectProto> FlatObject<T> {
                 offset,
                 _t: PhantomData,
             }),
+            spfs_proto::Object::ChunkedBlob => Enum::ChunkedBlob(ChunkedBlob {
+                buf: self.buf,
+                offset,
+                _t: PhantomData,
+            }),
             spfs_proto::Object::NONE | spfs_proto::Object(spfs_proto::Object::ENUM_MAX..) => {
                 unreachable!("already recognized kind")
             }
@@ -472,6 +502,14 @@ impl<T: ObjectProto> FlatObject<T> {
         }
     }
 
+    pub fn into_chunked_blob(self) -> Option<super::ChunkedBlob> {
+        if let Enum::ChunkedBlob(l) = self.into_enum() {
+            Some(l)
+        } else {
+            None
+        }
+    }
+
     /// Clone (cheaply) this object and make a generic one
     #[inline]
     pub fn to_object(&self) -> Object {
@@ -860,6 +898,7 @@ mod private {
     impl Sealed for spfs_proto::Layer<'_> {}
     impl Sealed for spfs_proto::Manifest<'_> {}
     impl Sealed for spfs_proto::Blob<'_> {}
+    impl Sealed for spfs_proto::ChunkedBlob<'_> {}
 
     impl<T> super::ObjectProto for T where T: Sealed {}
 }
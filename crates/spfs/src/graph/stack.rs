@@ -106,6 +106,26 @@ impl Stack {
         true
     }
 
+    /// Remove an item from the stack, wherever it is.
+    ///
+    /// False is returned if the digest was not in the stack.
+    pub fn remove(&mut self, digest: &Digest) -> bool {
+        let mut node = &mut self.bottom;
+        loop {
+            match node {
+                None => return false,
+                Some(entry) if entry.value == *digest => {
+                    let replace = entry.next.take();
+                    *node = replace;
+                    return true;
+                }
+                Some(entry) => {
+                    node = &mut entry.next;
+                }
+            };
+        }
+    }
+
     /// Iterate the stack lazily from bottom to top
     pub fn iter_bottom_up(&self) -> Iter<'_> {
         Iter(self.bottom.as_deref())
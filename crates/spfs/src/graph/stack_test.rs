@@ -42,3 +42,19 @@ fn test_stack_deduplication() {
     let actual = stack.iter_bottom_up().collect::<Vec<_>>();
     assert_eq!(actual, expected);
 }
+
+#[rstest]
+fn test_stack_remove() {
+    let digests = [random_digest(), random_digest(), random_digest()];
+    let mut stack = Stack::from_iter(digests);
+
+    assert!(stack.remove(&digests[1]), "should remove from the middle");
+    assert!(
+        !stack.remove(&digests[1]),
+        "should not find a removed digest"
+    );
+    assert!(stack.remove(&digests[0]), "should remove from the bottom");
+    assert_eq!(stack.iter_bottom_up().collect::<Vec<_>>(), [digests[2]]);
+    assert!(stack.remove(&digests[2]), "should remove from the top");
+    assert!(stack.is_empty());
+}
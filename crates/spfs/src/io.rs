@@ -127,18 +127,26 @@ pub fn format_sync_summary(summary: &super::sync::reporter::SyncSummary) -> Stri
         synced_tags,
         skipped_objects,
         synced_objects,
+        resumed_objects,
         skipped_payloads,
         synced_payloads,
         synced_payload_bytes,
     } = summary;
 
-    format!(
+    let mut out = format!(
         "synced:\n\t{synced_tags} tags ({})\n\t{synced_objects} objects ({})\n\t{synced_payloads} payloads ({}) ({})",
         format!("{skipped_tags} skipped").dimmed(),
         format!("{skipped_objects} skipped").dimmed(),
         format!("{skipped_payloads} skipped").dimmed(),
         format_size(*synced_payload_bytes)
-    )
+    );
+    if *resumed_objects > 0 {
+        out.push_str(&format!(
+            "\n\t{}",
+            format!("{resumed_objects} objects already synced before resuming").dimmed()
+        ));
+    }
+    out
 }
 
 /// Return a human-readable file size in bytes.
@@ -171,6 +179,7 @@ pub async fn pretty_print_filepath(
                     graph::object::Enum::Layer(_) => "layer",
                     graph::object::Enum::Manifest(_) => "manifest",
                     graph::object::Enum::Blob(_) => "blob",
+                    graph::object::Enum::ChunkedBlob(_) => "chunked blob",
                 };
 
                 println!(
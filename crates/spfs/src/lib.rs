@@ -10,7 +10,9 @@ pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub mod fixtures;
 
 pub mod bootstrap;
+mod bundle;
 pub mod check;
+pub mod chunking;
 pub mod clean;
 pub mod commit;
 pub mod config;
@@ -23,6 +25,7 @@ pub mod graph;
 pub mod io;
 #[cfg_attr(windows, path = "./monitor_win.rs")]
 pub mod monitor;
+pub mod oci;
 pub mod prelude;
 pub mod proto;
 mod prune;
@@ -31,10 +34,14 @@ mod resolve;
 pub mod runtime;
 #[cfg(feature = "server")]
 pub mod server;
+pub mod stats;
 mod status;
 pub mod storage;
 pub mod sync;
+mod tls;
 pub mod tracking;
+#[cfg(unix)]
+pub mod xattr;
 
 // re-exported to make downstream implementations easier
 pub use async_trait::async_trait;
@@ -45,6 +52,7 @@ pub use bootstrap::{
     build_interactive_shell_command,
     build_shell_initialized_command,
 };
+pub use bundle::{apply_bundle, create_bundle};
 pub use check::Checker;
 pub use clean::Cleaner;
 pub use commit::Committer;
@@ -62,10 +70,12 @@ pub use resolve::{
     which_spfs,
 };
 pub use spfs_encoding as encoding;
+pub use stats::UsageAnalyzer;
 pub use status::{
     active_runtime,
     change_to_durable_runtime,
     compute_runtime_manifest,
+    compute_stack_update_conflicts,
     exit_runtime,
     get_runtime_backing_repo,
     initialize_runtime,
@@ -0,0 +1,975 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+//! Conversion of spfs layers to and from OCI image layouts.
+//!
+//! Each spfs layer with a manifest becomes one uncompressed tar layer
+//! in the image, with the contents placed under the `spfs` directory
+//! where they would be mounted in a runtime. Masked entries are written
+//! as whiteout files, and layer annotations become image labels.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+
+use relative_path::{RelativePath, RelativePathBuf};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::graph::{AnnotationValue, KeyAnnotationValuePair};
+use crate::prelude::*;
+use crate::tracking::{Entry, EntryKind};
+use crate::{Error, Result, graph, tracking};
+
+#[cfg(test)]
+#[path = "./oci_test.rs"]
+mod oci_test;
+
+/// The annotation used to name the images in an OCI layout
+pub const REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+
+const LAYOUT_FILE: &str = "oci-layout";
+const LAYOUT_VERSION: &str = "1.0.0";
+const INDEX_FILE: &str = "index.json";
+const INDEX_MEDIA_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+const CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+const LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar";
+const LAYER_ZSTD_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar+zstd";
+const LAYER_GZIP_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar+gzip";
+const DOCKER_LAYER_MEDIA_TYPE: &str = "application/vnd.docker.image.rootfs.diff.tar";
+const DOCKER_LAYER_GZIP_MEDIA_TYPE: &str = "application/vnd.docker.image.rootfs.diff.tar.gzip";
+
+/// The directory in the image where layer contents are placed
+const SPFS_DIR: &str = "spfs";
+const WHITEOUT_PREFIX: &str = ".wh.";
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+const MODE_DIR: u32 = 0o040000;
+const MODE_FILE: u32 = 0o100000;
+const MODE_SYMLINK: u32 = 0o120000;
+const MODE_PERMS: u32 = 0o7777;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Descriptor {
+    media_type: String,
+    digest: String,
+    size: u64,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    annotations: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImageIndex {
+    schema_version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    media_type: Option<String>,
+    #[serde(default)]
+    manifests: Vec<Descriptor>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImageManifest {
+    schema_version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    media_type: Option<String>,
+    config: Descriptor,
+    layers: Vec<Descriptor>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ImageConfig {
+    #[serde(default)]
+    architecture: String,
+    #[serde(default)]
+    os: String,
+    #[serde(default)]
+    config: ContainerConfig,
+    #[serde(default)]
+    rootfs: RootFs,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ContainerConfig {
+    #[serde(rename = "Labels", default, skip_serializing_if = "BTreeMap::is_empty")]
+    labels: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RootFs {
+    #[serde(rename = "type")]
+    kind: String,
+    diff_ids: Vec<String>,
+}
+
+/// The path of a blob in an OCI layout, validating the digest
+/// so that it cannot name a file outside of the layout
+fn blob_path(layout: &Path, digest: &str) -> Result<PathBuf> {
+    let Some(hex) = digest.strip_prefix("sha256:") else {
+        return Err(Error::String(format!(
+            "Unsupported digest algorithm in OCI layout: {digest}"
+        )));
+    };
+    if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(Error::String(format!(
+            "Invalid digest in OCI layout: {digest}"
+        )));
+    }
+    Ok(layout.join("blobs").join("sha256").join(hex))
+}
+
+fn sha256_digest(data: &[u8]) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, data);
+    format!("sha256:{}", data_encoding::HEXLOWER.encode(digest.as_ref()))
+}
+
+/// The name of the current architecture, as used in OCI images
+fn oci_architecture() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" => "386",
+        "powerpc64" => "ppc64le",
+        arch => arch,
+    }
+}
+
+async fn read_json_blob<T: serde::de::DeserializeOwned>(
+    layout: &Path,
+    descriptor: &Descriptor,
+) -> Result<T> {
+    let path = blob_path(layout, &descriptor.digest)?;
+    let data = tokio::fs::read(&path)
+        .await
+        .map_err(|err| Error::StorageReadError("read of OCI blob", path.clone(), err))?;
+    let actual = sha256_digest(&data);
+    if actual != descriptor.digest {
+        return Err(Error::String(format!(
+            "OCI blob did not match its digest: {actual} != {}",
+            descriptor.digest
+        )));
+    }
+    serde_json::from_slice(&data)
+        .map_err(|err| Error::String(format!("Invalid OCI blob {}: {err}", path.display())))
+}
+
+async fn write_json_blob<T: Serialize>(
+    layout: &Path,
+    media_type: &str,
+    value: &T,
+) -> Result<Descriptor> {
+    let data = serde_json::to_vec(value)
+        .map_err(|err| Error::String(format!("Failed to serialize OCI {media_type}: {err}")))?;
+    let digest = sha256_digest(&data);
+    let path = blob_path(layout, &digest)?;
+    tokio::fs::write(&path, &data)
+        .await
+        .map_err(|err| Error::StorageWriteError("write of OCI blob", path, err))?;
+    Ok(Descriptor {
+        media_type: media_type.to_owned(),
+        digest,
+        size: data.len() as u64,
+        annotations: Default::default(),
+    })
+}
+
+async fn read_index(layout: &Path) -> Result<Option<ImageIndex>> {
+    let path = layout.join(INDEX_FILE);
+    let data = match tokio::fs::read(&path).await {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(Error::StorageReadError("read of OCI index", path, err)),
+    };
+    serde_json::from_slice(&data)
+        .map(Some)
+        .map_err(|err| Error::String(format!("Invalid OCI index {}: {err}", path.display())))
+}
+
+/// Read the string value of an annotation, loading it from
+/// its blob when it was too large to store in the layer
+async fn read_annotation_value(
+    repo: &RepositoryHandle,
+    value: AnnotationValue<'_>,
+) -> Result<String> {
+    match value {
+        AnnotationValue::String(s) => Ok(s.into_owned()),
+        AnnotationValue::Blob(digest) => {
+            let blob = repo.read_blob(*digest).await?;
+            let (mut payload, filename) = repo.open_payload(*blob.digest()).await?;
+            let mut value = String::new();
+            payload.read_to_string(&mut value).await.map_err(|err| {
+                Error::StorageReadError("read of annotation payload", filename, err)
+            })?;
+            Ok(value)
+        }
+    }
+}
+
+/// Writes spfs layers into an OCI image layout on disk
+pub struct OciExporter<'repo> {
+    repo: &'repo RepositoryHandle,
+    reference: Option<String>,
+}
+
+impl<'repo> OciExporter<'repo> {
+    pub fn new(repo: &'repo RepositoryHandle) -> Self {
+        Self {
+            repo,
+            reference: None,
+        }
+    }
+
+    /// Name the exported image in the layout's index, replacing
+    /// any existing image with the same name.
+    pub fn with_reference(mut self, reference: impl Into<String>) -> Self {
+        self.reference = Some(reference.into());
+        self
+    }
+
+    /// Export the layers of a stack as a single image, creating the
+    /// layout directory if needed. Images that are already in the layout
+    /// are kept, so that one layout can hold many environments.
+    ///
+    /// Returns the digest of the image manifest.
+    pub async fn export(&self, stack: &graph::Stack, layout: impl AsRef<Path>) -> Result<String> {
+        let layout = layout.as_ref();
+        let blobs = layout.join("blobs").join("sha256");
+        tokio::fs::create_dir_all(&blobs)
+            .await
+            .map_err(|err| Error::StorageWriteError("create OCI blobs dir", blobs.clone(), err))?;
+        let layout_file = layout.join(LAYOUT_FILE);
+        let layout_data = serde_json::json!({ "imageLayoutVersion": LAYOUT_VERSION });
+        tokio::fs::write(&layout_file, layout_data.to_string())
+            .await
+            .map_err(|err| Error::StorageWriteError("write OCI layout file", layout_file, err))?;
+
+        let layers = crate::resolve_stack_to_layers(stack, Some(self.repo)).await?;
+        let mut labels = BTreeMap::new();
+        let mut descriptors = Vec::new();
+        let mut diff_ids = Vec::new();
+        for layer in layers {
+            // later layers take precedence, as in a runtime
+            for annotation in layer.annotations() {
+                let annotation: graph::Annotation = annotation.into();
+                let value = read_annotation_value(self.repo, annotation.value()).await?;
+                labels.insert(annotation.key().to_owned(), value);
+            }
+            let Some(manifest) = layer.manifest() else {
+                continue;
+            };
+            let manifest = self.repo.read_manifest(*manifest).await?;
+            let descriptor = self
+                .export_manifest(&manifest.to_tracking_manifest(), layout)
+                .await?;
+            // layers are not compressed, so their diff id is the same
+            diff_ids.push(descriptor.digest.clone());
+            descriptors.push(descriptor);
+        }
+
+        let config = ImageConfig {
+            architecture: oci_architecture().to_owned(),
+            os: "linux".to_owned(),
+            config: ContainerConfig { labels },
+            rootfs: RootFs {
+                kind: "layers".to_owned(),
+                diff_ids,
+            },
+        };
+        let config = write_json_blob(layout, CONFIG_MEDIA_TYPE, &config).await?;
+        let manifest = ImageManifest {
+            schema_version: 2,
+            media_type: Some(MANIFEST_MEDIA_TYPE.to_owned()),
+            config,
+            layers: descriptors,
+        };
+        let mut manifest = write_json_blob(layout, MANIFEST_MEDIA_TYPE, &manifest).await?;
+        if let Some(reference) = &self.reference {
+            manifest
+                .annotations
+                .insert(REF_NAME_ANNOTATION.to_owned(), reference.clone());
+        }
+        let digest = manifest.digest.clone();
+
+        let mut index = read_index(layout).await?.unwrap_or(ImageIndex {
+            schema_version: 2,
+            media_type: Some(INDEX_MEDIA_TYPE.to_owned()),
+            manifests: Vec::new(),
+        });
+        index.manifests.retain(|existing| {
+            let existing_ref = existing.annotations.get(REF_NAME_ANNOTATION);
+            match &self.reference {
+                Some(_) => existing_ref != self.reference.as_ref(),
+                None => existing_ref.is_some() || existing.digest != manifest.digest,
+            }
+        });
+        index.manifests.push(manifest);
+        let index_file = layout.join(INDEX_FILE);
+        let index_data = serde_json::to_vec_pretty(&index)
+            .map_err(|err| Error::String(format!("Failed to serialize OCI index: {err}")))?;
+        tokio::fs::write(&index_file, index_data)
+            .await
+            .map_err(|err| Error::StorageWriteError("write OCI index", index_file, err))?;
+        Ok(digest)
+    }
+
+    /// Write the contents of one manifest as a tar layer
+    async fn export_manifest(
+        &self,
+        manifest: &tracking::Manifest,
+        layout: &Path,
+    ) -> Result<Descriptor> {
+        let tmp_path = layout
+            .join("blobs")
+            .join("sha256")
+            .join(format!(".tmp-{}", uuid::Uuid::new_v4()));
+        let file = tokio::fs::File::create(&tmp_path).await.map_err(|err| {
+            Error::StorageWriteError("create OCI layer file", tmp_path.clone(), err)
+        })?;
+        let mut writer = LayerWriter::new(file);
+        let result = self.write_layer(manifest, &mut writer).await;
+        let result = match result {
+            Ok(()) => writer.finish().await.map_err(|err| {
+                Error::StorageWriteError("write of OCI layer", tmp_path.clone(), err)
+            }),
+            Err(err) => Err(err),
+        };
+        let (digest, size) = match result {
+            Ok(r) => r,
+            Err(err) => {
+                let _ = tokio::fs::remove_file(&tmp_path).await;
+                return Err(err);
+            }
+        };
+        let path = blob_path(layout, &digest)?;
+        tokio::fs::rename(&tmp_path, &path)
+            .await
+            .map_err(|err| Error::StorageWriteError("rename of OCI layer", path, err))?;
+        Ok(Descriptor {
+            media_type: LAYER_MEDIA_TYPE.to_owned(),
+            digest,
+            size,
+            annotations: Default::default(),
+        })
+    }
+
+    async fn write_layer(
+        &self,
+        manifest: &tracking::Manifest,
+        writer: &mut LayerWriter,
+    ) -> Result<()> {
+        let mut links = HashMap::new();
+        for (primary, members) in manifest.hardlink_groups() {
+            for member in members {
+                links.insert(member, primary.clone());
+            }
+        }
+
+        let mut nodes = manifest.walk().collect::<Vec<_>>();
+        nodes.sort_by(|a, b| a.path.as_str().cmp(b.path.as_str()));
+
+        let write_err = |path: &str, err| {
+            Error::StorageWriteError("write of OCI layer entry", path.into(), err)
+        };
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_mode(0o755);
+        writer
+            .append(&format!("{SPFS_DIR}/"), header, None, None)
+            .await
+            .map_err(|err| write_err(SPFS_DIR, err))?;
+
+        let mut written = HashSet::new();
+        for node in nodes {
+            let rel_path = RelativePathBuf::from(tracking::Manifest::<()>::trim_leading_slash(
+                node.path.as_str(),
+            ));
+            let path = format!("{SPFS_DIR}/{rel_path}");
+            let entry = node.entry;
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(entry.mode & MODE_PERMS);
+            match entry.kind {
+                EntryKind::Tree => {
+                    header.set_entry_type(tar::EntryType::Directory);
+                    writer
+                        .append(&format!("{path}/"), header, None, None)
+                        .await
+                        .map_err(|err| write_err(&path, err))?;
+                }
+                EntryKind::Mask => {
+                    let whiteout = match rel_path.parent() {
+                        Some(parent) if !parent.as_str().is_empty() => {
+                            format!(
+                                "{SPFS_DIR}/{parent}/{WHITEOUT_PREFIX}{}",
+                                node_name(&rel_path)
+                            )
+                        }
+                        _ => format!("{SPFS_DIR}/{WHITEOUT_PREFIX}{}", node_name(&rel_path)),
+                    };
+                    header.set_entry_type(tar::EntryType::Regular);
+                    header.set_mode(0o644);
+                    writer
+                        .append(&whiteout, header, None, None)
+                        .await
+                        .map_err(|err| write_err(&whiteout, err))?;
+                }
+                EntryKind::Blob(_) if entry.is_symlink() => {
+                    let (mut payload, filename) = self.repo.open_payload(entry.object).await?;
+                    let mut target = String::new();
+                    payload.read_to_string(&mut target).await.map_err(|err| {
+                        Error::StorageReadError("read of symlink payload", filename, err)
+                    })?;
+                    header.set_entry_type(tar::EntryType::Symlink);
+                    writer
+                        .append(&path, header, Some(&target), None)
+                        .await
+                        .map_err(|err| write_err(&path, err))?;
+                }
+                EntryKind::Blob(size) => {
+                    // a link can only be written once the file that
+                    // it points to has already been written
+                    if let Some(primary) = links.get(&rel_path).filter(|p| written.contains(*p)) {
+                        header.set_entry_type(tar::EntryType::Link);
+                        let target = format!("{SPFS_DIR}/{primary}");
+                        writer
+                            .append(&path, header, Some(&target), None)
+                            .await
+                            .map_err(|err| write_err(&path, err))?;
+                        continue;
+                    }
+                    let (payload, _) = self.repo.open_payload(entry.object).await?;
+                    header.set_entry_type(tar::EntryType::Regular);
+                    header.set_size(size);
+                    writer
+                        .append(&path, header, None, Some(payload))
+                        .await
+                        .map_err(|err| write_err(&path, err))?;
+                    written.insert(rel_path);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn node_name(path: &RelativePathBuf) -> &str {
+    path.file_name().unwrap_or_default()
+}
+
+/// Writes a tar archive while computing its digest
+struct LayerWriter {
+    file: tokio::io::BufWriter<tokio::fs::File>,
+    hasher: ring::digest::Context,
+    size: u64,
+}
+
+impl LayerWriter {
+    const BLOCK_SIZE: u64 = 512;
+
+    fn new(file: tokio::fs::File) -> Self {
+        Self {
+            file: tokio::io::BufWriter::new(file),
+            hasher: ring::digest::Context::new(&ring::digest::SHA256),
+            size: 0,
+        }
+    }
+
+    async fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.hasher.update(data);
+        self.size += data.len() as u64;
+        self.file.write_all(data).await
+    }
+
+    /// Pad the archive with zeros up to the next block
+    async fn pad(&mut self) -> std::io::Result<()> {
+        let remainder = self.size % Self::BLOCK_SIZE;
+        if remainder != 0 {
+            let padding = vec![0; (Self::BLOCK_SIZE - remainder) as usize];
+            self.write(&padding).await?;
+        }
+        Ok(())
+    }
+
+    /// Write a gnu extension entry that holds a name which
+    /// is too long to fit into a standard header
+    async fn append_long_name(&mut self, kind: tar::EntryType, name: &str) -> std::io::Result<()> {
+        let mut header = tar::Header::new_gnu();
+        let long_link = b"././@LongLink";
+        header.as_gnu_mut().expect("gnu header").name[..long_link.len()].copy_from_slice(long_link);
+        header.set_mode(0o644);
+        header.set_entry_type(kind);
+        header.set_size(name.len() as u64 + 1);
+        header.set_cksum();
+        self.write(header.as_bytes()).await?;
+        self.write(name.as_bytes()).await?;
+        self.write(&[0]).await?;
+        self.pad().await
+    }
+
+    async fn append(
+        &mut self,
+        path: &str,
+        mut header: tar::Header,
+        link_name: Option<&str>,
+        data: Option<Pin<Box<dyn BlobRead>>>,
+    ) -> std::io::Result<()> {
+        // entries are written with fixed ownership and times so
+        // that the same layer always produces the same archive
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_mtime(0);
+        if data.is_none() {
+            header.set_size(0);
+        }
+        if header.set_path(path).is_err() {
+            self.append_long_name(tar::EntryType::GNULongName, path)
+                .await?;
+            let name = &mut header.as_old_mut().name;
+            let len = name.len().min(path.len());
+            name[..len].copy_from_slice(&path.as_bytes()[..len]);
+        }
+        if let Some(link_name) = link_name {
+            if header.set_link_name(link_name).is_err() {
+                self.append_long_name(tar::EntryType::GNULongLink, link_name)
+                    .await?;
+                let name = &mut header.as_old_mut().linkname;
+                let len = name.len().min(link_name.len());
+                name[..len].copy_from_slice(&link_name.as_bytes()[..len]);
+            }
+        }
+        header.set_cksum();
+        self.write(header.as_bytes()).await?;
+        let Some(mut data) = data else {
+            return Ok(());
+        };
+        let expected = header.size()?;
+        let mut buf = vec![0; 64 * 1024];
+        let mut copied = 0;
+        loop {
+            let count = data.read(&mut buf).await?;
+            if count == 0 {
+                break;
+            }
+            copied += count as u64;
+            self.write(&buf[..count]).await?;
+        }
+        if copied != expected {
+            return Err(std::io::Error::other(format!(
+                "payload size changed while writing layer: {copied} != {expected}"
+            )));
+        }
+        self.pad().await
+    }
+
+    /// Finish the archive, returning its digest and size
+    async fn finish(mut self) -> std::io::Result<(String, u64)> {
+        self.write(&[0; 2 * Self::BLOCK_SIZE as usize]).await?;
+        self.file.flush().await?;
+        let digest = self.hasher.finish();
+        Ok((
+            format!("sha256:{}", data_encoding::HEXLOWER.encode(digest.as_ref())),
+            self.size,
+        ))
+    }
+}
+
+/// An item read from a layer archive
+enum LayerItem {
+    Dir,
+    File {
+        offset: u64,
+        size: u64,
+    },
+    Symlink(String),
+    Link(RelativePathBuf),
+    Whiteout,
+    /// Hides everything from lower layers in this directory
+    OpaqueWhiteout,
+}
+
+/// Reads OCI images from a layout on disk into spfs layers
+pub struct OciImporter<'repo> {
+    repo: &'repo RepositoryHandle,
+    reference: Option<String>,
+    annotation_size_limit: usize,
+}
+
+impl<'repo> OciImporter<'repo> {
+    pub fn new(repo: &'repo RepositoryHandle) -> Self {
+        Self {
+            repo,
+            reference: None,
+            annotation_size_limit: graph::DEFAULT_SPFS_ANNOTATION_LAYER_MAX_STRING_VALUE_SIZE,
+        }
+    }
+
+    /// Import the image with this name in the layout's index.
+    ///
+    /// When not set, the layout must contain exactly one image.
+    pub fn with_reference(mut self, reference: impl Into<String>) -> Self {
+        self.reference = Some(reference.into());
+        self
+    }
+
+    /// Labels larger than this are stored in a separate
+    /// blob when they are converted into annotations.
+    pub fn with_annotation_size_limit(mut self, size_limit: usize) -> Self {
+        self.annotation_size_limit = size_limit;
+        self
+    }
+
+    /// Import an image, creating one layer for each layer in the
+    /// image and a platform that holds them all.
+    ///
+    /// Any labels on the image are stored as annotations in an
+    /// additional layer at the bottom of the platform.
+    pub async fn import(&self, layout: impl AsRef<Path>) -> Result<graph::Platform> {
+        let layout = layout.as_ref();
+        let Some(index) = read_index(layout).await? else {
+            return Err(Error::String(format!(
+                "Not an OCI image layout, no {INDEX_FILE} found in {}",
+                layout.display()
+            )));
+        };
+        let descriptor = self.select_image(&index)?;
+        if descriptor.media_type == INDEX_MEDIA_TYPE {
+            return Err(Error::String(format!(
+                "Nested OCI image indexes are not supported: {}",
+                descriptor.digest
+            )));
+        }
+        let manifest: ImageManifest = read_json_blob(layout, descriptor).await?;
+        let config: ImageConfig = read_json_blob(layout, &manifest.config).await?;
+
+        let mut stack = graph::Stack::default();
+        if !config.config.labels.is_empty() {
+            let mut annotations: Vec<KeyAnnotationValuePair> = Vec::new();
+            for (key, value) in config.config.labels.iter() {
+                let value = if value.len() <= self.annotation_size_limit {
+                    AnnotationValue::string(value.as_str())
+                } else {
+                    let digest = self
+                        .repo
+                        .commit_blob(Box::pin(std::io::Cursor::new(value.clone().into_bytes())))
+                        .await?;
+                    AnnotationValue::blob(digest)
+                };
+                annotations.push((key.as_str(), value));
+            }
+            let layer = graph::Layer::new_with_annotations(annotations);
+            self.repo.write_object(&layer).await?;
+            stack.push(layer.digest()?);
+        }
+        // the merged contents of the layers imported so far, which
+        // opaque directories in later layers need to mask out
+        let mut lower = tracking::Manifest::default();
+        for descriptor in manifest.layers.iter() {
+            let manifest = self.import_layer(layout, descriptor, &lower).await?;
+            let layer = self.repo.create_layer_from_manifest(&manifest).await?;
+            stack.push(layer.digest()?);
+            lower.update(&manifest);
+        }
+        self.repo.create_platform(stack).await
+    }
+
+    fn select_image<'a>(&self, index: &'a ImageIndex) -> Result<&'a Descriptor> {
+        match &self.reference {
+            Some(reference) => index
+                .manifests
+                .iter()
+                .find(|m| m.annotations.get(REF_NAME_ANNOTATION) == Some(reference))
+                .ok_or_else(|| {
+                    Error::String(format!("No image named '{reference}' in OCI layout"))
+                }),
+            None => match index.manifests.as_slice() {
+                [descriptor] => Ok(descriptor),
+                [] => Err(Error::String("OCI layout contains no images".into())),
+                _ => Err(Error::String(
+                    "OCI layout contains more than one image, a reference must be given".into(),
+                )),
+            },
+        }
+    }
+
+    /// Read one tar layer of an image into a manifest,
+    /// storing the data of each file along the way.
+    ///
+    /// The lower manifest holds the contents of the layers below
+    /// this one, so that opaque directories can mask them out.
+    async fn import_layer(
+        &self,
+        layout: &Path,
+        descriptor: &Descriptor,
+        lower: &tracking::Manifest,
+    ) -> Result<tracking::Manifest> {
+        let blob = blob_path(layout, &descriptor.digest)?;
+        // the archive is read by position, so compressed
+        // layers need to be decompressed to disk first
+        let _decompressed;
+        let archive = match descriptor.media_type.as_str() {
+            LAYER_MEDIA_TYPE | DOCKER_LAYER_MEDIA_TYPE => blob,
+            LAYER_ZSTD_MEDIA_TYPE => {
+                let tmp = decompress_layer(&blob, |file| {
+                    async_compression::tokio::bufread::ZstdDecoder::new(file)
+                })
+                .await?;
+                let path = tmp.path().to_owned();
+                _decompressed = tmp;
+                path
+            }
+            LAYER_GZIP_MEDIA_TYPE | DOCKER_LAYER_GZIP_MEDIA_TYPE => {
+                let tmp = decompress_layer(&blob, |file| {
+                    async_compression::tokio::bufread::GzipDecoder::new(file)
+                })
+                .await?;
+                let path = tmp.path().to_owned();
+                _decompressed = tmp;
+                path
+            }
+            media_type => {
+                return Err(Error::String(format!(
+                    "Unsupported OCI layer media type: {media_type}"
+                )));
+            }
+        };
+
+        let path = archive.clone();
+        let items = tokio::task::spawn_blocking(move || read_layer_items(&path))
+            .await
+            .expect("layer read should not panic")?;
+
+        let mut manifest = tracking::Manifest::default();
+        let mut opaque_dirs = Vec::new();
+        let mut file = tokio::fs::File::open(&archive)
+            .await
+            .map_err(|err| Error::StorageReadError("open of OCI layer", archive.clone(), err))?;
+        for (path, mode, item) in items {
+            let mk_err = |err: tracking::manifest::MkError| {
+                Error::String(format!("Invalid entry in OCI layer {path}: {err}"))
+            };
+            if let Some(parent) = path.parent().filter(|p| !p.as_str().is_empty()) {
+                manifest.mkdirs(parent.as_str()).map_err(mk_err)?;
+            }
+            let entry = match item {
+                LayerItem::Dir => {
+                    let dir = manifest.mkdirs(path.as_str()).map_err(mk_err)?;
+                    dir.mode = MODE_DIR | mode;
+                    continue;
+                }
+                LayerItem::Whiteout => Entry::mask(),
+                LayerItem::OpaqueWhiteout => {
+                    if !path.as_str().is_empty() {
+                        manifest.mkdirs(path.as_str()).map_err(mk_err)?;
+                    }
+                    opaque_dirs.push(path);
+                    continue;
+                }
+                LayerItem::File { offset, size } => {
+                    file.seek(SeekFrom::Start(offset)).await.map_err(|err| {
+                        Error::StorageReadError("seek in OCI layer", archive.clone(), err)
+                    })?;
+                    let reader = tokio::io::BufReader::new(
+                        file.try_clone()
+                            .await
+                            .map_err(|err| {
+                                Error::StorageReadError("open of OCI layer", archive.clone(), err)
+                            })?
+                            .take(size),
+                    )
+                    .with_permissions(mode);
+                    let mut entry = Entry::empty_file_with_open_perms();
+                    entry.kind = EntryKind::Blob(size);
+                    entry.mode = MODE_FILE | mode;
+                    entry.object = self.repo.commit_blob(Box::pin(reader)).await?;
+                    entry
+                }
+                LayerItem::Symlink(target) => {
+                    let mut entry = Entry::empty_symlink();
+                    entry.kind = EntryKind::Blob(target.len() as u64);
+                    entry.mode = MODE_SYMLINK | 0o777;
+                    entry.object = self
+                        .repo
+                        .commit_blob(Box::pin(std::io::Cursor::new(target.into_bytes())))
+                        .await?;
+                    entry
+                }
+                LayerItem::Link(target) => {
+                    let Some(existing) = manifest.get_path(target.as_str()) else {
+                        return Err(Error::String(format!(
+                            "Hard link in OCI layer points to a missing file: {path} -> {target}"
+                        )));
+                    };
+                    let mut entry = existing.clone();
+                    entry.hardlink = Some(existing.hardlink.clone().unwrap_or(target.to_string()));
+                    entry
+                }
+            };
+            manifest.mknod(path.as_str(), entry).map_err(mk_err)?;
+        }
+        // done last, so that the contents of this layer are
+        // known and do not get masked along with the rest
+        for dir in opaque_dirs {
+            mask_lower_entries(&mut manifest, lower, &dir)?;
+        }
+        Ok(manifest)
+    }
+}
+
+/// Add a mask for everything in a directory of the lower manifest
+/// that is not replaced by the upper one, so that the directory
+/// only contains what the upper manifest provides
+fn mask_lower_entries(
+    upper: &mut tracking::Manifest,
+    lower: &tracking::Manifest,
+    dir: &RelativePath,
+) -> Result<()> {
+    let Some(entries) = lower.read_dir(dir.as_str()) else {
+        return Ok(());
+    };
+    for (name, entry) in entries {
+        if entry.kind.is_mask() {
+            continue;
+        }
+        let path = dir.join(name);
+        match upper.get_path(path.as_str()) {
+            None => {
+                upper.mknod(path.as_str(), Entry::mask()).map_err(|err| {
+                    Error::String(format!(
+                        "Invalid opaque directory in OCI layer {dir}: {err}"
+                    ))
+                })?;
+            }
+            // a directory in the upper layer is merged with the lower
+            // one, so its lower contents must be masked as well
+            Some(existing) if existing.kind.is_tree() && entry.kind.is_tree() => {
+                mask_lower_entries(upper, lower, &path)?;
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Decompress a layer blob into a temporary file
+async fn decompress_layer<D, F>(blob: &Path, decoder: F) -> Result<tempfile::NamedTempFile>
+where
+    D: tokio::io::AsyncRead + Unpin,
+    F: FnOnce(tokio::io::BufReader<tokio::fs::File>) -> D,
+{
+    let tmp = tempfile::NamedTempFile::new().map_err(|err| {
+        Error::StorageWriteError("create temp layer file", std::env::temp_dir(), err)
+    })?;
+    let file = tokio::fs::File::open(blob)
+        .await
+        .map_err(|err| Error::StorageReadError("open of OCI layer", blob.to_owned(), err))?;
+    let mut decoder = decoder(tokio::io::BufReader::new(file));
+    let mut out = tokio::fs::File::create(tmp.path()).await.map_err(|err| {
+        Error::StorageWriteError("open temp layer file", tmp.path().to_owned(), err)
+    })?;
+    tokio::io::copy(&mut decoder, &mut out)
+        .await
+        .map_err(|err| Error::StorageReadError("decompress of OCI layer", blob.to_owned(), err))?;
+    Ok(tmp)
+}
+
+/// Strip the spfs directory from a path in a layer archive, returning
+/// None for paths that are outside of it
+fn spfs_relative_path(path: &Path) -> Option<RelativePathBuf> {
+    let path = path.to_str()?;
+    let path = path.trim_start_matches("./").trim_start_matches('/');
+    let rel = path.strip_prefix(SPFS_DIR)?;
+    if !rel.is_empty() && !rel.starts_with('/') {
+        return None;
+    }
+    let rel = RelativePathBuf::from(rel.trim_matches('/')).normalize();
+    if rel.as_str().starts_with("..") {
+        return None;
+    }
+    Some(rel)
+}
+
+/// List the entries of a layer archive and where their data can be found
+fn read_layer_items(path: &Path) -> Result<Vec<(RelativePathBuf, u32, LayerItem)>> {
+    let read_err = |err| Error::StorageReadError("read of OCI layer", path.to_owned(), err);
+    let file = std::fs::File::open(path).map_err(read_err)?;
+    let mut archive = tar::Archive::new(file);
+    let mut items = Vec::new();
+    let mut skipped = 0usize;
+    for entry in archive.entries().map_err(read_err)? {
+        let entry = entry.map_err(read_err)?;
+        let entry_path = entry.path().map_err(read_err)?;
+        let Some(rel_path) = spfs_relative_path(&entry_path) else {
+            skipped += 1;
+            continue;
+        };
+        if rel_path.as_str().is_empty() {
+            continue;
+        }
+        let mode = entry.header().mode().map_err(read_err)? & MODE_PERMS;
+        let name = rel_path.file_name().unwrap_or_default();
+        if name == OPAQUE_WHITEOUT {
+            let dir = rel_path.parent().map(ToOwned::to_owned).unwrap_or_default();
+            items.push((dir, 0o777, LayerItem::OpaqueWhiteout));
+            continue;
+        }
+        if let Some(name) = name.strip_prefix(WHITEOUT_PREFIX) {
+            let masked = match rel_path.parent() {
+                Some(parent) => parent.join(name),
+                None => RelativePathBuf::from(name),
+            };
+            items.push((masked, 0o777, LayerItem::Whiteout));
+            continue;
+        }
+        let item = match entry.header().entry_type() {
+            tar::EntryType::Directory => LayerItem::Dir,
+            tar::EntryType::Regular | tar::EntryType::Continuous => LayerItem::File {
+                offset: entry.raw_file_position(),
+                size: entry.size(),
+            },
+            tar::EntryType::Symlink => {
+                let Some(target) = entry.link_name().map_err(read_err)? else {
+                    continue;
+                };
+                let Some(target) = target.to_str() else {
+                    return Err(Error::String(format!(
+                        "Symlinks must point to a valid utf-8 path: {rel_path}"
+                    )));
+                };
+                LayerItem::Symlink(target.to_owned())
+            }
+            tar::EntryType::Link => {
+                let Some(target) = entry.link_name().map_err(read_err)? else {
+                    continue;
+                };
+                let Some(target) = spfs_relative_path(&target) else {
+                    return Err(Error::String(format!(
+                        "Hard link in OCI layer points outside of /{SPFS_DIR}: {rel_path}"
+                    )));
+                };
+                LayerItem::Link(target)
+            }
+            kind => {
+                tracing::warn!(path = %rel_path, ?kind, "skipping unsupported file type in OCI layer");
+                continue;
+            }
+        };
+        items.push((rel_path, mode, item));
+    }
+    if skipped > 0 {
+        if items.is_empty() {
+            return Err(Error::String(format!(
+                "OCI layer has no content under /{SPFS_DIR}: {}",
+                path.display()
+            )));
+        }
+        tracing::warn!(
+            count = skipped,
+            "skipped files outside of /{SPFS_DIR} in OCI layer"
+        );
+    }
+    Ok(items)
+}
@@ -0,0 +1,273 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use rstest::rstest;
+
+use super::{
+    LayerItem,
+    OciExporter,
+    OciImporter,
+    decompress_layer,
+    mask_lower_entries,
+    read_layer_items,
+};
+use crate::fixtures::*;
+use crate::graph::{self, AnnotationValue};
+use crate::prelude::*;
+use crate::tracking;
+
+/// The kind, object and mode of every entry in a manifest, by path
+fn entries(manifest: &tracking::Manifest) -> Vec<(String, String, u32)> {
+    let mut entries: Vec<_> = manifest
+        .walk()
+        .map(|node| {
+            (
+                node.path.to_string(),
+                format!("{}:{}", node.entry.kind, node.entry.object),
+                node.entry.mode,
+            )
+        })
+        .collect();
+    entries.sort();
+    entries
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_oci_export_import_round_trip(tmpdir: tempfile::TempDir, #[future] tmprepo: TempRepo) {
+    init_logging();
+    let tmprepo = tmprepo.await;
+
+    let src_dir = tmpdir.path().join("source");
+    ensure(src_dir.join("dir/file.txt"), "hello");
+    ensure(src_dir.join("dir2/otherfile.txt"), "hello2");
+    let long_dir = "nested/".repeat(20);
+    ensure(src_dir.join(&long_dir).join("deep.txt"), "a long path");
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("dir/file.txt", src_dir.join("link")).unwrap();
+    let base = crate::Committer::new(&tmprepo)
+        .commit_dir(src_dir.as_path())
+        .await
+        .unwrap();
+    let base_layer = tmprepo.create_layer_from_manifest(&base).await.unwrap();
+
+    let mut masked = tracking::Manifest::default();
+    masked.mkdirs("dir2").unwrap();
+    masked
+        .mknod("dir2/otherfile.txt", tracking::Entry::mask())
+        .unwrap();
+    let mask_layer = tmprepo.create_layer_from_manifest(&masked).await.unwrap();
+
+    let annotations = graph::Layer::new_with_annotations(vec![(
+        "example.com/owner",
+        AnnotationValue::string("someone"),
+    )]);
+    tmprepo.write_object(&annotations).await.unwrap();
+
+    let stack: graph::Stack = [
+        annotations.digest().unwrap(),
+        base_layer.digest().unwrap(),
+        mask_layer.digest().unwrap(),
+    ]
+    .into_iter()
+    .collect();
+
+    let layout = tmpdir.path().join("layout");
+    OciExporter::new(&tmprepo)
+        .with_reference("latest")
+        .export(&stack, &layout)
+        .await
+        .expect("export should succeed");
+    assert!(layout.join("oci-layout").is_file());
+    assert!(layout.join("index.json").is_file());
+
+    let platform = OciImporter::new(&tmprepo)
+        .with_reference("latest")
+        .import(&layout)
+        .await
+        .expect("import should succeed");
+    let layers = crate::resolve_stack_to_layers(&platform.to_stack(), Some(&*tmprepo))
+        .await
+        .unwrap();
+    assert_eq!(layers.len(), 3, "expected annotations and two layers");
+
+    let labels: Vec<_> = layers[0]
+        .annotations()
+        .into_iter()
+        .map(|a| {
+            let a: graph::Annotation = a.into();
+            let value = match a.value() {
+                AnnotationValue::String(s) => s.into_owned(),
+                AnnotationValue::Blob(d) => panic!("expected a string annotation, got blob {d}"),
+            };
+            (a.key().to_owned(), value)
+        })
+        .collect();
+    assert_eq!(
+        labels,
+        vec![("example.com/owner".to_owned(), "someone".to_owned())]
+    );
+
+    for (layer, expected) in layers[1..].iter().zip([&base, &masked]) {
+        let manifest = tmprepo
+            .read_manifest(*layer.manifest().expect("layer should have a manifest"))
+            .await
+            .unwrap()
+            .to_tracking_manifest();
+        assert_eq!(entries(&manifest), entries(expected));
+    }
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_oci_import_requires_reference(
+    tmpdir: tempfile::TempDir,
+    #[future] tmprepo: TempRepo,
+) {
+    init_logging();
+    let tmprepo = tmprepo.await;
+
+    let src_dir = tmpdir.path().join("source");
+    ensure(src_dir.join("file.txt"), "hello");
+    let manifest = crate::Committer::new(&tmprepo)
+        .commit_dir(src_dir.as_path())
+        .await
+        .unwrap();
+    let layer = tmprepo.create_layer_from_manifest(&manifest).await.unwrap();
+    let stack = graph::Stack::from(layer.digest().unwrap());
+
+    let layout = tmpdir.path().join("layout");
+    for name in ["first", "second"] {
+        OciExporter::new(&tmprepo)
+            .with_reference(name)
+            .export(&stack, &layout)
+            .await
+            .unwrap();
+    }
+
+    OciImporter::new(&tmprepo)
+        .import(&layout)
+        .await
+        .expect_err("should fail when more than one image could be imported");
+    OciImporter::new(&tmprepo)
+        .with_reference("second")
+        .import(&layout)
+        .await
+        .expect("should import a named image");
+}
+
+#[rstest]
+fn test_oci_opaque_whiteout_is_read(tmpdir: tempfile::TempDir) {
+    let path = tmpdir.path().join("layer.tar");
+    let mut builder = tar::Builder::new(std::fs::File::create(&path).unwrap());
+    let mut header = tar::Header::new_gnu();
+    header.set_size(0);
+    header.set_mode(0o644);
+    builder
+        .append_data(&mut header, "spfs/dir/.wh..wh..opq", std::io::empty())
+        .unwrap();
+    builder.finish().unwrap();
+
+    let items = read_layer_items(&path).unwrap();
+    assert!(
+        matches!(
+            items.as_slice(),
+            [(dir, _, LayerItem::OpaqueWhiteout)] if dir.as_str() == "dir"
+        ),
+        "an opaque whiteout should apply to its parent directory"
+    );
+}
+
+#[rstest]
+fn test_oci_layer_without_spfs_content_is_rejected(tmpdir: tempfile::TempDir) {
+    let path = tmpdir.path().join("layer.tar");
+    let mut builder = tar::Builder::new(std::fs::File::create(&path).unwrap());
+    let mut header = tar::Header::new_gnu();
+    header.set_size(5);
+    header.set_mode(0o644);
+    builder
+        .append_data(&mut header, "etc/hostname", "hello".as_bytes())
+        .unwrap();
+    builder.finish().unwrap();
+
+    read_layer_items(&path).expect_err("should fail when nothing is under /spfs");
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_oci_gzip_layer_is_decompressed(tmpdir: tempfile::TempDir) {
+    let mut tar_data = Vec::new();
+    {
+        let mut builder = tar::Builder::new(&mut tar_data);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "spfs/file.txt", "hello".as_bytes())
+            .unwrap();
+        builder.finish().unwrap();
+    }
+    let mut encoder = async_compression::tokio::bufread::GzipEncoder::new(tar_data.as_slice());
+    let mut compressed = Vec::new();
+    tokio::io::AsyncReadExt::read_to_end(&mut encoder, &mut compressed)
+        .await
+        .unwrap();
+    let blob = tmpdir.path().join("layer.tar.gz");
+    std::fs::write(&blob, compressed).unwrap();
+
+    let archive = decompress_layer(&blob, |file| {
+        async_compression::tokio::bufread::GzipDecoder::new(file)
+    })
+    .await
+    .unwrap();
+    let items = read_layer_items(archive.path()).unwrap();
+    assert!(
+        matches!(
+            items.as_slice(),
+            [(path, 0o644, LayerItem::File { size: 5, .. })] if path.as_str() == "file.txt"
+        ),
+        "the decompressed layer should be read as a tar archive"
+    );
+}
+
+#[rstest]
+fn test_oci_opaque_whiteout_masks_lower_entries() {
+    let mut lower = tracking::Manifest::default();
+    lower.mkdirs("dir/sub").unwrap();
+    lower.mkdirs("other").unwrap();
+    for path in ["dir/a.txt", "dir/kept.txt", "dir/sub/b.txt", "other/c.txt"] {
+        lower
+            .mknod(path, tracking::Entry::empty_file_with_open_perms())
+            .unwrap();
+    }
+    lower
+        .mknod("dir/gone.txt", tracking::Entry::mask())
+        .unwrap();
+
+    let mut upper = tracking::Manifest::default();
+    upper.mkdirs("dir/sub").unwrap();
+    upper
+        .mknod(
+            "dir/kept.txt",
+            tracking::Entry::empty_file_with_open_perms(),
+        )
+        .unwrap();
+    mask_lower_entries(&mut upper, &lower, relative_path::RelativePath::new("dir")).unwrap();
+
+    let kinds: Vec<_> = entries(&upper)
+        .into_iter()
+        .map(|(path, kind, _)| (path, kind.split(':').next().unwrap().to_owned()))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            ("dir".to_owned(), "tree".to_owned()),
+            ("dir/a.txt".to_owned(), "mask".to_owned()),
+            ("dir/kept.txt".to_owned(), "file".to_owned()),
+            ("dir/sub".to_owned(), "tree".to_owned()),
+            ("dir/sub/b.txt".to_owned(), "mask".to_owned()),
+        ],
+        "everything from the lower directory should be masked except what the upper provides"
+    );
+}
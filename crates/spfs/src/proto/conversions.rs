@@ -82,10 +82,37 @@ impl TryFrom<super::Tag> for tracking::Tag {
         tag.parent = convert_digest(source.parent)?;
         tag.user = source.user;
         tag.time = convert_to_datetime(source.time)?;
+        tag.signature = source.signature.map(TryInto::try_into).transpose()?;
         Ok(tag)
     }
 }
 
+impl TryFrom<super::TagSignature> for tracking::TagSignature {
+    type Error = Error;
+    fn try_from(source: super::TagSignature) -> Result<Self> {
+        if source.algorithm != tracking::TAG_SIGNATURE_ALGORITHM {
+            return Err(Error::String(format!(
+                "Unsupported tag signature algorithm in rpc message: {}",
+                source.algorithm
+            )));
+        }
+        Ok(Self {
+            public_key: source.public_key,
+            signature: source.signature,
+        })
+    }
+}
+
+impl From<&tracking::TagSignature> for super::TagSignature {
+    fn from(source: &tracking::TagSignature) -> Self {
+        Self {
+            algorithm: tracking::TAG_SIGNATURE_ALGORITHM.to_string(),
+            public_key: source.public_key.clone(),
+            signature: source.signature.clone(),
+        }
+    }
+}
+
 impl From<&tracking::Tag> for super::Tag {
     fn from(source: &tracking::Tag) -> Self {
         Self {
@@ -95,6 +122,7 @@ impl From<&tracking::Tag> for super::Tag {
             parent: Some((&source.parent).into()),
             user: source.user.clone(),
             time: Some(convert_from_datetime(&source.time)),
+            signature: source.signature.as_ref().map(Into::into),
         }
     }
 }
@@ -166,6 +194,9 @@ impl From<&graph::object::Enum> for super::Object {
                 graph::object::Enum::Layer(o) => Kind::Layer(o.into()),
                 graph::object::Enum::Manifest(o) => Kind::Manifest(o.into()),
                 graph::object::Enum::Blob(o) => Kind::Blob(o.into()),
+                // chunked blobs have no protobuf representation
+                // and are only ever sent in flatbuffers format
+                graph::object::Enum::ChunkedBlob(o) => Kind::Buffer(o.inner_bytes().clone()),
             }),
         }
     }
@@ -300,21 +331,25 @@ impl TryFrom<super::Manifest> for graph::Manifest {
                 .into_iter()
                 .map(|entry: super::Entry| {
                     let kind = match super::EntryKind::try_from(entry.kind) {
-                        Ok(super::EntryKind::Tree) => spfs_proto::EntryKind::Tree,
-                        Ok(super::EntryKind::Blob) => spfs_proto::EntryKind::Blob,
-                        Ok(super::EntryKind::Mask) => spfs_proto::EntryKind::Mask,
+                        Ok(super::EntryKind::Tree) => tracking::EntryKind::Tree,
+                        Ok(super::EntryKind::Blob) => tracking::EntryKind::Blob(entry.size),
+                        Ok(super::EntryKind::Mask) => tracking::EntryKind::Mask,
                         Err(_) => return Err("Received unknown entry kind in rpc data".into()),
                     };
-                    let name = builder.create_string(&entry.name);
-                    Ok(spfs_proto::Entry::create(
+                    let xattrs = entry
+                        .xattrs
+                        .into_iter()
+                        .map(|xattr| (xattr.name, xattr.value))
+                        .collect();
+                    Ok(graph::Entry::build(
                         &mut builder,
-                        &spfs_proto::EntryArgs {
-                            kind,
-                            object: Some(&convert_digest(entry.object)?),
-                            mode: entry.mode,
-                            size_: entry.size,
-                            name: Some(name),
-                        },
+                        &entry.name,
+                        kind,
+                        entry.mode,
+                        entry.size,
+                        &convert_digest(entry.object)?,
+                        &xattrs,
+                        (!entry.hardlink.is_empty()).then_some(entry.hardlink.as_str()),
                     ))
                 })
                 .collect::<Result<Vec<_>>>()?;
@@ -406,6 +441,14 @@ impl From<&graph::Entry<'_>> for super::Entry {
             mode: source.mode(),
             size: source.size(),
             name: source.name().to_owned(),
+            xattrs: source
+                .xattrs()
+                .map(|(name, value)| super::Xattr {
+                    name: name.to_owned(),
+                    value: value.to_owned(),
+                })
+                .collect(),
+            hardlink: source.hardlink().unwrap_or_default().to_owned(),
         }
     }
 }
@@ -426,6 +469,33 @@ impl TryFrom<super::Blob> for graph::Blob {
     }
 }
 
+impl From<&storage::MissingData> for super::find_missing_response::MissingData {
+    fn from(source: &storage::MissingData) -> Self {
+        Self {
+            objects: source.objects.iter().map(Into::into).collect(),
+            payloads: source.payloads.iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl TryFrom<super::find_missing_response::MissingData> for storage::MissingData {
+    type Error = Error;
+    fn try_from(source: super::find_missing_response::MissingData) -> Result<Self> {
+        Ok(Self {
+            objects: source
+                .objects
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_>>()?,
+            payloads: source
+                .payloads
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
 impl From<&storage::EntryType> for super::ls_tags_response::Entry {
     fn from(e: &storage::EntryType) -> Self {
         Self {
@@ -119,6 +119,11 @@ rpc_result!(
     g::walk_objects_response::Result,
     g::walk_objects_response::WalkObjectsItem
 );
+rpc_result!(
+    g::FindMissingResponse,
+    g::find_missing_response::Result,
+    g::find_missing_response::MissingData
+);
 rpc_result!(g::WriteObjectResponse, g::write_object_response::Result);
 rpc_result!(g::RemoveObjectResponse, g::remove_object_response::Result);
 rpc_result!(
@@ -402,6 +402,7 @@ pub async fn resolve_stack_to_layers(
         storage::RepositoryHandle::FS(r) => resolve_stack_to_layers_with_repo(stack, r).await,
         storage::RepositoryHandle::Tar(r) => resolve_stack_to_layers_with_repo(stack, r).await,
         storage::RepositoryHandle::Rpc(r) => resolve_stack_to_layers_with_repo(stack, r).await,
+        storage::RepositoryHandle::S3(r) => resolve_stack_to_layers_with_repo(stack, r).await,
         storage::RepositoryHandle::FallbackProxy(r) => {
             resolve_stack_to_layers_with_repo(stack, &**r).await
         }
@@ -434,7 +435,7 @@ where
             graph::object::Enum::Manifest(manifest) => {
                 layers.push(graph::Layer::new(manifest.digest().unwrap()))
             }
-            obj @ graph::object::Enum::Blob(_) => {
+            obj @ (graph::object::Enum::Blob(_) | graph::object::Enum::ChunkedBlob(_)) => {
                 return Err(format!(
                     "Cannot resolve object into a mountable filesystem layer: {:?}",
                     obj.kind()
@@ -7,9 +7,12 @@
 pub mod live_layer;
 #[cfg(unix)]
 pub mod overlayfs;
+pub mod sandbox;
 pub mod spec_api_version;
 #[cfg(unix)]
 mod startup_csh;
+#[cfg(unix)]
+mod startup_fish;
 #[cfg(windows)]
 mod startup_ps;
 #[cfg(unix)]
@@ -21,6 +24,7 @@ pub mod winfsp;
 pub use live_layer::{BindMount, LiveLayer, LiveLayerContents};
 #[cfg(unix)]
 pub use overlayfs::is_removed_entry;
+pub use sandbox::{HostPathRestriction, HostPaths, Sandbox};
 pub use spec_api_version::SpecApiVersion;
 pub use storage::{
     Author,
@@ -33,6 +37,7 @@ pub use storage::{
     OwnedRuntime,
     Runtime,
     STARTUP_FILES_LOCATION,
+    Snapshot,
     Status,
     Storage,
     makedirs_with_perms,
@@ -0,0 +1,127 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+//! Optional isolation of the processes in a runtime.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+#[path = "./sandbox_test.rs"]
+mod sandbox_test;
+
+/// Isolation applied to the processes in a runtime, beyond
+/// the mount namespace that every runtime has.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Sandbox {
+    /// Run in a new network namespace, where only the
+    /// loopback interface is available
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub network: bool,
+    /// Run in a new PID namespace, so that processes outside of the
+    /// runtime can not be signaled. The host's /proc is kept so that
+    /// the runtime can still be monitored, and so still lists them
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pids: bool,
+    /// Run in a new IPC namespace, so that System V IPC objects
+    /// and POSIX message queues are not shared with the host
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ipc: bool,
+    /// How host paths outside of the allowed paths are presented
+    #[serde(default, skip_serializing_if = "HostPaths::is_visible")]
+    pub host_paths: HostPaths,
+    /// Host paths that are left as they are, when host
+    /// paths are otherwise restricted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_host_paths: Vec<PathBuf>,
+}
+
+impl Sandbox {
+    /// True if no isolation is applied
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Identify the directories at the root of the host filesystem that
+    /// need to be restricted, given the paths that must remain available.
+    ///
+    /// A root entry that is itself allowed, or is within an allowed path,
+    /// is left alone. Otherwise it is restricted, except for any allowed
+    /// paths found within it.
+    pub fn host_path_restrictions<I>(
+        root_entries: I,
+        allowed: &[PathBuf],
+    ) -> Vec<HostPathRestriction>
+    where
+        I: IntoIterator<Item = PathBuf>,
+    {
+        let mut restrictions: Vec<_> = root_entries
+            .into_iter()
+            .filter(|entry| !allowed.iter().any(|path| entry.starts_with(path)))
+            .map(|path| {
+                let mut within: Vec<_> = allowed
+                    .iter()
+                    .filter(|allowed| allowed.starts_with(&path))
+                    .collect();
+                within.sort();
+                within.dedup();
+                // sorting puts parents first, and an allowed path
+                // within another is already included with it
+                let allowed = within
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, p)| !within[..*i].iter().any(|parent| p.starts_with(parent)))
+                    .map(|(_, p)| p.to_path_buf())
+                    .collect();
+                HostPathRestriction { path, allowed }
+            })
+            .collect();
+        restrictions.sort_by(|a, b| a.path.cmp(&b.path));
+        restrictions
+    }
+}
+
+/// How the host filesystem is presented in a sandboxed runtime
+#[derive(
+    Default,
+    Clone,
+    Copy,
+    Debug,
+    Eq,
+    PartialEq,
+    strum::Display,
+    strum::EnumString,
+    strum::VariantNames,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum HostPaths {
+    /// Host paths are visible and writable as usual
+    #[default]
+    Visible,
+    /// Host paths outside of the allowed paths are read-only
+    ReadOnly,
+    /// Host paths outside of the allowed paths are replaced
+    /// with empty, read-only directories
+    Hidden,
+}
+
+impl HostPaths {
+    pub fn is_visible(&self) -> bool {
+        matches!(self, Self::Visible)
+    }
+}
+
+/// A directory at the root of the host filesystem that is
+/// restricted in a sandboxed runtime, see [`HostPaths`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostPathRestriction {
+    /// The restricted directory
+    pub path: PathBuf,
+    /// Allowed paths within the directory, which remain available
+    /// as they were
+    pub allowed: Vec<PathBuf>,
+}
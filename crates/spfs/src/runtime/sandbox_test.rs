@@ -0,0 +1,64 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::path::PathBuf;
+
+use rstest::rstest;
+
+use super::{HostPathRestriction, HostPaths, Sandbox};
+
+#[rstest]
+fn test_host_path_restrictions() {
+    let entries = ["/usr", "/home", "/spfs", "/tmp", "/etc"].map(PathBuf::from);
+    let allowed = [
+        "/spfs",
+        "/usr",
+        "/tmp/spfs-runtime",
+        "/home/user/project/src",
+        "/home/user/project",
+        "/home/user/project",
+    ]
+    .map(PathBuf::from);
+
+    let restrictions = Sandbox::host_path_restrictions(entries, &allowed);
+    assert_eq!(
+        restrictions,
+        vec![
+            HostPathRestriction {
+                path: "/etc".into(),
+                allowed: vec![],
+            },
+            HostPathRestriction {
+                path: "/home".into(),
+                allowed: vec!["/home/user/project".into()],
+            },
+            HostPathRestriction {
+                path: "/tmp".into(),
+                allowed: vec!["/tmp/spfs-runtime".into()],
+            },
+        ],
+        "allowed roots should be skipped, and nested allowed paths collapsed"
+    );
+}
+
+#[rstest]
+fn test_sandbox_serialization() {
+    let sandbox = Sandbox::default();
+    assert!(sandbox.is_empty());
+    assert_eq!(serde_json::to_string(&sandbox).unwrap(), "{}");
+
+    let sandbox = Sandbox {
+        network: true,
+        host_paths: HostPaths::ReadOnly,
+        allowed_host_paths: vec!["/usr".into()],
+        ..Default::default()
+    };
+    let data = serde_json::to_string(&sandbox).unwrap();
+    let actual: Sandbox = serde_json::from_str(&data).unwrap();
+    assert_eq!(actual, sandbox);
+    assert_eq!(
+        "read-only".parse::<HostPaths>().unwrap(),
+        HostPaths::ReadOnly
+    );
+}
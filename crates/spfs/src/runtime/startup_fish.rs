@@ -0,0 +1,76 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use itertools::Itertools;
+
+use super::EnvKeyValue;
+
+pub fn source(environment_overrides: &[EnvKeyValue]) -> String {
+    let mut env_replacement = String::new();
+    for (position, key_value) in environment_overrides.iter().with_position() {
+        match position {
+            itertools::Position::First | itertools::Position::Only => {
+                env_replacement.push_str("# Re-assign variables as configured.\n");
+                env_replacement.push_str("# The values of these variables may be lost when exec'ing a privileged process or unsharing the mount namespace.\n");
+            }
+            _ => {}
+        };
+        let value = key_value.1.replace("\"", "\\\"");
+        env_replacement.push_str(&format!("set -gx {key} \"{value}\"\n", key = key_value.0));
+        match position {
+            itertools::Position::Last | itertools::Position::Only => {
+                env_replacement.push('\n');
+            }
+            _ => {}
+        };
+    }
+
+    format!(
+        r#"#!/usr/bin/env fish
+{env_replacement}
+set -l startup_dir "/spfs/etc/spfs/startup.d"
+if test -d "$startup_dir"
+    for file in (/bin/ls $startup_dir)
+        switch $file
+            case '*.fish'
+                set -q SPFS_DEBUG; and echo source $startup_dir/$file 1>&2
+                source $startup_dir/$file; or true
+            case '*.sh'
+                # sh scripts are only used when there is no fish version
+                # of the same script, and are run through sh so that the
+                # changes they make to the environment can be imported
+                if test -f $startup_dir/(string replace -r '\.sh$' '.fish' -- $file)
+                    continue
+                end
+                set -q SPFS_DEBUG; and echo import $startup_dir/$file 1>&2
+                for pair in (/bin/sh -c '. "$1" 1>&2; env -0' sh $startup_dir/$file | string split0)
+                    set -l name (string split -m 1 = -- $pair)[1]
+                    set -l value (string split -m 1 = -- $pair)[2]
+                    switch $name
+                        case '' _ PWD OLDPWD SHLVL
+                            continue
+                    end
+                    if set -q $name; and test "$$name" = "$value"
+                        continue
+                    end
+                    if string match -q '*PATH' -- $name
+                        set -gx $name (string split : -- $value)
+                    else
+                        set -gx $name $value
+                    end
+                end
+        end
+    end
+end
+
+if test (count $argv) -ne 0
+    exec $argv
+end
+
+if test -n "$SPFS_SHELL_MESSAGE"
+    echo $SPFS_SHELL_MESSAGE 1>&2
+end
+"#
+    )
+}
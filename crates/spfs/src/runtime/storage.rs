@@ -24,13 +24,13 @@ use tokio::io::AsyncReadExt;
 #[cfg(windows)]
 use super::startup_ps;
 #[cfg(unix)]
-use super::{startup_csh, startup_sh};
+use super::{startup_csh, startup_fish, startup_sh};
 use crate::encoding::Digest;
 use crate::env::SPFS_DIR_PREFIX;
 use crate::graph::object::Enum;
 use crate::graph::{Annotation, AnnotationValue, KeyAnnotationValuePair};
 use crate::prelude::*;
-use crate::runtime::LiveLayer;
+use crate::runtime::{LiveLayer, Sandbox};
 use crate::storage::RepositoryHandle;
 use crate::storage::fs::DURABLE_EDITS_DIR;
 use crate::{Error, Result, bootstrap, graph, storage, tracking};
@@ -55,6 +55,32 @@ pub type KeyValuePair<'a> = (&'a str, &'a str);
 /// An owned instance of [`KeyValuePair`].
 pub type KeyValuePairBuf = (String, String);
 
+/// A saved copy of the working changes in a runtime,
+/// see [`Runtime::snapshot`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// The name of this snapshot, unique within its runtime
+    pub name: String,
+    /// The manifest of the runtime's upper directory
+    /// at the time that the snapshot was taken
+    pub manifest: Digest,
+    /// The user that took this snapshot
+    pub user: String,
+    /// When this snapshot was taken
+    pub time: chrono::DateTime<chrono::Utc>,
+}
+
+impl Snapshot {
+    fn from_tag<S: Into<String>>(name: S, tag: tracking::Tag) -> Self {
+        Self {
+            name: name.into(),
+            manifest: tag.target,
+            user: tag.user,
+            time: tag.time,
+        }
+    }
+}
+
 /// Information about the source of a runtime
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Author {
@@ -148,6 +174,9 @@ pub struct Config {
     pub sh_startup_file: PathBuf,
     /// The location of the startup script for csh-based shells
     pub csh_startup_file: PathBuf,
+    /// The location of the startup script for fish shells
+    #[serde(default = "Config::default_fish_startup_file")]
+    pub fish_startup_file: PathBuf,
     /// The location of the expect utility script used for csh-based shell environments
     /// \[DEPRECATED\] This field still exists for spk/spfs interop but is unused
     #[serde(skip_deserializing, default = "Config::default_csh_expect_file")]
@@ -173,6 +202,13 @@ pub struct Config {
     /// List of live layers to add on top of the runtime's overlayfs
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub live_layers: Vec<LiveLayer>,
+    /// Whether the runtime was set up in an unprivileged user namespace
+    /// rather than by a privileged spfs-enter
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub rootless: bool,
+    /// Additional isolation for the processes in this runtime
+    #[serde(default, skip_serializing_if = "Sandbox::is_empty")]
+    pub sandbox: Sandbox,
 }
 
 impl Default for Config {
@@ -188,6 +224,7 @@ impl Config {
     const WORK_DIR: &'static str = "work";
     const SH_STARTUP_FILE: &'static str = "startup.sh";
     const CSH_STARTUP_FILE: &'static str = ".cshrc";
+    const FISH_STARTUP_FILE: &'static str = "startup.fish";
     const PS_STARTUP_FILE: &'static str = "startup.ps1";
     const DEV_NULL: &'static str = "/dev/null";
 
@@ -196,6 +233,12 @@ impl Config {
         Self::DEV_NULL.into()
     }
 
+    /// Return the location of the fish startup script for runtimes
+    /// that were created before it existed.
+    fn default_fish_startup_file() -> PathBuf {
+        Path::new(Self::RUNTIME_DIR).join(Self::FISH_STARTUP_FILE)
+    }
+
     fn from_root<P: Into<PathBuf>>(root: P) -> Self {
         let root = root.into();
         let tmpfs_size = std::env::var(SPFS_FILESYSTEM_TMPFS_SIZE)
@@ -207,6 +250,7 @@ impl Config {
             work_dir: root.join(Self::WORK_DIR),
             sh_startup_file: root.join(Self::SH_STARTUP_FILE),
             csh_startup_file: root.join(Self::CSH_STARTUP_FILE),
+            fish_startup_file: root.join(Self::FISH_STARTUP_FILE),
             csh_expect_file: Self::default_csh_expect_file(),
             ps_startup_file: temp_dir().join(Self::PS_STARTUP_FILE),
             runtime_dir: Some(root),
@@ -216,6 +260,8 @@ impl Config {
             secondary_repositories: Vec::new(),
             durable: false,
             live_layers: Vec::new(),
+            rootless: false,
+            sandbox: Sandbox::default(),
         }
     }
 
@@ -227,6 +273,7 @@ impl Config {
         self.work_dir = root.join(Self::WORK_DIR);
         self.sh_startup_file = root.join(Self::SH_STARTUP_FILE);
         self.csh_startup_file = root.join(Self::CSH_STARTUP_FILE);
+        self.fish_startup_file = root.join(Self::FISH_STARTUP_FILE);
         self.runtime_dir = Some(root);
     }
 
@@ -258,7 +305,8 @@ pub enum MountBackend {
     /// Mounts a fuse filesystem as the lower directory to
     /// overlayfs, using the overlayfs upper directory for edits
     OverlayFsWithFuse,
-    /// Mounts a fuse filesystem directly
+    /// Mounts a fuse filesystem directly, which stores
+    /// any edits in the runtime's upper directory
     FuseOnly,
     /// Leverages the win file system protocol system to present
     /// dynamic file system entries to runtime processes
@@ -752,24 +800,86 @@ impl Runtime {
         Ok(())
     }
 
+    /// Save the working changes in this runtime's upper dir as a
+    /// named snapshot, which can be restored later with
+    /// [`Self::rollback_to_snapshot`].
+    ///
+    /// The upper dir is stored as a manifest and payloads in the same
+    /// repository as the runtime, without creating a layer or
+    /// changing the runtime's stack.
+    pub async fn snapshot<S: AsRef<str>>(&self, name: S) -> Result<Snapshot> {
+        let manifest = crate::Committer::new(&self.storage.inner)
+            .commit_dir(&self.config.upper_dir)
+            .await?;
+        self.storage
+            .save_snapshot(self.name(), name.as_ref(), &manifest)
+            .await
+    }
+
+    /// Replace the working changes in this runtime's upper dir
+    /// with those saved in a snapshot, see [`Self::snapshot`].
+    ///
+    /// The runtime is remounted to pick up the restored changes,
+    /// which means that this must be called from within it, the
+    /// same as [`crate::remount_runtime`].
+    #[cfg(unix)]
+    pub async fn rollback_to_snapshot<S: AsRef<str>>(&mut self, name: S) -> Result<Snapshot> {
+        let snapshot = self
+            .storage
+            .read_snapshot(self.name(), name.as_ref())
+            .await?;
+        let manifest = self
+            .storage
+            .inner
+            .read_manifest(snapshot.manifest)
+            .await?
+            .to_tracking_manifest();
+
+        self.reset_all()?;
+        let masks =
+            restore_manifest(&self.storage.inner, &manifest, &self.config.upper_dir).await?;
+        if !manifest.is_empty() {
+            self.status.editable = true;
+        }
+        self.save_state_to_storage().await?;
+        crate::remount_runtime(self).await?;
+
+        // overlayfs whiteout files cannot be created in the upper dir
+        // without privileges, but removing the masked paths through the
+        // mounted filesystem creates them as needed
+        for path in masks {
+            let path = path.to_path(crate::env::SPFS_DIR);
+            let result = match std::fs::symlink_metadata(&path) {
+                Ok(meta) if meta.is_dir() => std::fs::remove_dir_all(&path),
+                Ok(_) => std::fs::remove_file(&path),
+                Err(err) => Err(err),
+            };
+            match result {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(Error::RuntimeWriteError(path, err)),
+            }
+        }
+        Ok(snapshot)
+    }
+
     /// Return true if the upper dir of this runtime has changes.
     pub fn is_dirty(&self) -> bool {
         match self.config.mount_backend {
-            MountBackend::OverlayFsWithFuse | MountBackend::OverlayFsWithRenders => {
-                match std::fs::metadata(&self.config.upper_dir) {
-                    #[cfg(unix)]
-                    Ok(meta) => meta.size() != 0,
-                    #[cfg(windows)]
-                    Ok(meta) => meta.file_size() != 0,
-                    Err(err) => {
-                        // Treating other error types as dirty is not strictly
-                        // accurate, but it is not worth the trouble of needing
-                        // to return an error from this function
-                        !matches!(err.kind(), std::io::ErrorKind::NotFound)
-                    }
+            MountBackend::OverlayFsWithFuse
+            | MountBackend::OverlayFsWithRenders
+            | MountBackend::FuseOnly => match std::fs::metadata(&self.config.upper_dir) {
+                #[cfg(unix)]
+                Ok(meta) => meta.size() != 0,
+                #[cfg(windows)]
+                Ok(meta) => meta.file_size() != 0,
+                Err(err) => {
+                    // Treating other error types as dirty is not strictly
+                    // accurate, but it is not worth the trouble of needing
+                    // to return an error from this function
+                    !matches!(err.kind(), std::io::ErrorKind::NotFound)
                 }
-            }
-            MountBackend::FuseOnly => false,
+            },
             MountBackend::WinFsp => false,
         }
     }
@@ -811,6 +921,12 @@ impl Runtime {
             startup_csh::source(environment_overrides_for_child_process),
         )
         .map_err(|err| Error::RuntimeWriteError(self.config.csh_startup_file.clone(), err))?;
+        #[cfg(unix)]
+        std::fs::write(
+            &self.config.fish_startup_file,
+            startup_fish::source(environment_overrides_for_child_process),
+        )
+        .map_err(|err| Error::RuntimeWriteError(self.config.fish_startup_file.clone(), err))?;
         #[cfg(windows)]
         std::fs::write(
             &self.config.ps_startup_file,
@@ -986,6 +1102,15 @@ impl Storage {
             }
         }
 
+        for snapshot in self.list_snapshots(name.as_ref()).await? {
+            let tag = snapshot_tag(name.as_ref(), &snapshot.name)?;
+            match self.inner.remove_tag_stream(&tag).await {
+                Ok(_) => {}
+                Err(Error::UnknownReference(_)) => {}
+                err => return err,
+            }
+        }
+
         Ok(())
     }
 
@@ -1268,6 +1393,48 @@ impl Storage {
         Ok(())
     }
 
+    /// Save a manifest of a runtime's working changes as a named snapshot.
+    ///
+    /// Any existing snapshot of the same name is replaced.
+    pub async fn save_snapshot(
+        &self,
+        runtime: &str,
+        name: &str,
+        manifest: &tracking::Manifest,
+    ) -> Result<Snapshot> {
+        let tag_spec = snapshot_tag(runtime, name)?;
+        let manifest = manifest.to_graph_manifest();
+        self.inner.write_object(&manifest).await?;
+        let tag = self.inner.push_tag(&tag_spec, &manifest.digest()?).await?;
+        Ok(Snapshot::from_tag(name, tag))
+    }
+
+    /// Load a named snapshot of a runtime
+    ///
+    /// # Errors:
+    /// - [`Error::UnknownReference`] if the snapshot does not exist
+    pub async fn read_snapshot(&self, runtime: &str, name: &str) -> Result<Snapshot> {
+        let tag = self
+            .inner
+            .resolve_tag(&snapshot_tag(runtime, name)?)
+            .await?;
+        Ok(Snapshot::from_tag(name, tag))
+    }
+
+    /// List the snapshots of a runtime, oldest first
+    pub async fn list_snapshots(&self, runtime: &str) -> Result<Vec<Snapshot>> {
+        let path = format!("spfs/runtimes/{SNAPSHOTS_TAG_DIR}/{runtime}");
+        let mut names = self.inner.ls_tags(relative_path::RelativePath::new(&path));
+        let mut snapshots = Vec::new();
+        while let Some(entry) = names.try_next().await? {
+            if let storage::EntryType::Tag(name) = entry {
+                snapshots.push(self.read_snapshot(runtime, &name).await?);
+            }
+        }
+        snapshots.sort_by_key(|s| s.time);
+        Ok(snapshots)
+    }
+
     /// Iterate through all currently stored runtimes
     pub async fn iter_runtimes(&self) -> Pin<Box<dyn Stream<Item = Result<Runtime>> + Send>> {
         let storage = self.clone();
@@ -1304,6 +1471,81 @@ impl std::fmt::Display for RuntimeDataType {
     }
 }
 
+/// Snapshots are tagged separately from the other runtime data
+const SNAPSHOTS_TAG_DIR: &str = "snapshots";
+
+fn snapshot_tag(runtime: &str, name: &str) -> Result<tracking::TagSpec> {
+    tracking::TagSpec::parse(format!(
+        "spfs/runtimes/{SNAPSHOTS_TAG_DIR}/{runtime}/{name}"
+    ))
+}
+
+/// Write the files and directories of a manifest into an existing
+/// directory, returning the paths of any masks, which cannot be
+/// written to the directory as regular files.
+#[cfg(unix)]
+async fn restore_manifest(
+    repo: &RepositoryHandle,
+    manifest: &tracking::Manifest,
+    root: &Path,
+) -> Result<Vec<relative_path::RelativePathBuf>> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut masks = Vec::new();
+    let mut dirs = Vec::new();
+    for node in manifest.walk() {
+        let path = node.path.to_path(root);
+        let entry = node.entry;
+        match entry.kind {
+            tracking::EntryKind::Mask => {
+                masks.push(node.path);
+                continue;
+            }
+            tracking::EntryKind::Tree => {
+                tokio::fs::create_dir_all(&path)
+                    .await
+                    .map_err(|err| Error::RuntimeWriteError(path.clone(), err))?;
+                // directories are given their permissions last, in
+                // case they would not allow their contents to be written
+                dirs.push((path, entry.mode));
+                continue;
+            }
+            tracking::EntryKind::Blob(_) => {}
+        }
+
+        let (mut reader, _) = repo.open_payload(entry.object).await?;
+        if entry.is_symlink() {
+            let mut target = String::new();
+            reader
+                .read_to_string(&mut target)
+                .await
+                .map_err(|err| Error::RuntimeReadError(path.clone(), err))?;
+            tokio::fs::symlink(&target, &path)
+                .await
+                .map_err(|err| Error::RuntimeWriteError(path.clone(), err))?;
+            continue;
+        }
+        let mut file = tokio::fs::File::create(&path)
+            .await
+            .map_err(|err| Error::RuntimeWriteError(path.clone(), err))?;
+        tokio::io::copy(&mut reader, &mut file)
+            .await
+            .map_err(|err| Error::RuntimeWriteError(path.clone(), err))?;
+        file.flush()
+            .await
+            .map_err(|err| Error::RuntimeWriteError(path.clone(), err))?;
+        tokio::fs::set_permissions(&path, std::fs::Permissions::from_mode(entry.mode & 0o7777))
+            .await
+            .map_err(|err| Error::RuntimeWriteError(path.clone(), err))?;
+    }
+    for (path, mode) in dirs.into_iter().rev() {
+        tokio::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode & 0o7777))
+            .await
+            .map_err(|err| Error::RuntimeWriteError(path, err))?;
+    }
+    Ok(masks)
+}
+
 fn runtime_tag<S: std::fmt::Display>(
     data_type: RuntimeDataType,
     name: S,
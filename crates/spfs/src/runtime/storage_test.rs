@@ -548,6 +548,80 @@ async fn test_runtime_reset(tmpdir: tempfile::TempDir) {
     assert_eq!(listdir(upper_dir), Vec::<String>::new());
 }
 
+#[cfg(unix)]
+#[rstest]
+#[tokio::test]
+async fn test_runtime_snapshot_and_restore(tmpdir: tempfile::TempDir) {
+    let root = tmpdir.path().join("repo");
+    let repo = crate::storage::RepositoryHandle::from(
+        crate::storage::fs::MaybeOpenFsRepository::create(&root)
+            .await
+            .unwrap(),
+    );
+    let storage = Storage::new(repo).unwrap();
+
+    let mut runtime = storage
+        .create_owned_runtime()
+        .await
+        .expect("failed to create runtime in storage");
+    let upper_dir = tmpdir.path().join("upper");
+    runtime.data.config.upper_dir.clone_from(&upper_dir);
+
+    ensure(upper_dir.join("file"), "before");
+    ensure(upper_dir.join("dir/file"), "nested");
+    std::os::unix::fs::symlink("file", upper_dir.join("link")).unwrap();
+
+    let snapshot = runtime
+        .snapshot("before")
+        .await
+        .expect("failed to snapshot runtime");
+    assert_eq!(snapshot.name, "before");
+
+    ensure(upper_dir.join("file"), "after");
+    ensure(upper_dir.join("other"), "new file");
+    runtime.reset_all().expect("failed to reset runtime paths");
+
+    let manifest = storage
+        .read_snapshot(runtime.name(), "before")
+        .await
+        .expect("snapshot should exist");
+    let manifest = storage
+        .inner
+        .read_manifest(manifest.manifest)
+        .await
+        .unwrap()
+        .to_tracking_manifest();
+    let masks = super::restore_manifest(&storage.inner, &manifest, &upper_dir)
+        .await
+        .expect("failed to restore snapshot");
+    assert!(masks.is_empty());
+    assert_eq!(
+        std::fs::read_to_string(upper_dir.join("file")).unwrap(),
+        "before"
+    );
+    assert_eq!(
+        std::fs::read_to_string(upper_dir.join("dir/file")).unwrap(),
+        "nested"
+    );
+    assert_eq!(
+        std::fs::read_link(upper_dir.join("link")).unwrap(),
+        std::path::PathBuf::from("file")
+    );
+    assert!(!upper_dir.join("other").exists());
+
+    let snapshots = storage.list_snapshots(runtime.name()).await.unwrap();
+    assert_eq!(snapshots, vec![snapshot]);
+    storage
+        .remove_runtime(runtime.name())
+        .await
+        .expect("should remove runtime properly");
+    let snapshots = storage.list_snapshots(runtime.name()).await.unwrap();
+    assert!(
+        snapshots.is_empty(),
+        "snapshots should be removed with the runtime"
+    );
+}
+
 #[rstest]
 #[tokio::test]
 async fn test_runtime_ensure_extra_bind_mount_locations_exist(tmpdir: tempfile::TempDir) {
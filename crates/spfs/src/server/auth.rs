@@ -0,0 +1,232 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use data_encoding::HEXLOWER;
+use tonic::{Request, Status};
+
+use crate::storage::{TagNamespace, TagNamespaceBuf};
+use crate::{Error, Result};
+
+#[cfg(test)]
+#[path = "./auth_test.rs"]
+mod auth_test;
+
+/// The prefix of an authorization header that carries a bearer token
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// The level of access granted to a token
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Access {
+    /// Objects, payloads and tags can be read but not modified
+    #[default]
+    ReadOnly,
+    /// Objects, payloads and tags can be read, written and removed
+    Push,
+}
+
+/// One entry of a token file, describing a single token
+/// and what its bearer is allowed to do.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct TokenConfig {
+    /// A name for the bearer of this token, used for logging
+    pub name: String,
+    /// The hex encoded sha256 hash of the token
+    ///
+    /// Tokens are never stored directly so that the token
+    /// file does not need to be kept secret.
+    pub sha256: String,
+    #[serde(default)]
+    pub access: Access,
+    /// If set, tags can only be written and removed in these
+    /// tag namespaces, and no objects or payloads can be removed.
+    ///
+    /// Only applies to tokens with push access.
+    #[serde(default)]
+    pub tag_namespaces: Option<Vec<TagNamespaceBuf>>,
+}
+
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+struct TokenFile {
+    #[serde(default)]
+    tokens: Vec<TokenConfig>,
+}
+
+/// The permissions of an authenticated request.
+#[derive(Debug)]
+pub struct TokenGrant {
+    name: String,
+    access: Access,
+    tag_namespaces: Option<Vec<TagNamespaceBuf>>,
+}
+
+impl TokenGrant {
+    /// The name of the token that was used
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// True if new objects and payloads can be written
+    pub fn can_write(&self) -> bool {
+        self.access == Access::Push
+    }
+
+    /// True if existing objects and payloads can be removed
+    pub fn can_remove(&self) -> bool {
+        self.can_write() && self.tag_namespaces.is_none()
+    }
+
+    /// True if tags can be written or removed in the given namespace
+    pub fn can_write_tags_in(&self, namespace: Option<&TagNamespace>) -> bool {
+        if !self.can_write() {
+            return false;
+        }
+        match (&self.tag_namespaces, namespace) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(allowed), Some(namespace)) => allowed.iter().any(|ns| **ns == *namespace),
+        }
+    }
+}
+
+/// Checks the bearer tokens of incoming requests against a token file.
+///
+/// A default authenticator has no tokens and allows all requests,
+/// which matches the behavior of a server without authentication.
+#[derive(Clone, Debug, Default)]
+pub struct Authenticator {
+    /// token grants by the sha256 of the token
+    tokens: Option<Arc<HashMap<Vec<u8>, Arc<TokenGrant>>>>,
+}
+
+impl Authenticator {
+    /// Load the tokens that are allowed to access the server from a yaml file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path)
+            .map_err(|err| Error::StorageReadError("read of token file", path.into(), err))?;
+        let file: TokenFile = serde_yaml::from_str(&data)?;
+        Self::from_tokens(file.tokens)
+    }
+
+    /// Create an authenticator that allows only the given tokens.
+    pub fn from_tokens<I>(tokens: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = TokenConfig>,
+    {
+        let mut grants = HashMap::new();
+        for token in tokens {
+            let hash = HEXLOWER
+                .decode(token.sha256.trim().to_ascii_lowercase().as_bytes())
+                .map_err(|err| {
+                    Error::String(format!("Invalid sha256 for token '{}': {err}", token.name))
+                })?;
+            let grant = TokenGrant {
+                name: token.name,
+                access: token.access,
+                tag_namespaces: token.tag_namespaces,
+            };
+            grants.insert(hash, Arc::new(grant));
+        }
+        Ok(Self {
+            tokens: Some(Arc::new(grants)),
+        })
+    }
+
+    /// True if requests must present a valid token
+    pub fn is_enabled(&self) -> bool {
+        self.tokens.is_some()
+    }
+
+    /// The hex encoded sha256 of a token, as it would appear in a token file
+    pub fn hash_token(token: &str) -> String {
+        HEXLOWER.encode(ring::digest::digest(&ring::digest::SHA256, token.as_bytes()).as_ref())
+    }
+
+    /// Identify the grant for the given authorization header value.
+    ///
+    /// Returns `None` when authentication is disabled.
+    pub fn authenticate(
+        &self,
+        authorization: Option<&str>,
+    ) -> std::result::Result<Option<Arc<TokenGrant>>, Status> {
+        let Some(tokens) = &self.tokens else {
+            return Ok(None);
+        };
+        let Some(token) = authorization.and_then(|value| value.strip_prefix(BEARER_PREFIX)) else {
+            return Err(Status::unauthenticated("a bearer token is required"));
+        };
+        let hash = ring::digest::digest(&ring::digest::SHA256, token.trim().as_bytes());
+        match tokens.get(hash.as_ref()) {
+            Some(grant) => Ok(Some(Arc::clone(grant))),
+            None => Err(Status::unauthenticated("invalid bearer token")),
+        }
+    }
+}
+
+impl tonic::service::Interceptor for Authenticator {
+    fn call(&mut self, mut request: Request<()>) -> std::result::Result<Request<()>, Status> {
+        let authorization = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok());
+        if let Some(grant) = self.authenticate(authorization)? {
+            tracing::trace!(token = grant.name(), "authenticated request");
+            request.extensions_mut().insert(grant);
+        }
+        Ok(request)
+    }
+}
+
+fn grant<T>(request: &Request<T>) -> Option<&TokenGrant> {
+    request
+        .extensions()
+        .get::<Arc<TokenGrant>>()
+        .map(|grant| grant.as_ref())
+}
+
+/// Ensure that the given request can write objects and payloads
+pub(crate) fn require_write<T>(request: &Request<T>) -> std::result::Result<(), Status> {
+    match grant(request) {
+        Some(grant) if !grant.can_write() => Err(Status::permission_denied(format!(
+            "token '{}' does not have write access",
+            grant.name()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Ensure that the given request can remove objects and payloads
+pub(crate) fn require_remove<T>(request: &Request<T>) -> std::result::Result<(), Status> {
+    match grant(request) {
+        Some(grant) if !grant.can_remove() => Err(Status::permission_denied(format!(
+            "token '{}' cannot remove data from this repository",
+            grant.name()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Ensure that the given request can write tags in the given namespace
+pub(crate) fn require_tag_write<T>(
+    request: &Request<T>,
+    namespace: Option<&TagNamespace>,
+) -> std::result::Result<(), Status> {
+    match grant(request) {
+        Some(grant) if !grant.can_write_tags_in(namespace) => {
+            Err(Status::permission_denied(format!(
+                "token '{}' cannot write tags in the {} namespace",
+                grant.name(),
+                namespace
+                    .map(ToString::to_string)
+                    .unwrap_or_else(|| "root".into())
+            )))
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use rstest::rstest;
+use tonic::service::Interceptor;
+
+use super::{Access, Authenticator, TokenConfig, require_remove, require_tag_write, require_write};
+use crate::fixtures::*;
+use crate::storage::{TagNamespace, TagNamespaceBuf};
+
+fn token(name: &str, access: Access, tag_namespaces: Option<Vec<&str>>) -> TokenConfig {
+    TokenConfig {
+        name: name.into(),
+        sha256: Authenticator::hash_token(name),
+        access,
+        tag_namespaces: tag_namespaces
+            .map(|namespaces| namespaces.into_iter().map(TagNamespaceBuf::from).collect()),
+    }
+}
+
+fn authenticator() -> Authenticator {
+    Authenticator::from_tokens([
+        token("reader", Access::ReadOnly, None),
+        token("pusher", Access::Push, None),
+        token("builder", Access::Push, Some(vec!["builds"])),
+    ])
+    .unwrap()
+}
+
+fn request_as(token: Option<&str>) -> Result<tonic::Request<()>, tonic::Status> {
+    let mut request = tonic::Request::new(());
+    if let Some(token) = token {
+        request
+            .metadata_mut()
+            .insert("authorization", format!("Bearer {token}").parse().unwrap());
+    }
+    authenticator().call(request)
+}
+
+#[rstest]
+fn test_authenticator_disabled() {
+    let request = Authenticator::default()
+        .call(tonic::Request::new(()))
+        .expect("should allow requests when disabled");
+    assert!(require_write(&request).is_ok());
+    assert!(require_remove(&request).is_ok());
+    assert!(require_tag_write(&request, None).is_ok());
+}
+
+#[rstest]
+#[case::missing(None)]
+#[case::unknown(Some("unknown"))]
+fn test_authenticator_rejects(#[case] token: Option<&str>) {
+    let status = request_as(token).expect_err("should reject the request");
+    assert_eq!(status.code(), tonic::Code::Unauthenticated);
+}
+
+#[rstest]
+fn test_authenticator_read_only() {
+    let request = request_as(Some("reader")).unwrap();
+    assert!(require_write(&request).is_err());
+    assert!(require_remove(&request).is_err());
+    assert!(require_tag_write(&request, None).is_err());
+}
+
+#[rstest]
+fn test_authenticator_push() {
+    let request = request_as(Some("pusher")).unwrap();
+    let builds = TagNamespace::new(relative_path::RelativePath::new("builds")).unwrap();
+    assert!(require_write(&request).is_ok());
+    assert!(require_remove(&request).is_ok());
+    assert!(require_tag_write(&request, None).is_ok());
+    assert!(require_tag_write(&request, Some(builds)).is_ok());
+}
+
+#[rstest]
+fn test_authenticator_namespace_restricted() {
+    let request = request_as(Some("builder")).unwrap();
+    let builds = TagNamespace::new(relative_path::RelativePath::new("builds")).unwrap();
+    let other = TagNamespace::new(relative_path::RelativePath::new("other")).unwrap();
+    assert!(require_write(&request).is_ok());
+    assert!(require_remove(&request).is_err());
+    assert!(require_tag_write(&request, Some(builds)).is_ok());
+    assert!(require_tag_write(&request, Some(other)).is_err());
+    assert!(require_tag_write(&request, None).is_err());
+}
+
+#[rstest]
+fn test_authenticator_load(tmpdir: tempfile::TempDir) {
+    let path = tmpdir.path().join("tokens.yaml");
+    std::fs::write(
+        &path,
+        format!(
+            "tokens:\n  - name: ci\n    sha256: {}\n    access: push\n    tag_namespaces: [builds]\n",
+            Authenticator::hash_token("secret")
+        ),
+    )
+    .unwrap();
+    let auth = Authenticator::load(&path).expect("should load a valid token file");
+    let grant = auth
+        .authenticate(Some("Bearer secret"))
+        .unwrap()
+        .expect("token should be known");
+    assert_eq!(grant.name(), "ci");
+    assert!(grant.can_write());
+    assert!(!grant.can_remove());
+}
@@ -10,6 +10,7 @@ use chrono::{DateTime, Utc};
 use futures::{Stream, StreamExt};
 use tonic::{Request, Response, Status};
 
+use super::auth;
 use crate::prelude::*;
 use crate::proto::database_service_server::DatabaseServiceServer;
 use crate::proto::{self, RpcResult, convert_digest, convert_to_datetime};
@@ -41,6 +42,23 @@ impl proto::database_service_server::DatabaseService for DatabaseService {
         }))
     }
 
+    async fn has_objects(
+        &self,
+        request: Request<proto::HasObjectsRequest>,
+    ) -> Result<Response<proto::HasObjectsResponse>, Status> {
+        let request = request.into_inner();
+        let digests = request
+            .digests
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<crate::Result<Vec<crate::encoding::Digest>>>()
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+        let existing = self.repo.has_objects(&digests).await;
+        Ok(Response::new(proto::HasObjectsResponse {
+            exists: digests.iter().map(|d| existing.contains(d)).collect(),
+        }))
+    }
+
     async fn read_object(
         &self,
         request: Request<proto::ReadObjectRequest>,
@@ -89,10 +107,22 @@ impl proto::database_service_server::DatabaseService for DatabaseService {
         ))
     }
 
+    async fn find_missing(
+        &self,
+        request: Request<proto::FindMissingRequest>,
+    ) -> Result<Response<proto::FindMissingResponse>, Status> {
+        let request = request.into_inner();
+        let root = proto::handle_error!(convert_digest(request.root));
+        let missing = proto::handle_error!(storage::find_missing_data(&*self.repo, root).await);
+        let result = proto::FindMissingResponse::ok((&missing).into());
+        Ok(Response::new(result))
+    }
+
     async fn write_object(
         &self,
         request: Request<proto::WriteObjectRequest>,
     ) -> Result<Response<proto::WriteObjectResponse>, Status> {
+        auth::require_write(&request)?;
         let request = request.into_inner();
         let object = proto::handle_error!(request.object.try_into());
         {
@@ -106,6 +136,7 @@ impl proto::database_service_server::DatabaseService for DatabaseService {
         &self,
         request: Request<proto::RemoveObjectRequest>,
     ) -> Result<Response<proto::RemoveObjectResponse>, Status> {
+        auth::require_remove(&request)?;
         let request = request.into_inner();
         let digest: crate::encoding::Digest = proto::handle_error!(convert_digest(request.digest));
         proto::handle_error!(self.repo.remove_object(digest).await);
@@ -117,6 +148,7 @@ impl proto::database_service_server::DatabaseService for DatabaseService {
         &self,
         request: Request<proto::RemoveObjectIfOlderThanRequest>,
     ) -> Result<Response<proto::RemoveObjectIfOlderThanResponse>, Status> {
+        auth::require_remove(&request)?;
         let request = request.into_inner();
         let older_than: DateTime<Utc> =
             proto::handle_error!(convert_to_datetime(request.older_than));
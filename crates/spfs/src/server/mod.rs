@@ -3,12 +3,16 @@
 // https://github.com/spkenv/spk
 
 //! Remote rpc server implementation of the spfs repository
+mod auth;
 mod database;
 mod payload;
 mod repository;
 mod tag;
+mod tls;
 
+pub use auth::{Access, Authenticator, TokenConfig, TokenGrant};
 pub use database::DatabaseService;
 pub use payload::PayloadService;
 pub use repository::Repository;
 pub use tag::TagService;
+pub use tls::TlsConfig;
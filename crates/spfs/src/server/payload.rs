@@ -9,6 +9,7 @@ use futures::{Stream, StreamExt, TryStreamExt};
 use prost::Message;
 use tonic::{Request, Response, Status};
 
+use super::auth::{self, Authenticator};
 use crate::prelude::*;
 use crate::proto::payload_service_server::PayloadServiceServer;
 use crate::proto::{self, RpcResult, convert_digest};
@@ -26,6 +27,7 @@ use crate::storage;
 pub struct PayloadService {
     repo: Arc<storage::RepositoryHandle>,
     external_root: url::Url,
+    authenticator: Authenticator,
 }
 
 #[tonic::async_trait]
@@ -49,8 +51,9 @@ impl proto::payload_service_server::PayloadService for PayloadService {
 
     async fn write_payload(
         &self,
-        _request: Request<proto::WritePayloadRequest>,
+        request: Request<proto::WritePayloadRequest>,
     ) -> Result<Response<proto::WritePayloadResponse>, Status> {
+        auth::require_write(&request)?;
         let data = proto::write_payload_response::UploadOption {
             url: self.external_root.to_string(),
         };
@@ -70,6 +73,24 @@ impl proto::payload_service_server::PayloadService for PayloadService {
         Ok(Response::new(result))
     }
 
+    async fn has_payloads(
+        &self,
+        request: Request<proto::HasPayloadsRequest>,
+    ) -> Result<Response<proto::HasPayloadsResponse>, Status> {
+        let request = request.into_inner();
+        let digests = request
+            .digests
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<crate::Result<Vec<crate::encoding::Digest>>>()
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+        let existing = self.repo.has_payloads(&digests).await;
+        let result = proto::HasPayloadsResponse {
+            exists: digests.iter().map(|d| existing.contains(d)).collect(),
+        };
+        Ok(Response::new(result))
+    }
+
     async fn open_payload(
         &self,
         request: Request<proto::OpenPayloadRequest>,
@@ -93,6 +114,7 @@ impl proto::payload_service_server::PayloadService for PayloadService {
         &self,
         request: Request<proto::RemovePayloadRequest>,
     ) -> Result<Response<proto::RemovePayloadResponse>, Status> {
+        auth::require_remove(&request)?;
         let request = request.into_inner();
         let digest: crate::encoding::Digest = proto::handle_error!(convert_digest(request.digest));
         proto::handle_error!(self.repo.remove_payload(digest).await);
@@ -113,6 +135,27 @@ where
         std::pin::Pin<Box<dyn futures::Future<Output = crate::Result<Self::Response>> + Send>>;
 
     fn call(&self, req: hyper::http::Request<B>) -> Self::Future {
+        // the http server does not pass through the grpc interceptors
+        // and so must check the bearer token of each request itself
+        let authorization = req
+            .headers()
+            .get(hyper::http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok());
+        match self.authenticator.authenticate(authorization) {
+            Err(status) => {
+                return Box::pin(futures::future::ready(error_response(
+                    hyper::http::StatusCode::UNAUTHORIZED,
+                    status.message(),
+                )));
+            }
+            Ok(Some(grant)) if *req.method() == hyper::Method::POST && !grant.can_write() => {
+                return Box::pin(futures::future::ready(error_response(
+                    hyper::http::StatusCode::FORBIDDEN,
+                    &format!("token '{}' does not have write access", grant.name()),
+                )));
+            }
+            Ok(_) => {}
+        }
         match *req.method() {
             hyper::Method::POST => Box::pin(handle_upload(self.repo.clone(), req)),
             hyper::Method::GET => Box::pin(handle_download(self.repo.clone(), req)),
@@ -131,9 +174,20 @@ impl PayloadService {
         Self {
             repo,
             external_root,
+            authenticator: Authenticator::default(),
         }
     }
 
+    /// Require that http requests present a token allowed by the given
+    /// authenticator.
+    ///
+    /// The grpc service does not use this authenticator, which
+    /// must instead be added as an interceptor to the server.
+    pub fn with_authenticator(mut self, authenticator: Authenticator) -> Self {
+        self.authenticator = authenticator;
+        self
+    }
+
     pub fn new_srv(
         repo: Arc<storage::RepositoryHandle>,
         external_root: url::Url,
@@ -146,6 +200,18 @@ impl PayloadService {
     }
 }
 
+fn error_response(
+    status: hyper::http::StatusCode,
+    message: &str,
+) -> crate::Result<hyper::Response<ResponseBody>> {
+    hyper::Response::builder()
+        .status(status)
+        .body(http_body_util::StreamBody::new(FramedReader::from(
+            message.as_bytes().to_vec(),
+        )))
+        .map_err(|e| crate::Error::String(e.to_string()))
+}
+
 async fn handle_upload<B>(
     repo: Arc<storage::RepositoryHandle>,
     mut req: hyper::http::Request<B>,
@@ -10,6 +10,7 @@ use relative_path::RelativePath;
 use tokio_stream::StreamExt;
 use tonic::{Request, Response, Status};
 
+use super::auth;
 use crate::prelude::*;
 use crate::proto::tag_service_server::TagServiceServer;
 use crate::proto::{self, RpcResult, convert_digest};
@@ -127,6 +128,7 @@ impl proto::tag_service_server::TagService for TagService {
         &self,
         request: tonic::Request<proto::InsertTagRequest>,
     ) -> Result<tonic::Response<proto::InsertTagResponse>, tonic::Status> {
+        auth::require_tag_write(&request, string_to_namespace(&request.get_ref().namespace))?;
         let request = request.into_inner();
         let tag = proto::handle_error!(request.tag.try_into());
         proto::handle_error!(
@@ -142,6 +144,7 @@ impl proto::tag_service_server::TagService for TagService {
         &self,
         request: tonic::Request<proto::RemoveTagStreamRequest>,
     ) -> Result<tonic::Response<proto::RemoveTagStreamResponse>, tonic::Status> {
+        auth::require_tag_write(&request, string_to_namespace(&request.get_ref().namespace))?;
         let request = request.into_inner();
         let tag_spec = proto::handle_error!(request.tag_spec.parse());
         proto::handle_error!(
@@ -158,6 +161,7 @@ impl proto::tag_service_server::TagService for TagService {
         &self,
         request: tonic::Request<proto::RemoveTagRequest>,
     ) -> Result<tonic::Response<proto::RemoveTagResponse>, tonic::Status> {
+        auth::require_tag_write(&request, string_to_namespace(&request.get_ref().namespace))?;
         let request = request.into_inner();
         let tag = proto::handle_error!(request.tag.try_into());
         proto::handle_error!(
@@ -0,0 +1,70 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+
+use crate::Result;
+
+#[cfg(test)]
+#[path = "./tls_test.rs"]
+mod tls_test;
+
+/// The certificates used to serve the grpc and http endpoints over tls.
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    /// A PEM file containing the server certificate chain
+    pub cert_file: PathBuf,
+    /// A PEM file containing the private key of the server certificate
+    pub key_file: PathBuf,
+    /// If set, clients must present a certificate signed by
+    /// one of the certificate authorities in this PEM file
+    pub client_ca_file: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    pub fn new<C, K>(cert_file: C, key_file: K) -> Self
+    where
+        C: Into<PathBuf>,
+        K: Into<PathBuf>,
+    {
+        Self {
+            cert_file: cert_file.into(),
+            key_file: key_file.into(),
+            client_ca_file: None,
+        }
+    }
+
+    /// Require mutual tls, where clients must present a certificate
+    /// signed by one of the authorities in the given file.
+    pub fn with_client_ca_file<P: Into<PathBuf>>(mut self, client_ca_file: P) -> Self {
+        self.client_ca_file = Some(client_ca_file.into());
+        self
+    }
+
+    /// The tls configuration for a grpc server
+    pub fn grpc_config(&self) -> Result<ServerTlsConfig> {
+        let cert = crate::tls::read_pem(&self.cert_file, "read of tls certificate")?;
+        let key = crate::tls::read_pem(&self.key_file, "read of tls private key")?;
+        let mut config = ServerTlsConfig::new().identity(Identity::from_pem(cert, key));
+        if let Some(path) = &self.client_ca_file {
+            let ca = crate::tls::read_pem(path, "read of tls client certificate authority")?;
+            config = config.client_ca_root(Certificate::from_pem(ca));
+        }
+        Ok(config)
+    }
+
+    /// An acceptor that can be used to wrap incoming http connections
+    pub fn http_acceptor(&self) -> Result<tokio_rustls::TlsAcceptor> {
+        let mut config = crate::tls::server_config(
+            &self.cert_file,
+            &self.key_file,
+            self.client_ca_file.as_deref(),
+        )?;
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(tokio_rustls::TlsAcceptor::from(Arc::new(config)))
+    }
+}
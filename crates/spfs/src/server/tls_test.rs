@@ -0,0 +1,95 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rstest::rstest;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_rustls::rustls::pki_types::ServerName;
+
+use super::TlsConfig;
+
+fn testdata(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src/server/testdata")
+        .join(name)
+}
+
+fn server_config(mutual: bool) -> TlsConfig {
+    let config = TlsConfig::new(testdata("server.pem"), testdata("server.key"));
+    match mutual {
+        true => config.with_client_ca_file(testdata("ca.pem")),
+        false => config,
+    }
+}
+
+/// Serve a single connection that echoes one message, returning
+/// the message as seen by the client
+async fn echo(server: TlsConfig, client_identity: bool) -> crate::Result<Vec<u8>> {
+    let acceptor = server.http_acceptor()?;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = tokio::task::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let Ok(mut stream) = acceptor.accept(stream).await else {
+            return;
+        };
+        let mut buf = [0; 5];
+        if stream.read_exact(&mut buf).await.is_ok() {
+            stream.write_all(&buf).await.unwrap();
+            stream.shutdown().await.unwrap();
+        }
+    });
+
+    let (cert, key) = (testdata("client.pem"), testdata("client.key"));
+    let identity = client_identity.then_some((cert.as_path(), key.as_path()));
+    let config = crate::tls::client_config(Some(&testdata("ca.pem")), identity)?;
+    let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+    let stream = tokio::net::TcpStream::connect(address).await.unwrap();
+    let name = ServerName::try_from("localhost").unwrap();
+    let mut received = Vec::new();
+    let result: std::io::Result<()> = async {
+        let mut stream = connector.connect(name, stream).await?;
+        stream.write_all(b"hello").await?;
+        stream.read_to_end(&mut received).await?;
+        Ok(())
+    }
+    .await;
+    server.await.unwrap();
+    result.map_err(|err| crate::Error::String(err.to_string()))?;
+    Ok(received)
+}
+
+#[rstest]
+#[case::server_only(false, false)]
+#[case::server_only_with_client_cert(false, true)]
+#[case::mutual(true, true)]
+#[tokio::test]
+async fn test_tls_connection(#[case] mutual: bool, #[case] client_identity: bool) {
+    let received = echo(server_config(mutual), client_identity)
+        .await
+        .expect("connection should succeed");
+    assert_eq!(received, b"hello");
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_tls_mutual_requires_client_cert() {
+    let result = echo(server_config(true), false).await;
+    assert!(
+        !matches!(&result, Ok(received) if !received.is_empty()),
+        "a client without a certificate should be rejected, got {result:?}"
+    );
+}
+
+#[rstest]
+fn test_tls_grpc_config() {
+    server_config(true)
+        .grpc_config()
+        .expect("should load a valid grpc tls config");
+    TlsConfig::new(testdata("missing.pem"), testdata("server.key"))
+        .grpc_config()
+        .expect_err("should fail to load a missing certificate");
+}
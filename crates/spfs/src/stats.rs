@@ -0,0 +1,267 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+//! Attribution of repository storage to the tags that use it
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use futures::TryStreamExt;
+use serde::Serialize;
+
+use crate::prelude::*;
+use crate::storage::TagNamespaceBuf;
+use crate::{Error, Result, encoding, graph, storage};
+
+#[cfg(test)]
+#[path = "./stats_test.rs"]
+mod stats_test;
+
+/// The payloads needed by some object, and their sizes
+type PayloadSizes = HashMap<encoding::Digest, u64>;
+
+/// Which of a set of groups refer to a payload
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Owner {
+    One(usize),
+    Many,
+}
+
+impl Owner {
+    fn add(owner: &mut Option<Self>, group: usize) {
+        *owner = match *owner {
+            None => Some(Self::One(group)),
+            Some(Self::One(existing)) if existing == group => Some(Self::One(group)),
+            Some(_) => Some(Self::Many),
+        };
+    }
+}
+
+#[derive(Default)]
+struct PayloadUsage {
+    size: u64,
+    tag: Option<Owner>,
+    stream: Option<Owner>,
+    namespace: Option<Owner>,
+}
+
+/// The storage used by one tag, tag stream or tag namespace
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct UsageSummary {
+    /// The tag namespace, or none for tags outside of any namespace
+    pub namespace: Option<String>,
+    /// The tag or tag stream, or none when summarizing a namespace
+    pub tag: Option<String>,
+    /// The number of distinct payloads used
+    pub payloads: usize,
+    /// The size of all payloads used
+    pub total_bytes: u64,
+    /// The size of payloads that are used by nothing else at the same
+    /// level, which is roughly what would be freed by removing this
+    pub exclusive_bytes: u64,
+    /// The size of payloads that are also used by something else
+    pub shared_bytes: u64,
+}
+
+impl UsageSummary {
+    fn new(namespace: Option<&TagNamespaceBuf>, tag: Option<String>) -> Self {
+        Self {
+            namespace: namespace.map(ToString::to_string),
+            tag,
+            ..Default::default()
+        }
+    }
+
+    fn add_total(&mut self, payloads: &PayloadSizes) {
+        self.payloads = payloads.len();
+        self.total_bytes = payloads.values().sum();
+    }
+
+    fn finish(&mut self) {
+        self.shared_bytes = self.total_bytes - self.exclusive_bytes;
+    }
+}
+
+/// The storage used by everything that is tagged in a repository
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsageReport {
+    /// The number of distinct payloads used by any tag
+    pub payloads: usize,
+    /// The size of all payloads used by any tag
+    pub total_bytes: u64,
+    pub namespaces: Vec<UsageSummary>,
+    pub tag_streams: Vec<UsageSummary>,
+    pub tags: Vec<UsageSummary>,
+}
+
+/// Computes how the payloads in a repository are shared between tags.
+///
+/// Every version of every tag is walked, and each payload is attributed
+/// to the tags, tag streams and tag namespaces that need it. A payload
+/// needed by only one of these is counted as exclusive to it, so that
+/// the exclusive bytes show what would be freed by removing it.
+pub struct UsageAnalyzer<'repo> {
+    repo: &'repo storage::RepositoryHandle,
+    all_tag_namespaces: bool,
+    /// payloads of each object that has been visited
+    cache: HashMap<encoding::Digest, Arc<PayloadSizes>>,
+}
+
+impl<'repo> UsageAnalyzer<'repo> {
+    pub fn new(repo: &'repo storage::RepositoryHandle) -> Self {
+        Self {
+            repo,
+            all_tag_namespaces: false,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Whether to visit the tags in all tag namespaces or only
+    /// the tag namespace configured on the repository.
+    ///
+    /// Payloads are only counted as exclusive against the tags that
+    /// are visited, so this should be enabled to see what removing
+    /// a namespace would free.
+    pub fn with_all_tag_namespaces(mut self, all_tag_namespaces: bool) -> Self {
+        self.all_tag_namespaces = all_tag_namespaces;
+        self
+    }
+
+    /// Walk all of the visited tags and attribute their payloads
+    pub async fn analyze(&mut self) -> Result<UsageReport> {
+        let mut namespaces = vec![self.repo.get_tag_namespace().map(|ns| ns.into_owned())];
+        if self.all_tag_namespaces {
+            namespaces = vec![None];
+            let mut found: Vec<_> = self.repo.ls_tag_namespaces().try_collect().await?;
+            found.sort();
+            namespaces.extend(found.into_iter().map(Some));
+        }
+
+        let mut report = UsageReport::default();
+        let mut usage: HashMap<encoding::Digest, PayloadUsage> = HashMap::new();
+        for namespace in namespaces {
+            let namespace_index = report.namespaces.len();
+            let mut namespace_summary = UsageSummary::new(namespace.as_ref(), None);
+            let mut namespace_payloads = PayloadSizes::new();
+
+            let mut streams: Vec<_> = self
+                .repo
+                .iter_tag_streams_in_namespace(namespace.as_deref())
+                .try_collect()
+                .await?;
+            streams.sort_by_key(|(spec, _)| spec.to_string());
+            for (spec, stream) in streams {
+                let stream_index = report.tag_streams.len();
+                let mut stream_summary =
+                    UsageSummary::new(namespace.as_ref(), Some(spec.to_string()));
+                let mut stream_payloads = PayloadSizes::new();
+
+                let tags: Vec<_> = stream.try_collect().await?;
+                for (version, tag) in tags.into_iter().enumerate() {
+                    let tag_index = report.tags.len();
+                    let mut tag_summary = UsageSummary::new(
+                        namespace.as_ref(),
+                        Some(spec.with_version(version as u64).to_string()),
+                    );
+                    let payloads = self.payloads_of(tag.target).await?;
+                    tag_summary.add_total(&payloads);
+                    for (digest, size) in payloads.iter() {
+                        let entry = usage.entry(*digest).or_default();
+                        entry.size = *size;
+                        Owner::add(&mut entry.tag, tag_index);
+                        stream_payloads.insert(*digest, *size);
+                    }
+                    report.tags.push(tag_summary);
+                }
+
+                stream_summary.add_total(&stream_payloads);
+                for (digest, size) in stream_payloads {
+                    if let Some(entry) = usage.get_mut(&digest) {
+                        Owner::add(&mut entry.stream, stream_index);
+                    }
+                    namespace_payloads.insert(digest, size);
+                }
+                report.tag_streams.push(stream_summary);
+            }
+
+            namespace_summary.add_total(&namespace_payloads);
+            for digest in namespace_payloads.into_keys() {
+                if let Some(entry) = usage.get_mut(&digest) {
+                    Owner::add(&mut entry.namespace, namespace_index);
+                }
+            }
+            report.namespaces.push(namespace_summary);
+        }
+
+        for entry in usage.values() {
+            report.payloads += 1;
+            report.total_bytes += entry.size;
+            if let Some(Owner::One(index)) = entry.tag {
+                report.tags[index].exclusive_bytes += entry.size;
+            }
+            if let Some(Owner::One(index)) = entry.stream {
+                report.tag_streams[index].exclusive_bytes += entry.size;
+            }
+            if let Some(Owner::One(index)) = entry.namespace {
+                report.namespaces[index].exclusive_bytes += entry.size;
+            }
+        }
+        report
+            .namespaces
+            .iter_mut()
+            .chain(report.tag_streams.iter_mut())
+            .chain(report.tags.iter_mut())
+            .for_each(UsageSummary::finish);
+        Ok(report)
+    }
+
+    /// Find all of the payloads needed by an object.
+    ///
+    /// The payloads of every platform, layer and manifest are remembered,
+    /// so that objects shared between tags are only read once. Payload
+    /// sizes are taken from the manifest entries that use them, so the
+    /// blob objects themselves are never read.
+    #[async_recursion::async_recursion]
+    async fn payloads_of(&mut self, digest: encoding::Digest) -> Result<Arc<PayloadSizes>> {
+        if let Some(cached) = self.cache.get(&digest) {
+            return Ok(Arc::clone(cached));
+        }
+        let obj = match self.repo.read_object(digest).await {
+            Ok(obj) => obj,
+            // payloads are not always stored as objects, and are
+            // already counted from the manifests that use them
+            Err(Error::UnknownObject(_)) => return Ok(Arc::default()),
+            Err(err) => return Err(err),
+        };
+        let mut payloads = PayloadSizes::new();
+        match obj.into_enum() {
+            graph::object::Enum::Manifest(manifest) => {
+                for entry in manifest.iter_entries() {
+                    if entry.kind().is_blob() {
+                        payloads.insert(*entry.object(), entry.size());
+                    }
+                }
+            }
+            graph::object::Enum::Blob(blob) => {
+                payloads.insert(*blob.payload(), blob.size());
+            }
+            graph::object::Enum::ChunkedBlob(blob) => {
+                payloads.insert(*blob.payload(), blob.size());
+            }
+            graph::object::Enum::Platform(platform) => {
+                for child in platform.child_objects() {
+                    payloads.extend(self.payloads_of(child).await?.iter());
+                }
+            }
+            graph::object::Enum::Layer(layer) => {
+                for child in layer.child_objects() {
+                    payloads.extend(self.payloads_of(child).await?.iter());
+                }
+            }
+        }
+        let payloads = Arc::new(payloads);
+        self.cache.insert(digest, Arc::clone(&payloads));
+        Ok(payloads)
+    }
+}
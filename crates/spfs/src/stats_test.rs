@@ -0,0 +1,93 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use rstest::rstest;
+
+use super::UsageAnalyzer;
+use crate::fixtures::*;
+use crate::prelude::*;
+use crate::tracking;
+
+#[rstest]
+#[tokio::test]
+async fn test_usage_exclusive_and_shared(tmpdir: tempfile::TempDir, #[future] tmprepo: TempRepo) {
+    init_logging();
+    let tmprepo = tmprepo.await;
+
+    ensure(tmpdir.path().join("a/shared.txt"), "shared");
+    ensure(tmpdir.path().join("a/only_a.txt"), "only in a");
+    ensure(tmpdir.path().join("b/shared.txt"), "shared");
+    ensure(tmpdir.path().join("b/only_b.txt"), "only in b!");
+    for name in ["a", "b"] {
+        let manifest = crate::Committer::new(&tmprepo)
+            .commit_dir(tmpdir.path().join(name).as_path())
+            .await
+            .unwrap();
+        let layer = tmprepo.create_layer_from_manifest(&manifest).await.unwrap();
+        tmprepo
+            .push_tag(
+                &tracking::TagSpec::parse(format!("test/{name}")).unwrap(),
+                &layer.digest().unwrap(),
+            )
+            .await
+            .unwrap();
+    }
+
+    let report = UsageAnalyzer::new(&tmprepo).analyze().await.unwrap();
+    assert_eq!(report.payloads, 3, "shared payload should be counted once");
+    assert_eq!(
+        report.total_bytes,
+        ("shared".len() + "only in a".len() + "only in b!".len()) as u64
+    );
+
+    assert_eq!(report.tag_streams.len(), 2);
+    let stream_a = &report.tag_streams[0];
+    assert_eq!(stream_a.tag.as_deref(), Some("test/a"));
+    assert_eq!(stream_a.exclusive_bytes, "only in a".len() as u64);
+    assert_eq!(stream_a.shared_bytes, "shared".len() as u64);
+    let stream_b = &report.tag_streams[1];
+    assert_eq!(stream_b.exclusive_bytes, "only in b!".len() as u64);
+
+    assert_eq!(report.namespaces.len(), 1);
+    assert_eq!(
+        report.namespaces[0].exclusive_bytes, report.total_bytes,
+        "everything is exclusive to the only namespace"
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_usage_platform_shares_layer(tmpdir: tempfile::TempDir, #[future] tmprepo: TempRepo) {
+    init_logging();
+    let tmprepo = tmprepo.await;
+
+    ensure(tmpdir.path().join("file.txt"), "in the layer");
+    let manifest = crate::Committer::new(&tmprepo)
+        .commit_dir(tmpdir.path())
+        .await
+        .unwrap();
+    let layer = tmprepo.create_layer_from_manifest(&manifest).await.unwrap();
+    let platform = tmprepo
+        .create_platform(layer.digest().unwrap().into())
+        .await
+        .unwrap();
+    for (name, digest) in [
+        ("test/layer", layer.digest().unwrap()),
+        ("test/platform", platform.digest().unwrap()),
+    ] {
+        tmprepo
+            .push_tag(&tracking::TagSpec::parse(name).unwrap(), &digest)
+            .await
+            .unwrap();
+    }
+
+    let report = UsageAnalyzer::new(&tmprepo).analyze().await.unwrap();
+    assert_eq!(report.payloads, 1);
+    assert_eq!(report.total_bytes, "in the layer".len() as u64);
+    assert_eq!(report.tags.len(), 2);
+    for tag in report.tags.iter() {
+        assert_eq!(tag.shared_bytes, report.total_bytes);
+        assert_eq!(tag.exclusive_bytes, 0);
+    }
+}
@@ -8,9 +8,13 @@ mod os;
 
 pub use os::*;
 
+#[cfg(test)]
+#[path = "./status_test.rs"]
+mod status_test;
+
 use super::config::get_config;
 use crate::storage::FromConfig;
-use crate::{Error, Result, runtime, tracking};
+use crate::{Error, Result, graph, runtime, tracking};
 
 static SPFS_RUNTIME: &str = "SPFS_RUNTIME";
 const RUNTIME_REPO_NAME: &str = "<runtime>";
@@ -73,6 +77,38 @@ pub async fn compute_runtime_manifest(rt: &runtime::Runtime) -> Result<tracking:
     super::compute_environment_manifest(&spec, &get_runtime_backing_repo(rt).await?).await
 }
 
+/// Identify the active changes in a runtime that would hide
+/// the effects of replacing its stack with the given one.
+///
+/// Edits in the runtime always take precedence over its layers, so
+/// any file that is both edited and changed between the two stacks
+/// would not appear updated once the new stack is mounted.
+pub async fn compute_stack_update_conflicts(
+    rt: &runtime::Runtime,
+    stack: &graph::Stack,
+) -> Result<Vec<tracking::Diff>> {
+    if !rt.is_dirty() {
+        return Ok(Vec::new());
+    }
+    let repo = get_runtime_backing_repo(rt).await?;
+    let current = compute_runtime_manifest(rt).await?;
+    let spec = stack.iter_bottom_up().collect();
+    let updated = super::compute_environment_manifest(&spec, &repo).await?;
+    let (_, edits) = crate::Committer::new(&repo)
+        .manifest_for_path(&rt.config.upper_dir)
+        .await?;
+
+    Ok(tracking::compute_diff(&current, &updated)
+        .into_iter()
+        .filter(|diff| !diff.mode.is_unchanged() && !diff.mode.is_dir())
+        .filter(|diff| {
+            edits
+                .get_path(&diff.path)
+                .is_some_and(|entry| !entry.is_dir())
+        })
+        .collect())
+}
+
 /// Return the currently active runtime
 ///
 /// # Errors:
@@ -0,0 +1,81 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use rstest::rstest;
+
+use super::compute_stack_update_conflicts;
+use crate::fixtures::*;
+use crate::prelude::*;
+use crate::{Config, graph, tracking};
+
+#[rstest]
+#[tokio::test]
+#[serial_test::serial(config)]
+async fn test_stack_update_conflicts(tmpdir: tempfile::TempDir) {
+    let mut config = Config::default();
+    config.storage.root = tmpdir.path().join("repo");
+    let config = config.make_current().unwrap();
+    let repo: crate::storage::RepositoryHandle =
+        crate::storage::fs::MaybeOpenFsRepository::create(&config.storage.root)
+            .await
+            .unwrap()
+            .into();
+
+    let base = tmpdir.path().join("base");
+    for path in ["changed.txt", "edited.txt", "masked.txt", "removed.txt"] {
+        ensure(base.join(path), "base");
+    }
+    ensure(base.join("dir/file.txt"), "base");
+    let base = crate::Committer::new(&repo)
+        .commit_dir(&base)
+        .await
+        .unwrap();
+    let base = repo.create_layer_from_manifest(&base).await.unwrap();
+
+    let update = tmpdir.path().join("update");
+    ensure(update.join("changed.txt"), "update");
+    ensure(update.join("edited.txt"), "update");
+    ensure(update.join("dir/new.txt"), "update");
+    let mut update = crate::Committer::new(&repo)
+        .commit_dir(&update)
+        .await
+        .unwrap();
+    update.mknod("masked.txt", tracking::Entry::mask()).unwrap();
+    update
+        .mknod("removed.txt", tracking::Entry::mask())
+        .unwrap();
+    let update = repo.create_layer_from_manifest(&update).await.unwrap();
+
+    let storage = crate::runtime::Storage::new(repo).unwrap();
+    let mut runtime = storage.create_owned_runtime().await.unwrap();
+    runtime.push_digest(base.digest().unwrap());
+    runtime.config.upper_dir = tmpdir.path().join("upper");
+    // the edited files and a directory that the update adds to, alongside
+    // a new file that does not exist in either stack
+    for path in ["edited.txt", "removed.txt", "dir/file.txt", "untouched.txt"] {
+        ensure(runtime.config.upper_dir.join(path), "edit");
+    }
+
+    let mut stack = runtime.status.stack.clone();
+    stack.push(update.digest().unwrap());
+    let conflicts = compute_stack_update_conflicts(&runtime, &stack)
+        .await
+        .expect("conflicts should be computed");
+    let mut paths: Vec<_> = conflicts.iter().map(|diff| diff.path.to_string()).collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec!["edited.txt", "removed.txt"],
+        "only edited files that are changed or masked by the update should conflict"
+    );
+
+    let unchanged = graph::Stack::from(base.digest().unwrap());
+    assert!(
+        compute_stack_update_conflicts(&runtime, &unchanged)
+            .await
+            .unwrap()
+            .is_empty(),
+        "edits cannot conflict with a stack that has no changes"
+    );
+}
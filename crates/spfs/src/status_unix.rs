@@ -7,6 +7,9 @@ use crate::storage::fs::RenderSummary;
 use crate::{Error, Result, bootstrap, env, runtime};
 
 /// Remount the given runtime as configured.
+///
+/// The new filesystem replaces the current one without /spfs ever
+/// being empty, see [`reinitialize_runtime`].
 pub async fn remount_runtime(rt: &runtime::Runtime) -> Result<()> {
     let command = bootstrap::build_spfs_remount_command(rt)?;
     // Not using `tokio::process` here because it relies on `SIGCHLD` to know
@@ -93,7 +96,7 @@ pub async fn make_runtime_durable(rt: &runtime::Runtime) -> Result<()> {
 pub async unsafe fn change_to_durable_runtime(rt: &mut runtime::Runtime) -> Result<RenderSummary> {
     // Safety: the responsibility of the caller.
     let in_namespace = unsafe { env::RuntimeConfigurator::default().current_runtime(rt)? };
-    let with_root = in_namespace.become_root()?;
+    let with_root = in_namespace.become_root_for_runtime(rt)?;
 
     with_root.change_runtime_to_durable(rt).await?;
     tracing::info!("runtime changed to durable");
@@ -129,6 +132,10 @@ pub async unsafe fn change_to_durable_runtime(rt: &mut runtime::Runtime) -> Resu
 
 /// Reinitialize the current spfs runtime as rt (in case of runtime config changes).
 ///
+/// The updated filesystem is mounted separately and then moved into
+/// /spfs in place of the current one, so that processes in the runtime
+/// never see an empty /spfs while it is being remounted.
+///
 /// This function will run blocking IO on the current thread. Although this is not ideal,
 /// the mount namespacing operated per-thread and so restricts our ability to move execution.
 ///
@@ -156,32 +163,35 @@ pub async unsafe fn reinitialize_runtime(rt: &mut runtime::Runtime) -> Result<Re
     tracing::debug!("computing runtime manifest");
     let manifest = super::compute_runtime_manifest(rt).await?;
     in_namespace.ensure_mounts_already_exist().await?;
-    const LAZY: bool = true; // because we are about to re-mount over it
-    let with_root = in_namespace.become_root()?;
-    with_root.unmount_env(rt, LAZY).await?;
+    let with_root = in_namespace.become_root_for_runtime(rt)?;
+
+    // the updated filesystem is mounted off to the side and then moved
+    // into place, so that /spfs is never empty while it is remounted
+    let staging = remount_staging_dir(rt)?;
+    let previous_lower_dir = rt.config.lower_dir.clone();
     match rt.config.mount_backend {
         runtime::MountBackend::OverlayFsWithRenders => {
             with_root
-                .mount_env_overlayfs(rt, &render_result.paths_rendered)
+                .mount_env_overlayfs_onto(rt, &render_result.paths_rendered, &staging)
                 .await?;
             with_root.mask_files(&rt.config, manifest).await?;
         }
         #[cfg(feature = "fuse-backend")]
         runtime::MountBackend::OverlayFsWithFuse => {
-            // Switch to using a different lower_dir otherwise if we use the
-            // same one as the previous runtime when it lazy unmounts it will
-            // unmount our active lower_dir.
+            // Switch to using a different lower_dir, because the
+            // previous one is still in use until the new filesystem
+            // has replaced it.
             rt.rotate_lower_dir().await?;
             rt.save_state_to_storage().await?;
 
             with_root.mount_fuse_lower_dir(rt).await?;
             with_root
-                .mount_env_overlayfs(rt, &render_result.paths_rendered)
+                .mount_env_overlayfs_onto(rt, &render_result.paths_rendered, &staging)
                 .await?;
         }
         #[cfg(feature = "fuse-backend")]
         runtime::MountBackend::FuseOnly => {
-            with_root.mount_env_fuse(rt).await?;
+            with_root.mount_env_fuse_onto(rt, &staging).await?;
         }
         #[allow(unreachable_patterns)]
         _ => {
@@ -191,10 +201,31 @@ pub async unsafe fn reinitialize_runtime(rt: &mut runtime::Runtime) -> Result<Re
             )));
         }
     }
+    with_root.replace_env(&staging)?;
+    if let runtime::MountBackend::OverlayFsWithFuse = rt.config.mount_backend {
+        with_root.detach_mount(&previous_lower_dir)?;
+    }
+    if let Err(err) = std::fs::remove_dir(&staging) {
+        tracing::debug!(?staging, "failed to remove remount directory: {err}");
+    }
     with_root.become_original_user()?;
     Ok(render_result.render_summary)
 }
 
+/// Create an empty directory where the filesystem of the given
+/// runtime can be mounted before it replaces the one in /spfs.
+fn remount_staging_dir(rt: &runtime::Runtime) -> Result<std::path::PathBuf> {
+    let root = rt
+        .config
+        .upper_dir
+        .parent()
+        .ok_or_else(|| Error::String("upper_dir had no parent directory".into()))?;
+    let staging = root.join("remount");
+    runtime::makedirs_with_perms(&staging, 0o755)
+        .map_err(|err| Error::RuntimeWriteError(staging.clone(), err))?;
+    Ok(staging)
+}
+
 /// Initialize the current runtime as rt.
 ///
 /// This function will run blocking IO on the current thread. Although this is not ideal,
@@ -228,11 +259,15 @@ pub async fn initialize_runtime(rt: &mut runtime::Runtime) -> Result<RenderSumma
     tracing::debug!("computing runtime manifest");
     let manifest = super::compute_runtime_manifest(rt).await?;
 
-    let in_namespace = env::RuntimeConfigurator::default().enter_mount_namespace()?;
+    let in_namespace = if rt.config.rootless {
+        env::RuntimeConfigurator::default().rootless_mount_namespace()?
+    } else {
+        env::RuntimeConfigurator::default().enter_mount_namespace()?
+    };
     rt.config.mount_namespace = Some(in_namespace.mount_namespace().to_path_buf());
     rt.save_state_to_storage().await?;
 
-    let with_root = in_namespace.become_root()?;
+    let with_root = in_namespace.become_root_for_runtime(rt)?;
     with_root.remove_mount_propagation().await?;
     with_root.ensure_mount_targets_exist(&rt.config)?;
     match rt.config.mount_backend {
@@ -255,6 +290,10 @@ pub async fn initialize_runtime(rt: &mut runtime::Runtime) -> Result<RenderSumma
         }
         #[cfg(feature = "fuse-backend")]
         runtime::MountBackend::FuseOnly => {
+            // the runtime directory holds the upper dir
+            // where edits are stored by the fuse filesystem
+            with_root.mount_runtime(&rt.config)?;
+            with_root.setup_runtime(rt).await?;
             with_root.mount_env_fuse(rt).await?;
         }
         #[allow(unreachable_patterns)]
@@ -265,6 +304,13 @@ pub async fn initialize_runtime(rt: &mut runtime::Runtime) -> Result<RenderSumma
             )));
         }
     }
-    with_root.become_original_user()?;
+    with_root.apply_sandbox(&rt.config)?;
+    if rt.config.sandbox.pids {
+        // the pid namespace is entered just before the command is run,
+        // so that the monitor for this runtime remains outside of it
+        with_root.become_original_user_for_pid_namespace()?;
+    } else {
+        with_root.become_original_user()?;
+    }
     Ok(render_result.render_summary)
 }
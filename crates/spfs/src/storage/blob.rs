@@ -7,6 +7,7 @@ use std::pin::Pin;
 use futures::Stream;
 use tokio_stream::StreamExt;
 
+use crate::graph::object::Enum;
 use crate::{Error, Result, encoding, graph};
 
 pub type BlobStreamItem = Result<(encoding::Digest, graph::Blob)>;
@@ -23,11 +24,15 @@ pub trait BlobStorage: graph::Database + Sync + Send {
     }
 
     /// Return the blob identified by the given digest.
+    ///
+    /// A [`graph::ChunkedBlob`] is returned as the blob that it
+    /// represents, since the two share a digest and a payload.
     async fn read_blob(&self, digest: encoding::Digest) -> Result<graph::Blob> {
-        match self.read_object(digest).await.map(graph::Object::into_blob) {
+        match self.read_object(digest).await.map(graph::Object::into_enum) {
             Err(err) => Err(err),
-            Ok(Some(blob)) => Ok(blob),
-            Ok(None) => Err(Error::NotCorrectKind {
+            Ok(Enum::Blob(blob)) => Ok(blob),
+            Ok(Enum::ChunkedBlob(blob)) => Ok(graph::Blob::new(*blob.payload(), blob.size())),
+            Ok(_) => Err(Error::NotCorrectKind {
                 desired: graph::ObjectKind::Blob,
                 digest,
             }),
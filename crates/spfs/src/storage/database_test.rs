@@ -12,6 +12,7 @@ use crate::{encoding, graph};
 #[case::fs(tmprepo("fs"))]
 #[case::tar(tmprepo("tar"))]
 #[cfg_attr(feature = "server", case::rpc(tmprepo("rpc")))]
+#[cfg_attr(feature = "server", case::s3(tmprepo("s3")))]
 #[tokio::test]
 async fn test_object_existence(
     #[case]
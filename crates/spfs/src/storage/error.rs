@@ -46,6 +46,12 @@ pub enum OpenRepositoryError {
         #[label = "should be followed by '?<query>'"]
         query_span: miette::SourceSpan,
     },
+    #[error("This repository type requires a host")]
+    #[diagnostic(code("spfs::storage::missing_host"))]
+    MissingHost {
+        #[source_code]
+        address: String,
+    },
 
     #[error("Failed to create/validate target directory: {0:?}")]
     CouldNotCreateTarParent(std::path::PathBuf),
@@ -90,9 +96,38 @@ pub enum OpenRepositoryError {
         #[from]
         source: tonic::transport::Error,
     },
+    #[error("Invalid s3 repository: {reason}")]
+    #[diagnostic(
+        code("spfs::storage::s3::invalid_address"),
+        help("s3 repositories are addressed as s3://<bucket>/<prefix>?endpoint=<url>")
+    )]
+    InvalidS3Address {
+        #[source_code]
+        address: String,
+        reason: String,
+    },
     #[error("Pinned repository is read only")]
     RepositoryIsPinned,
 
+    #[error("Failed to load credentials from {path:?}")]
+    FailedToLoadCredentials {
+        path: std::path::PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Invalid tag signature policy")]
+    InvalidTagSignaturePolicy {
+        #[source]
+        source: Box<crate::Error>,
+    },
+
+    #[error("Invalid tls configuration")]
+    InvalidTlsConfig {
+        #[source]
+        source: Box<crate::Error>,
+    },
+
     #[error("Failed to set tag namespace '{tag_namespace}'")]
     FailedToSetTagNamespace {
         tag_namespace: TagNamespaceBuf,
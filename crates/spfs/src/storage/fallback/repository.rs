@@ -295,6 +295,20 @@ impl TagStorage for FallbackProxy {
         self.primary.get_tag_namespace()
     }
 
+    async fn tag_signing_key(&self) -> Option<Arc<tracking::TagSigningKey>> {
+        self.primary.tag_signing_key().await
+    }
+
+    async fn resolve_tag_in_namespace(
+        &self,
+        namespace: Option<&TagNamespace>,
+        tag_spec: &tracking::TagSpec,
+    ) -> Result<tracking::Tag> {
+        self.primary
+            .resolve_tag_in_namespace(namespace, tag_spec)
+            .await
+    }
+
     fn ls_tags_in_namespace(
         &self,
         namespace: Option<&TagNamespace>,
@@ -390,6 +404,11 @@ impl LocalRepository for FallbackProxy {
         self.primary.payloads()
     }
 
+    #[inline]
+    fn compressed_payloads(&self) -> Option<&FsHashStore> {
+        self.primary.compressed_payloads()
+    }
+
     #[inline]
     fn render_store(&self) -> Result<&RenderStore> {
         self.primary.render_store()
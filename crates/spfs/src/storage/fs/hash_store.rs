@@ -12,8 +12,9 @@ use async_stream::try_stream;
 use close_err::Closable;
 use futures::{Stream, TryStreamExt};
 use tokio::fs::DirEntry;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 
+use super::PayloadCompression;
 use crate::runtime::makedirs_with_perms;
 use crate::storage::{OpenRepositoryError, OpenRepositoryResult};
 use crate::tracking::BlobRead;
@@ -42,6 +43,8 @@ pub struct FsHashStore {
     pub directory_permissions: u32,
     /// permissions used when creating new files
     pub file_permissions: u32,
+    /// how the data written to this storage is compressed
+    pub compression: PayloadCompression,
 }
 
 impl FsHashStore {
@@ -60,9 +63,18 @@ impl FsHashStore {
             root: root.as_ref().to_path_buf(),
             directory_permissions: 0o777, // this is a shared store for all users
             file_permissions: 0o666,      // read+write is required to make hard links
+            compression: PayloadCompression::None,
         }
     }
 
+    /// Compress all data written to this storage.
+    ///
+    /// Digests are always calculated from the uncompressed data.
+    pub fn with_compression(mut self, compression: PayloadCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
     /// The folder where payloads are copied to have the expected ownership
     /// and permissions suitable for hard-linking into a render.
     pub fn proxydir(&self) -> PathBuf {
@@ -239,27 +251,27 @@ impl FsHashStore {
                     )
                 })?,
         );
-        let mut hasher = encoding::Hasher::with_target(&mut writer);
-        let copied = match tokio::io::copy(&mut reader, &mut hasher).await {
-            Err(err) => {
+        let written = match self.compression {
+            PayloadCompression::None => Self::copy_and_hash(&mut reader, &mut writer).await,
+            PayloadCompression::Zstd => {
+                let mut encoder = async_compression::tokio::write::ZstdEncoder::new(&mut writer);
+                match Self::copy_and_hash(&mut reader, &mut encoder).await {
+                    Ok(written) => encoder
+                        .shutdown()
+                        .await
+                        .map(|_| written)
+                        .map_err(|err| ("shutdown on compressed hash store object file", err)),
+                    Err(err) => Err(err),
+                }
+            }
+        };
+        let (copied, digest) = match written {
+            Err((operation, err)) => {
                 let _ = tokio::fs::remove_file(&working_file).await;
-                return Err(Error::StorageWriteError(
-                    "copy on hash store object file",
-                    working_file,
-                    err,
-                ));
+                return Err(Error::StorageWriteError(operation, working_file, err));
             }
-            Ok(s) => s,
+            Ok(written) => written,
         };
-
-        if let Err(err) = hasher.flush().await {
-            return Err(Error::StorageWriteError(
-                "flush on hash store object file",
-                working_file,
-                err,
-            ));
-        }
-        let digest = hasher.digest();
         if let Err(err) = writer.into_inner().into_std().await.close() {
             return Err(Error::StorageWriteError(
                 "close on hash store object file",
@@ -279,6 +291,26 @@ impl FsHashStore {
         .await
     }
 
+    /// Copy all data from the reader into the writer, returning the
+    /// number of bytes copied and the digest of the copied data
+    async fn copy_and_hash<W>(
+        reader: &mut Pin<Box<dyn BlobRead>>,
+        writer: W,
+    ) -> std::result::Result<(u64, encoding::Digest), (&'static str, std::io::Error)>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut hasher = encoding::Hasher::with_target(writer);
+        let copied = tokio::io::copy(reader, &mut hasher)
+            .await
+            .map_err(|err| ("copy on hash store object file", err))?;
+        hasher
+            .flush()
+            .await
+            .map_err(|err| ("flush on hash store object file", err))?;
+        Ok((copied, hasher.digest()))
+    }
+
     pub(crate) async fn persist_object_with_digest(
         &self,
         persistable_object: PersistableObject,
@@ -8,6 +8,7 @@ mod database;
 mod hash_store;
 mod manifest_render_path;
 mod payloads;
+mod quarantine;
 mod render_summary;
 mod renderer;
 mod repository;
@@ -18,6 +19,7 @@ mod render_reporter;
 
 pub use hash_store::FsHashStore;
 pub use manifest_render_path::ManifestRenderPath;
+pub use quarantine::{QUARANTINE_DIR, Quarantine, QuarantineBatch, RestoredData};
 pub use render_reporter::{
     ConsoleRenderReporter,
     MultiReporter,
@@ -42,6 +44,8 @@ pub use repository::{
     MaybeOpenFsRepository,
     OpenFsRepository,
     Params,
+    PayloadCompression,
     RenderStore,
     read_last_migration_version,
 };
+pub(crate) use tag::{decode_tag_stream, encode_tag_stream};
@@ -5,12 +5,16 @@
 use std::io::ErrorKind;
 use std::pin::Pin;
 
+use async_compression::tokio::bufread::ZstdDecoder;
 use futures::future::ready;
-use futures::{Stream, StreamExt, TryFutureExt};
+use futures::{Stream, StreamExt, TryFutureExt, TryStreamExt};
 
-use super::{MaybeOpenFsRepository, OpenFsRepository};
+use super::{FsHashStore, MaybeOpenFsRepository, OpenFsRepository, PayloadCompression};
+use crate::chunking::ChunkedBlobReader;
+use crate::graph::object::Enum;
 use crate::storage::prelude::*;
 use crate::tracking::BlobRead;
+use crate::tracking::blob_reader::SyncReader;
 use crate::{Error, Result, encoding, graph};
 
 #[async_trait::async_trait]
@@ -55,18 +59,35 @@ impl crate::storage::PayloadStorage for MaybeOpenFsRepository {
 impl crate::storage::PayloadStorage for OpenFsRepository {
     async fn has_payload(&self, digest: encoding::Digest) -> bool {
         let path = self.payloads.build_digest_path(&digest);
+        if tokio::fs::symlink_metadata(path).await.is_ok() {
+            return true;
+        }
+        let path = self.compressed_payloads.build_digest_path(&digest);
         tokio::fs::symlink_metadata(path).await.is_ok()
     }
 
     fn iter_payload_digests(&self) -> Pin<Box<dyn Stream<Item = Result<encoding::Digest>> + Send>> {
-        Box::pin(self.payloads.iter())
+        if !self.compressed_payloads.root().exists() {
+            return Box::pin(self.payloads.iter());
+        }
+        // a payload may be stored both ways if the compression
+        // setting was changed, but should only be reported once
+        let payloads = FsHashStore::open_unchecked(self.payloads.root());
+        let compressed = self
+            .compressed_payloads
+            .iter()
+            .try_filter(move |digest| ready(!payloads.has_digest(digest)));
+        Box::pin(self.payloads.iter().chain(compressed))
     }
 
     async unsafe fn write_data(
         &self,
         reader: Pin<Box<dyn BlobRead>>,
     ) -> Result<(encoding::Digest, u64)> {
-        self.payloads.write_data(reader).await
+        match self.compression() {
+            PayloadCompression::None => self.payloads.write_data(reader).await,
+            PayloadCompression::Zstd => self.compressed_payloads.write_data(reader).await,
+        }
     }
 
     async fn open_payload(
@@ -78,16 +99,36 @@ impl crate::storage::PayloadStorage for OpenFsRepository {
             Ok(file) => Ok((Box::pin(tokio::io::BufReader::new(file)), path)),
             Err(err) => match err.kind() {
                 ErrorKind::NotFound => {
-                    // Return an error specific to this situation, whether the
-                    // blob is really unknown or just the payload is missing.
-                    match self.read_blob(digest).await {
-                        Ok(blob) => Err(Error::ObjectMissingPayload(blob.into(), digest)),
-                        Err(
-                            err @ Error::NotCorrectKind {
-                                desired: graph::ObjectKind::Blob,
-                                ..
-                            },
-                        ) => Err(err),
+                    let path = self.compressed_payloads.build_digest_path(&digest);
+                    match tokio::fs::File::open(&path).await {
+                        Ok(file) => {
+                            let reader = compressed_payload_reader(file);
+                            return Ok((Box::pin(tokio::io::BufReader::new(reader)), path));
+                        }
+                        Err(err) if err.kind() != ErrorKind::NotFound => {
+                            return Err(Error::StorageReadError(
+                                "open on compressed payload",
+                                path,
+                                err,
+                            ));
+                        }
+                        Err(_) => {}
+                    }
+                    // Chunked blobs can be reassembled into the missing payload,
+                    // otherwise return an error specific to this situation, whether
+                    // the blob is really unknown or just the payload is missing.
+                    match self.read_object(digest).await.map(|o| o.into_enum()) {
+                        Ok(Enum::Blob(blob)) => {
+                            Err(Error::ObjectMissingPayload(blob.into(), digest))
+                        }
+                        Ok(Enum::ChunkedBlob(blob)) => {
+                            self.materialize_chunked_blob(blob).await?;
+                            self.open_payload(digest).await
+                        }
+                        Ok(_) => Err(Error::NotCorrectKind {
+                            desired: graph::ObjectKind::Blob,
+                            digest,
+                        }),
                         Err(_) => Err(Error::UnknownObject(digest)),
                     }
                 }
@@ -97,17 +138,62 @@ impl crate::storage::PayloadStorage for OpenFsRepository {
     }
 
     async fn remove_payload(&self, digest: encoding::Digest) -> Result<()> {
-        let path = self.payloads.build_digest_path(&digest);
-        match tokio::fs::remove_file(&path).await {
-            Ok(()) => Ok(()),
-            Err(err) => match err.kind() {
-                ErrorKind::NotFound => Err(Error::UnknownObject(digest)),
-                _ => Err(Error::StorageWriteError(
-                    "remove_file on payload",
-                    path,
-                    err,
-                )),
-            },
+        let mut removed = false;
+        for store in [&self.payloads, &self.compressed_payloads] {
+            let path = store.build_digest_path(&digest);
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => removed = true,
+                Err(err) => match err.kind() {
+                    ErrorKind::NotFound => continue,
+                    _ => {
+                        return Err(Error::StorageWriteError(
+                            "remove_file on payload",
+                            path,
+                            err,
+                        ));
+                    }
+                },
+            }
+        }
+        if removed {
+            Ok(())
+        } else {
+            Err(Error::UnknownObject(digest))
+        }
+    }
+}
+
+/// Decompresses a payload from the compressed payload storage
+type CompressedPayloadReader = SyncReader<ZstdDecoder<tokio::io::BufReader<tokio::fs::File>>>;
+
+fn compressed_payload_reader(file: tokio::fs::File) -> CompressedPayloadReader {
+    SyncReader::new(ZstdDecoder::new(tokio::io::BufReader::new(file)))
+}
+
+impl OpenFsRepository {
+    /// Reassemble the chunks of a blob into a single payload file.
+    ///
+    /// Local payload files are needed to render and hard link
+    /// blobs, so chunked blobs are reassembled when first opened.
+    async fn materialize_chunked_blob(&self, blob: graph::ChunkedBlob) -> Result<()> {
+        let digest = *blob.payload();
+        for chunk in blob.child_objects() {
+            if !self.has_payload(chunk).await {
+                // report the whole blob as missing its payload so
+                // that any repair can sync the blob and all its chunks
+                return Err(Error::ObjectMissingPayload(blob.into(), digest));
+            }
+        }
+        let reader = ChunkedBlobReader::from_repo(&blob, self.clone());
+        // Safety: the chunked blob that tracks this payload
+        // already exists, as it is what is being reassembled
+        let (created_digest, _) = unsafe { self.write_data(Box::pin(reader)).await? };
+        if created_digest != digest {
+            let _ = self.remove_payload(created_digest).await;
+            return Err(Error::String(format!(
+                "Reassembled chunks did not match the expected payload: wanted {digest}, got {created_digest}",
+            )));
         }
+        Ok(())
     }
 }
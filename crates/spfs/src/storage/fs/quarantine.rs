@@ -0,0 +1,413 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDateTime, SubsecRound, Utc};
+use futures::TryStreamExt;
+
+use super::{FsHashStore, OpenFsRepository};
+use crate::encoding::prelude::*;
+use crate::runtime::makedirs_with_perms;
+use crate::storage::{TAG_NAMESPACE_MARKER, TagNamespace, TagNamespaceBuf, TagStorage};
+use crate::{Error, Result, encoding, tracking};
+
+#[cfg(test)]
+#[path = "./quarantine_test.rs"]
+mod quarantine_test;
+
+/// The directory within a filesystem repository that holds quarantined data
+pub const QUARANTINE_DIR: &str = "quarantine";
+
+/// Batches are named for the time that they were created
+const BATCH_NAME_FORMAT: &str = "%Y%m%dT%H%M%S%.6fZ";
+
+/// The directory within a batch that holds pruned tags
+const TAGS_DIR: &str = "tags";
+
+/// Everything that was moved out of the quarantine and back into
+/// the repository, see [`Quarantine::restore_since`]
+#[derive(Debug, Default)]
+pub struct RestoredData {
+    /// The restored objects and payloads
+    pub digests: HashSet<encoding::Digest>,
+    /// The restored tags, and the namespace that each one is in
+    pub tags: Vec<(Option<TagNamespaceBuf>, tracking::Tag)>,
+}
+
+/// A holding area within a filesystem repository for data that
+/// would otherwise have been deleted by a clean.
+///
+/// Each clean moves data into a new batch, named for the time that
+/// the clean started, which mirrors the layout of the repository's
+/// own object and payload storage, and also holds the tags that the
+/// clean pruned. Data can be restored from a batch until the batch
+/// is expired. Restored files are given a new modification time, so
+/// that they are not immediately removed again by the next clean.
+#[derive(Debug, Clone)]
+pub struct Quarantine {
+    repo: OpenFsRepository,
+    root: PathBuf,
+}
+
+impl Quarantine {
+    pub fn new(repo: OpenFsRepository) -> Self {
+        let root = repo.root().join(QUARANTINE_DIR);
+        Self { repo, root }
+    }
+
+    /// The directory that holds all quarantined data
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Start a new batch, for quarantining data from a single clean
+    pub fn start_batch(&self) -> QuarantineBatch {
+        // batch names only hold microseconds
+        let created = Utc::now().trunc_subsecs(6);
+        QuarantineBatch::new(self.repo.clone(), &self.root, created)
+    }
+
+    /// All of the batches in this quarantine, oldest first
+    pub async fn batches(&self) -> Result<Vec<QuarantineBatch>> {
+        let mut entries = match tokio::fs::read_dir(&self.root).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(Error::StorageReadError(
+                    "read_dir on quarantine",
+                    self.root.clone(),
+                    err,
+                ));
+            }
+        };
+        let mut batches = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(|err| {
+            Error::StorageReadError("next_entry on quarantine", self.root.clone(), err)
+        })? {
+            let name = entry.file_name();
+            let Some(created) = name
+                .to_str()
+                .and_then(|name| NaiveDateTime::parse_from_str(name, BATCH_NAME_FORMAT).ok())
+            else {
+                tracing::debug!(?name, "ignoring unknown entry in quarantine");
+                continue;
+            };
+            batches.push(QuarantineBatch::new(
+                self.repo.clone(),
+                &self.root,
+                created.and_utc(),
+            ));
+        }
+        batches.sort_by_key(|b| b.created);
+        Ok(batches)
+    }
+
+    /// Move the identified object and/or payload back into the
+    /// repository, from the most recent batch that contains it.
+    ///
+    /// Returns false if the digest was not found in any batch.
+    pub async fn restore_digest(&self, digest: encoding::Digest) -> Result<bool> {
+        for batch in self.batches().await?.into_iter().rev() {
+            if batch.restore(digest).await? {
+                tracing::debug!(%digest, batch = %batch.created, "restored from quarantine");
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Move everything that was quarantined at or after the
+    /// given time back into the repository, including pruned tags.
+    pub async fn restore_since(&self, since: DateTime<Utc>) -> Result<RestoredData> {
+        let mut restored = RestoredData::default();
+        for batch in self.batches().await? {
+            if batch.created < since {
+                continue;
+            }
+            let batch_restored = batch.restore_all().await?;
+            restored.digests.extend(batch_restored.digests);
+            restored.tags.extend(batch_restored.tags);
+        }
+        Ok(restored)
+    }
+
+    /// Permanently remove all batches created before the given time.
+    ///
+    /// Returns the creation time of each removed batch.
+    pub async fn expire_older_than(&self, cutoff: DateTime<Utc>) -> Result<Vec<DateTime<Utc>>> {
+        let mut expired = Vec::new();
+        for batch in self.batches().await? {
+            if batch.created >= cutoff {
+                continue;
+            }
+            batch.purge().await?;
+            expired.push(batch.created);
+        }
+        Ok(expired)
+    }
+}
+
+/// The data quarantined by a single clean, see [`Quarantine`]
+#[derive(Debug)]
+pub struct QuarantineBatch {
+    repo: OpenFsRepository,
+    root: PathBuf,
+    created: DateTime<Utc>,
+    objects: FsHashStore,
+    payloads: FsHashStore,
+    compressed_payloads: FsHashStore,
+}
+
+impl QuarantineBatch {
+    fn new(repo: OpenFsRepository, quarantine_root: &Path, created: DateTime<Utc>) -> Self {
+        let root = quarantine_root.join(created.format(BATCH_NAME_FORMAT).to_string());
+        let store_for = |original: &FsHashStore| {
+            let name = original
+                .root()
+                .file_name()
+                .expect("repository storage is always in a named directory");
+            FsHashStore::open_unchecked(root.join(name))
+        };
+        Self {
+            objects: store_for(&repo.objects),
+            payloads: store_for(&repo.payloads),
+            compressed_payloads: store_for(&repo.compressed_payloads),
+            repo,
+            root,
+            created,
+        }
+    }
+
+    /// The time that this batch was started
+    pub fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+
+    /// The directory that holds this batch
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Pairs of repository storage and the matching quarantine storage
+    fn stores(&self) -> [(&FsHashStore, &FsHashStore); 3] {
+        [
+            (&self.repo.objects, &self.objects),
+            (&self.repo.payloads, &self.payloads),
+            (&self.repo.compressed_payloads, &self.compressed_payloads),
+        ]
+    }
+
+    /// Move an object into this batch, unless it was modified
+    /// after the given time.
+    ///
+    /// Behaves the same as [`crate::graph::Database::remove_object_if_older_than`],
+    /// returning whether the object was moved.
+    pub async fn quarantine_object_if_older_than(
+        &self,
+        older_than: DateTime<Utc>,
+        digest: encoding::Digest,
+    ) -> Result<bool> {
+        let filepath = self.repo.objects.build_digest_path(&digest);
+        let metadata = tokio::fs::symlink_metadata(&filepath)
+            .await
+            .map_err(|err| match err.kind() {
+                ErrorKind::NotFound => Error::UnknownObject(digest),
+                _ => Error::StorageReadError(
+                    "symlink_metadata on digest path",
+                    filepath.clone(),
+                    err,
+                ),
+            })?;
+        let mtime = metadata.modified().map_err(|err| {
+            Error::StorageReadError(
+                "modified on symlink metadata of digest path",
+                filepath.clone(),
+                err,
+            )
+        })?;
+        if DateTime::<Utc>::from(mtime) >= older_than {
+            return Ok(false);
+        }
+        move_between(&self.repo.objects, &self.objects, &digest).await?;
+        Ok(true)
+    }
+
+    /// Move a payload into this batch.
+    ///
+    /// Behaves the same as [`crate::storage::PayloadStorage::remove_payload`],
+    /// failing with [`Error::UnknownObject`] if there was no such payload.
+    pub async fn quarantine_payload(&self, digest: encoding::Digest) -> Result<()> {
+        let mut moved = false;
+        for (repo_store, quarantine_store) in &self.stores()[1..] {
+            moved |= move_between(repo_store, quarantine_store, &digest).await?;
+        }
+        if moved {
+            Ok(())
+        } else {
+            Err(Error::UnknownObject(digest))
+        }
+    }
+
+    /// Save a tag that is being pruned into this batch.
+    pub async fn quarantine_tag(
+        &self,
+        namespace: Option<&TagNamespace>,
+        tag: &tracking::Tag,
+    ) -> Result<()> {
+        let dir = self.tags_dir(namespace);
+        makedirs_with_perms(&dir, self.repo.objects.directory_permissions).map_err(|err| {
+            Error::StorageWriteError("makedirs on quarantined tags", dir.clone(), err)
+        })?;
+        let mut encoded = Vec::new();
+        tag.encode(&mut encoded)?;
+        let path = dir.join(tag.digest()?.to_string());
+        tokio::fs::write(&path, encoded)
+            .await
+            .map_err(|err| Error::StorageWriteError("write on quarantined tag", path, err))
+    }
+
+    /// Move the identified object and/or payload back into the repository.
+    ///
+    /// Returns false if neither was found in this batch.
+    pub async fn restore(&self, digest: encoding::Digest) -> Result<bool> {
+        let mut restored = false;
+        for (repo_store, quarantine_store) in self.stores() {
+            restored |= restore_between(quarantine_store, repo_store, &digest).await?;
+        }
+        Ok(restored)
+    }
+
+    /// Move everything in this batch back into the repository, including
+    /// any pruned tags, and remove the batch
+    pub async fn restore_all(&self) -> Result<RestoredData> {
+        let mut restored = RestoredData::default();
+        for (repo_store, quarantine_store) in self.stores() {
+            let digests: Vec<_> = quarantine_store.iter().try_collect().await?;
+            for digest in digests {
+                restore_between(quarantine_store, repo_store, &digest).await?;
+                restored.digests.insert(digest);
+            }
+        }
+        for (namespace, tag) in self.read_tags().await? {
+            self.repo
+                .insert_tag_in_namespace(namespace.as_deref(), &tag)
+                .await?;
+            restored.tags.push((namespace, tag));
+        }
+        self.purge().await?;
+        Ok(restored)
+    }
+
+    /// The directory in this batch for tags from the given namespace,
+    /// which mirrors the layout of the repository's own tags
+    fn tags_dir(&self, namespace: Option<&TagNamespace>) -> PathBuf {
+        let root = self.root.join(TAGS_DIR);
+        match namespace {
+            Some(ns) => root.join(format!("{}{TAG_NAMESPACE_MARKER}", ns.as_rel_path())),
+            None => root,
+        }
+    }
+
+    /// Read all of the tags that were saved into this batch
+    async fn read_tags(&self) -> Result<Vec<(Option<TagNamespaceBuf>, tracking::Tag)>> {
+        let mut tags = Vec::new();
+        let mut dirs = vec![(None, self.tags_dir(None))];
+        while let Some((namespace, dir)) = dirs.pop() {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => {
+                    return Err(Error::StorageReadError(
+                        "read_dir on quarantined tags",
+                        dir,
+                        err,
+                    ));
+                }
+            };
+            while let Some(entry) = entries.next_entry().await.map_err(|err| {
+                Error::StorageReadError("next_entry on quarantined tags", dir.clone(), err)
+            })? {
+                let path = entry.path();
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                if let Some(ns) = name.strip_suffix(TAG_NAMESPACE_MARKER) {
+                    if namespace.is_none() {
+                        dirs.push((Some(TagNamespaceBuf::new(ns)?), path));
+                    }
+                    continue;
+                }
+                let encoded = tokio::fs::read(&path).await.map_err(|err| {
+                    Error::StorageReadError("read on quarantined tag", path.clone(), err)
+                })?;
+                let tag = tracking::Tag::decode(&mut encoded.as_slice())?;
+                tags.push((namespace.clone(), tag));
+            }
+        }
+        Ok(tags)
+    }
+
+    /// Permanently remove this batch and everything in it
+    pub async fn purge(&self) -> Result<()> {
+        match tokio::fs::remove_dir_all(&self.root).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(Error::StorageWriteError(
+                "remove_dir_all on quarantine batch",
+                self.root.clone(),
+                err,
+            )),
+        }
+    }
+}
+
+/// Move the file for a digest from the quarantine back into the
+/// repository, returning false if it was not in the quarantine.
+///
+/// The restored file is given a new modification time, since a
+/// clean only keeps detached data that was modified recently.
+async fn restore_between(
+    from: &FsHashStore,
+    to: &FsHashStore,
+    digest: &encoding::Digest,
+) -> Result<bool> {
+    if !move_between(from, to, digest).await? {
+        return Ok(false);
+    }
+    let path = to.build_digest_path(digest);
+    let touch_path = path.clone();
+    tokio::task::spawn_blocking(move || {
+        std::fs::File::open(&touch_path)?.set_modified(std::time::SystemTime::now())
+    })
+    .await?
+    .map_err(|err| Error::StorageWriteError("set_modified on restored file", path, err))?;
+    Ok(true)
+}
+
+/// Move the file for a digest from one store to the other,
+/// returning false if it did not exist in the source store
+async fn move_between(
+    from: &FsHashStore,
+    to: &FsHashStore,
+    digest: &encoding::Digest,
+) -> Result<bool> {
+    let source = from.build_digest_path(digest);
+    let target = to.build_digest_path(digest);
+    if let Some(parent) = target.parent() {
+        makedirs_with_perms(parent, to.directory_permissions).map_err(|err| {
+            Error::StorageWriteError("makedirs on quarantine", parent.into(), err)
+        })?;
+    }
+    match tokio::fs::rename(&source, &target).await {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(Error::StorageWriteError(
+            "rename on quarantined file",
+            source,
+            err,
+        )),
+    }
+}
@@ -0,0 +1,80 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use rstest::rstest;
+
+use super::Quarantine;
+use crate::fixtures::*;
+use crate::prelude::*;
+use crate::storage::RepositoryHandle;
+
+#[rstest]
+#[tokio::test]
+async fn test_quarantine_restore_and_expire(
+    #[future] tmprepo: TempRepo,
+    tmpdir: tempfile::TempDir,
+) {
+    init_logging();
+    let tmprepo = tmprepo.await;
+    ensure(tmpdir.path().join("first.txt"), "first");
+    ensure(tmpdir.path().join("second.txt"), "second");
+    let manifest = crate::Committer::new(&tmprepo)
+        .commit_dir(tmpdir.path())
+        .await
+        .unwrap();
+    let first = manifest.get_path("first.txt").unwrap().object;
+    let second = manifest.get_path("second.txt").unwrap().object;
+    let manifest_digest = manifest.to_graph_manifest().digest().unwrap();
+
+    let RepositoryHandle::FS(fs_repo) = &*tmprepo else {
+        panic!("Unexpected tmprepo type!");
+    };
+    let quarantine = Quarantine::new(fs_repo.opened().await.unwrap());
+    let batch = quarantine.start_batch();
+    assert!(
+        batch
+            .quarantine_object_if_older_than(chrono::Utc::now(), manifest_digest)
+            .await
+            .unwrap()
+    );
+    batch.quarantine_payload(first).await.unwrap();
+    batch.quarantine_payload(second).await.unwrap();
+    batch
+        .quarantine_payload(first)
+        .await
+        .expect_err("should fail for a payload that is not in the repo");
+    assert!(!tmprepo.has_object(manifest_digest).await);
+    assert!(!tmprepo.has_payload(first).await);
+
+    assert!(quarantine.restore_digest(first).await.unwrap());
+    assert!(tmprepo.has_payload(first).await);
+    assert!(
+        !quarantine.restore_digest(first).await.unwrap(),
+        "a digest can only be restored once"
+    );
+
+    let restored = quarantine.restore_since(batch.created()).await.unwrap();
+    assert_eq!(
+        restored.digests.len(),
+        2,
+        "should restore the manifest and payload"
+    );
+    assert!(tmprepo.has_object(manifest_digest).await);
+    assert!(tmprepo.has_payload(second).await);
+    assert!(
+        quarantine.batches().await.unwrap().is_empty(),
+        "a fully restored batch should be removed"
+    );
+
+    let batch = quarantine.start_batch();
+    batch.quarantine_payload(second).await.unwrap();
+    let expired = quarantine.expire_older_than(batch.created()).await.unwrap();
+    assert!(expired.is_empty(), "batch is not older than the cutoff");
+    let expired = quarantine
+        .expire_older_than(chrono::Utc::now())
+        .await
+        .unwrap();
+    assert_eq!(expired.len(), 1);
+    assert!(!quarantine.restore_digest(second).await.unwrap());
+}
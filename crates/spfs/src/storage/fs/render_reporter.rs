@@ -19,6 +19,8 @@ pub enum RenderBlobResult {
     PayloadCopiedWrongMode,
     /// Was not possible to hard link because of different file ownership.
     PayloadCopiedWrongOwner,
+    /// Was not possible to hard link because the payload is compressed.
+    PayloadCopiedDecompressed,
     /// Payload was able to be hard linked.
     PayloadHardLinked,
     /// Payload was a symlink and already existed.
@@ -18,6 +18,8 @@ pub struct RenderSummary {
     pub copy_link_limit_count: AtomicUsize,
     pub copy_wrong_mode_count: AtomicUsize,
     pub copy_wrong_owner_count: AtomicUsize,
+    #[serde(default)]
+    pub copy_decompressed_count: AtomicUsize,
     pub link_count: AtomicUsize,
     pub symlink_count: AtomicUsize,
 
@@ -27,6 +29,8 @@ pub struct RenderSummary {
     pub total_bytes_copied_link_limit: AtomicUsize,
     pub total_bytes_copied_wrong_mode: AtomicUsize,
     pub total_bytes_copied_wrong_owner: AtomicUsize,
+    #[serde(default)]
+    pub total_bytes_copied_decompressed: AtomicUsize,
     pub total_bytes_linked: AtomicUsize,
 }
 
@@ -94,6 +98,17 @@ impl RenderSummary {
                 self.total_bytes_copied_wrong_owner
                     .fetch_add(entry_size, Ordering::Relaxed);
             }
+            RenderBlobResult::PayloadCopiedDecompressed => {
+                self.copy_count.fetch_add(1, Ordering::Relaxed);
+                self.copy_decompressed_count.fetch_add(1, Ordering::Relaxed);
+
+                self.total_bytes_rendered
+                    .fetch_add(entry_size, Ordering::Relaxed);
+                self.total_bytes_copied
+                    .fetch_add(entry_size, Ordering::Relaxed);
+                self.total_bytes_copied_decompressed
+                    .fetch_add(entry_size, Ordering::Relaxed);
+            }
             RenderBlobResult::PayloadHardLinked => {
                 self.link_count.fetch_add(1, Ordering::Relaxed);
 
@@ -10,8 +10,16 @@ use super::was_render_completed;
 use crate::encoding::prelude::*;
 use crate::fixtures::*;
 use crate::graph::object::{DigestStrategy, EncodingFormat};
-use crate::storage::fs::{MaybeOpenFsRepository, OpenFsRepository};
-use crate::storage::{RepositoryExt, RepositoryHandle};
+use crate::storage::fs::repository::OpenFsRepositoryImpl;
+use crate::storage::fs::{
+    HardLinkRenderType,
+    MaybeOpenFsRepository,
+    OpenFsRepository,
+    Params,
+    PayloadCompression,
+    RenderType,
+};
+use crate::storage::{FromConfig, RepositoryExt, RepositoryHandle};
 use crate::{Config, tracking};
 
 #[rstest(
@@ -129,3 +137,60 @@ async fn test_render_manifest_with_repo(
         rendered_manifest.to_graph_manifest().digest().unwrap()
     );
 }
+
+#[rstest]
+#[case::hard_link(RenderType::HardLink(HardLinkRenderType::WithProxy))]
+#[case::hard_link_no_proxy(RenderType::HardLink(HardLinkRenderType::WithoutProxy))]
+#[case::copy(RenderType::Copy)]
+#[tokio::test]
+#[serial_test::serial(config)]
+async fn test_render_manifest_compressed(
+    tmpdir: tempfile::TempDir,
+    #[case] render_type: RenderType,
+) {
+    let storage = OpenFsRepositoryImpl::from_config(crate::storage::fs::Config {
+        path: tmpdir.path().join("storage"),
+        params: Params {
+            create: true,
+            compression: PayloadCompression::Zstd,
+            ..Default::default()
+        },
+    })
+    .await
+    .unwrap();
+    let storage = OpenFsRepository {
+        fs_impl: Arc::new(storage),
+    };
+
+    let src_dir = tmpdir.path().join("source");
+    ensure(src_dir.join("dir1.0/dir2.0/file.txt"), "somedata");
+    ensure(src_dir.join("dir2.0/file.txt"), "evenmoredata");
+    ensure(src_dir.join("file.txt"), "rootdata");
+
+    let handle = RepositoryHandle::from(storage.clone());
+    let expected_manifest = crate::Committer::new(&handle)
+        .commit_dir(&src_dir)
+        .await
+        .unwrap();
+    let manifest = expected_manifest.to_graph_manifest();
+    for entry in manifest.iter_entries() {
+        if entry.is_regular_file() {
+            assert!(
+                !storage.payloads.has_digest(entry.object())
+                    && storage.compressed_payloads.has_digest(entry.object()),
+                "payloads should only be written compressed"
+            );
+        }
+    }
+
+    let render = super::Renderer::new(&storage)
+        .render_manifest(&manifest, Some(render_type))
+        .await
+        .expect("should render compressed payloads");
+    let rendered_manifest = tracking::compute_manifest(&render).await.unwrap();
+    assert_eq!(
+        manifest.digest().unwrap(),
+        rendered_manifest.to_graph_manifest().digest().unwrap(),
+        "rendered files should be decompressed"
+    );
+}
@@ -2,10 +2,13 @@
 // SPDX-License-Identifier: Apache-2.0
 // https://github.com/spkenv/spk
 
+use std::collections::HashMap;
 use std::os::fd::{AsRawFd, FromRawFd};
 use std::os::unix::fs::PermissionsExt;
 use std::os::unix::prelude::MetadataExt;
 use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
 
 use futures::future::ready;
 use futures::{FutureExt, StreamExt};
@@ -13,6 +16,7 @@ use nix::fcntl::OFlag;
 use nix::sys::stat::Mode;
 use nix::unistd::geteuid;
 use rand::prelude::*;
+use relative_path::{RelativePath, RelativePathBuf};
 use tokio::io::AsyncReadExt;
 
 use super::{BlobSemaphorePermit, HardLinkRenderType, RenderType, Renderer};
@@ -64,8 +68,16 @@ where
         }
 
         let manifest_tree_cache = manifest.get_tree_cache();
+        let links = RenderLinks::new(manifest, target_dir);
         let mut res = self
-            .render_into_dir_fd(root_dir, &root_node, &manifest_tree_cache, render_type)
+            .render_into_dir_fd(
+                root_dir,
+                &root_node,
+                &manifest_tree_cache,
+                RelativePath::new(""),
+                &links,
+                render_type,
+            )
             .await;
         if let Err(Error::StorageWriteError(_, p, _)) = &mut res {
             *p = target_dir.join(p.as_path());
@@ -81,6 +93,8 @@ where
         root_dir_fd: Fd,
         tree: &graph::Tree<'async_recursion>,
         manifest_tree_cache: &graph::ManifestTreeCache,
+        dir_path: &RelativePath,
+        links: &RenderLinks,
         render_type: RenderType,
     ) -> Result<()>
     where
@@ -126,26 +140,32 @@ where
                                     child_dir.as_raw_fd(),
                                     tree,
                                     manifest_tree_cache,
+                                    &dir_path.join(entry.name()),
+                                    links,
                                     render_type,
                                 )
                                 .await;
                             if res.is_ok() {
                                 let mode = Mode::from_bits_truncate(entry.mode());
+                                let xattrs = entry.to_xattrs();
                                 res = tokio::task::spawn_blocking(move || {
+                                    // xattrs are restored first, as the final
+                                    // mode may not allow them to be written
+                                    restore_xattrs(child_dir.as_raw_fd(), Path::new(""), &xattrs)?;
                                     nix::sys::stat::fchmod(
                                         child_dir.as_raw_fd(),
                                         mode,
                                     )
+                                    .map_err(|err| {
+                                        Error::StorageWriteError(
+                                            "set_permissions on rendered dir",
+                                            PathBuf::new(),
+                                            err.into(),
+                                        )
+                                    })
                                 })
                                 .await
-                                .expect("syscall should not panic")
-                                .map_err(|err| {
-                                    Error::StorageWriteError(
-                                        "set_permissions on rendered dir",
-                                        PathBuf::new(),
-                                        err.into(),
-                                    )
-                                });
+                                .expect("syscall should not panic");
                             }
                             if let Err(Error::StorageWriteError(_, p, _)) = &mut res {
                                 root_path.push(p.as_path());
@@ -155,7 +175,8 @@ where
                         }
                         tracking::EntryKind::Mask => Ok(None),
                         tracking::EntryKind::Blob(_) => {
-                            self.render_blob(root_dir_fd, entry, render_type).await.map(Some).map_err(|err| err.wrap(format!("render blob '{}'", entry.name())))
+                            let path = dir_path.join(entry.name());
+                            self.render_linked_blob(root_dir_fd, entry, &path, links, render_type).await.map(Some).map_err(|err| err.wrap(format!("render blob '{}'", entry.name())))
                         }
                     }.map(|render_blob_result_opt| (entry, render_blob_result_opt))
                 };
@@ -178,6 +199,51 @@ where
         Ok(())
     }
 
+    /// Renders a file that may be hard linked to others in the manifest.
+    ///
+    /// The first file of each group to be rendered is written as its own
+    /// copy, and the rest are linked to it. Files that are not in a group,
+    /// or cannot be linked, are rendered like any other.
+    async fn render_linked_blob(
+        &self,
+        dir_fd: i32,
+        entry: graph::Entry<'_>,
+        path: &RelativePath,
+        links: &RenderLinks,
+        render_type: RenderType,
+    ) -> Result<RenderBlobResult> {
+        let Some(group) = links.groups.get(path) else {
+            return self.render_blob(dir_fd, entry, render_type).await;
+        };
+        let mut rendered = group.lock().await;
+        if let Some(existing) = rendered.as_ref() {
+            match nix::unistd::linkat(
+                None,
+                existing.as_path(),
+                Some(dir_fd),
+                Path::new(entry.name()),
+                nix::fcntl::AtFlags::empty(),
+            ) {
+                Ok(()) => return Ok(RenderBlobResult::PayloadHardLinked),
+                Err(err) => {
+                    tracing::debug!(
+                        ?existing,
+                        name = entry.name(),
+                        "unable to preserve hard link, rendering separately: {err}"
+                    );
+                }
+            }
+            drop(rendered);
+            return self.render_blob(dir_fd, entry, render_type).await;
+        }
+        // the group gets a file of its own, because linking it to the
+        // payload would also link it to every other file of the same
+        // content and mode, inside and outside of this manifest
+        let result = self.render_blob(dir_fd, entry, RenderType::Copy).await?;
+        *rendered = Some(path.to_path(&links.target_dir));
+        Ok(result)
+    }
+
     /// Renders the file into a path on disk, changing its permissions
     /// as necessary / appropriate
     async fn render_blob<Fd>(
@@ -208,6 +274,7 @@ where
         entry: graph::Entry<'async_recursion>,
         render_type: HardLinkRenderType,
         mut committed_path: PathBuf,
+        payload_compressed: bool,
         permit: BlobSemaphorePermit<'a>,
     ) -> Result<RenderBlobResult>
     where
@@ -263,6 +330,9 @@ where
 
                     let has_correct_mode = metadata.permissions().mode() == entry.mode();
                     let mut has_correct_owner = metadata.uid() == geteuid().as_raw();
+                    // a compressed payload can never be linked
+                    // directly, and is decompressed into the proxy
+                    let can_link_payload = !payload_compressed && has_correct_mode;
 
                     // Can we still share this payload if it doesn't
                     // have the correct owner?
@@ -278,7 +348,7 @@ where
                         }
                     }
 
-                    if has_correct_mode && has_correct_owner {
+                    if can_link_payload && has_correct_owner {
                         // This still creates the proxy "hop" to the
                         // real payload file. It helps keep the code
                         // simple and could be a debugging aid if
@@ -333,7 +403,12 @@ where
                             RenderBlobResult::PayloadHardLinked
                         }
                     } else {
-                        if !has_correct_mode {
+                        if payload_compressed {
+                            tracing::debug!(
+                                ?payload_path,
+                                "couldn't skip proxy copy; payload is compressed"
+                            );
+                        } else if !has_correct_mode {
                             tracing::debug!(actual_mode = ?metadata.permissions().mode(), expected_mode = ?entry.mode(), ?payload_path, "couldn't skip proxy copy; payload had wrong mode");
                         } else if !has_correct_owner {
                             tracing::debug!(actual_uid = ?metadata.uid(), expected_uid = ?geteuid().as_raw(), ?payload_path, "couldn't skip proxy copy; payload had wrong uid");
@@ -350,8 +425,9 @@ where
                                     err,
                                 )
                             })?;
-                        let mut payload_file =
-                            tokio::fs::File::open(&payload_path).await.map_err(|err| {
+                        let mut payload_file = open_payload_file(&payload_path, payload_compressed)
+                            .await
+                            .map_err(|err| {
                                 if err.kind() == std::io::ErrorKind::NotFound {
                                     // in the case of a corrupt repository, this is a more appropriate error
                                     Error::UnknownObject(*entry.object())
@@ -401,6 +477,8 @@ where
                                     ));
                                 }
                             }
+                        } else if payload_compressed {
+                            RenderBlobResult::PayloadCopiedDecompressed
                         } else if !has_correct_mode {
                             RenderBlobResult::PayloadCopiedWrongMode
                         } else {
@@ -493,10 +571,11 @@ where
         target_dir_fd: i32,
         entry: graph::Entry<'async_recursion>,
         committed_path: PathBuf,
+        payload_compressed: bool,
         _permit: BlobSemaphorePermit<'a>,
     ) -> Result<RenderBlobResult> {
         let name = entry.name().to_owned();
-        let mut payload_file = tokio::fs::File::open(&committed_path)
+        let mut payload_file = open_payload_file(&committed_path, payload_compressed)
             .await
             .map_err(|err| {
                 Error::StorageReadError("open of payload source file", committed_path, err)
@@ -532,19 +611,24 @@ where
                 )
             })?;
         let mode = entry.mode();
+        let xattrs = entry.to_xattrs();
+        let name = entry.name().to_owned();
         tokio::task::spawn_blocking(move || {
+            // xattrs are restored after the data is written, because
+            // writing to a file clears any capabilities that it has
+            restore_xattrs(rendered_file.as_raw_fd(), Path::new(&name), &xattrs)?;
             nix::sys::stat::fchmod(rendered_file.as_raw_fd(), Mode::from_bits_truncate(mode))
+                .map_err(|err| {
+                    Error::StorageWriteError(
+                        "set permissions on copied payload",
+                        PathBuf::from(name),
+                        err.into(),
+                    )
+                })
         })
         .await
         .expect("syscall should not panic")
         .map(|_| RenderBlobResult::PayloadCopiedByRequest)
-        .map_err(|err| {
-            Error::StorageWriteError(
-                "set permissions on copied payload",
-                PathBuf::from(entry.name()),
-                err.into(),
-            )
-        })
     }
 
     /// Render a single blob onto disk
@@ -581,6 +665,14 @@ where
                     Error::StorageReadError("read_to_string on render blob", filename, err)
                 })?;
             }
+            if entry.has_xattrs() {
+                // symlinks cannot be opened to set their attributes, and
+                // most attributes are not permitted on symlinks anyway
+                tracing::debug!(
+                    name = entry.name(),
+                    "extended attributes are not restored on symlinks"
+                );
+            }
             return if let Err(err) =
                 nix::unistd::symlinkat(target.as_str(), Some(target_dir_fd), entry.name())
             {
@@ -599,8 +691,37 @@ where
         // Free up file resources as early as possible.
         drop(reader);
 
-        let committed_path = self.repo.payloads().build_digest_path(entry.object());
+        let render_type = match render_type {
+            // hard links share their xattrs with the payload and with every
+            // other render of it, so entries with xattrs need their own copy
+            RenderType::HardLink(_) if entry.has_xattrs() => RenderType::Copy,
+            render_type => render_type,
+        };
+
+        let mut committed_path = self.repo.payloads().build_digest_path(entry.object());
+        let mut payload_compressed = false;
+        if !committed_path.exists() {
+            if let Some(compressed) = self.repo.compressed_payloads() {
+                let compressed_path = compressed.build_digest_path(entry.object());
+                if compressed_path.exists() {
+                    committed_path = compressed_path;
+                    payload_compressed = true;
+                }
+            }
+        }
         Ok(match render_type {
+            // a compressed payload cannot be linked into the render
+            // without a decompressed proxy, so it must be copied instead
+            RenderType::HardLink(HardLinkRenderType::WithoutProxy) if payload_compressed => {
+                self.render_blob_as_copy_with_permit(
+                    target_dir_fd,
+                    entry,
+                    committed_path,
+                    payload_compressed,
+                    permit,
+                )
+                .await?
+            }
             RenderType::HardLink(hard_link_type) => {
                 self.render_blob_as_hard_link_with_permit(
                     dir_fd,
@@ -608,18 +729,85 @@ where
                     entry,
                     hard_link_type,
                     committed_path,
+                    payload_compressed,
                     permit,
                 )
                 .await?
             }
             RenderType::Copy => {
-                self.render_blob_as_copy_with_permit(target_dir_fd, entry, committed_path, permit)
-                    .await?
+                self.render_blob_as_copy_with_permit(
+                    target_dir_fd,
+                    entry,
+                    committed_path,
+                    payload_compressed,
+                    permit,
+                )
+                .await?
             }
         })
     }
 }
 
+/// The hard link groups of a manifest being rendered
+struct RenderLinks {
+    target_dir: PathBuf,
+    /// The shared state of the group that each linked file belongs
+    /// to, holding the path of the first file rendered in the group
+    groups: HashMap<RelativePathBuf, Arc<tokio::sync::Mutex<Option<PathBuf>>>>,
+}
+
+impl RenderLinks {
+    fn new(manifest: &graph::Manifest, target_dir: &Path) -> Self {
+        let mut groups = HashMap::new();
+        // finding groups requires the whole manifest to be
+        // converted, which is skipped when there are no links
+        if manifest
+            .iter_entries()
+            .any(|entry| entry.hardlink().is_some())
+        {
+            for (primary, members) in manifest.to_tracking_manifest().hardlink_groups() {
+                let group = Arc::default();
+                for member in members {
+                    groups.insert(member, Arc::clone(&group));
+                }
+                groups.insert(primary, group);
+            }
+        }
+        Self {
+            target_dir: target_dir.to_owned(),
+            groups,
+        }
+    }
+}
+
+/// Restore extended attributes onto an open file or directory.
+///
+/// Attributes that cannot be set by the current user, such as file
+/// capabilities when rendering without privileges, are skipped.
+fn restore_xattrs(fd: i32, path: &Path, xattrs: &tracking::Xattrs) -> Result<()> {
+    for (name, value) in xattrs {
+        match crate::xattr::fset(fd, name, value) {
+            Ok(()) => {}
+            Err(err)
+                if matches!(
+                    err.raw_os_error(),
+                    Some(libc::EPERM | libc::EACCES | libc::ENOTSUP)
+                ) =>
+            {
+                tracing::warn!("unable to restore extended attribute {name}: {err}");
+            }
+            Err(err) => {
+                return Err(Error::StorageWriteError(
+                    "set xattr on rendered entry",
+                    path.to_owned(),
+                    err,
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
 async fn create_and_open_dir_at<A>(dir_fd: A, name: String) -> std::io::Result<tokio::fs::File>
 where
     A: AsRawFd + Send + 'static,
@@ -645,3 +833,20 @@ where
     .await
     .map_err(|_join_err| std::io::Error::other("mkdir task panic'd"))?
 }
+
+/// Open a committed payload file so that its data can be copied,
+/// decompressing it if it came from compressed payload storage.
+async fn open_payload_file(
+    path: &Path,
+    compressed: bool,
+) -> std::io::Result<Pin<Box<dyn tokio::io::AsyncRead + Send>>> {
+    let file = tokio::fs::File::open(path).await?;
+    if compressed {
+        let reader = tokio::io::BufReader::new(file);
+        Ok(Box::pin(
+            async_compression::tokio::bufread::ZstdDecoder::new(reader),
+        ))
+    } else {
+        Ok(Box::pin(file))
+    }
+}
@@ -30,12 +30,16 @@ use crate::storage::{
     TagNamespace,
     TagNamespaceBuf,
 };
+use crate::tracking::{TagSignaturePolicy, TagSigningKey};
 use crate::{Error, Result};
 
 /// The directory name within the repo where durable runtimes keep
 /// their upper path roots and upper/work directories.
 pub const DURABLE_EDITS_DIR: &str = "durable_edits";
 
+/// The directory name within the repo where compressed payloads are stored.
+const COMPRESSED_PAYLOADS_DIR: &str = "payloads.zst";
+
 /// Configuration for an fs repository
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct Config {
@@ -67,6 +71,31 @@ pub struct Params {
     #[serde(default)]
     pub lazy: bool,
     pub tag_namespace: Option<TagNamespaceBuf>,
+    /// How newly written payloads are compressed
+    #[serde(default)]
+    pub compression: PayloadCompression,
+    /// If true, tags that are not signed by one of
+    /// the trusted keys cannot be resolved
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub require_signed_tags: bool,
+    /// The base64 encoded ed25519 public keys that are trusted to sign tags
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_tag_keys: Vec<String>,
+}
+
+/// How payload data is stored on disk in an fs repository
+///
+/// Payloads are always readable regardless of this setting,
+/// which only affects how new payloads are written.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadCompression {
+    /// Payloads are stored as-is, and can be hard linked into renders
+    #[default]
+    None,
+    /// Payloads are compressed using zstd, and must be
+    /// decompressed into the render store to be rendered
+    Zstd,
 }
 
 #[async_trait::async_trait]
@@ -253,6 +282,10 @@ where
         self.fs_impl.payloads()
     }
 
+    fn compressed_payloads(&self) -> Option<&FsHashStore> {
+        self.fs_impl.compressed_payloads()
+    }
+
     fn render_store(&self) -> Result<&RenderStore> {
         self.fs_impl.render_store()
     }
@@ -492,6 +525,14 @@ pub struct OpenFsRepositoryImpl {
     tag_namespace: Option<TagNamespaceBuf>,
     /// stores the actual file data/payloads of this repo
     pub payloads: FsHashStore,
+    /// stores the file data/payloads of this repo that were compressed
+    pub compressed_payloads: FsHashStore,
+    /// how newly written payloads are compressed
+    compression: PayloadCompression,
+    /// which tags can be resolved from this repo
+    tag_signatures: TagSignaturePolicy,
+    /// signs the tags that are pushed to this repo
+    tag_signing_key: Option<Arc<TagSigningKey>>,
     /// stores all digraph object data for this repo
     pub objects: FsHashStore,
     /// stores rendered file system layers for use in overlayfs
@@ -503,6 +544,13 @@ impl FromConfig for OpenFsRepositoryImpl {
     type Config = Config;
 
     async fn from_config(config: Self::Config) -> crate::storage::OpenRepositoryResult<Self> {
+        let tag_signatures = TagSignaturePolicy::new(
+            config.params.require_signed_tags,
+            &config.params.trusted_tag_keys,
+        )
+        .map_err(|err| OpenRepositoryError::InvalidTagSignaturePolicy {
+            source: Box::new(err),
+        })?;
         let repo = if config.params.create {
             Self::create(&config.path).await
         } else {
@@ -510,6 +558,8 @@ impl FromConfig for OpenFsRepositoryImpl {
         };
         repo.map(|mut repo| {
             repo.set_tag_namespace(config.params.tag_namespace);
+            repo.set_compression(config.params.compression);
+            repo.set_tag_signature_policy(tag_signatures);
             repo
         })
     }
@@ -521,6 +571,11 @@ impl Clone for OpenFsRepositoryImpl {
         Self {
             objects: FsHashStore::open_unchecked(root.join("objects")),
             payloads: FsHashStore::open_unchecked(root.join("payloads")),
+            compressed_payloads: FsHashStore::open_unchecked(root.join(COMPRESSED_PAYLOADS_DIR))
+                .with_compression(PayloadCompression::Zstd),
+            compression: self.compression,
+            tag_signatures: self.tag_signatures.clone(),
+            tag_signing_key: self.tag_signing_key.clone(),
             renders: self.renders.clone(),
             root,
             tag_namespace: self.tag_namespace.clone(),
@@ -534,6 +589,11 @@ impl LocalRepository for OpenFsRepositoryImpl {
         &self.payloads
     }
 
+    #[inline]
+    fn compressed_payloads(&self) -> Option<&FsHashStore> {
+        Some(&self.compressed_payloads)
+    }
+
     #[inline]
     fn render_store(&self) -> Result<&RenderStore> {
         self.renders
@@ -551,6 +611,9 @@ impl OpenFsRepositoryImpl {
                 create: false,
                 lazy: false,
                 tag_namespace: self.tag_namespace.clone(),
+                compression: self.compression,
+                require_signed_tags: self.tag_signatures.is_required(),
+                trusted_tag_keys: self.tag_signatures.trusted_keys_base64(),
             },
         }
         .to_address()
@@ -580,6 +643,7 @@ impl OpenFsRepositoryImpl {
             root.join("tags"),
             root.join("objects"),
             root.join("payloads"),
+            root.join(COMPRESSED_PAYLOADS_DIR),
             root.join("renders").join(username).join(PROXY_DIRNAME),
             root.join(DURABLE_EDITS_DIR),
         ] {
@@ -665,6 +729,13 @@ impl OpenFsRepositoryImpl {
         Ok(Self {
             objects: FsHashStore::open(root.join("objects"))?,
             payloads: FsHashStore::open(root.join("payloads"))?,
+            // repositories created before compression was supported
+            // will not have this directory until it is first written to
+            compressed_payloads: FsHashStore::open_unchecked(root.join(COMPRESSED_PAYLOADS_DIR))
+                .with_compression(PayloadCompression::Zstd),
+            compression: PayloadCompression::None,
+            tag_signatures: TagSignaturePolicy::default(),
+            tag_signing_key: None,
             renders: RenderStore::for_user(root, username).ok(),
             root: root.to_owned(),
             tag_namespace: None,
@@ -691,6 +762,45 @@ impl OpenFsRepositoryImpl {
     ) -> Option<TagNamespaceBuf> {
         std::mem::replace(&mut self.tag_namespace, tag_namespace)
     }
+
+    /// How newly written payloads are compressed
+    #[inline]
+    pub fn compression(&self) -> PayloadCompression {
+        self.compression
+    }
+
+    /// Set how newly written payloads are compressed, returning
+    /// the previous setting.
+    pub fn set_compression(&mut self, compression: PayloadCompression) -> PayloadCompression {
+        std::mem::replace(&mut self.compression, compression)
+    }
+
+    /// Which tags can be resolved from this repository
+    #[inline]
+    pub fn tag_signature_policy(&self) -> &TagSignaturePolicy {
+        &self.tag_signatures
+    }
+
+    /// Set which tags can be resolved from this repository,
+    /// returning the previous policy.
+    pub fn set_tag_signature_policy(&mut self, policy: TagSignaturePolicy) -> TagSignaturePolicy {
+        std::mem::replace(&mut self.tag_signatures, policy)
+    }
+
+    /// The key used to sign tags that are pushed to this repository
+    #[inline]
+    pub fn tag_signing_key(&self) -> Option<&Arc<TagSigningKey>> {
+        self.tag_signing_key.as_ref()
+    }
+
+    /// Set the key used to sign tags that are pushed to this
+    /// repository, returning the previous key.
+    pub fn set_tag_signing_key(
+        &mut self,
+        key: Option<Arc<TagSigningKey>>,
+    ) -> Option<Arc<TagSigningKey>> {
+        std::mem::replace(&mut self.tag_signing_key, key)
+    }
 }
 
 #[async_trait::async_trait]
@@ -810,6 +920,11 @@ impl FsRepositoryOps for OpenFsRepositoryImpl {
                     Self {
                         objects: FsHashStore::open_unchecked(self.root.join("objects")),
                         payloads: FsHashStore::open_unchecked(self.root.join("payloads")),
+                        compressed_payloads: FsHashStore::open_unchecked(
+                            self.root.join(COMPRESSED_PAYLOADS_DIR),
+                        )
+                        .with_compression(PayloadCompression::Zstd),
+                        compression: self.compression,
                         renders: self
                             .renders
                             .as_ref()
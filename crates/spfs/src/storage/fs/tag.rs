@@ -86,6 +86,21 @@ impl TagStorage for MaybeOpenFsRepository {
             .boxed()
     }
 
+    async fn tag_signing_key(&self) -> Option<Arc<tracking::TagSigningKey>> {
+        self.opened().await.ok()?.tag_signing_key().await
+    }
+
+    async fn resolve_tag_in_namespace(
+        &self,
+        namespace: Option<&TagNamespace>,
+        tag_spec: &tracking::TagSpec,
+    ) -> Result<tracking::Tag> {
+        self.opened()
+            .await?
+            .resolve_tag_in_namespace(namespace, tag_spec)
+            .await
+    }
+
     async fn read_tag_in_namespace(
         &self,
         namespace: Option<&TagNamespace>,
@@ -289,6 +304,31 @@ impl TagStorage for OpenFsRepository {
         Box::pin(TagStreamIter::new(self.tags_root_in_namespace(namespace)))
     }
 
+    async fn tag_signing_key(&self) -> Option<Arc<tracking::TagSigningKey>> {
+        self.fs_impl.tag_signing_key().cloned()
+    }
+
+    /// Return the tag identified by the given tag spec.
+    ///
+    /// # Errors:
+    /// - if the tag does not exist in this storage
+    /// - if the tag is not allowed by the signature policy of this repository
+    async fn resolve_tag_in_namespace(
+        &self,
+        namespace: Option<&TagNamespace>,
+        tag_spec: &tracking::TagSpec,
+    ) -> Result<tracking::Tag> {
+        let mut stream = self
+            .read_tag_in_namespace(namespace, tag_spec)
+            .await?
+            .skip(tag_spec.version() as usize);
+        let Some(tag) = stream.next().await.transpose()? else {
+            return Err(Error::UnknownReference(tag_spec.to_string()));
+        };
+        self.fs_impl.tag_signature_policy().check(&tag)?;
+        Ok(tag)
+    }
+
     async fn read_tag_in_namespace(
         &self,
         namespace: Option<&TagNamespace>,
@@ -565,6 +605,28 @@ trait TagReader: AsyncRead + AsyncSeek + Send + Unpin {}
 
 impl TagReader for tokio::io::BufReader<tokio::fs::File> {}
 
+impl TagReader for std::io::Cursor<Vec<u8>> {}
+
+/// Encode a complete tag stream in the same format as a tag file.
+///
+/// The given tags are expected to be ordered from earliest to latest.
+pub(crate) fn encode_tag_stream(tags: &[tracking::Tag]) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    for tag in tags.iter() {
+        let buf = tag.encode_to_bytes()?;
+        data.extend_from_slice(&(buf.len() as i64).to_be_bytes());
+        data.extend_from_slice(&buf);
+    }
+    Ok(data)
+}
+
+/// Return a stream over all tags in the given tag file contents.
+///
+/// Like [`read_tag_file`], tags are returned from latest to earliest.
+pub(crate) fn decode_tag_stream(data: Vec<u8>, filename: PathBuf) -> TagStream {
+    Box::pin(TagIter::new(Box::new(std::io::Cursor::new(data)), filename))
+}
+
 async fn write_tags_to_path(filepath: &PathBuf, tags: &[tracking::Tag]) -> Result<()> {
     crate::runtime::makedirs_with_perms(filepath.parent().unwrap(), 0o777)
         .map_err(|err| Error::StorageWriteError("write_tags_to_path", filepath.clone(), err))?;
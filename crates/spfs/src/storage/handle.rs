@@ -3,6 +3,7 @@
 // https://github.com/spkenv/spk
 
 use std::borrow::Cow;
+use std::collections::HashSet;
 use std::pin::Pin;
 use std::sync::Arc;
 
@@ -24,6 +25,7 @@ pub enum RepositoryHandle {
     FS(super::fs::MaybeOpenFsRepository),
     Tar(super::tar::TarRepository),
     Rpc(super::rpc::RpcRepository),
+    S3(super::s3::S3Repository),
     FallbackProxy(Box<super::fallback::FallbackProxy>),
     Proxy(Box<super::proxy::ProxyRepository>),
     Pinned(Box<super::pinned::PinnedRepository<RepositoryHandle>>),
@@ -73,6 +75,7 @@ impl RepositoryHandle {
             RepositoryHandle::FS(repo) => Ok(repo),
             RepositoryHandle::Tar(repo) => Ok(repo),
             RepositoryHandle::Rpc(repo) => Ok(repo),
+            RepositoryHandle::S3(repo) => Ok(repo),
             RepositoryHandle::FallbackProxy(repo) => Ok(&mut **repo),
             RepositoryHandle::Proxy(repo) => Ok(&mut **repo),
             RepositoryHandle::Pinned(_) => Err(Error::RepositoryIsPinned),
@@ -110,6 +113,12 @@ impl From<super::rpc::RpcRepository> for RepositoryHandle {
     }
 }
 
+impl From<super::s3::S3Repository> for RepositoryHandle {
+    fn from(repo: super::s3::S3Repository) -> Self {
+        RepositoryHandle::S3(repo)
+    }
+}
+
 impl From<super::fallback::FallbackProxy> for RepositoryHandle {
     fn from(repo: super::fallback::FallbackProxy) -> Self {
         RepositoryHandle::FallbackProxy(Box::new(repo))
@@ -137,6 +146,7 @@ macro_rules! each_variant {
             RepositoryHandle::FS($inner) => $ops,
             RepositoryHandle::Tar($inner) => $ops,
             RepositoryHandle::Rpc($inner) => $ops,
+            RepositoryHandle::S3($inner) => $ops,
             RepositoryHandle::FallbackProxy($inner) => $ops,
             RepositoryHandle::Proxy($inner) => $ops,
             RepositoryHandle::Pinned($inner) => $ops,
@@ -157,6 +167,10 @@ impl TagStorage for RepositoryHandle {
         each_variant!(self, repo, { repo.get_tag_namespace() })
     }
 
+    async fn tag_signing_key(&self) -> Option<Arc<tracking::TagSigningKey>> {
+        each_variant!(self, repo, { repo.tag_signing_key().await })
+    }
+
     async fn resolve_tag_in_namespace(
         &self,
         namespace: Option<&TagNamespace>,
@@ -244,6 +258,7 @@ impl TagStorageMut for RepositoryHandle {
             RepositoryHandle::FS(repo) => repo.try_set_tag_namespace(tag_namespace),
             RepositoryHandle::Tar(repo) => repo.try_set_tag_namespace(tag_namespace),
             RepositoryHandle::Rpc(repo) => repo.try_set_tag_namespace(tag_namespace),
+            RepositoryHandle::S3(repo) => repo.try_set_tag_namespace(tag_namespace),
             RepositoryHandle::FallbackProxy(repo) => repo.try_set_tag_namespace(tag_namespace),
             RepositoryHandle::Proxy(repo) => repo.try_set_tag_namespace(tag_namespace),
             RepositoryHandle::Pinned(_) => Err(Error::RepositoryIsPinned),
@@ -257,6 +272,10 @@ impl PayloadStorage for RepositoryHandle {
         each_variant!(self, repo, { repo.has_payload(digest).await })
     }
 
+    async fn has_payloads(&self, digests: &[encoding::Digest]) -> HashSet<encoding::Digest> {
+        each_variant!(self, repo, { repo.has_payloads(digests).await })
+    }
+
     fn iter_payload_digests(&self) -> Pin<Box<dyn Stream<Item = Result<encoding::Digest>> + Send>> {
         each_variant!(self, repo, { repo.iter_payload_digests() })
     }
@@ -288,6 +307,10 @@ impl DatabaseView for RepositoryHandle {
         each_variant!(self, repo, { repo.has_object(digest).await })
     }
 
+    async fn has_objects(&self, digests: &[encoding::Digest]) -> HashSet<encoding::Digest> {
+        each_variant!(self, repo, { repo.has_objects(digests).await })
+    }
+
     async fn read_object(&self, digest: encoding::Digest) -> Result<graph::Object> {
         each_variant!(self, repo, { repo.read_object(digest).await })
     }
@@ -346,6 +369,10 @@ impl TagStorage for Arc<RepositoryHandle> {
         RepositoryHandle::get_tag_namespace(self)
     }
 
+    async fn tag_signing_key(&self) -> Option<Arc<tracking::TagSigningKey>> {
+        each_variant!(&**self, repo, { repo.tag_signing_key().await })
+    }
+
     async fn resolve_tag_in_namespace(
         &self,
         namespace: Option<&TagNamespace>,
@@ -432,6 +459,10 @@ impl PayloadStorage for Arc<RepositoryHandle> {
         each_variant!(&**self, repo, { repo.has_payload(digest).await })
     }
 
+    async fn has_payloads(&self, digests: &[encoding::Digest]) -> HashSet<encoding::Digest> {
+        each_variant!(&**self, repo, { repo.has_payloads(digests).await })
+    }
+
     fn iter_payload_digests(&self) -> Pin<Box<dyn Stream<Item = Result<encoding::Digest>> + Send>> {
         each_variant!(&**self, repo, { repo.iter_payload_digests() })
     }
@@ -463,6 +494,10 @@ impl DatabaseView for Arc<RepositoryHandle> {
         each_variant!(&**self, repo, { repo.has_object(digest).await })
     }
 
+    async fn has_objects(&self, digests: &[encoding::Digest]) -> HashSet<encoding::Digest> {
+        each_variant!(&**self, repo, { repo.has_objects(digests).await })
+    }
+
     async fn read_object(&self, digest: encoding::Digest) -> Result<graph::Object> {
         each_variant!(&**self, repo, { repo.read_object(digest).await })
     }
@@ -13,6 +13,7 @@ use crate::tracking;
 #[case::fs(tmprepo("fs"))]
 #[case::tar(tmprepo("tar"))]
 #[cfg_attr(feature = "server", case::rpc(tmprepo("rpc")))]
+#[cfg_attr(feature = "server", case::s3(tmprepo("s3")))]
 #[tokio::test]
 async fn test_read_write_manifest(
     #[case]
@@ -51,6 +52,7 @@ async fn test_read_write_manifest(
 #[case::fs(tmprepo("fs"))]
 #[case::tar(tmprepo("tar"))]
 #[cfg_attr(feature = "server", case::rpc(tmprepo("rpc")))]
+#[cfg_attr(feature = "server", case::s3(tmprepo("s3")))]
 #[tokio::test]
 async fn test_manifest_parity(
     #[case]
@@ -0,0 +1,78 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::collections::{BTreeSet, HashSet};
+
+use super::PayloadStorage;
+use crate::graph::DatabaseView;
+use crate::{Error, Result, encoding};
+
+#[cfg(test)]
+#[path = "./missing_test.rs"]
+mod missing_test;
+
+/// The objects and payloads that are reachable from some
+/// root object but do not exist in a repository.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MissingData {
+    pub objects: BTreeSet<encoding::Digest>,
+    pub payloads: BTreeSet<encoding::Digest>,
+}
+
+impl MissingData {
+    /// True if nothing is missing
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty() && self.payloads.is_empty()
+    }
+}
+
+/// Walk the graph of objects from the given root, identifying
+/// everything that is missing from the repository.
+///
+/// The children of missing objects cannot be known and
+/// so are not included in the result. The existence of each
+/// level of the graph is checked in a single batch, which
+/// allows repositories with batched lookups to answer quickly.
+pub async fn find_missing_data<R>(repo: &R, root: encoding::Digest) -> Result<MissingData>
+where
+    R: DatabaseView + PayloadStorage + ?Sized,
+{
+    let mut missing = MissingData::default();
+    let mut visited = HashSet::new();
+    let mut pending = vec![root];
+    while !pending.is_empty() {
+        let level: Vec<_> = pending
+            .drain(..)
+            .filter(|digest| visited.insert(*digest))
+            .collect();
+        let existing = repo.has_objects(&level).await;
+        let mut payloads = Vec::new();
+        for digest in level {
+            if !existing.contains(&digest) {
+                missing.objects.insert(digest);
+                continue;
+            }
+            let object = match repo.read_object(digest).await {
+                Err(Error::UnknownObject(_)) => {
+                    missing.objects.insert(digest);
+                    continue;
+                }
+                res => res?,
+            };
+            if object.has_payload() {
+                // blobs share their digest with their payload
+                payloads.push(digest);
+            } else {
+                pending.extend(object.child_objects());
+            }
+        }
+        let existing = repo.has_payloads(&payloads).await;
+        missing.payloads.extend(
+            payloads
+                .into_iter()
+                .filter(|digest| !existing.contains(digest)),
+        );
+    }
+    Ok(missing)
+}
@@ -0,0 +1,100 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use rstest::rstest;
+
+use super::find_missing_data;
+use crate::encoding::prelude::*;
+use crate::fixtures::*;
+use crate::graph::{Database, DatabaseView};
+use crate::storage::{PayloadStorage, RepositoryHandle};
+
+#[rstest]
+#[case::fs(tmprepo("fs"))]
+#[cfg_attr(feature = "server", case::rpc(tmprepo("rpc")))]
+#[tokio::test]
+async fn test_has_objects_and_payloads(
+    #[case]
+    #[future]
+    tmprepo: TempRepo,
+    tmpdir: tempfile::TempDir,
+) {
+    init_logging();
+    let tmprepo = tmprepo.await;
+
+    ensure(tmpdir.path().join("dir/file.txt"), "hello");
+    let manifest = crate::Committer::new(&tmprepo)
+        .commit_dir(tmpdir.path())
+        .await
+        .unwrap()
+        .to_graph_manifest();
+    let file = *manifest
+        .iter_entries()
+        .find(|entry| entry.is_regular_file())
+        .expect("at least one regular file")
+        .object();
+    let unknown: crate::encoding::Digest = crate::encoding::EMPTY_DIGEST.into();
+
+    let existing = tmprepo
+        .has_objects(&[manifest.digest().unwrap(), file, unknown])
+        .await;
+    assert!(existing.contains(&manifest.digest().unwrap()));
+    assert!(existing.contains(&file));
+    assert!(
+        !existing.contains(&unknown),
+        "should not find unknown object"
+    );
+
+    let existing = tmprepo.has_payloads(&[file, unknown]).await;
+    assert!(existing.contains(&file));
+    assert!(
+        !existing.contains(&unknown),
+        "should not find unknown payload"
+    );
+}
+
+#[rstest]
+#[case::fs(tmprepo("fs"))]
+#[cfg_attr(feature = "server", case::rpc(tmprepo("rpc")))]
+#[tokio::test]
+async fn test_find_missing_data(
+    #[case]
+    #[future]
+    tmprepo: TempRepo,
+    tmpdir: tempfile::TempDir,
+) {
+    init_logging();
+    let tmprepo = tmprepo.await;
+
+    ensure(tmpdir.path().join("dir/file.txt"), "hello");
+    ensure(tmpdir.path().join("dir2/otherfile.txt"), "hello2");
+    ensure(tmpdir.path().join("file3.txt"), "hello3");
+    let manifest = crate::Committer::new(&tmprepo)
+        .commit_dir(tmpdir.path())
+        .await
+        .unwrap()
+        .to_graph_manifest();
+    let root = manifest.digest().unwrap();
+
+    let missing = find_missing_data(&*tmprepo, root).await.unwrap();
+    assert!(missing.is_empty(), "nothing should be missing: {missing:?}");
+
+    let mut files = manifest
+        .iter_entries()
+        .filter(|entry| entry.is_regular_file())
+        .map(|entry| *entry.object());
+    let missing_payload = files.next().unwrap();
+    let missing_object = files.next().unwrap();
+    tmprepo.remove_payload(missing_payload).await.unwrap();
+    tmprepo.remove_object(missing_object).await.unwrap();
+
+    let missing = find_missing_data(&*tmprepo, root).await.unwrap();
+    assert_eq!(missing.payloads, [missing_payload].into());
+    assert_eq!(missing.objects, [missing_object].into());
+
+    if let RepositoryHandle::Rpc(repo) = &*tmprepo {
+        let remote = repo.find_missing_data(root).await.unwrap();
+        assert_eq!(remote, missing, "server should find the same missing data");
+    }
+}
@@ -7,6 +7,7 @@ mod blob;
 mod error;
 mod layer;
 mod manifest;
+mod missing;
 pub mod payload;
 mod platform;
 mod repository;
@@ -21,6 +22,7 @@ pub mod pinned;
 pub mod prelude;
 pub mod proxy;
 pub mod rpc;
+pub mod s3;
 pub mod tar;
 
 pub use address::Address;
@@ -29,6 +31,7 @@ pub use error::OpenRepositoryError;
 pub use handle::RepositoryHandle;
 pub use layer::{LayerStorage, LayerStorageExt};
 pub use manifest::ManifestStorage;
+pub use missing::{MissingData, find_missing_data};
 pub use payload::PayloadStorage;
 pub use platform::{PlatformStorage, PlatformStorageExt};
 pub use proxy::{Config, ProxyRepository};
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 // https://github.com/spkenv/spk
 
+use std::collections::HashSet;
 use std::pin::Pin;
 
 use futures::Stream;
@@ -22,6 +23,20 @@ pub trait PayloadStorage: Sync + Send {
     /// Return true if the identified payload exists.
     async fn has_payload(&self, digest: encoding::Digest) -> bool;
 
+    /// Return the subset of the identified payloads that exist.
+    ///
+    /// By default this checks each payload individually.
+    /// Other implementations may provide better results.
+    async fn has_payloads(&self, digests: &[encoding::Digest]) -> HashSet<encoding::Digest> {
+        let mut existing = HashSet::new();
+        for digest in digests {
+            if self.has_payload(*digest).await {
+                existing.insert(*digest);
+            }
+        }
+        existing
+    }
+
     /// Store the contents of the given stream, returning its digest and size
     ///
     /// # Safety
@@ -56,6 +71,10 @@ impl<T: PayloadStorage> PayloadStorage for &T {
         PayloadStorage::has_payload(&**self, digest).await
     }
 
+    async fn has_payloads(&self, digests: &[encoding::Digest]) -> HashSet<encoding::Digest> {
+        PayloadStorage::has_payloads(&**self, digests).await
+    }
+
     fn iter_payload_digests(&self) -> Pin<Box<dyn Stream<Item = Result<encoding::Digest>> + Send>> {
         PayloadStorage::iter_payload_digests(&**self)
     }
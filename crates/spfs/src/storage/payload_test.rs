@@ -13,6 +13,7 @@ use crate::prelude::*;
 #[case::fs(tmprepo("fs"))]
 #[case::tar(tmprepo("tar"))]
 #[cfg_attr(feature = "server", case::rpc(tmprepo("rpc")))]
+#[cfg_attr(feature = "server", case::s3(tmprepo("s3")))]
 #[tokio::test]
 async fn test_payload_io(
     #[case]
@@ -48,6 +49,7 @@ async fn test_payload_io(
 #[case::fs(tmprepo("fs"))]
 #[case::tar(tmprepo("tar"))]
 #[cfg_attr(feature = "server", case::rpc(tmprepo("rpc")))]
+#[cfg_attr(feature = "server", case::s3(tmprepo("s3")))]
 #[tokio::test]
 async fn test_payload_existence(
     #[case]
@@ -80,6 +82,7 @@ async fn test_payload_existence(
 #[case::fs(tmprepo("fs"))]
 #[case::tar(tmprepo("tar"))]
 #[cfg_attr(feature = "server", case::rpc(tmprepo("rpc")))]
+#[cfg_attr(feature = "server", case::s3(tmprepo("s3")))]
 #[tokio::test]
 async fn test_payloads_iter(
     #[case]
@@ -4,6 +4,7 @@
 
 use std::borrow::Cow;
 use std::pin::Pin;
+use std::sync::Arc;
 
 use chrono::{DateTime, Utc};
 use futures::Stream;
@@ -250,6 +251,47 @@ impl TagStorage for ProxyRepository {
         self.primary.get_tag_namespace()
     }
 
+    async fn tag_signing_key(&self) -> Option<Arc<tracking::TagSigningKey>> {
+        self.primary.tag_signing_key().await
+    }
+
+    /// Resolve the tag from the first repository that has it.
+    ///
+    /// Tags from the secondary repositories are also checked against
+    /// the signature policy of the primary, when it has one, so that
+    /// the proxy does not allow more than the primary alone.
+    async fn resolve_tag_in_namespace(
+        &self,
+        namespace: Option<&TagNamespace>,
+        tag_spec: &tracking::TagSpec,
+    ) -> Result<tracking::Tag> {
+        let res = self
+            .primary
+            .resolve_tag_in_namespace(namespace, tag_spec)
+            .await;
+        if !matches!(res, Err(crate::Error::UnknownReference(_))) {
+            return res;
+        }
+
+        let mut res = res;
+        for repo in self.secondary.iter() {
+            res = repo.resolve_tag_in_namespace(namespace, tag_spec).await;
+            if !matches!(res, Err(crate::Error::UnknownReference(_))) {
+                break;
+            }
+        }
+        let tag = res?;
+        if let crate::storage::RepositoryHandle::FS(primary) = &self.primary {
+            primary
+                .opened()
+                .await?
+                .fs_impl
+                .tag_signature_policy()
+                .check(&tag)?;
+        }
+        Ok(tag)
+    }
+
     fn ls_tags_in_namespace(
         &self,
         namespace: Option<&TagNamespace>,
@@ -2,6 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 // https://github.com/spkenv/spk
 
+use std::sync::Arc;
+
 use rstest::rstest;
 
 use crate::fixtures::*;
@@ -95,3 +97,49 @@ async fn test_proxy_tag_read_through(tmpdir: tempfile::TempDir) {
         .await
         .expect("tag should be resolvable via the secondary repo");
 }
+
+#[rstest]
+#[tokio::test]
+async fn test_proxy_resolve_tag_signature_policy(tmpdir: tempfile::TempDir) {
+    use ring::signature::Ed25519KeyPair;
+
+    init_logging();
+
+    let document = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new()).unwrap();
+    let trusted = crate::tracking::TagSigningKey::from_pkcs8(document.as_ref()).unwrap();
+    let mut primary = crate::storage::fs::OpenFsRepository::create(tmpdir.path().join("primary"))
+        .await
+        .unwrap();
+    let policy =
+        crate::tracking::TagSignaturePolicy::new(true, [trusted.public_key_base64()]).unwrap();
+    Arc::make_mut(&mut primary.fs_impl).set_tag_signature_policy(policy);
+    let secondary =
+        crate::storage::fs::MaybeOpenFsRepository::create(tmpdir.path().join("secondary"))
+            .await
+            .unwrap();
+
+    let tag_spec = crate::tracking::TagSpec::parse("spfs-test/proxy-signed").unwrap();
+    let mut tag = crate::tracking::Tag::new(
+        tag_spec.org(),
+        tag_spec.name(),
+        crate::encoding::EMPTY_DIGEST.into(),
+    )
+    .unwrap();
+    secondary.insert_tag(&tag).await.unwrap();
+
+    let proxy = super::ProxyRepository {
+        primary: crate::storage::fs::MaybeOpenFsRepository::from(primary).into(),
+        secondary: vec![secondary.into()],
+    };
+    proxy
+        .resolve_tag(&tag_spec)
+        .await
+        .expect_err("unsigned tag from the secondary should not resolve");
+
+    tag.sign(&trusted).unwrap();
+    proxy.secondary[0].insert_tag(&tag).await.unwrap();
+    proxy
+        .resolve_tag(&tag_spec)
+        .await
+        .expect("tag signed by a trusted key should resolve via the secondary repo");
+}
@@ -140,6 +140,14 @@ pub trait LocalRepository {
     /// Return the payload storage type
     fn payloads(&self) -> &FsHashStore;
 
+    /// Return the storage for compressed payloads, if supported
+    ///
+    /// Payloads in this storage cannot be hard linked into a render,
+    /// and must be decompressed into the render store instead.
+    fn compressed_payloads(&self) -> Option<&FsHashStore> {
+        None
+    }
+
     /// If supported, returns the type responsible for locally rendered manifests
     ///
     /// # Errors:
@@ -23,6 +23,7 @@ use crate::tracking::TagSpec;
 #[case::fs(tmprepo("fs"))]
 #[case::tar(tmprepo("tar"))]
 #[cfg_attr(feature = "server", case::rpc(tmprepo("rpc")))]
+#[cfg_attr(feature = "server", case::s3(tmprepo("s3")))]
 #[tokio::test]
 async fn test_find_aliases(
     #[case]
@@ -121,6 +122,7 @@ async fn test_commit_mode_fs(tmpdir: tempfile::TempDir) {
 #[case::fs(tmprepo("fs"))]
 #[case::tar(tmprepo("tar"))]
 #[cfg_attr(feature = "server", case::rpc(tmprepo("rpc")))]
+#[cfg_attr(feature = "server", case::s3(tmprepo("s3")))]
 #[tokio::test]
 async fn test_commit_broken_link(
     #[case]
@@ -148,6 +150,7 @@ async fn test_commit_broken_link(
 #[case::fs(tmprepo("fs"))]
 #[case::tar(tmprepo("tar"))]
 #[cfg_attr(feature = "server", case::rpc(tmprepo("rpc")))]
+#[cfg_attr(feature = "server", case::s3(tmprepo("s3")))]
 #[tokio::test]
 // This test just needs the config to not change while it is running.
 #[serial_test::serial(config)]
@@ -0,0 +1,67 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::path::Path;
+
+use tonic::metadata::AsciiMetadataValue;
+use tonic::service::interceptor::InterceptedService;
+
+use crate::storage::{OpenRepositoryError, OpenRepositoryResult};
+
+/// The channel used by all grpc clients of an rpc repository
+pub(super) type Channel = InterceptedService<tonic::transport::Channel, Credentials>;
+
+/// The credentials presented to an spfs server with each request
+#[derive(Clone, Debug, Default)]
+pub struct Credentials {
+    authorization: Option<AsciiMetadataValue>,
+}
+
+impl Credentials {
+    /// Present the given bearer token to the server
+    pub fn from_token(token: &str) -> Result<Self, tonic::metadata::errors::InvalidMetadataValue> {
+        let mut value: AsciiMetadataValue = format!("Bearer {}", token.trim()).parse()?;
+        value.set_sensitive(true);
+        Ok(Self {
+            authorization: Some(value),
+        })
+    }
+
+    /// Present the bearer token stored in the given file to the server
+    pub fn load<P: AsRef<Path>>(path: P) -> OpenRepositoryResult<Self> {
+        let path = path.as_ref();
+        let token = std::fs::read_to_string(path).map_err(|source| {
+            OpenRepositoryError::FailedToLoadCredentials {
+                path: path.to_owned(),
+                source,
+            }
+        })?;
+        Self::from_token(&token).map_err(|err| OpenRepositoryError::FailedToLoadCredentials {
+            path: path.to_owned(),
+            source: std::io::Error::new(std::io::ErrorKind::InvalidData, err),
+        })
+    }
+
+    /// The value of the authorization header for
+    /// http requests, if there is one
+    pub(super) fn authorization(&self) -> Option<&str> {
+        self.authorization
+            .as_ref()
+            .and_then(|value| value.to_str().ok())
+    }
+}
+
+impl tonic::service::Interceptor for Credentials {
+    fn call(
+        &mut self,
+        mut request: tonic::Request<()>,
+    ) -> Result<tonic::Request<()>, tonic::Status> {
+        if let Some(value) = &self.authorization {
+            request
+                .metadata_mut()
+                .insert("authorization", value.clone());
+        }
+        Ok(request)
+    }
+}
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 // https://github.com/spkenv/spk
 
+use std::collections::HashSet;
 use std::convert::TryInto;
 use std::pin::Pin;
 
@@ -9,7 +10,7 @@ use futures::{Stream, TryStreamExt};
 use proto::RpcResult;
 
 use crate::graph::{self, ObjectProto};
-use crate::{Result, encoding, proto};
+use crate::{Result, encoding, proto, storage};
 
 #[async_trait::async_trait]
 impl graph::DatabaseView for super::RpcRepository {
@@ -26,6 +27,33 @@ impl graph::DatabaseView for super::RpcRepository {
             .unwrap_or(false)
     }
 
+    async fn has_objects(&self, digests: &[encoding::Digest]) -> HashSet<encoding::Digest> {
+        let mut existing = HashSet::new();
+        for batch in digests.chunks(super::EXISTENCE_BATCH_SIZE) {
+            let request = proto::HasObjectsRequest {
+                digests: batch.iter().map(Into::into).collect(),
+            };
+            match self.db_client.clone().has_objects(request).await {
+                Ok(resp) => existing.extend(
+                    batch
+                        .iter()
+                        .zip(resp.into_inner().exists)
+                        .filter_map(|(digest, exists)| exists.then_some(*digest)),
+                ),
+                Err(err) => {
+                    // older servers do not support batched requests
+                    tracing::debug!("batched object check failed, checking individually: {err}");
+                    for digest in batch {
+                        if self.has_object(*digest).await {
+                            existing.insert(*digest);
+                        }
+                    }
+                }
+            }
+        }
+        existing
+    }
+
     async fn read_object(&self, digest: encoding::Digest) -> Result<graph::Object> {
         let request = proto::ReadObjectRequest {
             digest: Some(digest.into()),
@@ -115,3 +143,23 @@ impl graph::DatabaseExt for super::RpcRepository {
         Ok(())
     }
 }
+
+impl super::RpcRepository {
+    /// Identify everything reachable from the given root that is
+    /// missing from this repository.
+    ///
+    /// The graph is walked by the server in a single request,
+    /// see [`storage::find_missing_data`].
+    pub async fn find_missing_data(&self, root: encoding::Digest) -> Result<storage::MissingData> {
+        let request = proto::FindMissingRequest {
+            root: Some(root.into()),
+        };
+        self.db_client
+            .clone()
+            .find_missing(request)
+            .await?
+            .into_inner()
+            .to_result()?
+            .try_into()
+    }
+}
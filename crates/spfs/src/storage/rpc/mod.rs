@@ -4,9 +4,16 @@
 
 //! Storage implementation which is a client of the built-in spfs server
 
+mod credentials;
 mod database;
 mod payload;
 mod repository;
 mod tag;
+mod tls;
 
-pub use repository::{Config, Params, RpcRepository};
+pub use credentials::Credentials;
+pub use repository::{Config, Params, RpcRepository, TLS_SCHEME};
+
+/// The maximum number of digests sent in a single batched existence check,
+/// which keeps requests well under the default grpc message size limit
+const EXISTENCE_BATCH_SIZE: usize = 4096;
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 // https://github.com/spkenv/spk
 
+use std::collections::HashSet;
 use std::convert::TryInto;
 use std::pin::Pin;
 
@@ -27,6 +28,33 @@ impl storage::PayloadStorage for super::RpcRepository {
             .unwrap_or(false)
     }
 
+    async fn has_payloads(&self, digests: &[encoding::Digest]) -> HashSet<encoding::Digest> {
+        let mut existing = HashSet::new();
+        for batch in digests.chunks(super::EXISTENCE_BATCH_SIZE) {
+            let request = proto::HasPayloadsRequest {
+                digests: batch.iter().map(Into::into).collect(),
+            };
+            match self.payload_client.clone().has_payloads(request).await {
+                Ok(resp) => existing.extend(
+                    batch
+                        .iter()
+                        .zip(resp.into_inner().exists)
+                        .filter_map(|(digest, exists)| exists.then_some(*digest)),
+                ),
+                Err(err) => {
+                    // older servers do not support batched requests
+                    tracing::debug!("batched payload check failed, checking individually: {err}");
+                    for digest in batch {
+                        if self.has_payload(*digest).await {
+                            existing.insert(*digest);
+                        }
+                    }
+                }
+            }
+        }
+        existing
+    }
+
     fn iter_payload_digests(&self) -> Pin<Box<dyn Stream<Item = Result<encoding::Digest>> + Send>> {
         let request = proto::IterDigestsRequest {};
         let mut client = self.payload_client.clone();
@@ -142,7 +170,7 @@ impl storage::PayloadStorage for super::RpcRepository {
 impl super::RpcRepository {
     async fn send_http_request<B>(
         &self,
-        request: hyper::Request<B>,
+        mut request: hyper::Request<B>,
     ) -> Result<hyper::Response<hyper::body::Incoming>>
     where
         B: hyper::body::Body + Send + Sync + 'static,
@@ -155,13 +183,57 @@ impl super::RpcRepository {
                 request.uri()
             ))
         })?;
-        let port = request.uri().port_u16().unwrap_or(80);
-        let address = format!("{host}:{port}");
+        let use_tls = request.uri().scheme() == Some(&hyper::http::uri::Scheme::HTTPS);
+        let port = request
+            .uri()
+            .port_u16()
+            .unwrap_or(if use_tls { 443 } else { 80 });
+        let server_name = host.to_owned();
+        if let Some(authorization) = self.credentials.authorization() {
+            let mut value = hyper::http::HeaderValue::from_str(authorization)
+                .map_err(|err| Error::new(format!("invalid authorization header: {err}")))?;
+            value.set_sensitive(true);
+            request
+                .headers_mut()
+                .insert(hyper::http::header::AUTHORIZATION, value);
+        }
+        let address = format!("{server_name}:{port}");
         tracing::trace!("Connecting to remote repository at {address}");
         let stream = tokio::net::TcpStream::connect(address)
             .await
             .map_err(|err| Error::new(format!("failed to connect to remote repository: {err}")))?;
-        let io = hyper_util::rt::TokioIo::new(stream);
+        if !use_tls {
+            return self
+                .send_http_request_over(hyper_util::rt::TokioIo::new(stream), request)
+                .await;
+        }
+        let server_name = tokio_rustls::rustls::pki_types::ServerName::try_from(server_name)
+            .map_err(|err| Error::new(format!("invalid server name for tls: {err}")))?;
+        let stream = self
+            .tls
+            .connector()?
+            .connect(server_name, stream)
+            .await
+            .map_err(|err| {
+                Error::new(format!(
+                    "failed to establish tls connection with remote repository: {err}"
+                ))
+            })?;
+        self.send_http_request_over(hyper_util::rt::TokioIo::new(stream), request)
+            .await
+    }
+
+    async fn send_http_request_over<I, B>(
+        &self,
+        io: I,
+        request: hyper::Request<B>,
+    ) -> Result<hyper::Response<hyper::body::Incoming>>
+    where
+        I: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+        B: hyper::body::Body + Send + Sync + 'static,
+        B::Data: Send + Sync,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
         let (mut sender, conn) = self.http_client.handshake(io).await.map_err(|err| {
             Error::new(format!(
                 "Failed to establish connection with remote repository: {err}"
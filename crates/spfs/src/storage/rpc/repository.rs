@@ -3,9 +3,12 @@
 // https://github.com/spkenv/spk
 
 use std::borrow::Cow;
+use std::path::PathBuf;
 
 use storage::FromUrl;
 
+use super::credentials::{Channel, Credentials};
+use super::tls::ClientTls;
 use crate::config::ToAddress;
 use crate::proto::database_service_client::DatabaseServiceClient;
 use crate::proto::payload_service_client::PayloadServiceClient;
@@ -14,6 +17,9 @@ use crate::proto::tag_service_client::TagServiceClient;
 use crate::storage::{OpenRepositoryError, OpenRepositoryResult, TagNamespace, TagNamespaceBuf};
 use crate::{Result, proto, storage};
 
+/// The url scheme of rpc repositories that are served over tls
+pub const TLS_SCHEME: &str = "grpcs";
+
 /// Configures an rpc repository connection
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct Config {
@@ -45,6 +51,26 @@ pub struct Params {
 
     /// optional tag namespace to use when querying tags
     pub tag_namespace: Option<TagNamespaceBuf>,
+
+    /// A file containing the bearer token to present to the server
+    ///
+    /// Default is to not present any credentials
+    pub token_file: Option<PathBuf>,
+
+    /// A PEM file containing the certificate authorities that are
+    /// trusted to sign the server's certificate
+    ///
+    /// Default is to use the native certificate store of the system
+    pub ca_file: Option<PathBuf>,
+
+    /// A PEM file containing a client certificate to present
+    /// to servers that require mutual tls
+    ///
+    /// Must be given along with a key file
+    pub cert_file: Option<PathBuf>,
+
+    /// A PEM file containing the private key of the client certificate
+    pub key_file: Option<PathBuf>,
 }
 
 #[async_trait::async_trait]
@@ -78,11 +104,13 @@ impl ToAddress for Config {
 #[derive(Clone, Debug)]
 pub struct RpcRepository {
     address: url::Url,
-    pub(super) repo_client: RepositoryClient<tonic::transport::Channel>,
-    pub(super) tag_client: TagServiceClient<tonic::transport::Channel>,
-    pub(super) db_client: DatabaseServiceClient<tonic::transport::Channel>,
-    pub(super) payload_client: PayloadServiceClient<tonic::transport::Channel>,
+    pub(super) repo_client: RepositoryClient<Channel>,
+    pub(super) tag_client: TagServiceClient<Channel>,
+    pub(super) db_client: DatabaseServiceClient<Channel>,
+    pub(super) payload_client: PayloadServiceClient<Channel>,
     pub(super) http_client: hyper::client::conn::http1::Builder,
+    pub(super) credentials: Credentials,
+    pub(super) tls: ClientTls,
     /// the namespace to use for tag resolution. If set, then this is treated
     /// as "chroot" of the real tag root.
     tag_namespace: Option<TagNamespaceBuf>,
@@ -108,22 +136,58 @@ impl RpcRepository {
 
     /// Create a new rpc repository client for the given configuration
     pub async fn new(config: Config) -> OpenRepositoryResult<Self> {
-        let mut endpoint = tonic::transport::Endpoint::from_shared(config.address.to_string())
-            .map_err(|source| OpenRepositoryError::InvalidTransportAddress {
-                address: config.address.to_string(),
-                source,
+        let invalid_tls = |err: crate::Error| OpenRepositoryError::InvalidTlsConfig {
+            source: Box::new(err),
+        };
+        let tls = ClientTls::new(&config.params).map_err(invalid_tls)?;
+        let tls_host = match config.address.scheme() {
+            TLS_SCHEME => Some(
+                config
+                    .address
+                    .host_str()
+                    .filter(|host| !host.is_empty())
+                    .ok_or_else(|| OpenRepositoryError::MissingHost {
+                        address: config.address.to_string(),
+                    })?,
+            ),
+            _ => None,
+        };
+        let transport_address = match tls_host {
+            // tonic only enables tls for https uris
+            Some(host) => format!("https://{host}:{}", config.address.port().unwrap_or(443)),
+            None => config.address.to_string(),
+        };
+        let mut endpoint =
+            tonic::transport::Endpoint::from_shared(transport_address).map_err(|source| {
+                OpenRepositoryError::InvalidTransportAddress {
+                    address: config.address.to_string(),
+                    source,
+                }
             })?;
+        if let Some(host) = tls_host {
+            endpoint = endpoint
+                .tls_config(tls.grpc_config(host).map_err(invalid_tls)?)
+                .map_err(|err| invalid_tls(crate::Error::String(err.to_string())))?;
+        }
         if let Some(ms) = config.params.timeout_ms {
             endpoint = endpoint.timeout(std::time::Duration::from_millis(ms));
         }
+        let credentials = match &config.params.token_file {
+            Some(path) => Credentials::load(path)?,
+            None => Credentials::default(),
+        };
         let channel = match config.params.lazy {
             true => endpoint.connect_lazy(),
             false => endpoint.connect().await?,
         };
-        let mut repo_client = RepositoryClient::new(channel.clone());
-        let mut tag_client = TagServiceClient::new(channel.clone());
-        let mut db_client = DatabaseServiceClient::new(channel.clone());
-        let mut payload_client = PayloadServiceClient::new(channel);
+        let mut repo_client =
+            RepositoryClient::with_interceptor(channel.clone(), credentials.clone());
+        let mut tag_client =
+            TagServiceClient::with_interceptor(channel.clone(), credentials.clone());
+        let mut db_client =
+            DatabaseServiceClient::with_interceptor(channel.clone(), credentials.clone());
+        let mut payload_client =
+            PayloadServiceClient::with_interceptor(channel, credentials.clone());
         if let Some(max) = config.params.max_decode_message_size_bytes {
             repo_client = repo_client.max_decoding_message_size(max);
             tag_client = tag_client.max_decoding_message_size(max);
@@ -143,6 +207,8 @@ impl RpcRepository {
             db_client,
             payload_client,
             http_client: hyper::client::conn::http1::Builder::new(),
+            credentials,
+            tls,
             tag_namespace: config.params.tag_namespace,
         })
     }
@@ -195,7 +195,7 @@ impl storage::TagStorageMut for super::RpcRepository {
 }
 
 async fn read_tag(
-    mut client: TagServiceClient<tonic::transport::Channel>,
+    mut client: TagServiceClient<super::credentials::Channel>,
     tag_namespace: Option<&TagNamespace>,
     tag: &tracking::TagSpec,
 ) -> Result<Pin<Box<dyn Stream<Item = Result<tracking::Tag>> + Send>>> {
@@ -0,0 +1,73 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use once_cell::sync::OnceCell;
+use tokio_rustls::rustls;
+use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+
+use super::Params;
+use crate::{Error, Result};
+
+/// The tls settings used when connecting to an spfs server
+/// over `grpcs` or downloading payloads over `https`
+#[derive(Clone, Debug, Default)]
+pub(super) struct ClientTls {
+    ca_file: Option<PathBuf>,
+    cert_file: Option<PathBuf>,
+    key_file: Option<PathBuf>,
+    /// loaded on first use, since many clients never need it
+    /// and the native certificate store can be slow to read
+    config: Arc<OnceCell<Arc<rustls::ClientConfig>>>,
+}
+
+impl ClientTls {
+    pub fn new(params: &Params) -> Result<Self> {
+        if params.cert_file.is_some() != params.key_file.is_some() {
+            return Err(Error::String(
+                "A client certificate and key must be provided together".into(),
+            ));
+        }
+        Ok(Self {
+            ca_file: params.ca_file.clone(),
+            cert_file: params.cert_file.clone(),
+            key_file: params.key_file.clone(),
+            config: Default::default(),
+        })
+    }
+
+    fn identity(&self) -> Option<(&Path, &Path)> {
+        self.cert_file.as_deref().zip(self.key_file.as_deref())
+    }
+
+    /// The tls configuration for a grpc channel to the given host
+    pub fn grpc_config(&self, domain: &str) -> Result<ClientTlsConfig> {
+        let mut config = ClientTlsConfig::new().domain_name(domain);
+        config = match &self.ca_file {
+            Some(path) => config.ca_certificate(Certificate::from_pem(crate::tls::read_pem(
+                path,
+                "read of tls certificate authority",
+            )?)),
+            None => config.with_native_roots(),
+        };
+        if let Some((cert_file, key_file)) = self.identity() {
+            let cert = crate::tls::read_pem(cert_file, "read of tls client certificate")?;
+            let key = crate::tls::read_pem(key_file, "read of tls client key")?;
+            config = config.identity(Identity::from_pem(cert, key));
+        }
+        Ok(config)
+    }
+
+    /// A connector that can be used to wrap outgoing http connections
+    pub fn connector(&self) -> Result<tokio_rustls::TlsConnector> {
+        let config = self.config.get_or_try_init(|| {
+            let mut config = crate::tls::client_config(self.ca_file.as_deref(), self.identity())?;
+            config.alpn_protocols = vec![b"http/1.1".to_vec()];
+            Result::Ok(Arc::new(config))
+        })?;
+        Ok(tokio_rustls::TlsConnector::from(Arc::clone(config)))
+    }
+}
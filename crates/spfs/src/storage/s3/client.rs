@@ -0,0 +1,648 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+//! A minimal client for the subset of the S3 REST api used by spfs.
+//!
+//! Requests are made path-style (`<endpoint>/<bucket>/<key>`) and are
+//! signed using AWS signature version 4 when credentials are available.
+
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{Stream, TryStreamExt};
+use http_body_util::BodyExt;
+use hyper::client::conn::http1::SendRequest;
+use hyper::http::{HeaderValue, Method, StatusCode, header};
+use once_cell::sync::OnceCell;
+use tokio_rustls::rustls;
+
+use crate::tracking::BlobRead;
+use crate::{Error, Result};
+
+/// The body type used for all requests made by the client
+type Body = http_body_util::combinators::BoxBody<Bytes, std::io::Error>;
+
+/// The data sent along with a request
+enum Payload {
+    Empty,
+    /// Data that is held in memory and included in the request signature
+    Bytes(Bytes),
+    /// Streamed data of a known size that is not included in the request signature
+    Unsigned(Body, u64),
+}
+
+/// A single request to be made against the bucket
+struct Request<'a> {
+    method: Method,
+    key: &'a str,
+    query: Vec<(&'a str, &'a str)>,
+    headers: Vec<(header::HeaderName, String)>,
+    payload: Payload,
+}
+
+impl<'a> Request<'a> {
+    fn new(method: Method, key: &'a str) -> Self {
+        Self {
+            method,
+            key,
+            query: Vec::new(),
+            headers: Vec::new(),
+            payload: Payload::Empty,
+        }
+    }
+
+    fn with_query(mut self, name: &'a str, value: &'a str) -> Self {
+        self.query.push((name, value));
+        self
+    }
+
+    fn with_header(mut self, name: header::HeaderName, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    fn with_payload(mut self, payload: Payload) -> Self {
+        self.payload = payload;
+        self
+    }
+}
+
+/// The hex-encoded sha256 of an empty request body
+const EMPTY_PAYLOAD_SHA256: &str =
+    "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+/// Signals that the request payload is not included in the signature
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+const SIGNING_ALGORITHM: &str = "AWS4-HMAC-SHA256";
+const AMZ_DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const AMZ_CONTENT_SHA256: &str = "x-amz-content-sha256";
+const AMZ_DATE: &str = "x-amz-date";
+/// The most connections that are kept open for reuse between requests
+const MAX_IDLE_CONNECTIONS: usize = 16;
+
+/// Access keys used to sign requests
+#[derive(Clone)]
+pub(super) struct Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"<redacted>")
+            .finish()
+    }
+}
+
+/// The metadata of a single stored object
+#[derive(Debug)]
+pub(super) struct ObjectHead {
+    pub last_modified: DateTime<Utc>,
+}
+
+/// The full contents of a stored object
+#[derive(Debug)]
+pub(super) struct ObjectData {
+    pub data: Bytes,
+    /// The entity tag of the object, used for conditional updates
+    pub etag: Option<String>,
+}
+
+/// A condition that must hold for a write to be accepted
+#[derive(Debug)]
+pub(super) enum Precondition<'a> {
+    /// The object must not already exist
+    IfNoneMatch,
+    /// The object must exist with the given entity tag
+    IfMatch(&'a str),
+}
+
+/// An entry returned when listing a bucket
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum ListEntry {
+    /// The full key of a stored object
+    Object(String),
+    /// A common prefix of multiple objects, ending with the delimiter
+    Prefix(String),
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct S3Client {
+    endpoint: url::Url,
+    bucket: String,
+    region: String,
+    credentials: Option<Credentials>,
+    http_client: hyper::client::conn::http1::Builder,
+    /// connections that have finished their last request and
+    /// can be reused for the next one
+    idle: Arc<Mutex<Vec<SendRequest<Body>>>>,
+    ca_file: Option<PathBuf>,
+    /// loaded on first use, since the native certificate
+    /// store can be slow to read
+    tls_config: Arc<OnceCell<Arc<rustls::ClientConfig>>>,
+}
+
+impl S3Client {
+    pub fn new(
+        endpoint: url::Url,
+        bucket: String,
+        region: String,
+        credentials: Option<Credentials>,
+        ca_file: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            endpoint,
+            bucket,
+            region,
+            credentials,
+            http_client: hyper::client::conn::http1::Builder::new(),
+            idle: Default::default(),
+            ca_file,
+            tls_config: Default::default(),
+        }
+    }
+
+    /// The public url of the identified object
+    pub fn object_url(&self, key: &str) -> url::Url {
+        let mut url = self.endpoint.clone();
+        url.set_path(&self.canonical_uri(key));
+        url
+    }
+
+    /// Fetch the metadata of an object, if it exists
+    pub async fn head_object(&self, key: &str) -> Result<Option<ObjectHead>> {
+        let resp = self.send(Request::new(Method::HEAD, key)).await?;
+        match resp.status() {
+            StatusCode::NOT_FOUND => return Ok(None),
+            status if !status.is_success() => return Err(response_error(key, resp).await),
+            _ => {}
+        }
+        let last_modified = resp
+            .headers()
+            .get(header::LAST_MODIFIED)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| Error::String(format!("s3 response for {key} has no Last-Modified")))?;
+        let last_modified = DateTime::parse_from_rfc2822(last_modified)?.with_timezone(&Utc);
+        Ok(Some(ObjectHead { last_modified }))
+    }
+
+    /// Read the complete contents of an object, if it exists
+    pub async fn get_object(&self, key: &str) -> Result<Option<ObjectData>> {
+        let resp = self.send(Request::new(Method::GET, key)).await?;
+        match resp.status() {
+            StatusCode::NOT_FOUND => return Ok(None),
+            status if !status.is_success() => return Err(response_error(key, resp).await),
+            _ => {}
+        }
+        let etag = resp
+            .headers()
+            .get(header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+        let data = resp
+            .into_body()
+            .collect()
+            .await
+            .map_err(|err| Error::String(format!("Failed to read s3 object {key}: {err}")))?
+            .to_bytes();
+        Ok(Some(ObjectData { data, etag }))
+    }
+
+    /// Open a streaming reader over the contents of an object, if it exists
+    pub async fn open_object(&self, key: &str) -> Result<Option<Pin<Box<dyn BlobRead>>>> {
+        let resp = self.send(Request::new(Method::GET, key)).await?;
+        match resp.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if !status.is_success() => Err(response_error(key, resp).await),
+            _ => Ok(Some(body_to_reader(resp.into_body()))),
+        }
+    }
+
+    /// Write the given data to an object.
+    ///
+    /// Returns false if the write was rejected because
+    /// the given precondition did not hold.
+    pub async fn put_object(
+        &self,
+        key: &str,
+        data: Bytes,
+        precondition: Precondition<'_>,
+    ) -> Result<bool> {
+        let request = Request::new(Method::PUT, key).with_payload(Payload::Bytes(data));
+        let request = match precondition {
+            Precondition::IfNoneMatch => request.with_header(header::IF_NONE_MATCH, "*"),
+            Precondition::IfMatch(etag) => request.with_header(header::IF_MATCH, etag),
+        };
+        let resp = self.send(request).await?;
+        match resp.status() {
+            StatusCode::PRECONDITION_FAILED | StatusCode::CONFLICT => Ok(false),
+            status if !status.is_success() => Err(response_error(key, resp).await),
+            _ => Ok(true),
+        }
+    }
+
+    /// Write an object by streaming `size` bytes from the given reader
+    pub async fn put_object_stream<R>(&self, key: &str, reader: R, size: u64) -> Result<()>
+    where
+        R: tokio::io::AsyncRead + Send + Sync + 'static,
+    {
+        let stream = tokio_util::io::ReaderStream::new(reader);
+        let body = http_body_util::StreamBody::new(stream.map_ok(hyper::body::Frame::data)).boxed();
+        let request = Request::new(Method::PUT, key).with_payload(Payload::Unsigned(body, size));
+        let resp = self.send(request).await?;
+        if !resp.status().is_success() {
+            return Err(response_error(key, resp).await);
+        }
+        Ok(())
+    }
+
+    /// Remove an object, which is not an error if it does not exist
+    pub async fn delete_object(&self, key: &str) -> Result<()> {
+        let resp = self.send(Request::new(Method::DELETE, key)).await?;
+        match resp.status() {
+            StatusCode::NOT_FOUND => Ok(()),
+            status if !status.is_success() => Err(response_error(key, resp).await),
+            _ => Ok(()),
+        }
+    }
+
+    /// List all objects with the given prefix.
+    ///
+    /// When a delimiter is given, any keys that contain the delimiter
+    /// after the prefix are rolled up into a single [`ListEntry::Prefix`].
+    pub fn list_objects(
+        &self,
+        prefix: String,
+        delimiter: Option<&'static str>,
+    ) -> Pin<Box<dyn Stream<Item = Result<ListEntry>> + Send>> {
+        let client = self.clone();
+        Box::pin(async_stream::try_stream! {
+            let mut continuation_token: Option<String> = None;
+            loop {
+                let (entries, next_token) = client
+                    .list_objects_page(&prefix, delimiter, continuation_token.take())
+                    .await?;
+                for entry in entries {
+                    yield entry;
+                }
+                match next_token {
+                    Some(token) => continuation_token = Some(token),
+                    None => break,
+                }
+            }
+        })
+    }
+
+    /// Fetch a single page of listing results, returning the
+    /// continuation token for the next page, if any
+    async fn list_objects_page(
+        &self,
+        prefix: &str,
+        delimiter: Option<&str>,
+        continuation_token: Option<String>,
+    ) -> Result<(Vec<ListEntry>, Option<String>)> {
+        let mut request = Request::new(Method::GET, "")
+            .with_query("list-type", "2")
+            .with_query("prefix", prefix);
+        if let Some(delimiter) = delimiter {
+            request = request.with_query("delimiter", delimiter);
+        }
+        if let Some(token) = continuation_token.as_deref() {
+            request = request.with_query("continuation-token", token);
+        }
+        let resp = self.send(request).await?;
+        if !resp.status().is_success() {
+            return Err(response_error(prefix, resp).await);
+        }
+        let body = resp
+            .into_body()
+            .collect()
+            .await
+            .map_err(|err| Error::String(format!("Failed to read s3 listing: {err}")))?
+            .to_bytes();
+        let body = String::from_utf8_lossy(&body);
+        let mut entries = Vec::new();
+        for contents in xml_elements(&body, "Contents") {
+            for key in xml_elements(contents, "Key") {
+                entries.push(ListEntry::Object(xml_unescape(key)));
+            }
+        }
+        for common in xml_elements(&body, "CommonPrefixes") {
+            for prefix in xml_elements(common, "Prefix") {
+                entries.push(ListEntry::Prefix(xml_unescape(prefix)));
+            }
+        }
+        let truncated = xml_elements(&body, "IsTruncated").first() == Some(&"true");
+        let next_token = xml_elements(&body, "NextContinuationToken")
+            .first()
+            .map(|token| xml_unescape(token))
+            .filter(|_| truncated);
+        Ok((entries, next_token))
+    }
+
+    fn canonical_uri(&self, key: &str) -> String {
+        if key.is_empty() {
+            format!("/{}", uri_encode(&self.bucket, true))
+        } else {
+            format!(
+                "/{}/{}",
+                uri_encode(&self.bucket, true),
+                uri_encode(key, false)
+            )
+        }
+    }
+
+    async fn send(&self, request: Request<'_>) -> Result<hyper::Response<hyper::body::Incoming>> {
+        let Request {
+            method,
+            key,
+            query,
+            mut headers,
+            payload,
+        } = request;
+        let (body, payload_hash) = match payload {
+            Payload::Empty => (
+                http_body_util::Empty::new()
+                    .map_err(|never| match never {})
+                    .boxed(),
+                EMPTY_PAYLOAD_SHA256.to_string(),
+            ),
+            Payload::Bytes(data) => {
+                let hash = sha256_hex(&data);
+                headers.push((header::CONTENT_LENGTH, data.len().to_string()));
+                let body = http_body_util::Full::new(data)
+                    .map_err(|never| match never {})
+                    .boxed();
+                (body, hash)
+            }
+            Payload::Unsigned(body, size) => {
+                headers.push((header::CONTENT_LENGTH, size.to_string()));
+                (body, UNSIGNED_PAYLOAD.to_string())
+            }
+        };
+        let host = self
+            .endpoint
+            .host_str()
+            .ok_or_else(|| Error::String(format!("s3 endpoint has no host: {}", self.endpoint)))?;
+        let scheme = self.endpoint.scheme();
+        let host_header = match self.endpoint.port() {
+            Some(port) => format!("{host}:{port}"),
+            None => host.to_string(),
+        };
+
+        let mut query = query
+            .iter()
+            .map(|(k, v)| (uri_encode(k, true), uri_encode(v, true)))
+            .collect::<Vec<_>>();
+        query.sort();
+        let canonical_query = query
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<_>>()
+            .join("&");
+        let canonical_uri = self.canonical_uri(key);
+        let uri = match canonical_query.is_empty() {
+            true => format!("{scheme}://{host_header}{canonical_uri}"),
+            false => format!("{scheme}://{host_header}{canonical_uri}?{canonical_query}"),
+        };
+
+        let now = Utc::now();
+        let amz_date = now.format(AMZ_DATE_FORMAT).to_string();
+        let mut builder = hyper::Request::builder()
+            .method(method.clone())
+            .uri(&uri)
+            .header(header::HOST, &host_header)
+            .header(AMZ_CONTENT_SHA256, &payload_hash)
+            .header(AMZ_DATE, &amz_date);
+        for (name, value) in headers {
+            builder = builder.header(name, value);
+        }
+        if let Some(credentials) = &self.credentials {
+            let canonical_headers = format!(
+                "host:{host_header}\n{AMZ_CONTENT_SHA256}:{payload_hash}\n{AMZ_DATE}:{amz_date}\n"
+            );
+            let signed_headers = format!("host;{AMZ_CONTENT_SHA256};{AMZ_DATE}");
+            let canonical_request = format!(
+                "{method}\n{canonical_uri}\n{canonical_query}\n{canonical_headers}\n{signed_headers}\n{payload_hash}"
+            );
+            let date = now.format("%Y%m%d").to_string();
+            let scope = format!("{date}/{}/s3/aws4_request", self.region);
+            let string_to_sign = format!(
+                "{SIGNING_ALGORITHM}\n{amz_date}\n{scope}\n{}",
+                sha256_hex(canonical_request.as_bytes())
+            );
+            let secret = format!("AWS4{}", credentials.secret_access_key);
+            let key = hmac_sha256(secret.as_bytes(), date.as_bytes());
+            let key = hmac_sha256(key.as_ref(), self.region.as_bytes());
+            let key = hmac_sha256(key.as_ref(), b"s3");
+            let key = hmac_sha256(key.as_ref(), b"aws4_request");
+            let signature = data_encoding::HEXLOWER
+                .encode(hmac_sha256(key.as_ref(), string_to_sign.as_bytes()).as_ref());
+            let authorization = format!(
+                "{SIGNING_ALGORITHM} Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+                credentials.access_key_id
+            );
+            builder = builder.header(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&authorization).map_err(|err| {
+                    Error::String(format!("Invalid s3 authorization header: {err}"))
+                })?,
+            );
+        }
+        let mut request = builder
+            .body(body)
+            .map_err(|err| Error::String(format!("Failed to build s3 request: {err:?}")))?;
+
+        tracing::trace!(%method, %uri, "sending s3 request");
+        if let Some(mut sender) = self.take_idle_connection() {
+            match sender.try_send_request(request).await {
+                Ok(resp) => {
+                    self.release_connection(sender);
+                    return Ok(resp);
+                }
+                Err(mut err) => match err.take_message() {
+                    // the connection was closed by the service before the
+                    // request was sent, so it is safe to send it again
+                    Some(unsent) => request = unsent,
+                    None => {
+                        return Err(Error::String(format!(
+                            "Failed to send s3 request: {}",
+                            err.into_error()
+                        )));
+                    }
+                },
+            }
+        }
+        let mut sender = self.connect(host).await?;
+        let resp = sender
+            .send_request(request)
+            .await
+            .map_err(|err| Error::String(format!("Failed to send s3 request: {err}")))?;
+        self.release_connection(sender);
+        Ok(resp)
+    }
+
+    /// Take an open connection that is ready for another request, if any
+    fn take_idle_connection(&self) -> Option<SendRequest<Body>> {
+        let mut idle = self
+            .idle
+            .lock()
+            .expect("s3 connection pool lock is not poisoned");
+        idle.retain(|sender| !sender.is_closed());
+        let index = idle.iter().position(SendRequest::is_ready)?;
+        Some(idle.swap_remove(index))
+    }
+
+    /// Keep a connection open for reuse once its current response
+    /// has been read, which hyper tracks through the sender
+    fn release_connection(&self, sender: SendRequest<Body>) {
+        let mut idle = self
+            .idle
+            .lock()
+            .expect("s3 connection pool lock is not poisoned");
+        idle.retain(|sender| !sender.is_closed());
+        if idle.len() < MAX_IDLE_CONNECTIONS {
+            idle.push(sender);
+        }
+    }
+
+    /// Open a new connection to the endpoint, using tls for https endpoints
+    async fn connect(&self, host: &str) -> Result<SendRequest<Body>> {
+        let use_tls = self.endpoint.scheme() == "https";
+        let port = self
+            .endpoint
+            .port_or_known_default()
+            .unwrap_or(if use_tls { 443 } else { 80 });
+        let stream = tokio::net::TcpStream::connect(format!("{host}:{port}"))
+            .await
+            .map_err(|err| Error::String(format!("Failed to connect to s3 endpoint: {err}")))?;
+        if !use_tls {
+            return self.handshake(hyper_util::rt::TokioIo::new(stream)).await;
+        }
+        let server_name = rustls::pki_types::ServerName::try_from(host.to_owned())
+            .map_err(|err| Error::String(format!("Invalid server name for tls: {err}")))?;
+        let stream = self
+            .tls_connector()?
+            .connect(server_name, stream)
+            .await
+            .map_err(|err| {
+                Error::String(format!(
+                    "Failed to establish tls connection with s3 endpoint: {err}"
+                ))
+            })?;
+        self.handshake(hyper_util::rt::TokioIo::new(stream)).await
+    }
+
+    async fn handshake<I>(&self, io: I) -> Result<SendRequest<Body>>
+    where
+        I: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+    {
+        let (sender, conn) = self.http_client.handshake(io).await.map_err(|err| {
+            Error::String(format!(
+                "Failed to establish connection with s3 endpoint: {err}"
+            ))
+        })?;
+        tokio::spawn(conn);
+        Ok(sender)
+    }
+
+    fn tls_connector(&self) -> Result<tokio_rustls::TlsConnector> {
+        let config = self.tls_config.get_or_try_init(|| {
+            let mut config = crate::tls::client_config(self.ca_file.as_deref(), None)?;
+            config.alpn_protocols = vec![b"http/1.1".to_vec()];
+            Result::Ok(Arc::new(config))
+        })?;
+        Ok(tokio_rustls::TlsConnector::from(Arc::clone(config)))
+    }
+}
+
+/// Create an error from an unsuccessful response, including
+/// the error code and message returned by the service, if any
+async fn response_error(key: &str, resp: hyper::Response<hyper::body::Incoming>) -> Error {
+    let status = resp.status();
+    let body = match resp.into_body().collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(_) => Bytes::new(),
+    };
+    let body = String::from_utf8_lossy(&body);
+    let code = xml_elements(&body, "Code").first().map(|c| xml_unescape(c));
+    let message = xml_elements(&body, "Message")
+        .first()
+        .map(|m| xml_unescape(m));
+    match (code, message) {
+        (Some(code), Some(message)) => {
+            Error::String(format!("s3 request for '{key}' failed: {code}: {message}"))
+        }
+        (Some(code), None) => Error::String(format!("s3 request for '{key}' failed: {code}")),
+        _ => Error::String(format!("s3 request for '{key}' failed: {status}")),
+    }
+}
+
+fn body_to_reader(body: hyper::body::Incoming) -> Pin<Box<dyn BlobRead>> {
+    // the stream must return io errors in order to be converted to a reader
+    let mapped_stream = http_body_util::BodyDataStream::new(body)
+        .map_err(|err| std::io::Error::other(format!("Failed to read s3 object: {err:?}")))
+        .into_async_read();
+    let stream_reader = tokio_util::compat::FuturesAsyncReadCompatExt::compat(mapped_stream);
+    Box::pin(tokio::io::BufReader::new(stream_reader))
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    data_encoding::HEXLOWER.encode(ring::digest::digest(&ring::digest::SHA256, data).as_ref())
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> ring::hmac::Tag {
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, key);
+    ring::hmac::sign(&key, data)
+}
+
+/// Percent-encode a string as required for canonical s3 requests.
+///
+/// All characters other than the unreserved set are encoded, with
+/// the exception of '/' when `encode_slash` is false.
+pub(super) fn uri_encode(input: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+/// Find the contents of all elements with the given name.
+///
+/// This is not a general xml parser, but is sufficient for the
+/// flat and predictable documents returned by the s3 api.
+pub(super) fn xml_elements<'a>(doc: &'a str, name: &str) -> Vec<&'a str> {
+    let open = format!("<{name}>");
+    let close = format!("</{name}>");
+    let mut found = Vec::new();
+    let mut remaining = doc;
+    while let Some(start) = remaining.find(&open) {
+        let after_open = &remaining[start + open.len()..];
+        let Some(end) = after_open.find(&close) else {
+            break;
+        };
+        found.push(&after_open[..end]);
+        remaining = &after_open[end + close.len()..];
+    }
+    found
+}
+
+pub(super) fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
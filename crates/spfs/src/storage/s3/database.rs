@@ -0,0 +1,131 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::pin::Pin;
+
+use chrono::{DateTime, Utc};
+use encoding::prelude::*;
+use futures::{Stream, StreamExt};
+
+use super::client::{ListEntry, Precondition};
+use crate::graph::{self, ObjectProto};
+use crate::{Error, Result, encoding};
+
+#[async_trait::async_trait]
+impl graph::DatabaseView for super::S3Repository {
+    async fn has_object(&self, digest: encoding::Digest) -> bool {
+        matches!(
+            self.client.head_object(&self.object_key(&digest)).await,
+            Ok(Some(_))
+        )
+    }
+
+    async fn read_object(&self, digest: encoding::Digest) -> Result<graph::Object> {
+        match self.client.get_object(&self.object_key(&digest)).await? {
+            Some(object) => graph::Object::new(object.data),
+            None => Err(Error::UnknownObject(digest)),
+        }
+    }
+
+    fn find_digests(
+        &self,
+        search_criteria: graph::DigestSearchCriteria,
+    ) -> Pin<Box<dyn Stream<Item = Result<encoding::Digest>> + Send>> {
+        let objects_prefix = self.objects_prefix();
+        let (list_prefix, partial) = match search_criteria {
+            graph::DigestSearchCriteria::All => (objects_prefix.clone(), None),
+            graph::DigestSearchCriteria::StartsWith(partial) => (
+                list_prefix_for_partial(&objects_prefix, &partial),
+                Some(partial),
+            ),
+        };
+        let stream = self
+            .client
+            .list_objects(list_prefix, None)
+            .filter_map(move |entry| {
+                let result = match entry {
+                    Ok(ListEntry::Object(key)) => {
+                        digest_from_key(&key, &objects_prefix).map(|digest| match &partial {
+                            Some(partial) if !digest.as_bytes().starts_with(partial) => None,
+                            _ => Some(digest),
+                        })
+                    }
+                    Ok(ListEntry::Prefix(_)) => Ok(None),
+                    Err(err) => Err(err),
+                };
+                futures::future::ready(result.transpose())
+            });
+        Box::pin(stream)
+    }
+
+    fn iter_objects(&self) -> graph::DatabaseIterator<'_> {
+        graph::DatabaseIterator::new(self)
+    }
+
+    fn walk_objects<'db>(&'db self, root: &encoding::Digest) -> graph::DatabaseWalker<'db> {
+        graph::DatabaseWalker::new(self, *root)
+    }
+}
+
+#[async_trait::async_trait]
+impl graph::Database for super::S3Repository {
+    async fn remove_object(&self, digest: encoding::Digest) -> Result<()> {
+        self.client.delete_object(&self.object_key(&digest)).await?;
+        tracing::trace!(%digest, "removed object from db");
+        Ok(())
+    }
+
+    async fn remove_object_if_older_than(
+        &self,
+        older_than: DateTime<Utc>,
+        digest: encoding::Digest,
+    ) -> Result<bool> {
+        let key = self.object_key(&digest);
+        let Some(head) = self.client.head_object(&key).await? else {
+            return Err(Error::UnknownObject(digest));
+        };
+        if head.last_modified >= older_than {
+            return Ok(false);
+        }
+        self.client.delete_object(&key).await?;
+        Ok(true)
+    }
+}
+
+#[async_trait::async_trait]
+impl graph::DatabaseExt for super::S3Repository {
+    async fn write_object<T: ObjectProto>(&self, obj: &graph::FlatObject<T>) -> Result<()> {
+        let digest = obj.digest()?;
+        let key = self.object_key(&digest);
+        let mut encoded = Vec::new();
+        obj.encode(&mut encoded)?;
+        // objects are immutable, so a rejected write simply
+        // means that this object has already been stored
+        if !self
+            .client
+            .put_object(&key, encoded.into(), Precondition::IfNoneMatch)
+            .await?
+        {
+            tracing::trace!(%digest, kind=%std::any::type_name::<T>(), "object already exists");
+        }
+        Ok(())
+    }
+}
+
+/// The longest key prefix that all digests starting
+/// with the given partial digest must share.
+pub(super) fn list_prefix_for_partial(prefix: &str, partial: &encoding::PartialDigest) -> String {
+    let encoded = partial.to_string();
+    // base 32 encodes 5 bits per character, and the last character
+    // may be partially filled and so cannot be trusted to match
+    let complete_chars = (partial.len() * 8 / 5).min(encoded.len());
+    format!("{prefix}{}", &encoded[..complete_chars])
+}
+
+/// Parse the digest from the key of an object or payload
+pub(super) fn digest_from_key(key: &str, prefix: &str) -> Result<encoding::Digest> {
+    let name = key.strip_prefix(prefix).unwrap_or(key);
+    encoding::parse_digest(name)
+        .map_err(|err| Error::String(format!("Invalid digest in s3 key '{key}': {err}")))
+}
@@ -0,0 +1,13 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+//! Storage implementation which uses an s3-compatible object store
+
+mod client;
+mod database;
+mod payload;
+mod repository;
+mod tag;
+
+pub use repository::{Config, Params, S3Repository};
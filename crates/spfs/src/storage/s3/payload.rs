@@ -0,0 +1,113 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::pin::Pin;
+
+use futures::{Stream, StreamExt};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+use super::client::ListEntry;
+use super::database::digest_from_key;
+use crate::chunking::ChunkedBlobReader;
+use crate::graph::object::Enum;
+use crate::storage::prelude::*;
+use crate::tracking::BlobRead;
+use crate::{Error, Result, encoding, graph};
+
+#[async_trait::async_trait]
+impl PayloadStorage for super::S3Repository {
+    async fn has_payload(&self, digest: encoding::Digest) -> bool {
+        matches!(
+            self.client.head_object(&self.payload_key(&digest)).await,
+            Ok(Some(_))
+        )
+    }
+
+    fn iter_payload_digests(&self) -> Pin<Box<dyn Stream<Item = Result<encoding::Digest>> + Send>> {
+        let payloads_prefix = self.payloads_prefix();
+        let stream = self
+            .client
+            .list_objects(payloads_prefix.clone(), None)
+            .filter_map(move |entry| {
+                let result = match entry {
+                    Ok(ListEntry::Object(key)) => Some(digest_from_key(&key, &payloads_prefix)),
+                    Ok(ListEntry::Prefix(_)) => None,
+                    Err(err) => Some(Err(err)),
+                };
+                futures::future::ready(result)
+            });
+        Box::pin(stream)
+    }
+
+    async unsafe fn write_data(
+        &self,
+        mut reader: Pin<Box<dyn BlobRead>>,
+    ) -> Result<(encoding::Digest, u64)> {
+        // the digest of the payload must be known before it can be
+        // uploaded, so the data is first spooled to a local file
+        let working_file = tempfile::tempfile().map_err(|err| {
+            Error::StorageWriteError("create spool file for s3 payload", "".into(), err)
+        })?;
+        let mut writer = tokio::io::BufWriter::new(tokio::fs::File::from_std(working_file));
+        let mut hasher = encoding::Hasher::with_target(&mut writer);
+        let size = tokio::io::copy(&mut reader, &mut hasher)
+            .await
+            .map_err(|err| Error::StorageWriteError("copy to s3 spool file", "".into(), err))?;
+        hasher
+            .flush()
+            .await
+            .map_err(|err| Error::StorageWriteError("flush on s3 spool file", "".into(), err))?;
+        let digest = hasher.digest();
+
+        let key = self.payload_key(&digest);
+        if self.client.head_object(&key).await?.is_some() {
+            tracing::trace!(%digest, "payload already exists");
+            return Ok((digest, size));
+        }
+        let mut file = writer.into_inner();
+        file.rewind()
+            .await
+            .map_err(|err| Error::StorageReadError("rewind on s3 spool file", "".into(), err))?;
+        self.client.put_object_stream(&key, file, size).await?;
+        Ok((digest, size))
+    }
+
+    async fn open_payload(
+        &self,
+        digest: encoding::Digest,
+    ) -> Result<(Pin<Box<dyn BlobRead>>, std::path::PathBuf)> {
+        let key = self.payload_key(&digest);
+        match self.client.open_object(&key).await? {
+            Some(reader) => Ok((reader, self.client.object_url(&key).to_string().into())),
+            None => {
+                // Chunked blobs are read directly from their chunks, otherwise
+                // return an error specific to this situation, whether the
+                // blob is really unknown or just the payload is missing.
+                match self.read_object(digest).await.map(|o| o.into_enum()) {
+                    Ok(Enum::Blob(blob)) => Err(Error::ObjectMissingPayload(blob.into(), digest)),
+                    Ok(Enum::ChunkedBlob(blob)) => {
+                        let reader = ChunkedBlobReader::from_repo(&blob, self.clone());
+                        Ok((
+                            Box::pin(reader),
+                            self.client.object_url(&key).to_string().into(),
+                        ))
+                    }
+                    Ok(_) => Err(Error::NotCorrectKind {
+                        desired: graph::ObjectKind::Blob,
+                        digest,
+                    }),
+                    Err(_) => Err(Error::UnknownObject(digest)),
+                }
+            }
+        }
+    }
+
+    async fn remove_payload(&self, digest: encoding::Digest) -> Result<()> {
+        let key = self.payload_key(&digest);
+        if self.client.head_object(&key).await?.is_none() {
+            return Err(Error::UnknownObject(digest));
+        }
+        self.client.delete_object(&key).await
+    }
+}
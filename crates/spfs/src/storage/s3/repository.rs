@@ -0,0 +1,240 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+use super::client::{Credentials, S3Client};
+use crate::config::ToAddress;
+use crate::storage::{OpenRepositoryError, OpenRepositoryResult, TagNamespace, TagNamespaceBuf};
+use crate::{Result, encoding, storage};
+
+const DEFAULT_REGION: &str = "us-east-1";
+
+/// Configures a connection to an s3 repository
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct Config {
+    /// The location of the repository, in the form `s3://<bucket>/<prefix>`
+    pub address: url::Url,
+    #[serde(flatten)]
+    pub params: Params,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize, Default)]
+pub struct Params {
+    /// The base url of the s3-compatible service
+    ///
+    /// Defaults to the value of `AWS_ENDPOINT_URL_S3` or `AWS_ENDPOINT_URL`
+    pub endpoint: Option<url::Url>,
+
+    /// The region used when signing requests
+    ///
+    /// Defaults to the value of `AWS_REGION`, or `us-east-1`
+    pub region: Option<String>,
+
+    /// The access key used to sign requests
+    ///
+    /// Defaults to the value of `AWS_ACCESS_KEY_ID`. Requests
+    /// are sent unsigned if no access key is available.
+    pub access_key_id: Option<String>,
+
+    /// The secret key used to sign requests
+    ///
+    /// Defaults to the value of `AWS_SECRET_ACCESS_KEY`. This value
+    /// is never included when the config is written back out.
+    #[serde(default, skip_serializing)]
+    pub secret_access_key: Option<String>,
+
+    /// A PEM file containing the certificate authorities that are
+    /// trusted to sign the certificate of an https endpoint
+    ///
+    /// Default is to use the native certificate store of the system
+    pub ca_file: Option<PathBuf>,
+
+    /// optional tag namespace to use when querying tags
+    pub tag_namespace: Option<TagNamespaceBuf>,
+}
+
+#[async_trait::async_trait]
+impl storage::FromUrl for Config {
+    async fn from_url(url: &url::Url) -> OpenRepositoryResult<Self> {
+        let mut address = url.clone();
+        let params = if let Some(qs) = address.query() {
+            serde_qs::from_str(qs)
+                .map_err(|source| OpenRepositoryError::invalid_query(url, source))?
+        } else {
+            Params::default()
+        };
+        address.set_query(None);
+        Ok(Self { address, params })
+    }
+}
+
+impl ToAddress for Config {
+    fn to_address(&self) -> Result<url::Url> {
+        let query = serde_qs::to_string(&self.params).map_err(|err| {
+            crate::Error::String(format!(
+                "S3 repo parameters do not create a valid url: {err:?}"
+            ))
+        })?;
+        let mut address = self.address.clone();
+        match query.as_str() {
+            "" => address.set_query(None),
+            query => address.set_query(Some(query)),
+        }
+        Ok(address)
+    }
+}
+
+/// A repository stored in a bucket of an s3-compatible object store.
+///
+/// Objects, payloads and tags are each stored as individual keys
+/// under the prefix of the repository, and are accessed directly
+/// without the need for any intermediate server.
+#[derive(Clone, Debug)]
+pub struct S3Repository {
+    address: url::Url,
+    pub(super) client: S3Client,
+    /// The prefix of all keys in this repository, empty
+    /// or ending with a forward slash
+    prefix: String,
+    /// the namespace to use for tag resolution. If set, then this is treated
+    /// as "chroot" of the real tag root.
+    tag_namespace: Option<TagNamespaceBuf>,
+}
+
+#[async_trait::async_trait]
+impl storage::FromConfig for S3Repository {
+    type Config = Config;
+
+    async fn from_config(config: Self::Config) -> OpenRepositoryResult<Self> {
+        Self::new(config).await
+    }
+}
+
+impl S3Repository {
+    /// Create a new s3 repository client for the given configuration
+    pub async fn new(config: Config) -> OpenRepositoryResult<Self> {
+        let invalid = |reason: &str| OpenRepositoryError::InvalidS3Address {
+            address: config.address.to_string(),
+            reason: reason.to_string(),
+        };
+        let bucket = match config.address.host_str() {
+            Some(bucket) if !bucket.is_empty() => bucket.to_string(),
+            _ => return Err(invalid("a bucket name is required")),
+        };
+        let mut prefix = config.address.path().trim_matches('/').to_string();
+        if !prefix.is_empty() {
+            prefix.push('/');
+        }
+
+        let endpoint = match config.params.endpoint.clone() {
+            Some(endpoint) => endpoint,
+            None => std::env::var("AWS_ENDPOINT_URL_S3")
+                .or_else(|_| std::env::var("AWS_ENDPOINT_URL"))
+                .map_err(|_| invalid("an endpoint is required"))?
+                .parse()
+                .map_err(|_| invalid("the configured endpoint is not a valid url"))?,
+        };
+        if !matches!(endpoint.scheme(), "http" | "https") {
+            return Err(invalid("the endpoint must be an http or https url"));
+        }
+        let region = config
+            .params
+            .region
+            .clone()
+            .or_else(|| std::env::var("AWS_REGION").ok())
+            .unwrap_or_else(|| DEFAULT_REGION.to_string());
+        let access_key_id = config
+            .params
+            .access_key_id
+            .clone()
+            .or_else(|| std::env::var("AWS_ACCESS_KEY_ID").ok());
+        let secret_access_key = config
+            .params
+            .secret_access_key
+            .clone()
+            .or_else(|| std::env::var("AWS_SECRET_ACCESS_KEY").ok());
+        let credentials = match (access_key_id, secret_access_key) {
+            (Some(access_key_id), Some(secret_access_key)) => Some(Credentials {
+                access_key_id,
+                secret_access_key,
+            }),
+            (None, None) => None,
+            _ => return Err(invalid("both or neither of the s3 access keys must be set")),
+        };
+
+        Ok(Self {
+            address: config.to_address().expect("an internally valid config"),
+            client: S3Client::new(
+                endpoint,
+                bucket,
+                region,
+                credentials,
+                config.params.ca_file.clone(),
+            ),
+            prefix,
+            tag_namespace: config.params.tag_namespace,
+        })
+    }
+
+    /// The namespace to use for tag resolution.
+    pub fn tag_namespace(&self) -> Option<&TagNamespace> {
+        self.tag_namespace.as_deref()
+    }
+
+    /// Set the namespace to use for tag resolution.
+    ///
+    /// Returns the previous namespace, if any.
+    pub fn set_tag_namespace(
+        &mut self,
+        tag_namespace: Option<TagNamespaceBuf>,
+    ) -> Option<TagNamespaceBuf> {
+        std::mem::replace(&mut self.tag_namespace, tag_namespace)
+    }
+
+    /// The key prefix under which all objects are stored
+    pub(super) fn objects_prefix(&self) -> String {
+        format!("{}objects/", self.prefix)
+    }
+
+    /// The key prefix under which all payloads are stored
+    pub(super) fn payloads_prefix(&self) -> String {
+        format!("{}payloads/", self.prefix)
+    }
+
+    /// The key prefix under which all tags in the given namespace are stored
+    pub(super) fn tags_prefix_in_namespace(&self, namespace: Option<&TagNamespace>) -> String {
+        let mut tags_prefix = format!("{}tags/", self.prefix);
+        if let Some(tag_namespace) = namespace {
+            for component in tag_namespace.as_rel_path().components() {
+                // Assuming the tag namespace is only made up of `Normal`
+                // elements (validated elsewhere).
+                let relative_path::Component::Normal(component) = component else {
+                    continue;
+                };
+                // Use the same `"#ns"` suffix as the filesystem repository to
+                // distinguish tag namespaces from normal tag folders.
+                tags_prefix.push_str(component);
+                tags_prefix.push_str(storage::TAG_NAMESPACE_MARKER);
+                tags_prefix.push('/');
+            }
+        }
+        tags_prefix
+    }
+
+    pub(super) fn object_key(&self, digest: &encoding::Digest) -> String {
+        format!("{}{digest}", self.objects_prefix())
+    }
+
+    pub(super) fn payload_key(&self, digest: &encoding::Digest) -> String {
+        format!("{}{digest}", self.payloads_prefix())
+    }
+}
+
+impl storage::Address for S3Repository {
+    fn address(&self) -> Cow<'_, url::Url> {
+        Cow::Borrowed(&self.address)
+    }
+}
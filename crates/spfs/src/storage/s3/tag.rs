@@ -0,0 +1,266 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::borrow::Cow;
+use std::pin::Pin;
+
+use futures::{Stream, StreamExt};
+use relative_path::RelativePath;
+
+use super::client::{ListEntry, Precondition};
+use crate::storage::fs::{decode_tag_stream, encode_tag_stream};
+use crate::storage::tag::{EntryType, TagSpecAndTagStream};
+use crate::storage::{TAG_NAMESPACE_MARKER, TagNamespace, TagNamespaceBuf, TagStorage};
+use crate::{Error, Result, encoding, storage, tracking};
+
+const TAG_EXT: &str = ".tag";
+
+#[async_trait::async_trait]
+impl TagStorage for super::S3Repository {
+    #[inline]
+    fn get_tag_namespace(&self) -> Option<Cow<'_, TagNamespace>> {
+        Self::tag_namespace(self).map(Cow::Borrowed)
+    }
+
+    fn ls_tags_in_namespace(
+        &self,
+        namespace: Option<&TagNamespace>,
+        path: &RelativePath,
+    ) -> Pin<Box<dyn Stream<Item = Result<EntryType>> + Send>> {
+        let mut prefix = self.tags_prefix_in_namespace(namespace);
+        let path = path.normalize();
+        if !path.as_str().is_empty() {
+            prefix.push_str(path.as_str());
+            prefix.push('/');
+        }
+        let stream = self
+            .client
+            .list_objects(prefix.clone(), Some("/"))
+            .filter_map(move |entry| {
+                let result = match entry {
+                    Err(err) => Some(Err(err)),
+                    Ok(ListEntry::Object(key)) => key
+                        .strip_prefix(&prefix)
+                        .and_then(|name| name.strip_suffix(TAG_EXT))
+                        .map(|name| Ok(EntryType::Tag(name.to_string()))),
+                    Ok(ListEntry::Prefix(common)) => common
+                        .strip_prefix(&prefix)
+                        .map(|name| name.trim_end_matches('/'))
+                        .filter(|name| !name.is_empty())
+                        .map(|name| match name.split_once(TAG_NAMESPACE_MARKER) {
+                            Some((name, _)) => Ok(EntryType::Namespace(name.into())),
+                            None => Ok(EntryType::Folder(name.to_string())),
+                        }),
+                };
+                futures::future::ready(result)
+            });
+        Box::pin(stream)
+    }
+
+    /// Find tags that point to the given digest.
+    ///
+    /// This is an O(n) operation based on the number of all
+    /// tag versions in each tag stream.
+    fn find_tags_in_namespace(
+        &self,
+        namespace: Option<&TagNamespace>,
+        digest: &encoding::Digest,
+    ) -> Pin<Box<dyn Stream<Item = Result<tracking::TagSpec>> + Send>> {
+        let digest = *digest;
+        let stream = self.iter_tag_streams_in_namespace(namespace);
+        let mapped = futures::StreamExt::filter_map(stream, move |res| async move {
+            let (spec, stream) = match res {
+                Ok(res) => res,
+                Err(err) => return Some(Err(err)),
+            };
+            let mut stream = futures::StreamExt::enumerate(stream);
+            while let Some((i, tag)) = stream.next().await {
+                match tag {
+                    Ok(tag) if tag.target == digest => {
+                        return Some(Ok(spec.with_version(i as u64)));
+                    }
+                    Ok(_) => continue,
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+            None
+        });
+        Box::pin(mapped)
+    }
+
+    fn iter_tag_streams_in_namespace(
+        &self,
+        namespace: Option<&TagNamespace>,
+    ) -> Pin<Box<dyn Stream<Item = Result<TagSpecAndTagStream>> + Send>> {
+        let repo = self.clone();
+        let prefix = self.tags_prefix_in_namespace(namespace);
+        Box::pin(async_stream::try_stream! {
+            let mut listing = repo.client.list_objects(prefix.clone(), None);
+            while let Some(entry) = listing.next().await {
+                let ListEntry::Object(key) = entry? else {
+                    continue;
+                };
+                let Some(name) = key
+                    .strip_prefix(&prefix)
+                    .and_then(|name| name.strip_suffix(TAG_EXT))
+                else {
+                    continue;
+                };
+                // tags in any nested namespace are not part of this one
+                if name
+                    .split('/')
+                    .any(|component| component.ends_with(TAG_NAMESPACE_MARKER))
+                {
+                    continue;
+                }
+                let spec = tracking::TagSpec::parse(name)?;
+                let Some(object) = repo.client.get_object(&key).await? else {
+                    // the tag stream was removed since being listed
+                    continue;
+                };
+                yield (spec, decode_tag_stream(object.data.to_vec(), key.into()));
+            }
+        })
+    }
+
+    async fn read_tag_in_namespace(
+        &self,
+        namespace: Option<&TagNamespace>,
+        tag: &tracking::TagSpec,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<tracking::Tag>> + Send>>> {
+        let key = self.tag_key_in_namespace(namespace, tag);
+        match self.client.get_object(&key).await? {
+            Some(object) => Ok(decode_tag_stream(object.data.to_vec(), key.into())),
+            None => Err(Error::UnknownReference(tag.to_string())),
+        }
+    }
+
+    async fn insert_tag_in_namespace(
+        &self,
+        namespace: Option<&TagNamespace>,
+        tag: &tracking::Tag,
+    ) -> Result<()> {
+        let tag_spec = tracking::build_tag_spec(tag.org(), tag.name(), 0)?;
+        self.update_tag_stream(namespace, &tag_spec, |tags| {
+            if tags.contains(tag) {
+                // this tag already exists in the stream
+                return false;
+            }
+            // tags are stored from earliest to latest, and the new tag
+            // is placed directly after the latest one that precedes it
+            let position = tags
+                .iter()
+                .rposition(|existing| existing < tag)
+                .map(|i| i + 1)
+                .unwrap_or_default();
+            tags.insert(position, tag.clone());
+            true
+        })
+        .await
+    }
+
+    async fn remove_tag_stream_in_namespace(
+        &self,
+        namespace: Option<&TagNamespace>,
+        tag: &tracking::TagSpec,
+    ) -> Result<()> {
+        let tag_spec = tracking::build_tag_spec(tag.org(), tag.name(), 0)?;
+        let key = self.tag_key_in_namespace(namespace, &tag_spec);
+        if self.client.head_object(&key).await?.is_none() {
+            return Err(Error::UnknownReference(tag.to_string()));
+        }
+        self.client.delete_object(&key).await
+    }
+
+    async fn remove_tag_in_namespace(
+        &self,
+        namespace: Option<&TagNamespace>,
+        tag: &tracking::Tag,
+    ) -> Result<()> {
+        let tag_spec = tracking::build_tag_spec(tag.org(), tag.name(), 0)?;
+        self.update_tag_stream(namespace, &tag_spec, |tags| {
+            let before = tags.len();
+            tags.retain(|existing| existing != tag);
+            tags.len() != before
+        })
+        .await
+    }
+}
+
+impl storage::TagStorageMut for super::S3Repository {
+    fn try_set_tag_namespace(
+        &mut self,
+        tag_namespace: Option<TagNamespaceBuf>,
+    ) -> Result<Option<TagNamespaceBuf>> {
+        Ok(Self::set_tag_namespace(self, tag_namespace))
+    }
+}
+
+impl super::S3Repository {
+    fn tag_key_in_namespace(
+        &self,
+        namespace: Option<&TagNamespace>,
+        tag: &tracking::TagSpec,
+    ) -> String {
+        format!(
+            "{}{}{TAG_EXT}",
+            self.tags_prefix_in_namespace(namespace),
+            tag.path()
+        )
+    }
+
+    /// Atomically modify the contents of a tag stream.
+    ///
+    /// The given function receives all tags in the stream ordered from
+    /// earliest to latest, and returns true if the stream was modified
+    /// and needs to be saved. Conflicting writes are detected using
+    /// the object's entity tag, and the update is retried until it can
+    /// be applied cleanly. A stream that is left empty is removed.
+    async fn update_tag_stream<F>(
+        &self,
+        namespace: Option<&TagNamespace>,
+        tag_spec: &tracking::TagSpec,
+        mut update: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&mut Vec<tracking::Tag>) -> bool + Send,
+    {
+        let key = self.tag_key_in_namespace(namespace, tag_spec);
+        loop {
+            let (mut tags, etag) = match self.client.get_object(&key).await? {
+                Some(object) => {
+                    let mut tags = decode_tag_stream(object.data.to_vec(), key.clone().into())
+                        .collect::<Vec<_>>()
+                        .await
+                        .into_iter()
+                        .collect::<Result<Vec<_>>>()?;
+                    tags.reverse();
+                    (tags, object.etag)
+                }
+                None => (Vec::new(), None),
+            };
+            if !update(&mut tags) {
+                return Ok(());
+            }
+            if tags.is_empty() {
+                // there is no conditional delete, so a concurrent
+                // insert may be lost in this rare case
+                return self.client.delete_object(&key).await;
+            }
+            let data = encode_tag_stream(&tags)?;
+            let precondition = match etag.as_deref() {
+                Some(etag) => Precondition::IfMatch(etag),
+                None => Precondition::IfNoneMatch,
+            };
+            if self
+                .client
+                .put_object(&key, data.into(), precondition)
+                .await?
+            {
+                return Ok(());
+            }
+            tracing::debug!(%key, "tag stream changed during update, retrying");
+        }
+    }
+}
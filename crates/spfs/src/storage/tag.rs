@@ -5,6 +5,7 @@
 use std::borrow::Cow;
 use std::fmt::Display;
 use std::pin::Pin;
+use std::sync::Arc;
 
 use futures::{Stream, StreamExt, TryStreamExt};
 use relative_path::RelativePath;
@@ -64,6 +65,11 @@ pub trait TagStorage: Send + Sync {
     /// Return the (optional) tag namespace to use for this tag storage.
     fn get_tag_namespace(&self) -> Option<Cow<'_, TagNamespace>>;
 
+    /// The key used to sign new tags that are pushed to this storage, if any.
+    async fn tag_signing_key(&self) -> Option<Arc<tracking::TagSigningKey>> {
+        None
+    }
+
     /// Return true if the given tag exists in this storage.
     async fn has_tag(&self, tag: &tracking::TagSpec) -> bool {
         self.resolve_tag(tag).await.is_ok()
@@ -221,7 +227,16 @@ pub trait TagStorage: Send + Sync {
         tag: &tracking::TagSpec,
         target: &encoding::Digest,
     ) -> Result<tracking::Tag> {
-        let parent = self.resolve_tag(tag).await.ok();
+        // the parent is read directly rather than resolved so that it
+        // is found even when it is not allowed by a signature policy
+        let parent = match self.read_tag(tag).await {
+            Ok(stream) => stream
+                .skip(tag.version() as usize)
+                .next()
+                .await
+                .and_then(Result::ok),
+            Err(_) => None,
+        };
         let parent_ref = match parent {
             Some(parent) => {
                 // do not push redundant/unchanged head tag
@@ -236,6 +251,9 @@ pub trait TagStorage: Send + Sync {
 
         let mut new_tag = tracking::Tag::new(tag.org(), tag.name(), *target)?;
         new_tag.parent = parent_ref;
+        if let Some(key) = self.tag_signing_key().await {
+            new_tag.sign(&key)?;
+        }
 
         self.insert_tag(&new_tag).await?;
         Ok(new_tag)
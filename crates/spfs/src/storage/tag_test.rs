@@ -21,6 +21,7 @@ use crate::{Result, encoding, tracking};
 #[case::fs(tmprepo("fs"))]
 #[case::tar(tmprepo("tar"))]
 #[cfg_attr(feature = "server", case::rpc(tmprepo("rpc")))]
+#[cfg_attr(feature = "server", case::s3(tmprepo("s3")))]
 #[tokio::test]
 async fn test_tag_stream(
     #[case]
@@ -73,6 +74,7 @@ async fn test_tag_stream(
 #[case::fs(tmprepo("fs"))]
 #[case::tar(tmprepo("tar"))]
 #[cfg_attr(feature = "server", case::rpc(tmprepo("rpc")))]
+#[cfg_attr(feature = "server", case::s3(tmprepo("s3")))]
 #[tokio::test]
 async fn test_tag_no_duplication(
     #[case]
@@ -137,6 +139,7 @@ async fn test_tag_permissions(tmpdir: tempfile::TempDir) {
 #[case::fs(tmprepo("fs"))]
 #[case::tar(tmprepo("tar"))]
 #[cfg_attr(feature = "server", case::rpc(tmprepo("rpc")))]
+#[cfg_attr(feature = "server", case::s3(tmprepo("s3")))]
 #[tokio::test]
 async fn test_ls_tags(
     #[case]
@@ -198,6 +201,7 @@ async fn test_ls_tags(
 #[case::fs(tmprepo("fs"))]
 #[case::tar(tmprepo("tar"))]
 #[cfg_attr(feature = "server", case::rpc(tmprepo("rpc")))]
+#[cfg_attr(feature = "server", case::s3(tmprepo("s3")))]
 #[tokio::test]
 async fn test_tag_ordering(
     #[case]
@@ -257,6 +261,7 @@ async fn test_tag_ordering(
 #[case::fs(tmprepo("fs"))]
 #[case::tar(tmprepo("tar"))]
 #[cfg_attr(feature = "server", case::rpc(tmprepo("rpc")))]
+#[cfg_attr(feature = "server", case::s3(tmprepo("s3")))]
 #[tokio::test]
 async fn test_rm_tags(
     #[case]
@@ -410,3 +415,54 @@ async fn test_tag_in_namespace_name_collision(
     let tag = tmprepo.resolve_tag(&spec_foo_bar_baz).await.unwrap();
     assert_eq!(tag.target, foo_bar_baz);
 }
+
+#[rstest]
+#[tokio::test]
+async fn test_resolve_tag_signature_policy(tmpdir: tempfile::TempDir) {
+    use ring::signature::Ed25519KeyPair;
+
+    use crate::storage::fs::OpenFsRepository;
+
+    init_logging();
+    let generate_key = || {
+        let document = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new()).unwrap();
+        tracking::TagSigningKey::from_pkcs8(document.as_ref()).unwrap()
+    };
+    let trusted = generate_key();
+    let untrusted = generate_key();
+    let mut storage = OpenFsRepository::create(tmpdir.path().join("repo"))
+        .await
+        .unwrap();
+    let policy = tracking::TagSignaturePolicy::new(true, [trusted.public_key_base64()]).unwrap();
+    std::sync::Arc::make_mut(&mut storage.fs_impl).set_tag_signature_policy(policy);
+
+    let spec = tracking::TagSpec::parse("signed/tag").unwrap();
+    let mut tag =
+        tracking::Tag::new(spec.org(), spec.name(), encoding::EMPTY_DIGEST.into()).unwrap();
+    storage.insert_tag(&tag).await.unwrap();
+    storage
+        .resolve_tag(&spec)
+        .await
+        .expect_err("unsigned tag should not resolve");
+
+    tag.time += chrono::Duration::seconds(1);
+    tag.sign(&untrusted).unwrap();
+    storage.insert_tag(&tag).await.unwrap();
+    storage
+        .resolve_tag(&spec)
+        .await
+        .expect_err("tag signed by an untrusted key should not resolve");
+
+    tag.time += chrono::Duration::seconds(1);
+    tag.sign(&trusted).unwrap();
+    storage.insert_tag(&tag).await.unwrap();
+    let resolved = storage
+        .resolve_tag(&spec)
+        .await
+        .expect("tag signed by a trusted key should resolve");
+    assert_eq!(resolved, tag, "signature should be stored in the tag file");
+    storage
+        .resolve_tag(&spec.with_version(1))
+        .await
+        .expect_err("older untrusted versions should still not resolve");
+}
@@ -6,6 +6,7 @@ use std::borrow::Cow;
 use std::io::BufReader;
 use std::path::Path;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 
 use chrono::{DateTime, Utc};
@@ -325,6 +326,10 @@ impl TagStorage for TarRepository {
         self.repo.get_tag_namespace()
     }
 
+    async fn tag_signing_key(&self) -> Option<Arc<tracking::TagSigningKey>> {
+        self.repo.tag_signing_key().await
+    }
+
     async fn resolve_tag_in_namespace(
         &self,
         namespace: Option<&TagNamespace>,
@@ -2,11 +2,18 @@
 // SPDX-License-Identifier: Apache-2.0
 // https://github.com/spkenv/spk
 
+pub mod journal;
+pub mod mirror;
 pub mod reporter;
+mod throttle;
 
+use std::io::Write;
+use std::num::NonZeroU64;
+use std::pin::Pin;
 use std::sync::Arc;
 
 use futures::stream::{FuturesUnordered, TryStreamExt};
+use journal::SyncJournal;
 use reporter::{
     SyncAnnotationResult,
     SyncBlobResult,
@@ -22,11 +29,13 @@ use reporter::{
     SyncReporters,
     SyncTagResult,
 };
+use throttle::{BandwidthLimiter, TransferReader};
 use tokio::sync::Semaphore;
 
+use crate::chunking::ChunkingConfig;
 use crate::graph::AnnotationValue;
 use crate::prelude::*;
-use crate::{Error, Result, encoding, graph, storage, tracking};
+use crate::{Error, Result, chunking, encoding, graph, storage, tracking};
 
 /// The default limit for concurrent manifest sync operations
 /// per-syncer if not otherwise specified using
@@ -94,7 +103,14 @@ pub struct Syncer<'src, 'dst> {
     policy: SyncPolicy,
     manifest_semaphore: Arc<Semaphore>,
     payload_semaphore: Arc<Semaphore>,
+    bandwidth_limiter: Option<Arc<BandwidthLimiter>>,
     processed_digests: Arc<dashmap::DashSet<encoding::Digest>>,
+    /// the results of batched existence checks in the destination,
+    /// each of which is consumed by the first lookup that uses it
+    dest_object_hints: Arc<dashmap::DashMap<encoding::Digest, bool>>,
+    dest_payload_hints: Arc<dashmap::DashMap<encoding::Digest, bool>>,
+    chunking: Option<ChunkingConfig>,
+    journal: Option<Arc<SyncJournal>>,
 }
 
 impl<'src, 'dst> Syncer<'src, 'dst> {
@@ -109,7 +125,15 @@ impl<'src, 'dst> Syncer<'src, 'dst> {
             policy: SyncPolicy::default(),
             manifest_semaphore: Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENT_MANIFESTS)),
             payload_semaphore: Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENT_PAYLOADS)),
+            bandwidth_limiter: None,
             processed_digests: Arc::new(Default::default()),
+            dest_object_hints: Arc::new(Default::default()),
+            dest_payload_hints: Arc::new(Default::default()),
+            chunking: crate::get_config()
+                .ok()
+                .map(|config| config.storage.chunking.clone())
+                .filter(|chunking| chunking.enabled),
+            journal: None,
         }
     }
 
@@ -130,7 +154,12 @@ impl<'src, 'dst> Syncer<'src, 'dst> {
             policy: self.policy,
             manifest_semaphore: Arc::clone(&self.manifest_semaphore),
             payload_semaphore: Arc::clone(&self.payload_semaphore),
+            bandwidth_limiter: self.bandwidth_limiter.clone(),
             processed_digests: Arc::clone(&self.processed_digests),
+            dest_object_hints: Arc::clone(&self.dest_object_hints),
+            dest_payload_hints: Arc::clone(&self.dest_payload_hints),
+            chunking: self.chunking.clone(),
+            journal: self.journal.clone(),
         }
     }
 
@@ -160,6 +189,51 @@ impl<'src, 'dst> Syncer<'src, 'dst> {
         self
     }
 
+    /// Limit the combined rate at which payload data is transferred,
+    /// or remove any limit if `None`.
+    ///
+    /// The limit is shared with any syncers created from this one
+    /// through [`Self::clone_with_source`].
+    pub fn with_max_bytes_per_second(mut self, bytes_per_second: Option<NonZeroU64>) -> Self {
+        self.bandwidth_limiter = bytes_per_second.map(|bps| Arc::new(BandwidthLimiter::new(bps)));
+        self
+    }
+
+    /// Split large payloads into content-defined chunks when
+    /// syncing them, or disable chunking if `None`.
+    ///
+    /// Chunks that already exist in the destination are not
+    /// transferred again. Defaults to the chunking settings
+    /// in the global config, if enabled there.
+    pub fn with_chunking(mut self, chunking: Option<ChunkingConfig>) -> Self {
+        self.chunking = chunking;
+        self
+    }
+
+    /// Record completed objects in the given journal, and skip
+    /// any that it shows were completed by a previous sync.
+    ///
+    /// This allows an interrupted sync to be resumed without
+    /// revisiting the work that it had already done. The caller
+    /// is responsible for calling [`SyncJournal::finish`] once
+    /// the sync has succeeded.
+    pub fn with_journal(mut self, journal: Arc<SyncJournal>) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Treat the given objects and payloads as already synced, so
+    /// that they are never copied even if missing in the destination.
+    ///
+    /// This is how a partial copy is made of data that the eventual
+    /// consumer is known to have already.
+    pub fn with_skipped_digests(self, digests: impl IntoIterator<Item = encoding::Digest>) -> Self {
+        for digest in digests {
+            self.processed_digests.insert(digest);
+        }
+        self
+    }
+
     /// Report progress to the given instance, replacing any existing one
     pub fn with_reporter(self, reporter: SyncReporters) -> Syncer<'src, 'dst> {
         Syncer {
@@ -169,7 +243,12 @@ impl<'src, 'dst> Syncer<'src, 'dst> {
             policy: self.policy,
             manifest_semaphore: self.manifest_semaphore,
             payload_semaphore: self.payload_semaphore,
+            bandwidth_limiter: self.bandwidth_limiter,
             processed_digests: self.processed_digests,
+            dest_object_hints: self.dest_object_hints,
+            dest_payload_hints: self.dest_payload_hints,
+            chunking: self.chunking,
+            journal: self.journal,
         }
     }
 
@@ -282,6 +361,7 @@ impl<'src, 'dst> Syncer<'src, 'dst> {
             Enum::Platform(obj) => SyncObjectResult::Platform(self.sync_platform(obj).await?),
             Enum::Blob(obj) => SyncObjectResult::Blob(self.sync_blob(&obj).await?),
             Enum::Manifest(obj) => SyncObjectResult::Manifest(self.sync_manifest(obj).await?),
+            Enum::ChunkedBlob(obj) => SyncObjectResult::Blob(self.sync_chunked_blob(obj).await?),
         };
         self.reporter.synced_object(&res);
         Ok(res)
@@ -292,6 +372,9 @@ impl<'src, 'dst> Syncer<'src, 'dst> {
         if !self.processed_digests.insert(digest) {
             return Ok(SyncPlatformResult::Duplicate);
         }
+        if self.resumed_from_journal(digest) {
+            return Ok(SyncPlatformResult::Resumed);
+        }
         if self.policy.check_existing_objects() && self.dest.has_object(digest).await {
             return Ok(SyncPlatformResult::Skipped);
         }
@@ -307,6 +390,7 @@ impl<'src, 'dst> Syncer<'src, 'dst> {
         }
 
         self.dest.write_object(&platform).await?;
+        self.record_in_journal(digest).await?;
 
         let res = SyncPlatformResult::Synced { platform, results };
         self.reporter.synced_platform(&res);
@@ -318,6 +402,9 @@ impl<'src, 'dst> Syncer<'src, 'dst> {
         if !self.processed_digests.insert(layer_digest) {
             return Ok(SyncLayerResult::Duplicate);
         }
+        if self.resumed_from_journal(layer_digest) {
+            return Ok(SyncLayerResult::Resumed);
+        }
         if self.policy.check_existing_objects() && self.dest.has_object(layer_digest).await {
             return Ok(SyncLayerResult::Skipped);
         }
@@ -346,6 +433,7 @@ impl<'src, 'dst> Syncer<'src, 'dst> {
         };
 
         self.dest.write_object(&layer).await?;
+        self.record_in_journal(layer_digest).await?;
 
         let mut results = vec![SyncObjectResult::Manifest(manifest_result)];
         results.extend(annotation_results);
@@ -360,6 +448,9 @@ impl<'src, 'dst> Syncer<'src, 'dst> {
         if !self.processed_digests.insert(manifest_digest) {
             return Ok(SyncManifestResult::Duplicate);
         }
+        if self.resumed_from_journal(manifest_digest) {
+            return Ok(SyncManifestResult::Resumed);
+        }
         if self.policy.check_existing_objects() && self.dest.has_object(manifest_digest).await {
             return Ok(SyncManifestResult::Skipped);
         }
@@ -374,6 +465,8 @@ impl<'src, 'dst> Syncer<'src, 'dst> {
             .iter_entries()
             .filter(|e| e.kind().is_blob())
             .collect();
+        self.prefetch_dest_existence(entries.iter().map(|e| *e.object()))
+            .await;
         let mut results = Vec::with_capacity(entries.len());
         let mut futures = FuturesUnordered::new();
         for entry in entries {
@@ -384,6 +477,7 @@ impl<'src, 'dst> Syncer<'src, 'dst> {
         }
 
         self.dest.write_object(&manifest).await?;
+        self.record_in_journal(manifest_digest).await?;
 
         drop(futures);
         let res = SyncManifestResult::Synced { manifest, results };
@@ -443,31 +537,234 @@ impl<'src, 'dst> Syncer<'src, 'dst> {
             // which should also must be visited at least once if needed
             return Ok(SyncBlobResult::Duplicate);
         }
+        if self.resumed_from_journal(*digest) {
+            self.processed_digests.insert(*digest);
+            return Ok(SyncBlobResult::Resumed);
+        }
 
         if self.policy.check_existing_objects()
-            && self.dest.has_object(*digest).await
-            && self.dest.has_payload(*blob.payload()).await
+            && self.dest_has_object(*digest).await
+            && self.dest_has_blob_payload(blob).await
         {
             self.processed_digests.insert(*digest);
             return Ok(SyncBlobResult::Skipped);
         }
         self.reporter.visit_blob(blob);
-        // Safety: sync_payload is unsafe to call unless the blob
-        // is synced with it, which is the purpose of this function.
-        let result = unsafe {
-            self.sync_payload_with_perms_opt(*blob.payload(), perms)
-                .await?
+        let chunking = self
+            .chunking
+            .as_ref()
+            .filter(|chunking| chunking.should_chunk(blob.size()));
+        let res = if let Some(chunked) = self.read_source_chunked_blob(blob).await {
+            self.sync_chunks(chunked).await?
+        } else if let Some(chunking) = chunking {
+            self.sync_payload_as_chunks(blob, chunking).await?
+        } else {
+            // Safety: sync_payload is unsafe to call unless the blob
+            // is synced with it, which is the purpose of this function.
+            let result = unsafe {
+                self.sync_payload_with_perms_opt(*blob.payload(), perms)
+                    .await?
+            };
+            self.dest.write_blob(blob.to_owned()).await?;
+            SyncBlobResult::Synced {
+                blob: blob.to_owned(),
+                result,
+            }
         };
-        self.dest.write_blob(blob.to_owned()).await?;
+        // blobs share a digest with their payload, and so are only
+        // recorded once both have been written to the destination
+        self.record_in_journal(*digest).await?;
         self.processed_digests.insert(*digest);
-        let res = SyncBlobResult::Synced {
-            blob: blob.to_owned(),
-            result,
-        };
         self.reporter.synced_blob(&res);
         Ok(res)
     }
 
+    /// Sync the identified chunked blob, and any of its
+    /// chunks that are missing from the destination repository.
+    pub async fn sync_chunked_blob(&self, blob: graph::ChunkedBlob) -> Result<SyncBlobResult> {
+        let digest = *blob.digest();
+        if self.processed_digests.contains(&digest) {
+            return Ok(SyncBlobResult::Duplicate);
+        }
+        if self.resumed_from_journal(digest) {
+            self.processed_digests.insert(digest);
+            return Ok(SyncBlobResult::Resumed);
+        }
+        if self.policy.check_existing_objects() && self.dest_has_object(digest).await {
+            self.processed_digests.insert(digest);
+            return Ok(SyncBlobResult::Skipped);
+        }
+        self.reporter
+            .visit_blob(&graph::Blob::new(digest, blob.size()));
+        let res = self.sync_chunks(blob).await?;
+        self.record_in_journal(digest).await?;
+        self.processed_digests.insert(digest);
+        self.reporter.synced_blob(&res);
+        Ok(res)
+    }
+
+    /// Sync each chunk of a chunked blob, followed by the chunked blob itself
+    async fn sync_chunks(&self, blob: graph::ChunkedBlob) -> Result<SyncBlobResult> {
+        let mut results = Vec::new();
+        for chunk in blob.iter_chunks() {
+            // Safety: sync_payload is unsafe to call unless the blob
+            // is synced with it, which is done immediately after
+            let result =
+                unsafe { self.sync_payload_with_perms_opt(*chunk.payload(), None) }.await?;
+            self.dest.write_blob(chunk).await?;
+            results.push(result);
+        }
+        // the chunked blob is written last so that its
+        // existence always implies that all of its chunks exist
+        self.dest.write_object(&blob).await?;
+        Ok(SyncBlobResult::SyncedChunks { blob, results })
+    }
+
+    /// Split the payload of a blob into chunks as it is synced,
+    /// transferring only the chunks that are missing from the destination
+    async fn sync_payload_as_chunks(
+        &self,
+        blob: &graph::Blob,
+        chunking: &ChunkingConfig,
+    ) -> Result<SyncBlobResult> {
+        let digest = *blob.payload();
+        let (payload, _) = self.src.open_payload(digest).await?;
+        let payload = self.transfer_reader(payload);
+        let mut chunks = std::pin::pin!(chunking.chunk_reader(payload));
+        let mut hasher = encoding::Hasher::new_sync();
+        let mut builder = graph::ChunkedBlob::builder().with_payload(digest);
+        let mut results = Vec::new();
+        while let Some(chunk) = chunks.try_next().await? {
+            hasher
+                .write_all(&chunk)
+                .map_err(encoding::Error::FailedWrite)?;
+            let chunk_blob = graph::Blob::new(
+                encoding::Hasher::hash_reader(chunk.as_ref())?,
+                chunk.len() as u64,
+            );
+            let chunk_digest = *chunk_blob.payload();
+            builder = builder.with_chunk(chunk_digest, chunk_blob.size());
+            if self.processed_digests.contains(&chunk_digest) {
+                results.push(SyncPayloadResult::Duplicate);
+                continue;
+            }
+            if self.policy.check_existing_payloads() && self.dest.has_payload(chunk_digest).await {
+                results.push(SyncPayloadResult::Skipped);
+            } else {
+                self.reporter.visit_payload(chunk_digest);
+                let _permit = self.payload_semaphore.acquire().await;
+                // Safety: the blob for this chunk is written immediately after
+                let (_, size) = unsafe {
+                    self.dest
+                        .write_data(Box::pin(std::io::Cursor::new(chunk)))
+                        .await?
+                };
+                let res = SyncPayloadResult::Synced { size };
+                self.reporter.synced_payload(&res);
+                results.push(res);
+            }
+            self.dest.write_blob(chunk_blob).await?;
+            self.processed_digests.insert(chunk_digest);
+        }
+        let created_digest = hasher.digest();
+        if digest != created_digest {
+            return Err(Error::String(format!(
+                "Source repository provided payload that did not match the requested digest: wanted {digest}, got {created_digest}",
+            )));
+        }
+        let blob = builder.build();
+        self.dest.write_object(&blob).await?;
+        Ok(SyncBlobResult::SyncedChunks { blob, results })
+    }
+
+    /// Check which of the given blobs and their payloads already exist in
+    /// the destination using batched requests, so that syncing each
+    /// one does not need its own round trip to a remote repository.
+    ///
+    /// The server-side walk of [`storage::find_missing_data`] is not
+    /// used here, because the syncer only descends into objects that
+    /// are missing from the destination, or re-copies all of them, and
+    /// a walk that starts from a missing object cannot find any of its
+    /// children. Checking each manifest's blobs in one batch already
+    /// needs the same number of requests.
+    async fn prefetch_dest_existence(&self, digests: impl Iterator<Item = encoding::Digest>) {
+        // local repositories answer each check just as quickly
+        let storage::RepositoryHandle::Rpc(_) = self.dest else {
+            return;
+        };
+        let digests: Vec<_> = digests
+            .filter(|digest| !self.processed_digests.contains(digest))
+            .collect();
+        if digests.is_empty() {
+            return;
+        }
+        if self.policy.check_existing_objects() {
+            let existing = self.dest.has_objects(&digests).await;
+            for digest in digests.iter() {
+                self.dest_object_hints
+                    .insert(*digest, existing.contains(digest));
+            }
+        }
+        if self.policy.check_existing_payloads() {
+            // blobs share their digest with their payload
+            let existing = self.dest.has_payloads(&digests).await;
+            for digest in digests.iter() {
+                self.dest_payload_hints
+                    .insert(*digest, existing.contains(digest));
+            }
+        }
+    }
+
+    /// True if the destination has the identified object, using
+    /// the result of an earlier batched check when available
+    async fn dest_has_object(&self, digest: encoding::Digest) -> bool {
+        match self.dest_object_hints.remove(&digest) {
+            Some((_, exists)) => exists,
+            None => self.dest.has_object(digest).await,
+        }
+    }
+
+    /// True if the destination has the identified payload, using
+    /// the result of an earlier batched check when available
+    async fn dest_has_payload(&self, digest: encoding::Digest) -> bool {
+        match self.dest_payload_hints.remove(&digest) {
+            Some((_, exists)) => exists,
+            None => self.dest.has_payload(digest).await,
+        }
+    }
+
+    /// True if the destination has the complete payload of a blob,
+    /// either directly or as a chunked blob.
+    async fn dest_has_blob_payload(&self, blob: &graph::Blob) -> bool {
+        if self.dest_has_payload(*blob.payload()).await {
+            return true;
+        }
+        if blob.size() < chunking::MIN_CHUNKED_PAYLOAD_SIZE {
+            return false;
+        }
+        // chunked blobs are only ever written once all of their chunks exist
+        matches!(
+            self.dest
+                .read_object(*blob.digest())
+                .await
+                .map(|o| o.into_enum()),
+            Ok(graph::object::Enum::ChunkedBlob(_))
+        )
+    }
+
+    /// Load the chunked blob for a blob from the source repository,
+    /// if its payload is stored there as chunks.
+    async fn read_source_chunked_blob(&self, blob: &graph::Blob) -> Option<graph::ChunkedBlob> {
+        if blob.size() < chunking::MIN_CHUNKED_PAYLOAD_SIZE {
+            return None;
+        }
+        self.src
+            .read_object(*blob.digest())
+            .await
+            .ok()
+            .and_then(|o| o.into_chunked_blob())
+    }
+
     /// Sync a payload with the provided digest
     ///
     /// # Safety
@@ -497,7 +794,7 @@ impl<'src, 'dst> Syncer<'src, 'dst> {
             return Ok(SyncPayloadResult::Duplicate);
         }
 
-        if self.policy.check_existing_payloads() && self.dest.has_payload(digest).await {
+        if self.policy.check_existing_payloads() && self.dest_has_payload(digest).await {
             return Ok(SyncPayloadResult::Skipped);
         }
 
@@ -507,7 +804,8 @@ impl<'src, 'dst> Syncer<'src, 'dst> {
             _permit.is_ok(),
             "We never close the semaphore and so should never see errors"
         );
-        let (mut payload, _) = self.src.open_payload(digest).await?;
+        let (payload, _) = self.src.open_payload(digest).await?;
+        let mut payload: Pin<Box<dyn tracking::BlobRead>> = Box::pin(self.transfer_reader(payload));
         if let Some(perms) = perms {
             payload = Box::pin(payload.with_permissions(perms));
         }
@@ -526,6 +824,36 @@ impl<'src, 'dst> Syncer<'src, 'dst> {
         Ok(res)
     }
 
+    /// Wrap a payload from the source repository so that its
+    /// transfer is reported and limited as configured
+    fn transfer_reader(&self, payload: Pin<Box<dyn tracking::BlobRead>>) -> TransferReader {
+        TransferReader::new(
+            payload,
+            self.bandwidth_limiter.clone(),
+            self.reporter.clone(),
+        )
+    }
+
+    /// True if the journal shows that the identified object
+    /// was completely synced by a previous, interrupted sync
+    fn resumed_from_journal(&self, digest: encoding::Digest) -> bool {
+        let resumed = self
+            .journal
+            .as_ref()
+            .is_some_and(|journal| journal.contains(&digest));
+        if resumed {
+            self.reporter.resumed_object(digest);
+        }
+        resumed
+    }
+
+    async fn record_in_journal(&self, digest: encoding::Digest) -> Result<()> {
+        match &self.journal {
+            Some(journal) => journal.record(digest).await,
+            None => Ok(()),
+        }
+    }
+
     async fn read_object_with_fallback(&self, digest: encoding::Digest) -> Result<graph::Object> {
         let res = self.src.read_object(digest).await;
         match res {
@@ -0,0 +1,148 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use tokio::io::AsyncWriteExt;
+
+use crate::prelude::*;
+use crate::{Error, Result, encoding, storage, tracking};
+
+#[cfg(test)]
+#[path = "./journal_test.rs"]
+mod journal_test;
+
+/// Records the objects that have been completely synced into
+/// a destination repository, so that an interrupted sync can
+/// be resumed without rediscovering everything from the top.
+///
+/// The journal is a file of digests, one per line, that is only
+/// ever appended to. An object is only recorded once it and all
+/// of its children have been written to the destination.
+#[derive(Debug)]
+pub struct SyncJournal {
+    path: PathBuf,
+    /// objects completed by previous syncs
+    previous: HashSet<encoding::Digest>,
+    /// objects recorded by this sync
+    recorded: dashmap::DashSet<encoding::Digest>,
+    file: tokio::sync::Mutex<tokio::fs::File>,
+}
+
+impl SyncJournal {
+    /// The default directory in which journals are stored.
+    pub fn default_root() -> PathBuf {
+        dirs::cache_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("spfs")
+            .join("sync-journals")
+    }
+
+    /// Open the journal for syncing the given env between two
+    /// repositories, stored in [`Self::default_root`].
+    ///
+    /// Syncing the same env between the same repositories always
+    /// opens the same journal, which is how a rerun resumes.
+    pub async fn open_for_env(
+        src: &storage::RepositoryHandle,
+        dest: &storage::RepositoryHandle,
+        env: &tracking::EnvSpec,
+    ) -> Result<Self> {
+        let mut hasher = encoding::Hasher::new_sync();
+        writeln!(hasher, "{}", src.address())
+            .and_then(|_| writeln!(hasher, "{}", dest.address()))
+            .and_then(|_| write!(hasher, "{env}"))
+            .map_err(encoding::Error::FailedWrite)?;
+        let name = hasher.digest().to_string();
+        Self::open(Self::default_root().join(name)).await
+    }
+
+    /// Open the journal at the given path, loading any progress
+    /// recorded there by a previous sync.
+    pub async fn open<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+        let mut previous = HashSet::new();
+        match tokio::fs::read_to_string(&path).await {
+            Ok(existing) => {
+                // the last line may be incomplete if the previous
+                // sync was interrupted while it was being written
+                previous.extend(existing.lines().filter_map(|l| l.parse().ok()));
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(Error::StorageReadError(
+                    "read_to_string on sync journal",
+                    path,
+                    err,
+                ));
+            }
+        }
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(|err| {
+                Error::StorageWriteError("create_dir_all on sync journal", path.clone(), err)
+            })?;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(|err| Error::StorageWriteError("open on sync journal", path.clone(), err))?;
+        // start on a fresh line, in case the last one was incomplete
+        file.write_all(b"\n")
+            .await
+            .map_err(|err| Error::StorageWriteError("write on sync journal", path.clone(), err))?;
+        Ok(Self {
+            path,
+            previous,
+            recorded: Default::default(),
+            file: tokio::sync::Mutex::new(file),
+        })
+    }
+
+    /// The location of this journal on disk
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of objects that were recorded by a previous sync
+    pub fn resumed_count(&self) -> usize {
+        self.previous.len()
+    }
+
+    /// True if the identified object was completely synced by a previous sync
+    pub fn contains(&self, digest: &encoding::Digest) -> bool {
+        self.previous.contains(digest)
+    }
+
+    /// Record that the identified object has been completely synced
+    pub async fn record(&self, digest: encoding::Digest) -> Result<()> {
+        if self.previous.contains(&digest) || !self.recorded.insert(digest) {
+            return Ok(());
+        }
+        let line = format!("{digest}\n");
+        let mut file = self.file.lock().await;
+        file.write_all(line.as_bytes())
+            .await
+            .and(file.flush().await)
+            .map_err(|err| {
+                Error::StorageWriteError("write on sync journal", self.path.clone(), err)
+            })
+    }
+
+    /// Remove this journal, once the sync that it tracks has succeeded
+    pub async fn finish(&self) -> Result<()> {
+        match tokio::fs::remove_file(&self.path).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(Error::StorageWriteError(
+                "remove_file on sync journal",
+                self.path.clone(),
+                err,
+            )),
+        }
+    }
+}
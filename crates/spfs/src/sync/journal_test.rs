@@ -0,0 +1,74 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use rstest::rstest;
+
+use super::SyncJournal;
+use crate::fixtures::*;
+
+#[rstest]
+#[tokio::test]
+async fn test_journal_reopen(tmpdir: tempfile::TempDir) {
+    let path = tmpdir.path().join("nested/journal");
+    let first = random_digest();
+    let second = random_digest();
+
+    let journal = SyncJournal::open(&path).await.unwrap();
+    assert_eq!(journal.resumed_count(), 0);
+    journal.record(first).await.unwrap();
+    assert!(
+        !journal.contains(&first),
+        "objects recorded in this sync should not be seen as resumed"
+    );
+    drop(journal);
+
+    let journal = SyncJournal::open(&path).await.unwrap();
+    assert_eq!(journal.resumed_count(), 1);
+    assert!(journal.contains(&first));
+    assert!(!journal.contains(&second));
+    journal.record(first).await.unwrap();
+    journal.record(second).await.unwrap();
+    drop(journal);
+
+    let journal = SyncJournal::open(&path).await.unwrap();
+    assert_eq!(journal.resumed_count(), 2);
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_journal_ignores_incomplete_lines(tmpdir: tempfile::TempDir) {
+    let path = tmpdir.path().join("journal");
+    let digest = random_digest();
+    let partial = &digest.to_string()[..10];
+    std::fs::write(&path, format!("{digest}\n{partial}")).unwrap();
+
+    let journal = SyncJournal::open(&path).await.unwrap();
+    assert_eq!(journal.resumed_count(), 1);
+    let other = random_digest();
+    journal.record(other).await.unwrap();
+    drop(journal);
+
+    let journal = SyncJournal::open(&path).await.unwrap();
+    assert!(
+        journal.contains(&other),
+        "records after an incomplete line should still be loaded"
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_journal_finish(tmpdir: tempfile::TempDir) {
+    let path = tmpdir.path().join("journal");
+    let journal = SyncJournal::open(&path).await.unwrap();
+    journal.record(random_digest()).await.unwrap();
+    journal.finish().await.unwrap();
+    assert!(!path.exists(), "journal should be removed when finished");
+
+    let journal = SyncJournal::open(&path).await.unwrap();
+    assert_eq!(
+        journal.resumed_count(),
+        0,
+        "should start over once finished"
+    );
+}
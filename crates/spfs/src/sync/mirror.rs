@@ -0,0 +1,302 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+
+use super::reporter::SyncSummary;
+use super::{SyncPolicy, Syncer};
+use crate::prelude::*;
+use crate::storage::{TagNamespace, TagNamespaceBuf};
+use crate::{Error, Result, encoding, storage, tracking};
+
+#[cfg(test)]
+#[path = "./mirror_test.rs"]
+mod mirror_test;
+
+/// Continuously replicates tags from one repository into another.
+///
+/// Each call to [`Mirror::poll`] checks the source repository for
+/// tags that have changed since they were last mirrored, and syncs
+/// the latest version of each one into the destination, following
+/// the semantics of [`SyncPolicy::LatestTags`].
+///
+/// The head of every mirrored tag is remembered, optionally in a
+/// state file so that a restarted mirror does not need to recheck
+/// every tag in the destination. Regardless, a tag is never pushed
+/// twice because its head is always checked in the destination
+/// before being written.
+pub struct Mirror<'src, 'dst> {
+    syncer: Syncer<'src, 'dst>,
+    prefixes: Vec<String>,
+    namespaces: Option<Vec<TagNamespaceBuf>>,
+    state: MirrorState,
+    state_path: Option<PathBuf>,
+}
+
+impl<'src, 'dst> Mirror<'src, 'dst> {
+    /// Create a mirror that replicates data using the given syncer.
+    ///
+    /// The policy of the syncer is replaced with [`SyncPolicy::LatestTags`].
+    pub fn new(syncer: Syncer<'src, 'dst>) -> Self {
+        Self {
+            syncer: syncer.with_policy(SyncPolicy::LatestTags),
+            prefixes: Vec::new(),
+            namespaces: None,
+            state: MirrorState::default(),
+            state_path: None,
+        }
+    }
+
+    /// The default location of the state file for mirroring
+    /// between the two given repositories.
+    pub fn default_state_path(
+        src: &storage::RepositoryHandle,
+        dest: &storage::RepositoryHandle,
+    ) -> Result<PathBuf> {
+        let mut hasher = encoding::Hasher::new_sync();
+        writeln!(hasher, "{}", src.address())
+            .and_then(|_| write!(hasher, "{}", dest.address()))
+            .map_err(encoding::Error::FailedWrite)?;
+        Ok(dirs::cache_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("spfs")
+            .join("mirrors")
+            .join(format!("{}.json", hasher.digest())))
+    }
+
+    /// Only mirror tags whose path is or is within one of these
+    /// prefixes, eg: `spk/pkg`. All tags are mirrored if this is empty.
+    pub fn with_prefixes<I, S>(mut self, prefixes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.prefixes = prefixes
+            .into_iter()
+            .map(|p| p.into().trim_matches('/').to_string())
+            .collect();
+        self
+    }
+
+    /// Mirror the tags from each of these tag namespaces into the
+    /// same namespace of the destination repository.
+    ///
+    /// By default, tags are mirrored from the tag namespace of the
+    /// source repository handle into that of the destination one.
+    pub fn with_namespaces<I>(mut self, namespaces: I) -> Self
+    where
+        I: IntoIterator<Item = TagNamespaceBuf>,
+    {
+        self.namespaces = Some(namespaces.into_iter().collect());
+        self
+    }
+
+    /// Remember mirrored tags in the given file, loading
+    /// anything that it contains from a previous run.
+    pub async fn with_state_file<P: Into<PathBuf>>(mut self, path: P) -> Result<Self> {
+        let path = path.into();
+        self.state = match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|err| {
+                tracing::warn!(?path, "ignoring unreadable mirror state: {err}");
+                MirrorState::default()
+            }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => MirrorState::default(),
+            Err(err) => {
+                return Err(Error::StorageReadError(
+                    "read on mirror state file",
+                    path,
+                    err,
+                ));
+            }
+        };
+        self.state_path = Some(path);
+        Ok(self)
+    }
+
+    /// The location of the state file, if any
+    pub fn state_path(&self) -> Option<&Path> {
+        self.state_path.as_deref()
+    }
+
+    /// The number of tags that are known to have been mirrored
+    pub fn mirrored_tag_count(&self) -> usize {
+        self.state.tags.len()
+    }
+
+    /// Check the source repository once, mirroring any
+    /// tags that have changed since they were last mirrored.
+    ///
+    /// Failing to mirror a single tag does not stop the others from
+    /// being mirrored. Such tags are reported in the result and will
+    /// be tried again on the next poll.
+    pub async fn poll(&mut self) -> Result<MirrorPollResult> {
+        let mut result = MirrorPollResult {
+            started: Utc::now(),
+            ..Default::default()
+        };
+        // the destination may have been cleaned since the last
+        // poll, so nothing can be assumed about what was synced
+        self.syncer.processed_digests = Arc::new(Default::default());
+        let scopes = match &self.namespaces {
+            None => vec![(
+                self.syncer
+                    .src
+                    .get_tag_namespace()
+                    .map(|ns| ns.into_owned()),
+                self.syncer
+                    .dest
+                    .get_tag_namespace()
+                    .map(|ns| ns.into_owned()),
+            )],
+            Some(namespaces) => namespaces
+                .iter()
+                .map(|ns| (Some(ns.clone()), Some(ns.clone())))
+                .collect(),
+        };
+        for (src_ns, dest_ns) in scopes {
+            self.poll_namespace(src_ns.as_deref(), dest_ns.as_deref(), &mut result)
+                .await?;
+        }
+        self.save_state().await?;
+        result.finished = Utc::now();
+        Ok(result)
+    }
+
+    async fn poll_namespace(
+        &mut self,
+        src_ns: Option<&TagNamespace>,
+        dest_ns: Option<&TagNamespace>,
+        result: &mut MirrorPollResult,
+    ) -> Result<()> {
+        let mut streams = self.syncer.src.iter_tag_streams_in_namespace(src_ns);
+        while let Some((spec, mut stream)) = streams.try_next().await? {
+            if !self.is_selected(&spec) {
+                continue;
+            }
+            let Some(head) = stream.try_next().await? else {
+                continue;
+            };
+            result.checked_tags += 1;
+            let key = match src_ns {
+                Some(ns) => format!("{ns}:{}", spec.path()),
+                None => format!(":{}", spec.path()),
+            };
+            let head_digest = head.digest()?;
+            if self.state.tags.get(&key) == Some(&head_digest) {
+                continue;
+            }
+            match self.mirror_tag(dest_ns, &head).await {
+                Ok(summary) => {
+                    if let Some(summary) = summary {
+                        result.mirrored_tags += 1;
+                        result.summary += summary;
+                        let lag = (Utc::now() - head.time).max(chrono::Duration::zero());
+                        result.max_lag = result.max_lag.max(Some(lag));
+                    }
+                    self.state.tags.insert(key, head_digest);
+                }
+                Err(err) => {
+                    tracing::warn!(tag = %spec, "failed to mirror tag: {err}");
+                    result.failed_tags.push(spec.with_version(0));
+                    // objects that failed to sync may have been marked as
+                    // processed, and must not be skipped by other tags
+                    self.syncer.processed_digests = Arc::new(Default::default());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Mirror the given tag, returning `None` if it was already
+    /// present in the destination.
+    async fn mirror_tag(
+        &self,
+        dest_ns: Option<&TagNamespace>,
+        head: &tracking::Tag,
+    ) -> Result<Option<SyncSummary>> {
+        let dest = self.syncer.dest;
+        match dest
+            .resolve_tag_in_namespace(dest_ns, &head.to_spec(0))
+            .await
+        {
+            Ok(existing) if existing.digest()? == head.digest()? => return Ok(None),
+            _ => {}
+        }
+        let summary = self.syncer.sync_digest(head.target).await?.summary();
+        dest.insert_tag_in_namespace(dest_ns, head).await?;
+        Ok(Some(summary))
+    }
+
+    fn is_selected(&self, spec: &tracking::TagSpec) -> bool {
+        if self.prefixes.is_empty() {
+            return true;
+        }
+        let path = spec.path();
+        let path = path.as_str();
+        self.prefixes.iter().any(|prefix| {
+            prefix.is_empty()
+                || path == prefix
+                || path
+                    .strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+
+    async fn save_state(&self) -> Result<()> {
+        let Some(path) = &self.state_path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(|err| {
+                Error::StorageWriteError("create_dir_all on mirror state", path.clone(), err)
+            })?;
+        }
+        let data = serde_json::to_vec(&self.state)
+            .map_err(|err| Error::String(format!("Failed to serialize mirror state: {err}")))?;
+        // write and rename so that an interrupted
+        // write never leaves behind a corrupt file
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, data)
+            .await
+            .map_err(|err| Error::StorageWriteError("write on mirror state", tmp.clone(), err))?;
+        tokio::fs::rename(&tmp, path)
+            .await
+            .map_err(|err| Error::StorageWriteError("rename on mirror state", path.clone(), err))
+    }
+}
+
+/// The tags mirrored so far, persisted between runs
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct MirrorState {
+    /// The digest of the latest mirrored tag, by
+    /// source namespace and tag path
+    tags: BTreeMap<String, encoding::Digest>,
+}
+
+/// The outcome of checking the source repository once
+#[derive(Debug, Default)]
+pub struct MirrorPollResult {
+    /// When the check started
+    pub started: DateTime<Utc>,
+    /// When the check finished
+    pub finished: DateTime<Utc>,
+    /// The number of tags that matched the mirror's filters
+    pub checked_tags: usize,
+    /// The number of tags that were mirrored into the destination
+    pub mirrored_tags: usize,
+    /// The tags that could not be mirrored, to be retried
+    pub failed_tags: Vec<tracking::TagSpec>,
+    /// The longest time between a tag being created in
+    /// the source and it being mirrored in the destination
+    pub max_lag: Option<chrono::Duration>,
+    /// The data synced while mirroring tags
+    pub summary: SyncSummary,
+}
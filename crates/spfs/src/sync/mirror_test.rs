@@ -0,0 +1,121 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use futures::StreamExt;
+use rstest::rstest;
+
+use super::Mirror;
+use crate::fixtures::*;
+use crate::prelude::*;
+use crate::sync::Syncer;
+use crate::{encoding, tracking};
+
+/// Commit some data to the repo and return the digest of its platform
+async fn commit_platform(repo: &TempRepo, content: &str) -> encoding::Digest {
+    let dir = tempfile::tempdir().unwrap();
+    ensure(dir.path().join("file.txt"), content);
+    let manifest = crate::Committer::new(repo)
+        .commit_dir(dir.path())
+        .await
+        .unwrap();
+    let layer = repo
+        .create_layer(&manifest.to_graph_manifest())
+        .await
+        .unwrap();
+    let platform = repo
+        .create_platform(layer.digest().unwrap().into())
+        .await
+        .unwrap();
+    platform.digest().unwrap()
+}
+
+#[rstest]
+#[case::fs(tmprepo("fs"), tmprepo("fs"))]
+#[tokio::test]
+async fn test_mirror_tags_with_prefix(
+    #[case]
+    #[future]
+    src: TempRepo,
+    #[case]
+    #[future]
+    dest: TempRepo,
+) {
+    init_logging();
+    let src = src.await;
+    let dest = dest.await;
+    let selected = tracking::TagSpec::parse("spk/pkg/my-pkg").unwrap();
+    let ignored = tracking::TagSpec::parse("other/my-pkg").unwrap();
+    let first = commit_platform(&src, "first").await;
+    src.push_tag(&selected, &first).await.unwrap();
+    src.push_tag(&ignored, &first).await.unwrap();
+
+    let mut mirror = Mirror::new(Syncer::new(&src, &dest)).with_prefixes(["spk/pkg"]);
+    let result = mirror.poll().await.unwrap();
+    assert_eq!(result.checked_tags, 1);
+    assert_eq!(result.mirrored_tags, 1);
+    assert!(result.failed_tags.is_empty());
+    assert_eq!(dest.resolve_tag(&selected).await.unwrap().target, first);
+    assert!(dest.resolve_tag(&ignored).await.is_err());
+    assert!(dest.has_object(first).await, "tag target should be synced");
+
+    let result = mirror.poll().await.unwrap();
+    assert_eq!(result.mirrored_tags, 0, "nothing should have changed");
+
+    let second = commit_platform(&src, "second").await;
+    src.push_tag(&selected, &second).await.unwrap();
+    let result = mirror.poll().await.unwrap();
+    assert_eq!(
+        result.mirrored_tags, 1,
+        "new tag version should be mirrored"
+    );
+    assert_eq!(dest.resolve_tag(&selected).await.unwrap().target, second);
+    assert!(dest.has_object(second).await, "tag target should be synced");
+}
+
+#[rstest]
+#[case::fs(tmprepo("fs"), tmprepo("fs"))]
+#[tokio::test]
+async fn test_mirror_resumes_after_restart(
+    #[case]
+    #[future]
+    src: TempRepo,
+    #[case]
+    #[future]
+    dest: TempRepo,
+    tmpdir: tempfile::TempDir,
+) {
+    init_logging();
+    let src = src.await;
+    let dest = dest.await;
+    let tag = tracking::TagSpec::parse("spk/pkg/my-pkg").unwrap();
+    let digest = commit_platform(&src, "data").await;
+    src.push_tag(&tag, &digest).await.unwrap();
+
+    let state = tmpdir.path().join("state.json");
+    let mut mirror = Mirror::new(Syncer::new(&src, &dest))
+        .with_state_file(&state)
+        .await
+        .unwrap();
+    assert_eq!(mirror.poll().await.unwrap().mirrored_tags, 1);
+    drop(mirror);
+
+    let mut mirror = Mirror::new(Syncer::new(&src, &dest))
+        .with_state_file(&state)
+        .await
+        .unwrap();
+    assert_eq!(mirror.mirrored_tag_count(), 1, "state should be reloaded");
+    let result = mirror.poll().await.unwrap();
+    assert_eq!(result.checked_tags, 1);
+    assert_eq!(
+        result.mirrored_tags, 0,
+        "mirrored tags should be remembered"
+    );
+
+    // even without any state, tags that are already in
+    // the destination should not be pushed again
+    let mut mirror = Mirror::new(Syncer::new(&src, &dest));
+    assert_eq!(mirror.poll().await.unwrap().mirrored_tags, 0);
+    let history: Vec<_> = dest.read_tag(&tag).await.unwrap().collect().await;
+    assert_eq!(history.len(), 1, "tag should only be written once");
+}
@@ -3,6 +3,7 @@
 // https://github.com/spkenv/spk
 
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use once_cell::sync::OnceCell;
 use progress_bar_derive_macro::ProgressBar;
@@ -108,6 +109,17 @@ pub trait SyncReporter: Send + Sync {
 
     /// Called when a payload has finished syncing
     fn synced_payload(&self, _result: &SyncPayloadResult) {}
+
+    /// Called as payload data is transferred, with the number of
+    /// bytes read since the last call for the same payload.
+    ///
+    /// This is called often and can be used to track the current
+    /// throughput, so implementations should do very little work.
+    fn transferred_payload_bytes(&self, _bytes: u64) {}
+
+    /// Called when an object is not synced because a journal shows
+    /// that a previous, interrupted sync already completed it
+    fn resumed_object(&self, _digest: encoding::Digest) {}
 }
 
 impl<T> SyncReporter for Arc<T>
@@ -174,6 +186,12 @@ where
     fn synced_payload(&self, result: &SyncPayloadResult) {
         (**self).synced_payload(result)
     }
+    fn transferred_payload_bytes(&self, bytes: u64) {
+        (**self).transferred_payload_bytes(bytes)
+    }
+    fn resumed_object(&self, digest: encoding::Digest) {
+        (**self).resumed_object(digest)
+    }
 }
 
 impl SyncReporter for Box<dyn SyncReporter> {
@@ -237,6 +255,12 @@ impl SyncReporter for Box<dyn SyncReporter> {
     fn synced_payload(&self, result: &SyncPayloadResult) {
         (**self).synced_payload(result)
     }
+    fn transferred_payload_bytes(&self, bytes: u64) {
+        (**self).transferred_payload_bytes(bytes)
+    }
+    fn resumed_object(&self, digest: encoding::Digest) {
+        (**self).resumed_object(digest)
+    }
 }
 
 impl<T> SyncReporter for Box<Arc<T>>
@@ -303,6 +327,12 @@ where
     fn synced_payload(&self, result: &SyncPayloadResult) {
         (***self).synced_payload(result)
     }
+    fn transferred_payload_bytes(&self, bytes: u64) {
+        (***self).transferred_payload_bytes(bytes)
+    }
+    fn resumed_object(&self, digest: encoding::Digest) {
+        (***self).resumed_object(digest)
+    }
 }
 
 #[derive(Default)]
@@ -313,6 +343,7 @@ impl SyncReporter for SilentSyncReporter {}
 #[derive(Default)]
 pub struct ConsoleSyncReporter {
     bars: OnceCell<ConsoleSyncReporterBars>,
+    resumed: AtomicUsize,
 }
 
 impl ConsoleSyncReporter {
@@ -339,7 +370,12 @@ impl SyncReporter for ConsoleSyncReporter {
     fn synced_blob(&self, result: &SyncBlobResult) {
         let bars = self.get_bars();
         bars.payloads.inc(1);
-        bars.bytes.inc(result.summary().synced_payload_bytes);
+        match result {
+            // chunks that were skipped still count towards the
+            // completion of the blob, which was counted in full
+            SyncBlobResult::SyncedChunks { blob, .. } => bars.bytes.inc(blob.size()),
+            _ => bars.bytes.inc(result.summary().synced_payload_bytes),
+        }
     }
 
     fn synced_env(&self, _result: &SyncEnvResult) {
@@ -349,8 +385,21 @@ impl SyncReporter for ConsoleSyncReporter {
             bars.manifests.abandon();
             bars.payloads.abandon();
             bars.bytes.abandon();
+            bars.throughput.abandon();
+        }
+        let resumed = self.resumed.swap(0, Ordering::Relaxed);
+        if resumed > 0 {
+            tracing::info!("resumed from checkpoint, skipped {resumed} objects synced previously");
         }
     }
+
+    fn resumed_object(&self, _digest: encoding::Digest) {
+        self.resumed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn transferred_payload_bytes(&self, bytes: u64) {
+        self.get_bars().throughput.inc(bytes);
+    }
 }
 
 #[derive(ProgressBar)]
@@ -370,6 +419,11 @@ struct ConsoleSyncReporterBars {
         template = "      {spinner} {msg:<16.green} [{bar:40.cyan/dim}] {bytes:>8}/{total_bytes:7}"
     )]
     bytes: indicatif::ProgressBar,
+    #[progress_bar(
+        message = "transfer rate",
+        template = "      {spinner} {msg:<16.green} {binary_bytes_per_sec}"
+    )]
+    throughput: indicatif::ProgressBar,
 }
 
 #[derive(Default, Debug)]
@@ -382,6 +436,9 @@ pub struct SyncSummary {
     pub skipped_objects: usize,
     /// The number of objects synced
     pub synced_objects: usize,
+    /// The number of objects not synced because a previous,
+    /// interrupted sync was recorded as having completed them
+    pub resumed_objects: usize,
     /// The number of payloads not synced because they already existed
     pub skipped_payloads: usize,
     /// The number of payloads synced
@@ -404,6 +461,13 @@ impl SyncSummary {
             ..Default::default()
         }
     }
+
+    fn resumed_one_object() -> Self {
+        Self {
+            resumed_objects: 1,
+            ..Default::default()
+        }
+    }
 }
 
 impl std::ops::AddAssign for SyncSummary {
@@ -415,6 +479,7 @@ impl std::ops::AddAssign for SyncSummary {
             synced_tags,
             skipped_objects,
             synced_objects,
+            resumed_objects,
             skipped_payloads,
             synced_payloads,
             synced_payload_bytes,
@@ -423,6 +488,7 @@ impl std::ops::AddAssign for SyncSummary {
         self.synced_tags += synced_tags;
         self.skipped_objects += skipped_objects;
         self.synced_objects += synced_objects;
+        self.resumed_objects += resumed_objects;
         self.skipped_payloads += skipped_payloads;
         self.synced_payloads += synced_payloads;
         self.synced_payload_bytes += synced_payload_bytes;
@@ -532,6 +598,8 @@ pub enum SyncPlatformResult {
     Skipped,
     /// The platform was already synced in this session
     Duplicate,
+    /// The platform was synced by a previous, interrupted session
+    Resumed,
     /// The platform was at least partially synced
     Synced {
         platform: graph::Platform,
@@ -543,6 +611,7 @@ impl SyncPlatformResult {
     pub fn summary(&self) -> SyncSummary {
         match self {
             Self::Skipped | Self::Duplicate => SyncSummary::skipped_one_object(),
+            Self::Resumed => SyncSummary::resumed_one_object(),
             Self::Synced { results, .. } => {
                 let mut summary = results.iter().map(|r| r.summary()).sum();
                 summary += SyncSummary::synced_one_object();
@@ -558,6 +627,8 @@ pub enum SyncLayerResult {
     Skipped,
     /// The layer was already synced in this session
     Duplicate,
+    /// The layer was synced by a previous, interrupted session
+    Resumed,
     /// The layer was synced
     Synced {
         layer: graph::Layer,
@@ -569,6 +640,7 @@ impl SyncLayerResult {
     pub fn summary(&self) -> SyncSummary {
         match self {
             Self::Skipped | Self::Duplicate => SyncSummary::skipped_one_object(),
+            Self::Resumed => SyncSummary::resumed_one_object(),
             Self::Synced { results, .. } => {
                 let mut summary = results.iter().map(|r| r.summary()).sum();
                 summary += SyncSummary::synced_one_object();
@@ -584,6 +656,8 @@ pub enum SyncManifestResult {
     Skipped,
     /// The manifest was already synced in this session
     Duplicate,
+    /// The manifest was synced by a previous, interrupted session
+    Resumed,
     /// The manifest was at least partially synced
     Synced {
         manifest: graph::Manifest,
@@ -595,6 +669,7 @@ impl SyncManifestResult {
     pub fn summary(&self) -> SyncSummary {
         match self {
             Self::Skipped | Self::Duplicate => SyncSummary::skipped_one_object(),
+            Self::Resumed => SyncSummary::resumed_one_object(),
             Self::Synced { results, .. } => {
                 let mut summary = results.iter().map(|r| r.summary()).sum();
                 summary += SyncSummary::synced_one_object();
@@ -658,22 +733,35 @@ pub enum SyncBlobResult {
     Skipped,
     /// The blob was already synced in this session
     Duplicate,
+    /// The blob was synced by a previous, interrupted session
+    Resumed,
     /// The blob was synced
     Synced {
         blob: graph::Blob,
         result: SyncPayloadResult,
     },
+    /// The blob was synced as a sequence of chunks
+    SyncedChunks {
+        blob: graph::ChunkedBlob,
+        results: Vec<SyncPayloadResult>,
+    },
 }
 
 impl SyncBlobResult {
     pub fn summary(&self) -> SyncSummary {
         match self {
             Self::Skipped | Self::Duplicate => SyncSummary::skipped_one_object(),
+            Self::Resumed => SyncSummary::resumed_one_object(),
             Self::Synced { result, .. } => {
                 let mut summary = result.summary();
                 summary += SyncSummary::synced_one_object();
                 summary
             }
+            Self::SyncedChunks { results, .. } => {
+                let mut summary: SyncSummary = results.iter().map(|r| r.summary()).sum();
+                summary += SyncSummary::synced_one_object();
+                summary
+            }
         }
     }
 }
@@ -0,0 +1,148 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::future::Future;
+use std::num::NonZeroU64;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+
+use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+use super::reporter::{SyncReporter, SyncReporters};
+use crate::tracking::BlobRead;
+
+#[cfg(test)]
+#[path = "./throttle_test.rs"]
+mod throttle_test;
+
+/// How far ahead of the allowed rate data can be transferred
+/// after a period of inactivity
+const MAX_BURST: Duration = Duration::from_secs(1);
+
+/// Limits the rate at which payload data is transferred.
+///
+/// A single limiter is shared by all concurrent transfers of a
+/// syncer, so that the limit applies to their combined throughput.
+#[derive(Debug)]
+pub struct BandwidthLimiter {
+    bytes_per_second: NonZeroU64,
+    /// The time at which all data transferred so far
+    /// would have completed at exactly the allowed rate
+    schedule: std::sync::Mutex<Instant>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(bytes_per_second: NonZeroU64) -> Self {
+        Self {
+            bytes_per_second,
+            schedule: std::sync::Mutex::new(Instant::now()),
+        }
+    }
+
+    /// The maximum number of bytes transferred per second
+    pub fn bytes_per_second(&self) -> NonZeroU64 {
+        self.bytes_per_second
+    }
+
+    /// The time until which no more data should be transferred,
+    /// or `None` if data can be transferred immediately.
+    fn delayed_until(&self) -> Option<Instant> {
+        let schedule = match self.schedule.lock() {
+            Ok(schedule) => *schedule,
+            Err(poisoned) => *poisoned.into_inner(),
+        };
+        let ready = schedule.checked_sub(MAX_BURST)?;
+        (ready > Instant::now()).then_some(ready)
+    }
+
+    /// Account for the transfer of some number of bytes
+    fn consume(&self, bytes: u64) {
+        let cost = Duration::from_secs_f64(bytes as f64 / self.bytes_per_second.get() as f64);
+        let now = Instant::now();
+        let mut schedule = match self.schedule.lock() {
+            Ok(schedule) => schedule,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        // time spent idle cannot be saved up for later
+        *schedule = (*schedule).max(now) + cost;
+    }
+}
+
+/// Wraps a payload being transferred between repositories,
+/// reporting the data read from it and limiting the rate
+/// at which it can be read.
+pub(super) struct TransferReader {
+    inner: Pin<Box<dyn BlobRead>>,
+    limiter: Option<Arc<BandwidthLimiter>>,
+    reporter: SyncReporters,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl TransferReader {
+    pub fn new(
+        inner: Pin<Box<dyn BlobRead>>,
+        limiter: Option<Arc<BandwidthLimiter>>,
+        reporter: SyncReporters,
+    ) -> Self {
+        Self {
+            inner,
+            limiter,
+            reporter,
+            sleep: None,
+        }
+    }
+}
+
+impl BlobRead for TransferReader {
+    fn permissions(&self) -> Option<u32> {
+        self.inner.permissions()
+    }
+}
+
+impl AsyncBufRead for TransferReader {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<&[u8]>> {
+        let this = self.get_mut();
+        if let Some(limiter) = &this.limiter {
+            loop {
+                if let Some(sleep) = this.sleep.as_mut() {
+                    ready!(sleep.as_mut().poll(cx));
+                    this.sleep = None;
+                }
+                // other transfers may have used up the allowance
+                // while sleeping, so always check again
+                match limiter.delayed_until() {
+                    Some(until) => this.sleep = Some(Box::pin(tokio::time::sleep_until(until))),
+                    None => break,
+                }
+            }
+        }
+        this.inner.as_mut().poll_fill_buf(cx)
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        if let Some(limiter) = &this.limiter {
+            limiter.consume(amt as u64);
+        }
+        this.reporter.transferred_payload_bytes(amt as u64);
+        this.inner.as_mut().consume(amt)
+    }
+}
+
+impl AsyncRead for TransferReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let available = ready!(self.as_mut().poll_fill_buf(cx))?;
+        let amt = available.len().min(buf.remaining());
+        buf.put_slice(&available[..amt]);
+        self.consume(amt);
+        Poll::Ready(Ok(()))
+    }
+}
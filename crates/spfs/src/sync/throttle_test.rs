@@ -0,0 +1,62 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::num::NonZeroU64;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rstest::rstest;
+use tokio::io::AsyncReadExt;
+
+use super::{BandwidthLimiter, TransferReader};
+use crate::sync::reporter::SyncReporters;
+
+async fn read_all(data: Vec<u8>, limiter: Option<Arc<BandwidthLimiter>>) -> Duration {
+    let reader = TransferReader::new(
+        Box::pin(std::io::Cursor::new(data.clone())),
+        limiter,
+        SyncReporters::silent(),
+    );
+    let start = tokio::time::Instant::now();
+    let mut actual = Vec::new();
+    Box::pin(reader).read_to_end(&mut actual).await.unwrap();
+    assert_eq!(actual, data, "reader should not modify the data");
+    start.elapsed()
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_transfer_reader_unlimited() {
+    let elapsed = read_all(vec![1; 1024 * 1024], None).await;
+    assert!(elapsed < Duration::from_secs(1));
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_transfer_reader_limited() {
+    let limiter = Arc::new(BandwidthLimiter::new(NonZeroU64::new(64 * 1024).unwrap()));
+    // the first second of data can be read in a burst,
+    // and the rest must be spread over the following second
+    let elapsed = read_all(vec![1; 128 * 1024], Some(limiter)).await;
+    assert!(
+        elapsed >= Duration::from_millis(900),
+        "expected transfer to be limited, took {elapsed:?}"
+    );
+}
+
+#[rstest]
+#[tokio::test]
+async fn test_transfer_reader_shares_limit() {
+    let limiter = Arc::new(BandwidthLimiter::new(NonZeroU64::new(64 * 1024).unwrap()));
+    // each transfer alone fits in the burst allowance,
+    // but not when they share the same limit
+    let (first, second) = tokio::join!(
+        read_all(vec![1; 64 * 1024], Some(Arc::clone(&limiter))),
+        read_all(vec![2; 64 * 1024], Some(limiter)),
+    );
+    assert!(
+        first.max(second) >= Duration::from_millis(900),
+        "expected transfers to share a limit, took {first:?} and {second:?}"
+    );
+}
@@ -11,7 +11,7 @@ use super::Syncer;
 use crate::config::Config;
 use crate::fixtures::*;
 use crate::prelude::*;
-use crate::{Error, encoding, storage, tracking};
+use crate::{Error, encoding, graph, storage, tracking};
 
 #[rstest]
 #[tokio::test]
@@ -291,3 +291,233 @@ async fn config(tmpdir: tempfile::TempDir) -> (tempfile::TempDir, Config) {
     conf.storage.root = repo_path;
     (tmpdir, conf)
 }
+
+/// Repeatable, pseudo-random data that is large enough to be chunked
+fn chunkable_data(seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..crate::chunking::MIN_CHUNKED_PAYLOAD_SIZE * 2)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 56) as u8
+        })
+        .collect()
+}
+
+#[rstest]
+#[case::fs(tmprepo("fs"), tmprepo("fs"))]
+#[tokio::test]
+async fn test_sync_blob_as_chunks(
+    #[case]
+    #[future]
+    repo_a: TempRepo,
+    #[case]
+    #[future]
+    repo_b: TempRepo,
+) {
+    init_logging();
+    let repo_a = repo_a.await;
+    let repo_b = repo_b.await;
+    let chunking = crate::chunking::ChunkingConfig {
+        enabled: true,
+        threshold: 0,
+        min_size: 16 * 1024,
+        avg_size: 64 * 1024,
+        max_size: 256 * 1024,
+    };
+
+    let data = chunkable_data(7);
+    let digest = repo_a
+        .commit_blob(Box::pin(std::io::Cursor::new(data.clone())))
+        .await
+        .unwrap();
+
+    let res = Syncer::new(&repo_a, &repo_b)
+        .with_chunking(Some(chunking.clone()))
+        .sync_digest(digest)
+        .await
+        .expect("failed to sync blob");
+    assert!(res.summary().synced_payloads > 1, "should sync many chunks");
+    assert!(
+        repo_b
+            .read_object(digest)
+            .await
+            .unwrap()
+            .into_chunked_blob()
+            .is_some(),
+        "blob should be stored as chunks in the destination"
+    );
+
+    let mut edited = data.clone();
+    edited[1024..1040].copy_from_slice(b"some edited data");
+    let edited_digest = repo_a
+        .commit_blob(Box::pin(std::io::Cursor::new(edited.clone())))
+        .await
+        .unwrap();
+    let res = Syncer::new(&repo_a, &repo_b)
+        .with_chunking(Some(chunking))
+        .sync_digest(edited_digest)
+        .await
+        .expect("failed to sync edited blob");
+    let summary = res.summary();
+    assert!(
+        summary.synced_payloads < summary.skipped_payloads,
+        "most chunks should already exist in the destination: {summary:?}"
+    );
+
+    let mut actual = Vec::new();
+    tokio::io::AsyncReadExt::read_to_end(
+        &mut repo_b.open_payload(edited_digest).await.unwrap().0,
+        &mut actual,
+    )
+    .await
+    .unwrap();
+    assert!(
+        actual == edited,
+        "chunks should reassemble the edited payload"
+    );
+}
+
+#[rstest]
+#[case::fs(tmprepo("fs"), tmprepo("fs"))]
+#[tokio::test]
+async fn test_sync_chunked_layer_is_usable(
+    #[case]
+    #[future]
+    repo_a: TempRepo,
+    #[case]
+    #[future]
+    repo_b: TempRepo,
+    tmpdir: tempfile::TempDir,
+) {
+    init_logging();
+    let repo_a = repo_a.await;
+    let repo_b = repo_b.await;
+    let chunking = crate::chunking::ChunkingConfig {
+        enabled: true,
+        threshold: 0,
+        min_size: 16 * 1024,
+        avg_size: 64 * 1024,
+        max_size: 256 * 1024,
+    };
+
+    let data = chunkable_data(11);
+    let src_dir = tmpdir.path().join("source");
+    std::fs::create_dir_all(&src_dir).unwrap();
+    std::fs::write(src_dir.join("large.bin"), &data).unwrap();
+    ensure(src_dir.join("small.txt"), "too small to chunk");
+    let manifest = crate::Committer::new(&repo_a)
+        .commit_dir(src_dir.as_path())
+        .await
+        .unwrap();
+    let layer = repo_a.create_layer_from_manifest(&manifest).await.unwrap();
+    let large_digest = manifest.get_path("large.bin").unwrap().object;
+
+    Syncer::new(&repo_a, &repo_b)
+        .with_chunking(Some(chunking))
+        .sync_digest(layer.digest().unwrap())
+        .await
+        .expect("failed to sync layer");
+    assert!(
+        repo_b
+            .read_object(large_digest)
+            .await
+            .unwrap()
+            .into_chunked_blob()
+            .is_some(),
+        "large file should be stored as chunks in the destination"
+    );
+
+    let blob = repo_b
+        .read_blob(large_digest)
+        .await
+        .expect("chunked blob should be readable as a blob");
+    assert_eq!(blob.size(), data.len() as u64);
+    let mut actual = Vec::new();
+    tokio::io::AsyncReadExt::read_to_end(
+        &mut repo_b.open_payload(*blob.payload()).await.unwrap().0,
+        &mut actual,
+    )
+    .await
+    .unwrap();
+    assert!(actual == data, "chunks should reassemble the payload");
+
+    let results = crate::Checker::new(&repo_b.repo())
+        .check_all_objects()
+        .await
+        .unwrap();
+    let summary: crate::check::CheckSummary = results.iter().map(|r| r.summary()).sum();
+    assert!(
+        summary.missing_objects.is_empty() && summary.missing_payloads.is_empty(),
+        "chunked data should pass a check: {summary:?}"
+    );
+
+    let stack = graph::Stack::from(layer.digest().unwrap());
+    crate::oci::OciExporter::new(&repo_b)
+        .export(&stack, tmpdir.path().join("layout"))
+        .await
+        .expect("chunked data should be exportable");
+}
+
+#[rstest]
+#[case::fs(tmprepo("fs"), tmprepo("fs"))]
+#[tokio::test]
+async fn test_sync_resumes_from_journal(
+    #[case]
+    #[future]
+    repo_a: TempRepo,
+    #[case]
+    #[future]
+    repo_b: TempRepo,
+    tmpdir: tempfile::TempDir,
+) {
+    init_logging();
+    let repo_a = repo_a.await;
+    let repo_b = repo_b.await;
+
+    let src_dir = tmpdir.path().join("source");
+    ensure(src_dir.join("dir/file.txt"), "hello");
+    ensure(src_dir.join("dir2/otherfile.txt"), "hello2");
+
+    let manifest = crate::Committer::new(&repo_a)
+        .commit_dir(src_dir.as_path())
+        .await
+        .unwrap();
+    let manifest = manifest.to_graph_manifest();
+    let manifest_digest = manifest.digest().unwrap();
+    let layer = repo_a.create_layer(&manifest).await.unwrap();
+
+    // simulate a previous sync that was interrupted after
+    // completing the manifest but before the layer
+    let journal_path = tmpdir.path().join("journal");
+    super::journal::SyncJournal::open(&journal_path)
+        .await
+        .unwrap()
+        .record(manifest_digest)
+        .await
+        .unwrap();
+
+    let journal = Arc::new(
+        super::journal::SyncJournal::open(&journal_path)
+            .await
+            .unwrap(),
+    );
+    assert_eq!(journal.resumed_count(), 1);
+    let res = Syncer::new(&repo_a, &repo_b)
+        .with_journal(Arc::clone(&journal))
+        .sync_digest(layer.digest().unwrap())
+        .await
+        .expect("failed to sync layer");
+    let summary = res.summary();
+    assert_eq!(summary.resumed_objects, 1, "manifest should be resumed");
+    assert_eq!(summary.synced_payloads, 0, "payloads should not be synced");
+    assert!(repo_b.has_object(layer.digest().unwrap()).await);
+    assert!(
+        !repo_b.has_object(manifest_digest).await,
+        "resumed manifest should not be visited again"
+    );
+
+    journal.finish().await.unwrap();
+    assert!(!journal_path.exists(), "journal should be removed");
+}
@@ -0,0 +1,128 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+//! Shared helpers for loading TLS certificates and keys
+
+use std::path::Path;
+use std::sync::Arc;
+
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::crypto::CryptoProvider;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+use crate::{Error, Result};
+
+/// The crypto implementation used for all tls connections
+pub(crate) fn crypto_provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// Read the contents of a PEM file, where the context
+/// describes the file in any error that is returned
+pub(crate) fn read_pem(path: &Path, context: &'static str) -> Result<Vec<u8>> {
+    std::fs::read(path).map_err(|err| Error::StorageReadError(context, path.into(), err))
+}
+
+/// Load all of the PEM encoded certificates in the given file
+pub(crate) fn load_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let data = read_pem(path, "read of tls certificates")?;
+    let certs = rustls_pemfile::certs(&mut data.as_slice())
+        .collect::<std::io::Result<Vec<_>>>()
+        .map_err(|err| Error::StorageReadError("parse of tls certificates", path.into(), err))?;
+    if certs.is_empty() {
+        return Err(Error::String(format!(
+            "No certificates found in {}",
+            path.display()
+        )));
+    }
+    Ok(certs)
+}
+
+/// Load the first PEM encoded private key in the given file
+pub(crate) fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let data = read_pem(path, "read of tls private key")?;
+    rustls_pemfile::private_key(&mut data.as_slice())
+        .map_err(|err| Error::StorageReadError("parse of tls private key", path.into(), err))?
+        .ok_or_else(|| Error::String(format!("No private key found in {}", path.display())))
+}
+
+/// Load the certificate authorities that are trusted to sign peer certificates.
+///
+/// When no file is given, the native certificate store of the
+/// current system is used instead.
+pub(crate) fn load_root_store(ca_file: Option<&Path>) -> Result<rustls::RootCertStore> {
+    let mut roots = rustls::RootCertStore::empty();
+    let certs = match ca_file {
+        Some(path) => load_certificates(path)?,
+        None => {
+            let native = rustls_native_certs::load_native_certs();
+            for err in native.errors {
+                tracing::debug!("failed to load a native certificate: {err}");
+            }
+            native.certs
+        }
+    };
+    let (_, ignored) = roots.add_parsable_certificates(certs);
+    if ignored > 0 {
+        tracing::warn!("ignored {ignored} invalid certificate authorities");
+    }
+    Ok(roots)
+}
+
+/// Create the configuration for a tls client.
+///
+/// The optional identity is a certificate and private key
+/// file pair that is presented to servers which require
+/// mutual tls.
+pub(crate) fn client_config(
+    ca_file: Option<&Path>,
+    identity: Option<(&Path, &Path)>,
+) -> Result<rustls::ClientConfig> {
+    let roots = load_root_store(ca_file)?;
+    let builder = rustls::ClientConfig::builder_with_provider(crypto_provider())
+        .with_safe_default_protocol_versions()
+        .map_err(|err| Error::String(format!("Invalid tls client configuration: {err}")))?
+        .with_root_certificates(roots);
+    let config = match identity {
+        Some((cert_file, key_file)) => builder
+            .with_client_auth_cert(load_certificates(cert_file)?, load_private_key(key_file)?)
+            .map_err(|err| Error::String(format!("Invalid tls client certificate: {err}")))?,
+        None => builder.with_no_client_auth(),
+    };
+    Ok(config)
+}
+
+/// Create the configuration for a tls server.
+///
+/// When a client certificate authority is given, clients must
+/// present a certificate signed by that authority (mutual tls).
+#[cfg(feature = "server")]
+pub(crate) fn server_config(
+    cert_file: &Path,
+    key_file: &Path,
+    client_ca_file: Option<&Path>,
+) -> Result<rustls::ServerConfig> {
+    let provider = crypto_provider();
+    let builder = rustls::ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .map_err(|err| Error::String(format!("Invalid tls server configuration: {err}")))?;
+    let builder = match client_ca_file {
+        Some(path) => {
+            let roots = load_root_store(Some(path))?;
+            let verifier = rustls::server::WebPkiClientVerifier::builder_with_provider(
+                Arc::new(roots),
+                provider,
+            )
+            .build()
+            .map_err(|err| {
+                Error::String(format!("Invalid tls client certificate authority: {err}"))
+            })?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    builder
+        .with_single_cert(load_certificates(cert_file)?, load_private_key(key_file)?)
+        .map_err(|err| Error::String(format!("Invalid tls server certificate: {err}")))
+}
@@ -102,3 +102,42 @@ where
         Some(self.permissions)
     }
 }
+
+/// Makes a reader that is only [`Send`] also [`Sync`], as
+/// required of any [`BlobRead`].
+///
+/// The reader is held in a mutex that is never locked, since it is
+/// only ever accessed through a mutable reference, but which allows
+/// this wrapper to be [`Sync`] even when the reader is not.
+pub(crate) struct SyncReader<R> {
+    inner: std::sync::Mutex<R>,
+}
+
+impl<R> SyncReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner: std::sync::Mutex::new(inner),
+        }
+    }
+
+    /// Access the wrapped reader
+    pub fn get_mut(&mut self) -> &mut R {
+        match self.inner.get_mut() {
+            Ok(inner) => inner,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+impl<R> AsyncRead for SyncReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        Pin::new(self.get_mut().get_mut()).poll_read(cx, buf)
+    }
+}
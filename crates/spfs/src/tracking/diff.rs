@@ -105,6 +105,12 @@ impl<U1, U2> Diff<U1, U2> {
             if a.object != b.object {
                 details = format!("{details} {{!content!}}");
             }
+            if a.xattrs != b.xattrs {
+                details = format!("{details} {{!xattrs!}}");
+            }
+            if a.hardlink != b.hardlink {
+                details = format!("{details} {{!hardlink!}}");
+            }
         }
         details
     }
@@ -12,6 +12,9 @@ use crate::{Error, Result, encoding};
 #[path = "./entry_test.rs"]
 mod entry_test;
 
+/// The extended attributes of a file or directory, by name
+pub type Xattrs = std::collections::BTreeMap<String, Vec<u8>>;
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum EntryKind {
     /// directory / node
@@ -113,6 +116,14 @@ pub struct Entry<T = ()> {
     pub user_data: T,
     /// The size associated with non-blob entries.
     pub legacy_size: u64,
+    /// Extended attributes, which are only captured when requested
+    pub xattrs: Xattrs,
+    /// The path of another entry in the same manifest that this
+    /// file was hard linked to when it was captured.
+    ///
+    /// Linked entries still hold their own object and mode, so
+    /// this is only a hint for how the file should be rendered.
+    pub hardlink: Option<String>,
 }
 
 impl<T> std::fmt::Debug for Entry<T>
@@ -129,14 +140,22 @@ where
             entries,
             user_data,
             legacy_size: _,
+            xattrs,
+            hardlink,
         } = self;
-        f.debug_struct("Entry")
-            .field("kind", kind)
+        let mut f = f.debug_struct("Entry");
+        f.field("kind", kind)
             .field("mode", &format!("{mode:#06o}"))
             .field("object", object)
             .field("entries", entries)
-            .field("user_data", user_data)
-            .finish()
+            .field("user_data", user_data);
+        if !xattrs.is_empty() {
+            f.field("xattrs", &xattrs.keys().collect::<Vec<_>>());
+        }
+        if let Some(hardlink) = hardlink {
+            f.field("hardlink", hardlink);
+        }
+        f.finish()
     }
 }
 
@@ -151,11 +170,15 @@ impl<T, T2> PartialEq<Entry<T2>> for Entry<T> {
             entries,
             user_data: _,
             legacy_size: _,
+            xattrs,
+            hardlink,
         } = other;
         if self.kind != *kind
             || self.mode != *mode
             || self.size() != other.size()
             || self.object != *object
+            || self.xattrs != *xattrs
+            || self.hardlink != *hardlink
         {
             return false;
         }
@@ -196,6 +219,8 @@ impl<T> Entry<T> {
             entries: Default::default(),
             user_data,
             legacy_size: 0,
+            xattrs: Default::default(),
+            hardlink: None,
         }
     }
 
@@ -208,6 +233,8 @@ impl<T> Entry<T> {
             entries: Default::default(),
             user_data,
             legacy_size: 0,
+            xattrs: Default::default(),
+            hardlink: None,
         }
     }
 
@@ -221,6 +248,8 @@ impl<T> Entry<T> {
             entries: Default::default(),
             user_data,
             legacy_size: 0,
+            xattrs: Default::default(),
+            hardlink: None,
         }
     }
 
@@ -234,6 +263,8 @@ impl<T> Entry<T> {
             entries: Default::default(),
             user_data,
             legacy_size: 0,
+            xattrs: Default::default(),
+            hardlink: None,
         }
     }
 
@@ -318,6 +349,8 @@ impl<T> Entry<T> {
                 .collect(),
             user_data: (),
             legacy_size: self.legacy_size,
+            xattrs: self.xattrs,
+            hardlink: self.hardlink,
         }
     }
 
@@ -334,6 +367,8 @@ impl<T> Entry<T> {
                 .collect(),
             user_data,
             legacy_size: self.legacy_size,
+            xattrs: self.xattrs,
+            hardlink: self.hardlink,
         }
     }
 
@@ -351,6 +386,8 @@ impl<T> Entry<T> {
                 .collect(),
             user_data,
             legacy_size: self.legacy_size,
+            xattrs: self.xattrs,
+            hardlink: self.hardlink,
         }
     }
 }
@@ -363,6 +400,8 @@ where
         self.kind = other.kind;
         self.object = other.object;
         self.mode = other.mode;
+        self.xattrs.clone_from(&other.xattrs);
+        self.hardlink.clone_from(&other.hardlink);
         if !self.kind.is_tree() {
             return;
         }
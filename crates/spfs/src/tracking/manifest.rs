@@ -132,6 +132,57 @@ impl<T> Manifest<T> {
         Some(entry)
     }
 
+    /// Get a mutable entry in this manifest given its filepath.
+    pub fn get_path_mut<P: AsRef<str>>(&mut self, path: P) -> Option<&mut Entry<T>> {
+        const TRIM_END: &[char] = &['/'];
+        let path = Self::trim_leading_slash(path.as_ref()).trim_end_matches(TRIM_END);
+        let mut entry = &mut self.root;
+        if path.is_empty() {
+            return Some(entry);
+        }
+        for step in path.split('/') {
+            if let EntryKind::Tree = entry.kind {
+                entry = entry.entries.get_mut(step)?;
+            } else {
+                return None;
+            }
+        }
+
+        Some(entry)
+    }
+
+    /// Find the groups of files in this manifest that were hard
+    /// linked together, by the path of the first file in each group.
+    ///
+    /// A file is only considered to be linked when the entry that it
+    /// names exists and is a regular file with the same content and mode,
+    /// otherwise it is treated like any other independent file.
+    pub fn hardlink_groups(&self) -> HashMap<RelativePathBuf, Vec<RelativePathBuf>> {
+        let mut groups: HashMap<RelativePathBuf, Vec<RelativePathBuf>> = HashMap::new();
+        for node in self.walk() {
+            let Some(target) = &node.entry.hardlink else {
+                continue;
+            };
+            let Some(primary) = self.get_path(target) else {
+                continue;
+            };
+            if !node.entry.is_regular_file()
+                || !primary.is_regular_file()
+                || primary.hardlink.is_some()
+                || primary.object != node.entry.object
+                || primary.mode != node.entry.mode
+            {
+                tracing::debug!(path = %node.path, %target, "ignoring invalid hard link in manifest");
+                continue;
+            }
+            groups
+                .entry(RelativePathBuf::from(Self::trim_leading_slash(target)))
+                .or_default()
+                .push(Self::trim_leading_slash(node.path.as_str()).into());
+        }
+        groups
+    }
+
     /// List the names in a directory in this manifest.
     ///
     /// None is returned if the directory does not exist or the provided entry is
@@ -442,6 +493,43 @@ impl<U1, U2> PathFilter for &[Diff<U1, U2>] {
     }
 }
 
+/// The paths of files with more than one link that were
+/// found while computing a manifest, by device and inode
+#[derive(Default)]
+struct HardLinks(std::sync::Mutex<HashMap<(u64, u64), Vec<RelativePathBuf>>>);
+
+impl HardLinks {
+    #[cfg(unix)]
+    fn insert(&self, dev: u64, ino: u64, path: &std::path::Path) {
+        let path = RelativePathBuf::from(path.to_string_lossy().as_ref());
+        self.0
+            .lock()
+            .expect("hard link lock poisoned")
+            .entry((dev, ino))
+            .or_default()
+            .push(path);
+    }
+
+    /// Mark every file in each group of links as linked to the
+    /// first path in that group, so that the choice is stable
+    /// no matter the order in which files were visited.
+    fn apply(self, manifest: &mut Manifest) {
+        let groups = self.0.into_inner().expect("hard link lock poisoned");
+        for mut paths in groups.into_values() {
+            if paths.len() < 2 {
+                continue;
+            }
+            paths.sort();
+            let primary = paths[0].to_string();
+            for path in &paths[1..] {
+                if let Some(entry) = manifest.get_path_mut(path) {
+                    entry.hardlink = Some(primary.clone());
+                }
+            }
+        }
+    }
+}
+
 /// Computes manifests from directory structures on disk
 pub struct ManifestBuilder<H = (), F = (), R = ()>
 where
@@ -454,6 +542,7 @@ where
     reporter: R,
     blob_semaphore: Arc<Semaphore>,
     max_concurrent_branches: usize,
+    capture_xattrs: bool,
 }
 
 impl ManifestBuilder<(), (), ()> {
@@ -470,6 +559,7 @@ impl Default for ManifestBuilder<(), (), ()> {
             reporter: (),
             blob_semaphore: Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENT_BLOBS)),
             max_concurrent_branches: DEFAULT_MAX_CONCURRENT_BRANCHES,
+            capture_xattrs: false,
         }
     }
 }
@@ -500,6 +590,15 @@ where
         self
     }
 
+    /// Set whether the extended attributes of each file
+    /// and directory should be included in the manifest.
+    ///
+    /// Defaults to false.
+    pub fn with_capture_xattrs(mut self, capture_xattrs: bool) -> Self {
+        self.capture_xattrs = capture_xattrs;
+        self
+    }
+
     /// Use the provided hasher when building the manifest.
     ///
     /// The hasher turns blob contents into a digest to be included
@@ -516,6 +615,7 @@ where
             reporter: self.reporter,
             blob_semaphore: self.blob_semaphore,
             max_concurrent_branches: self.max_concurrent_branches,
+            capture_xattrs: self.capture_xattrs,
         }
     }
 
@@ -535,6 +635,7 @@ where
             reporter: self.reporter,
             blob_semaphore: self.blob_semaphore,
             max_concurrent_branches: self.max_concurrent_branches,
+            capture_xattrs: self.capture_xattrs,
         }
     }
 
@@ -549,6 +650,7 @@ where
             reporter,
             blob_semaphore: self.blob_semaphore,
             max_concurrent_branches: self.max_concurrent_branches,
+            capture_xattrs: self.capture_xattrs,
         }
     }
 
@@ -558,15 +660,22 @@ where
         path: P,
     ) -> Result<Manifest> {
         tracing::trace!("computing manifest for {:?}", path.as_ref());
-        let manifest = Manifest::new(
-            self.compute_tree_node(Arc::new(path.as_ref().to_owned()), path.as_ref())
+        let links = HardLinks::default();
+        let mut manifest = Manifest::new(
+            self.compute_tree_node(Arc::new(path.as_ref().to_owned()), path.as_ref(), &links)
                 .await?,
         );
+        links.apply(&mut manifest);
         Ok(manifest)
     }
 
     #[async_recursion::async_recursion]
-    async fn compute_tree_node<P>(&self, root: Arc<std::path::PathBuf>, dirname: P) -> Result<Entry>
+    async fn compute_tree_node<P>(
+        &self,
+        root: Arc<std::path::PathBuf>,
+        dirname: P,
+        links: &HardLinks,
+    ) -> Result<Entry>
     where
         P: AsRef<std::path::Path> + Send,
     {
@@ -597,7 +706,7 @@ where
                 let dir_entry = Arc::clone(&dir_entry);
                 let file_name = dir_entry.file_name().to_string_lossy().to_string();
                 ready(Ok(Some(
-                    self.compute_node(root, path, dir_entry)
+                    self.compute_node(root, path, dir_entry, links)
                         .map_ok(|e| (file_name, e))
                         .boxed(),
                 )))
@@ -615,6 +724,7 @@ where
         root: Arc<std::path::PathBuf>,
         path: P,
         dir_entry: Arc<DirEntry>,
+        links: &HardLinks,
     ) -> Result<Entry> {
         self.reporter.visit_entry(path.as_ref());
         let stat_result = match tokio::fs::symlink_metadata(&path).await {
@@ -692,7 +802,7 @@ where
                 .hash_blob(Box::pin(std::io::Cursor::new(link_target)))
                 .await?;
         } else if file_type.is_dir() {
-            entry = self.compute_tree_node(root, path).await?;
+            entry = self.compute_tree_node(root, path, links).await?;
         } else if runtime::is_removed_entry(&stat_result) {
             tracing::trace!(" >    mask: {:?}", path.as_ref());
             entry = Entry::mask();
@@ -714,6 +824,15 @@ where
 
             entry.kind = EntryKind::Blob(file_size);
             entry.object = self.hasher.hash_blob(Box::pin(reader)).await?;
+
+            #[cfg(unix)]
+            if stat_result.nlink() > 1 {
+                if let Ok(rel_path) = path.as_ref().strip_prefix(&*root) {
+                    links.insert(stat_result.dev(), stat_result.ino(), rel_path);
+                }
+            }
+            #[cfg(windows)]
+            let _ = links;
         }
 
         #[cfg(unix)]
@@ -727,6 +846,17 @@ where
             entry.mode = 0o644;
         }
 
+        #[cfg(unix)]
+        if self.capture_xattrs {
+            let path = path.as_ref().to_owned();
+            entry.xattrs = tokio::task::spawn_blocking(move || {
+                crate::xattr::read_xattrs(&path)
+                    .map_err(|err| Error::StorageReadError("read xattrs of node", path, err))
+            })
+            .await
+            .expect("syscall should not panic")?;
+        }
+
         self.reporter.computed_entry(&entry);
         Ok(entry)
     }
@@ -48,6 +48,50 @@ async fn test_manifest_relative_paths(tmpdir: tempfile::TempDir) {
     assert!(manifest.get_path("/dir1.0/dir2.0/file.txt").is_some());
     assert!(manifest.get_path("dir1.0/dir2.1/file.txt").is_some());
 }
+
+#[rstest]
+#[tokio::test]
+async fn test_manifest_hardlink_groups(tmpdir: tempfile::TempDir) {
+    let dir = tmpdir.path();
+    ensure(dir.join("bin/tool"), "tooldata");
+    ensure(dir.join("other.txt"), "tooldata");
+    std::fs::hard_link(dir.join("bin/tool"), dir.join("bin/tool-alias")).unwrap();
+    std::fs::hard_link(dir.join("bin/tool"), dir.join("tool")).unwrap();
+
+    let manifest = compute_manifest(dir).await.unwrap();
+    assert_eq!(manifest.get_path("bin/tool").unwrap().hardlink, None);
+    assert_eq!(
+        manifest
+            .get_path("bin/tool-alias")
+            .unwrap()
+            .hardlink
+            .as_deref(),
+        Some("bin/tool"),
+        "links should name the first path in their group"
+    );
+    assert_eq!(
+        manifest.get_path("tool").unwrap().hardlink.as_deref(),
+        Some("bin/tool")
+    );
+    assert_eq!(
+        manifest.get_path("other.txt").unwrap().hardlink,
+        None,
+        "files with the same content are not linked"
+    );
+
+    let groups = manifest.hardlink_groups();
+    let mut members = groups
+        .get(relative_path::RelativePath::new("bin/tool"))
+        .unwrap()
+        .clone();
+    members.sort();
+    assert_eq!(members, vec!["bin/tool-alias", "tool"]);
+    assert_eq!(groups.len(), 1);
+
+    let round_trip = manifest.to_graph_manifest().to_tracking_manifest();
+    assert!(round_trip == manifest, "links should survive conversion");
+}
+
 #[rstest]
 #[tokio::test]
 async fn test_manifest_sorting(tmpdir: tempfile::TempDir) {
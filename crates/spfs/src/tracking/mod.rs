@@ -10,11 +10,12 @@ mod entry;
 mod env;
 pub mod manifest;
 mod object;
+mod signature;
 mod tag;
 
 pub use blob_reader::{BlobRead, BlobReadExt};
 pub use diff::{Diff, DiffMode, compute_diff};
-pub use entry::{Entry, EntryKind};
+pub use entry::{Entry, EntryKind, Xattrs};
 pub use env::{
     ENV_SPEC_EMPTY,
     ENV_SPEC_SEPARATOR,
@@ -36,6 +37,7 @@ pub use manifest::{
     compute_manifest,
 };
 pub use object::Object;
+pub use signature::{TAG_SIGNATURE_ALGORITHM, TagSignature, TagSignaturePolicy, TagSigningKey};
 pub use tag::{Tag, TagSpec, build_tag_spec, split_tag_spec};
 mod time_spec;
 pub use time_spec::{TimeSpec, parse_duration, parse_time};
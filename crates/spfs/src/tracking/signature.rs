@@ -0,0 +1,186 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use data_encoding::BASE64;
+use ring::signature::{ED25519, Ed25519KeyPair, KeyPair, UnparsedPublicKey};
+
+use super::Tag;
+use crate::encoding::prelude::*;
+use crate::{Error, Result};
+
+#[cfg(test)]
+#[path = "./signature_test.rs"]
+mod signature_test;
+
+/// The only signature algorithm currently supported for tags
+pub const TAG_SIGNATURE_ALGORITHM: &str = "ed25519";
+
+/// A cryptographic signature over the digest of a tag.
+///
+/// The signature covers every field of the tag, including its
+/// parent, but is not itself part of the tag's digest so that
+/// signing a tag does not change its identity.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct TagSignature {
+    /// The ed25519 public key of the signer
+    pub public_key: Vec<u8>,
+    /// The ed25519 signature of the tag digest
+    pub signature: Vec<u8>,
+}
+
+impl TagSignature {
+    /// The public key of the signer, as it would appear in a config file
+    pub fn public_key_base64(&self) -> String {
+        BASE64.encode(&self.public_key)
+    }
+
+    /// Check that this is a valid signature of the given tag.
+    pub fn verify(&self, tag: &Tag) -> Result<()> {
+        let digest = tag.digest()?;
+        UnparsedPublicKey::new(&ED25519, &self.public_key)
+            .verify(digest.as_bytes(), &self.signature)
+            .map_err(|_| {
+                Error::String(format!(
+                    "Invalid signature on tag {} by key {}",
+                    tag.path(),
+                    self.public_key_base64()
+                ))
+            })
+    }
+}
+
+/// A private key used to sign newly created tags.
+pub struct TagSigningKey {
+    key_pair: Ed25519KeyPair,
+}
+
+impl std::fmt::Debug for TagSigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TagSigningKey")
+            .field("public_key", &self.public_key_base64())
+            .finish_non_exhaustive()
+    }
+}
+
+impl TagSigningKey {
+    /// Load an ed25519 private key from a PKCS#8 file.
+    ///
+    /// The file can be PEM encoded, such as the ones created by
+    /// `openssl genpkey -algorithm ed25519`, or contain only the
+    /// base64 encoded key document.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path)
+            .map_err(|err| Error::StorageReadError("read of tag signing key", path.into(), err))?;
+        let encoded: String = data
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with("-----"))
+            .collect();
+        let document = BASE64.decode(encoded.as_bytes()).map_err(|err| {
+            Error::String(format!(
+                "Tag signing key is not valid base64 [{}]: {err}",
+                path.display()
+            ))
+        })?;
+        Self::from_pkcs8(&document).map_err(|err| {
+            Error::String(format!(
+                "Tag signing key could not be loaded [{}]: {err}",
+                path.display()
+            ))
+        })
+    }
+
+    /// Create a signing key from a PKCS#8 encoded ed25519 key document.
+    pub fn from_pkcs8(document: &[u8]) -> Result<Self> {
+        let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(document)
+            .map_err(|err| Error::String(format!("Invalid ed25519 key: {err}")))?;
+        Ok(Self { key_pair })
+    }
+
+    /// The public key of this signing key, as it would appear in a config file
+    pub fn public_key_base64(&self) -> String {
+        BASE64.encode(self.key_pair.public_key().as_ref())
+    }
+
+    /// Create a signature for the given tag.
+    ///
+    /// The tag must not be modified after being signed.
+    pub fn sign(&self, tag: &Tag) -> Result<TagSignature> {
+        let digest = tag.digest()?;
+        Ok(TagSignature {
+            public_key: self.key_pair.public_key().as_ref().to_vec(),
+            signature: self.key_pair.sign(digest.as_bytes()).as_ref().to_vec(),
+        })
+    }
+}
+
+/// Determines which tags can be resolved from a repository.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TagSignaturePolicy {
+    required: bool,
+    trusted_keys: BTreeSet<Vec<u8>>,
+}
+
+impl TagSignaturePolicy {
+    /// Create a policy from the values found in a config file.
+    ///
+    /// The trusted keys are expected to be base64 encoded ed25519 public keys.
+    pub fn new<I, S>(required: bool, trusted_keys: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let trusted_keys = trusted_keys
+            .into_iter()
+            .map(|key| {
+                let key = key.as_ref().trim();
+                BASE64
+                    .decode(key.as_bytes())
+                    .map_err(|err| Error::String(format!("Invalid trusted tag key {key}: {err}")))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            required,
+            trusted_keys,
+        })
+    }
+
+    /// True if tags must be signed by a trusted key to be resolved
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+
+    /// The trusted keys, as they would appear in a config file
+    pub fn trusted_keys_base64(&self) -> Vec<String> {
+        self.trusted_keys
+            .iter()
+            .map(|key| BASE64.encode(key))
+            .collect()
+    }
+
+    /// Check that the given tag is allowed by this policy.
+    pub fn check(&self, tag: &Tag) -> Result<()> {
+        if !self.required {
+            return Ok(());
+        }
+        let Some(signature) = &tag.signature else {
+            return Err(Error::String(format!(
+                "Tag {} is not signed, and this repository requires signed tags",
+                tag.path()
+            )));
+        };
+        if !self.trusted_keys.contains(&signature.public_key) {
+            return Err(Error::String(format!(
+                "Tag {} is signed by an untrusted key: {}",
+                tag.path(),
+                signature.public_key_base64()
+            )));
+        }
+        signature.verify(tag)
+    }
+}
@@ -2,11 +2,12 @@
 // SPDX-License-Identifier: Apache-2.0
 // https://github.com/spkenv/spk
 
-use std::io::BufRead;
+use std::io::{BufRead, Read};
 
 use chrono::prelude::*;
 use serde::Deserialize;
 
+use super::signature::{TAG_SIGNATURE_ALGORITHM, TagSignature, TagSigningKey};
 use crate::encoding::prelude::*;
 use crate::{Error, Result, encoding};
 
@@ -25,6 +26,9 @@ pub struct Tag {
     pub parent: encoding::Digest,
     pub user: String,
     pub time: DateTime<Utc>,
+    /// An optional signature of this tag, which is
+    /// not considered as part of the tag's digest
+    pub signature: Option<TagSignature>,
 }
 
 impl Tag {
@@ -44,9 +48,19 @@ impl Tag {
             parent: encoding::NULL_DIGEST.into(),
             user: format!("{}", config.user),
             time: Utc::now().trunc_subsecs(6), // ignore microseconds
+            signature: None,
         })
     }
 
+    /// Sign this tag with the given key, replacing any existing signature.
+    ///
+    /// Any changes made to this tag after signing
+    /// will invalidate the signature.
+    pub fn sign(&mut self, key: &TagSigningKey) -> Result<()> {
+        self.signature = Some(key.sign(self)?);
+        Ok(())
+    }
+
     pub fn to_spec(&self, version: u64) -> TagSpec {
         TagSpec(self.org.clone(), self.name.clone(), version)
     }
@@ -133,15 +147,14 @@ impl Digestible for Tag {
 
     fn digest(&self) -> std::result::Result<spfs_proto::Digest, Self::Error> {
         let mut hasher = encoding::Hasher::new_sync();
-        self.encode(&mut hasher)?;
+        self.encode_unsigned(&mut hasher)?;
         Ok(hasher.digest())
     }
 }
 
-impl Encodable for Tag {
-    type Error = Error;
-
-    fn encode(&self, writer: &mut impl std::io::Write) -> Result<()> {
+impl Tag {
+    /// Encode the fields of this tag that are covered by its digest.
+    fn encode_unsigned(&self, writer: &mut impl std::io::Write) -> Result<()> {
         if let Some(org) = self.org.as_ref() {
             encoding::write_string(&mut *writer, org)?;
         } else {
@@ -156,6 +169,29 @@ impl Encodable for Tag {
     }
 }
 
+impl Encodable for Tag {
+    type Error = Error;
+
+    fn encode(&self, writer: &mut impl std::io::Write) -> Result<()> {
+        self.encode_unsigned(&mut *writer)?;
+        // the signature is written after all other fields so that
+        // unsigned tags are encoded exactly as they were before
+        // signatures were introduced
+        if let Some(signature) = &self.signature {
+            encoding::write_string(&mut *writer, TAG_SIGNATURE_ALGORITHM)?;
+            encoding::write_uint64(&mut *writer, signature.public_key.len() as u64)?;
+            writer
+                .write_all(&signature.public_key)
+                .map_err(encoding::Error::FailedWrite)?;
+            encoding::write_uint64(&mut *writer, signature.signature.len() as u64)?;
+            writer
+                .write_all(&signature.signature)
+                .map_err(encoding::Error::FailedWrite)?;
+        }
+        Ok(())
+    }
+}
+
 impl encoding::Decodable for Tag {
     fn decode(mut reader: &mut impl BufRead) -> Result<Self> {
         let org = encoding::read_string(&mut reader)?;
@@ -169,11 +205,44 @@ impl encoding::Decodable for Tag {
             target: encoding::read_digest(&mut *reader)?,
             user: encoding::read_string(&mut *reader)?,
             time: DateTime::parse_from_rfc3339(&encoding::read_string(&mut *reader)?)?.into(),
-            parent: encoding::read_digest(reader)?,
+            parent: encoding::read_digest(&mut *reader)?,
+            signature: decode_signature(reader)?,
         })
     }
 }
 
+/// Read the optional signature that trails an encoded tag.
+fn decode_signature(reader: &mut impl BufRead) -> Result<Option<TagSignature>> {
+    let remaining = reader.fill_buf().map_err(encoding::Error::FailedRead)?;
+    if remaining.is_empty() {
+        return Ok(None);
+    }
+    let algorithm = encoding::read_string(&mut *reader)?;
+    if algorithm != TAG_SIGNATURE_ALGORITHM {
+        return Err(Error::String(format!(
+            "Unsupported tag signature algorithm: {algorithm}"
+        )));
+    }
+    let mut read_bytes = || -> Result<Vec<u8>> {
+        let size = encoding::read_uint64(&mut *reader)?;
+        let mut buf = Vec::new();
+        (&mut *reader)
+            .take(size)
+            .read_to_end(&mut buf)
+            .map_err(encoding::Error::FailedRead)?;
+        if buf.len() as u64 != size {
+            return Err(Error::String("Tag signature is truncated".into()));
+        }
+        Ok(buf)
+    };
+    let public_key = read_bytes()?;
+    let signature = read_bytes()?;
+    Ok(Some(TagSignature {
+        public_key,
+        signature,
+    }))
+}
+
 /// TagSpec identifies a tag within a tag stream.
 ///
 /// The tag spec represents a string specifier or the form:
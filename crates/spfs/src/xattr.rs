@@ -0,0 +1,157 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+//! Reading and writing the extended attributes of files
+
+use std::ffi::{CString, OsStr, OsString};
+use std::os::fd::RawFd;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::Path;
+
+use crate::tracking::Xattrs;
+
+#[cfg(test)]
+#[path = "./xattr_test.rs"]
+mod xattr_test;
+
+/// Attributes with these prefixes are managed by overlayfs in
+/// the upper directory of a runtime and must never be captured
+const OVERLAY_PREFIXES: &[&str] = &["trusted.overlay.", "user.overlay."];
+
+fn to_cstring(value: &OsStr) -> std::io::Result<CString> {
+    CString::new(value.as_bytes()).map_err(|_| std::io::ErrorKind::InvalidInput.into())
+}
+
+/// Call a function that fills a buffer, growing the buffer until the
+/// result fits. The function is called with an empty buffer first
+/// to find the required size, as is the convention for xattr syscalls.
+fn read_sized<F>(mut call: F) -> std::io::Result<Vec<u8>>
+where
+    F: FnMut(*mut libc::c_void, libc::size_t) -> libc::ssize_t,
+{
+    loop {
+        let size = call(std::ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let mut buf = vec![0u8; size as usize];
+        let size = call(buf.as_mut_ptr().cast(), buf.len());
+        if size < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ERANGE) {
+                // the value grew since it was measured
+                continue;
+            }
+            return Err(err);
+        }
+        buf.truncate(size as usize);
+        return Ok(buf);
+    }
+}
+
+/// List the names of the extended attributes on a path,
+/// without following symlinks.
+///
+/// Filesystems that do not support extended attributes
+/// have no names to list.
+pub fn list(path: &Path) -> std::io::Result<Vec<OsString>> {
+    let c_path = to_cstring(path.as_os_str())?;
+    let names = match read_sized(|buf, size| unsafe {
+        // Safety: the path is a valid c-string and the buffer is valid for size bytes
+        libc::llistxattr(c_path.as_ptr(), buf.cast(), size)
+    }) {
+        Ok(names) => names,
+        Err(err) if err.raw_os_error() == Some(libc::ENOTSUP) => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    Ok(names
+        .split(|b| *b == 0)
+        .filter(|name| !name.is_empty())
+        .map(|name| OsString::from_vec(name.to_vec()))
+        .collect())
+}
+
+/// Get the value of an extended attribute on a path, without
+/// following symlinks, or None if it does not exist.
+pub fn get(path: &Path, name: &OsStr) -> std::io::Result<Option<Vec<u8>>> {
+    let c_path = to_cstring(path.as_os_str())?;
+    let c_name = to_cstring(name)?;
+    match read_sized(|buf, size| unsafe {
+        // Safety: the path and name are valid c-strings and the buffer is valid for size bytes
+        libc::lgetxattr(c_path.as_ptr(), c_name.as_ptr(), buf, size)
+    }) {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if err.raw_os_error() == Some(libc::ENODATA) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Set an extended attribute on a path, without following symlinks.
+pub fn set(path: &Path, name: &OsStr, value: &[u8]) -> std::io::Result<()> {
+    let c_path = to_cstring(path.as_os_str())?;
+    let c_name = to_cstring(name)?;
+    // Safety: the path and name are valid c-strings and the value is valid for its length
+    let res = unsafe {
+        libc::lsetxattr(
+            c_path.as_ptr(),
+            c_name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+        )
+    };
+    if res < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Set an extended attribute on an open file or directory.
+pub fn fset(fd: RawFd, name: &str, value: &[u8]) -> std::io::Result<()> {
+    let c_name = to_cstring(OsStr::new(name))?;
+    // Safety: the name is a valid c-string and the value is valid for its length
+    let res =
+        unsafe { libc::fsetxattr(fd, c_name.as_ptr(), value.as_ptr().cast(), value.len(), 0) };
+    if res < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Read all of the extended attributes of a path that
+/// should be captured into a manifest.
+///
+/// Attributes that are managed by overlayfs are skipped, as
+/// are any with names that are not valid utf-8.
+pub fn read_xattrs(path: &Path) -> std::io::Result<Xattrs> {
+    let mut xattrs = Xattrs::new();
+    for name in list(path)? {
+        let Some(str_name) = name.to_str() else {
+            tracing::warn!(?name, "skipping extended attribute with non-utf8 name");
+            continue;
+        };
+        if OVERLAY_PREFIXES
+            .iter()
+            .any(|prefix| str_name.starts_with(prefix))
+        {
+            continue;
+        }
+        // the attribute may have been removed since being listed
+        if let Some(value) = get(path, &name)? {
+            xattrs.insert(str_name.to_owned(), value);
+        }
+    }
+    Ok(xattrs)
+}
+
+/// Format a list of attribute names in the layout used by listxattr,
+/// where each name is followed by a nul byte.
+pub fn encode_names<'a>(names: impl IntoIterator<Item = &'a OsStr>) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for name in names {
+        buf.extend_from_slice(name.as_bytes());
+        buf.push(0);
+    }
+    buf
+}
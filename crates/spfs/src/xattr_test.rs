@@ -0,0 +1,38 @@
+// Copyright (c) Contributors to the SPK project.
+// SPDX-License-Identifier: Apache-2.0
+// https://github.com/spkenv/spk
+
+use std::ffi::OsStr;
+
+use rstest::rstest;
+
+use super::{get, list, read_xattrs, set};
+use crate::fixtures::*;
+
+#[rstest]
+fn test_read_xattrs_skips_overlay_attributes(tmpdir: tempfile::TempDir) {
+    init_logging();
+    let path = tmpdir.path().join("file.txt");
+    ensure(path.clone(), "data");
+
+    match set(&path, OsStr::new("user.spfs.test"), b"value") {
+        Err(err) if err.raw_os_error() == Some(libc::ENOTSUP) => {
+            tracing::warn!("skipping test, user xattrs are not supported in {tmpdir:?}");
+            return;
+        }
+        res => res.unwrap(),
+    }
+    set(&path, OsStr::new("user.overlay.origin"), b"ignored").unwrap();
+
+    let names = list(&path).unwrap();
+    assert!(names.iter().any(|n| n == "user.spfs.test"));
+    assert_eq!(
+        get(&path, OsStr::new("user.spfs.test")).unwrap().as_deref(),
+        Some(&b"value"[..])
+    );
+    assert_eq!(get(&path, OsStr::new("user.spfs.missing")).unwrap(), None);
+
+    let xattrs = read_xattrs(&path).unwrap();
+    assert_eq!(xattrs.len(), 1, "overlay attributes should be skipped");
+    assert_eq!(xattrs.get("user.spfs.test"), Some(&b"value".to_vec()));
+}
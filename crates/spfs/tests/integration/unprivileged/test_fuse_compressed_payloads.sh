@@ -0,0 +1,40 @@
+#!/bin/bash
+
+# Copyright (c) Contributors to the SPK project.
+# SPDX-License-Identifier: Apache-2.0
+# https://github.com/spkenv/spk
+
+set -o errexit
+
+# test that payloads which are only stored compressed in a
+# repository can be read from a fuse-based runtime
+
+origin=$(mktemp -d)
+local_root=$(mktemp -d)
+trap "rm -rf $origin $local_root" EXIT
+
+export SPFS_REMOTE__ORIGIN__ADDRESS="file:${origin}?create=true&compression=zstd"
+
+cat <<'EOS' | spfs run - -- bash -ex
+echo "compressed contents" > /spfs/compressed.txt
+spfs commit layer -t spfs-test/fuse-compressed
+EOS
+spfs push spfs-test/fuse-compressed
+
+# the pushed payload must only exist in compressed form
+test -z "$(find $origin/payloads -type f 2>/dev/null)"
+test -n "$(find $origin/payloads.zst -type f)"
+
+# start from an empty local repository so that the
+# payload can only be read from the compressed origin
+export SPFS_STORAGE_ROOT=$local_root
+export SPFS_FILESYSTEM_BACKEND=FuseOnly
+
+contents=$(spfs run spfs-test/fuse-compressed -- cat /spfs/compressed.txt)
+test "$contents" == "compressed contents"
+
+# the payload cache also allows the decompressed payload to be seeked
+export SPFS_FUSE__PAYLOAD_CACHE_SIZE_MB=10
+export SPFS_FUSE__PAYLOAD_CACHE_DIR=$local_root/payload-cache
+contents=$(spfs run spfs-test/fuse-compressed -- tail -c 9 /spfs/compressed.txt)
+test "$contents" == "contents"
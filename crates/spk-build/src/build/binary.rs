@@ -699,23 +699,31 @@ where
 
         let mut startup_file_csh = startup_dir.join(format!("spk_{}.csh", package.name()));
         let mut startup_file_sh = startup_dir.join(format!("spk_{}.sh", package.name()));
+        let mut startup_file_fish = startup_dir.join(format!("spk_{}.fish", package.name()));
         let mut csh_file = std::fs::File::create(&startup_file_csh)
             .map_err(|err| Error::FileOpenError(startup_file_csh.to_owned(), err))?;
         let mut sh_file = std::fs::File::create(&startup_file_sh)
             .map_err(|err| Error::FileOpenError(startup_file_sh.to_owned(), err))?;
+        let mut fish_file = std::fs::File::create(&startup_file_fish)
+            .map_err(|err| Error::FileOpenError(startup_file_fish.to_owned(), err))?;
 
         for op in ops {
             if let Some(priority) = op.priority() {
                 let original_startup_file_sh_name = startup_file_sh.clone();
                 let original_startup_file_csh_name = startup_file_csh.clone();
+                let original_startup_file_fish_name = startup_file_fish.clone();
 
                 startup_file_sh.set_file_name(format!("{priority:02}_spk_{}.sh", package.name()));
                 startup_file_csh.set_file_name(format!("{priority:02}_spk_{}.csh", package.name()));
+                startup_file_fish
+                    .set_file_name(format!("{priority:02}_spk_{}.fish", package.name()));
 
                 std::fs::rename(original_startup_file_sh_name, &startup_file_sh)
                     .map_err(|err| Error::FileWriteError(startup_file_sh.to_owned(), err))?;
                 std::fs::rename(original_startup_file_csh_name, &startup_file_csh)
                     .map_err(|err| Error::FileWriteError(startup_file_csh.to_owned(), err))?;
+                std::fs::rename(original_startup_file_fish_name, &startup_file_fish)
+                    .map_err(|err| Error::FileWriteError(startup_file_fish.to_owned(), err))?;
 
                 continue;
             }
@@ -726,6 +734,9 @@ where
             sh_file
                 .write_fmt(format_args!("{}\n", op.bash_source()))
                 .map_err(|err| Error::FileWriteError(startup_file_sh.to_owned(), err))?;
+            fish_file
+                .write_fmt(format_args!("{}\n", op.fish_source()))
+                .map_err(|err| Error::FileWriteError(startup_file_fish.to_owned(), err))?;
         }
         Ok(())
     }
@@ -747,6 +758,7 @@ where
     let config = spfs::get_config()?;
     let repo = Arc::new(config.get_local_repository_handle().await?);
     let layer = spfs::Committer::new(&repo)
+        .with_capture_xattrs(config.filesystem.capture_xattrs)
         .with_path_filter(collected_changes.as_slice())
         .commit_layer(&mut runtime)
         .await?;
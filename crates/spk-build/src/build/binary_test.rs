@@ -36,6 +36,8 @@ fn test_split_manifest_permissions() {
                 entries: Default::default(),
                 user_data: (),
                 legacy_size: 0,
+                xattrs: Default::default(),
+                hardlink: None,
             },
         )
         .unwrap();
@@ -861,6 +863,8 @@ async fn test_build_add_startup_files(tmpdir: tempfile::TempDir, #[case] solver:
     assert!(bash_file.exists());
     let tcsh_file = tmpdir.path().join("etc/spfs/startup.d/spk_testpkg.csh");
     assert!(tcsh_file.exists());
+    let fish_file = tmpdir.path().join("etc/spfs/startup.d/spk_testpkg.fish");
+    assert!(fish_file.exists());
 
     let bash_value = std::process::Command::new("bash")
         .args(["--norc", "-c"])
@@ -932,6 +936,8 @@ async fn test_build_priority_startup_files(tmpdir: tempfile::TempDir, #[case] so
     assert!(bash_file.exists());
     let tcsh_file = tmpdir.path().join("etc/spfs/startup.d/99_spk_testpkg.csh");
     assert!(tcsh_file.exists());
+    let fish_file = tmpdir.path().join("etc/spfs/startup.d/99_spk_testpkg.fish");
+    assert!(fish_file.exists());
 }
 
 #[rstest]
@@ -376,7 +376,7 @@ impl<T: Output> Du<T> {
                                                     }
                                                 }
                                             }
-                                            Enum::Blob(_) => self.output.warn(format_args!("Blob object cannot have disk usage generated")),
+                                            Enum::Blob(_) | Enum::ChunkedBlob(_) => self.output.warn(format_args!("Blob object cannot have disk usage generated")),
                                         }
                                     }
                                     items_to_process = std::mem::take(&mut next_iter_objects);
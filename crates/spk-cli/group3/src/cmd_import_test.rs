@@ -90,6 +90,8 @@ async fn test_archive_io(#[case] solver: SolverImpl) {
             check: false,
             max_concurrent_manifests: 10,
             max_concurrent_payloads: 10,
+            max_bytes_per_second: None,
+            resumable: false,
             progress: None,
         },
         files: vec![filename],
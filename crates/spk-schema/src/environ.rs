@@ -18,6 +18,9 @@ const DEFAULT_VAR_SEP: &str = ";";
 #[cfg(unix)]
 const DEFAULT_VAR_SEP: &str = ":";
 
+/// The separator that fish joins the elements of PATH variables with
+const FISH_PATH_SEP: &str = ":";
+
 const OP_APPEND: &str = "append";
 const OP_COMMENT: &str = "comment";
 const OP_PREPEND: &str = "prepend";
@@ -100,6 +103,7 @@ impl EnvOp {
         match shell {
             spfs::ShellKind::Bash => self.bash_source(),
             spfs::ShellKind::Tcsh => self.tcsh_source(),
+            spfs::ShellKind::Fish => self.fish_source(),
             spfs::ShellKind::Powershell => self.powershell_source(),
         }
     }
@@ -182,6 +186,17 @@ impl EnvOp {
         }
     }
 
+    /// Construct the fish source representation for this operation
+    pub fn fish_source(&self) -> String {
+        match self {
+            Self::Append(op) => op.fish_source(),
+            Self::Comment(op) => op.fish_source(),
+            Self::Prepend(op) => op.fish_source(),
+            Self::Priority(op) => op.fish_source(),
+            Self::Set(op) => op.fish_source(),
+        }
+    }
+
     /// Construct the powershell source representation for this operation
     pub fn powershell_source(&self) -> String {
         todo!()
@@ -419,6 +434,28 @@ impl AppendEnv {
         ]
         .join("\n")
     }
+    /// Construct the fish source representation for this operation
+    pub fn fish_source(&self) -> String {
+        let value = fish_value(&self.value);
+        [
+            format!("if set -q {}", self.append),
+            format!(
+                "    {}",
+                fish_set(
+                    &self.append,
+                    self.sep(),
+                    &format!("\"${}\"\"{}{}\"", self.append, self.sep(), value)
+                )
+            ),
+            "else".to_string(),
+            format!(
+                "    {}",
+                fish_set(&self.append, self.sep(), &format!("\"{value}\""))
+            ),
+            "end".to_string(),
+        ]
+        .join("\n")
+    }
 }
 
 /// Adds a comment to the generated environment script
@@ -437,6 +474,10 @@ impl EnvComment {
         // Both bash and tcsh source use the same comment syntax
         self.bash_source()
     }
+    /// Construct the fish source representation for this operation
+    pub fn fish_source(&self) -> String {
+        self.bash_source()
+    }
 }
 
 /// Assigns a priority to the generated environment script
@@ -456,6 +497,11 @@ impl EnvPriority {
         String::from("")
     }
 
+    /// Construct the fish source representation for this operation
+    pub fn fish_source(&self) -> String {
+        String::from("")
+    }
+
     pub fn priority(&self) -> u8 {
         self.priority
     }
@@ -508,6 +554,28 @@ impl PrependEnv {
         ]
         .join("\n")
     }
+    /// Construct the fish source representation for this operation
+    pub fn fish_source(&self) -> String {
+        let value = fish_value(&self.value);
+        [
+            format!("if set -q {}", self.prepend),
+            format!(
+                "    {}",
+                fish_set(
+                    &self.prepend,
+                    self.sep(),
+                    &format!("\"{}{}\"\"${}\"", value, self.sep(), self.prepend)
+                )
+            ),
+            "else".to_string(),
+            format!(
+                "    {}",
+                fish_set(&self.prepend, self.sep(), &format!("\"{value}\""))
+            ),
+            "end".to_string(),
+        ]
+        .join("\n")
+    }
 }
 
 /// Operates on an environment variable by setting it to a value
@@ -526,4 +594,44 @@ impl SetEnv {
     pub fn tcsh_source(&self) -> String {
         format!("setenv {} \"{}\"", self.set, self.value)
     }
+    /// Construct the fish source representation for this operation
+    pub fn fish_source(&self) -> String {
+        fish_set(
+            &self.set,
+            DEFAULT_VAR_SEP,
+            &format!("\"{}\"", fish_value(&self.value)),
+        )
+    }
+}
+
+/// Construct the fish command that exports a variable with the given
+/// (quoted) value. Fish stores variables ending in PATH as lists and
+/// always joins them with ':', so these are split on the separator
+/// when it is ':' and otherwise kept as a single value, which keeps
+/// the separator that the variable was built with.
+fn fish_set(name: &str, sep: &str, value: &str) -> String {
+    if name.ends_with("PATH") && sep == FISH_PATH_SEP {
+        format!("set -gx {name} (string split {sep} -- {value})")
+    } else {
+        format!("set -gx {name} {value}")
+    }
+}
+
+/// Rewrite a value for use inside of double quotes in fish, which
+/// does not support referencing variables as `${NAME}`.
+fn fish_value(value: &str) -> String {
+    let mut rewritten = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        rewritten.push_str(&rest[..start]);
+        // the variable is quoted separately, so that
+        // it cannot run together with what follows it
+        rewritten.push_str(&format!("\"\"${}\"\"", &rest[start + 2..start + len]));
+        rest = &rest[start + len + 1..];
+    }
+    rewritten.push_str(rest);
+    rewritten
 }
@@ -97,3 +97,25 @@ fn test_var_expansion(#[case] op: &str, #[case] vars: &[(&str, &str)], #[case] e
     );
     assert_eq!(expanded.value().unwrap(), expected);
 }
+
+#[rstest]
+#[case(
+    "{set: SPK_TEST_VAR, value: simple}",
+    r#"set -gx SPK_TEST_VAR "simple""#
+)]
+#[case(
+    "{set: SPK_TEST_VAR, value: '${SPK_ROOT}lib'}",
+    r#"set -gx SPK_TEST_VAR """$SPK_ROOT""lib""#
+)]
+#[case(
+    "{prepend: SPK_TEST_PATH, value: /spfs/bin}",
+    "if set -q SPK_TEST_PATH\n    set -gx SPK_TEST_PATH (string split : -- \"/spfs/bin:\"\"$SPK_TEST_PATH\")\nelse\n    set -gx SPK_TEST_PATH (string split : -- \"/spfs/bin\")\nend"
+)]
+#[case(
+    "{append: SPK_TEST_PATH, value: /spfs/bin, separator: ';'}",
+    "if set -q SPK_TEST_PATH\n    set -gx SPK_TEST_PATH \"$SPK_TEST_PATH\"\";/spfs/bin\"\nelse\n    set -gx SPK_TEST_PATH \"/spfs/bin\"\nend"
+)]
+fn test_fish_source(#[case] op: &str, #[case] expected: &str) {
+    let op: EnvOp = serde_yaml::from_str(op).unwrap();
+    assert_eq!(op.fish_source(), expected);
+}
@@ -638,7 +638,7 @@ impl RepoWalker<'_> {
                                 }
                             }
                         }
-                        Enum::Blob(_object) => {
+                        Enum::Blob(_) | Enum::ChunkedBlob(_) => {
                             // These are ignored for finding files
                             continue;
                         }